@@ -40,6 +40,18 @@ pub enum RenameRule {
     ScreamingKebabCase,
 }
 
+/// The names of the rules in `#[serde(rename_all = "...")]`, the same as Serde's.
+const RENAME_RULES: &[(&str, RenameRule)] = &[
+    ("lowercase",            LowerCase),
+    ("UPPERCASE",            Uppercase),
+    ("PascalCase",           PascalCase),
+    ("camelCase",            CamelCase),
+    ("snake_case",           SnakeCase),
+    ("SCREAMING_SNAKE_CASE", ScreamingSnakeCase),
+    ("kebab-case",           KebabCase),
+    ("SCREAMING-KEBAB-CASE", ScreamingKebabCase),
+];
+
 impl RenameRule {
     /// Returns a string which is the given variant name, renamed according
     /// to the rule that is `self`.
@@ -48,7 +60,7 @@ impl RenameRule {
             PascalCase => variant,
             LowerCase => variant.to_ascii_lowercase(),
             Uppercase => variant.to_ascii_uppercase(),
-            CamelCase => variant[..1].to_ascii_lowercase() + &variant[1..],
+            CamelCase => lowercase_first(&variant),
             SnakeCase => {
                 let mut snake = String::new();
                 for (i, ch) in variant.char_indices() {
@@ -88,10 +100,7 @@ impl RenameRule {
                 }
                 pascal
             }
-            CamelCase => {
-                let pascal = PascalCase.apply_to_field(field);
-                pascal[..1].to_ascii_lowercase() + &pascal[1..]
-            }
+            CamelCase => lowercase_first(&PascalCase.apply_to_field(field)),
             ScreamingSnakeCase => field.to_ascii_uppercase(),
             KebabCase => field.replace('_', "-"),
             ScreamingKebabCase => ScreamingSnakeCase.apply_to_field(field).replace('_', "-"),
@@ -99,20 +108,27 @@ impl RenameRule {
     }
 }
 
+/// Lowercases the first character of `name`, if it's ASCII, like Serde.
+/// Unlike Serde, this doesn't panic if the first character is non-ASCII,
+/// or if `name` is empty.
+fn lowercase_first(name: &str) -> String {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
 impl FromStr for RenameRule {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        match s {
-            "lowercase"            => Ok(LowerCase),
-            "UPPERCASE"            => Ok(Uppercase),
-            "PascalCase"           => Ok(PascalCase),
-            "camelCase"            => Ok(CamelCase),
-            "snake_case"           => Ok(SnakeCase),
-            "SCREAMING_SNAKE_CASE" => Ok(ScreamingSnakeCase),
-            "kebab-case"           => Ok(KebabCase),
-            "SCREAMING-KEBAB-CASE" => Ok(ScreamingKebabCase),
-            _ => Err(Error::new(format!("Unknown `rename_all` rule: {}", s))),
-        }
+        RENAME_RULES.iter().find(|&&(name, _)| name == s).map(|&(_, rule)| rule).ok_or_else(|| {
+            let names: Vec<_> = RENAME_RULES.iter().map(|&(name, _)| format!("{:?}", name)).collect();
+            Error::new(format!(
+                "unknown rename rule `rename_all = {:?}`, expected one of {}", s, names.join(", ")
+            ))
+        })
     }
 }
@@ -1,57 +1,284 @@
 //! Code generation for `enum`s.
 
-use syn::{ Attribute, DataEnum, Variant, Fields };
-use proc_macro2::TokenStream;
+use syn::{ Attribute, DataEnum, Variant, Fields, Ident, Type };
+use proc_macro2::{ TokenStream, Span };
 use error::{ Error, Result };
 use case::RenameRule;
 use tag::SerdeEnumTag;
+use keys;
 use codegen_field::*;
 use meta;
 
-/// Implements `BsonSchema` for an `enum`.
-/// TODO(H2CO3): implement me
+/// Implements `BsonSchema` for an `enum`. Its doc comment becomes the
+/// description of the schema.
+/// With `#[magnet(tag_summary)]`, the schema of an internally or adjacently
+/// tagged enum also lists every possible value of the tag at the top level,
+/// next to the `anyOf` of its variants.
+/// An `enum` without variants has no values, so its schema matches nothing.
+/// Neither do variants marked `#[serde(skip)]` or `#[serde(skip_serializing)]`
+/// have any values that could be stored, so they are left out.
 pub fn impl_bson_schema_enum(attrs: Vec<Attribute>, ast: DataEnum) -> Result<TokenStream> {
-    let rename_all_str = meta::serde_name_value(&attrs, "rename_all")?;
-    let rename_all: Option<RenameRule> = match rename_all_str {
-        Some(s) => Some(meta::value_as_str(&s)?.parse()?),
-        None => None,
-    };
+    let rename_all = meta::serde_rename_all(&attrs)?;
     let tagging = SerdeEnumTag::from_attrs(&attrs)?;
+    let mut included = Vec::with_capacity(ast.variants.len());
 
-    let variants: Vec<_> = ast.variants
-        .into_iter()
-        .map(|variant| variant_schema(variant, rename_all, &tagging))
-        .collect::<Result<_>>()?;
+    for variant in ast.variants {
+        if !is_skipped_variant(&variant)? {
+            included.push(variant);
+        }
+    }
 
-    let tokens = quote! {
-        doc! {
-            "anyOf": [ #(#variants,)* ]
+    if included.is_empty() {
+        return Ok(extend_with_doc(quote!{ ::bson::doc! { "not": {} } }, &attrs));
+    }
+
+    let mut names: Vec<(String, Ident)> = Vec::with_capacity(included.len());
+    let mut variants = Vec::with_capacity(included.len());
+    let mut unit_names = Vec::with_capacity(included.len());
+    let mut unit_index = None;
+    let mut aliases: Vec<(String, Span, Ident)> = Vec::new();
+    let all_units = included.iter().all(is_mergeable_unit_variant);
+
+    // Unit variants of tagged enums only differ in the value of the tag,
+    // so they are merged into a single branch, listing every name.
+    let merge_units = all_units || match tagging {
+        SerdeEnumTag::Internal(_) | SerdeEnumTag::Adjacent { .. } => true,
+        SerdeEnumTag::External | SerdeEnumTag::Untagged => false,
+    };
+
+    for variant in included {
+        let span = variant.ident.span();
+        let name = variant_name(&variant, rename_all).map_err(|error| error.with_span(span))?;
+
+        // the names of the variants of untagged enums don't appear in the data
+        if tagging != SerdeEnumTag::Untagged {
+            if let Some(prev) = names.iter().find(|prev| prev.0 == name) {
+                let message = format!(
+                    "variants `{}` and `{}` are both named `{}` in the schema",
+                    prev.1, variant.ident, name
+                );
+                return Err(Error::spanned(span, message));
+            }
+        }
+
+        // the names of non-unit variants of externally-tagged enums are keys
+        let is_unit = match variant.fields {
+            Fields::Unit => true,
+            Fields::Named(_) | Fields::Unnamed(_) => false,
+        };
+
+        if tagging == SerdeEnumTag::External && !is_unit {
+            let relaxed = meta::has_magnet_word(&attrs, "relaxed_keys")?
+                || meta::has_magnet_word(&variant.attrs, "relaxed_keys")?;
+            let origin = format!("variant `{}`", variant.ident);
+            keys::check_key(&name, &origin, span, relaxed)?;
         }
+
+        names.push((name.clone(), variant.ident.clone()));
+
+        for (alias, alias_span) in meta::serde_aliases(&variant.attrs)? {
+            aliases.push((alias, alias_span, variant.ident.clone()));
+        }
+
+        if merge_units && is_mergeable_unit_variant(&variant) {
+            // the merged branch takes the place of the first unit variant
+            unit_index = unit_index.or(Some(variants.len()));
+            unit_names.push(name);
+            continue;
+        }
+
+        // The doc comment of the variant describes its payload, if it's
+        // wrapped in an object, or the whole branch otherwise: the latter
+        // is the case for unit variants, as well as for internally tagged
+        // and untagged ones, whose payload is the branch itself.
+        let describes_payload = !is_unit && match tagging {
+            SerdeEnumTag::Adjacent { .. } | SerdeEnumTag::External => true,
+            SerdeEnumTag::Internal(_) | SerdeEnumTag::Untagged => false,
+        };
+        let variant_attrs = variant.attrs.clone();
+        let schema = variant_schema(variant, &name, &tagging).map_err(|error| error.with_span(span))?;
+
+        if describes_payload {
+            variants.push(schema);
+        } else {
+            variants.push(extend_with_doc(schema, &variant_attrs));
+        }
+    }
+
+    // The tag only lists the serialized names, but an alias which is the
+    // name of another variant would make deserializing that one impossible.
+    // (Untagged enums are deserialized by their contents, not their names.)
+    let checked_aliases = if tagging == SerdeEnumTag::Untagged { &[][..] } else { &aliases[..] };
+
+    for &(ref alias, alias_span, ref ident) in checked_aliases {
+        if let Some(other) = names.iter().find(|other| other.0 == *alias && other.1 != *ident) {
+            let message = format!(
+                "alias `{}` of variant `{}` is the name of variant `{}` in the schema",
+                alias, ident, other.1
+            );
+            return Err(Error::spanned(alias_span, message));
+        }
+    }
+
+    // enums consisting of unit variants only don't need alternatives
+    if all_units {
+        let tokens = unit_variants_schema(&unit_names, &tagging);
+        return Ok(extend_with_doc(tokens, &attrs));
+    }
+
+    if let Some(index) = unit_index {
+        variants.insert(index, unit_variants_schema(&unit_names, &tagging));
+    }
+
+    let any_of = quote! {
+        ::magnet_schema::combine::any_of(&[ #(#variants,)* ])
     };
+    let tokens = match tagging.tag() {
+        Some(tag) if meta::has_magnet_word(&attrs, "tag_summary")? => {
+            let all_names = names.iter().map(|name| &name.0);
 
-    Ok(tokens)
+            // the summary comes first, so that it's reported first
+            quote! {
+                ::magnet_schema::combine::merge(
+                    ::bson::doc! {
+                        "required": [ #tag ],
+                        "properties": {
+                            #tag: { "enum": [ #(#all_names,)* ] },
+                        },
+                    },
+                    #any_of
+                )
+            }
+        },
+        _ => any_of,
+    };
+
+    Ok(extend_with_doc(tokens, &attrs))
 }
 
-/// Generates a `BsonSchema` for a single `enum` variant.
-fn variant_schema(
-    variant: Variant,
-    rename_all: Option<RenameRule>,
-    tagging: &SerdeEnumTag,
-) -> Result<TokenStream> {
-    // check for renaming directive attribute
-    if meta::magnet_name_value(&variant.attrs, "rename")?.is_some() {
-        return Err(Error::new("`#[magnet(rename = \"...\")]` no longer exists"))
+/// Implements the methods of `BsonSchemaKey` for the enum, if it's
+/// externally tagged, and it only has unit variants. These are serialized
+/// as their names, so the keys of maps are restricted to those names.
+/// Other enums can't be map keys, so `None` is returned for them.
+pub fn impl_bson_schema_key(attrs: &[Attribute], ast: &DataEnum) -> Result<Option<TokenStream>> {
+    if SerdeEnumTag::from_attrs(attrs)? != SerdeEnumTag::External {
+        return Ok(None);
+    }
+
+    let rename_all = meta::serde_rename_all(attrs)?;
+    let mut names = Vec::with_capacity(ast.variants.len());
+
+    for variant in &ast.variants {
+        if is_skipped_variant(variant)? {
+            continue;
+        }
+
+        match variant.fields {
+            Fields::Unit => names.push(variant_name(variant, rename_all)?),
+            Fields::Named(_) | Fields::Unnamed(_) => return Ok(None),
+        }
+    }
+
+    if names.is_empty() {
+        return Ok(None);
     }
 
-    let rename = meta::serde_name_value(&variant.attrs, "rename")?;
-    let variant_name = match rename {
-        Some(nv) => meta::value_as_str(&nv)?,
+    let alternatives: Vec<_> = names.iter().map(|name| escape_regex(name)).collect();
+    let pattern = format!("^(?:{})$", alternatives.join("|"));
+
+    Ok(Some(quote! {
+        fn key_pattern() -> Option<String> {
+            Some(String::from(#pattern))
+        }
+
+        fn known_keys() -> Option<Vec<String>> {
+            Some(vec![ #(String::from(#names),)* ])
+        }
+    }))
+}
+
+/// Escapes the characters of `string` that are special in regular expressions.
+fn escape_regex(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+
+    for ch in string.chars() {
+        if "\\.+*?()|[]{}^$/".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+
+    escaped
+}
+
+/// Returns `true` if a variant is never serialized, because of
+/// `#[serde(skip)]` or `#[serde(skip_serializing)]`.
+fn is_skipped_variant(variant: &Variant) -> Result<bool> {
+    Ok(
+        meta::has_serde_word(&variant.attrs, "skip")?
+        || meta::has_serde_word(&variant.attrs, "skip_serializing")?
+    )
+}
+
+/// Returns `true` if a variant can share a single schema with other unit
+/// variants: it has no fields, and no doc comment, which would otherwise
+/// need to describe it in a branch of its own.
+fn is_mergeable_unit_variant(variant: &Variant) -> bool {
+    match variant.fields {
+        Fields::Unit => meta::doc_comment(&variant.attrs).is_none(),
+        Fields::Named(_) | Fields::Unnamed(_) => false,
+    }
+}
+
+/// Generates a single schema matching any of the unit variants named
+/// `variant_names`, e.g. an `enum` of every name if the enum is
+/// externally tagged, instead of a separate branch for each variant.
+fn unit_variants_schema(variant_names: &[String], tagging: &SerdeEnumTag) -> TokenStream {
+    match *tagging {
+        SerdeEnumTag::Untagged => quote! {
+            <() as ::magnet_schema::BsonSchema>::bson_schema_with(ctx)
+        },
+        SerdeEnumTag::Adjacent { ref tag, .. } | SerdeEnumTag::Internal(ref tag) => quote! {
+            ::bson::doc! {
+                "type": "object",
+                "additionalProperties": false,
+                "required": [ #tag ],
+                "properties": {
+                    #tag: { "enum": [ #(#variant_names,)* ] },
+                },
+            }
+        },
+        SerdeEnumTag::External => quote! {
+            ::bson::doc! {
+                "enum": [ #(#variant_names,)* ],
+            }
+        },
+    }
+}
+
+/// Returns the potentially-`#[serde(rename(...))]`d name of a variant.
+/// An explicit `rename` of the variant takes precedence over the
+/// `rename_all` rule of the enum. Aliases don't affect the name, since
+/// they are only accepted when deserializing, whereas the schema only
+/// lists the names which are actually serialized.
+fn variant_name(variant: &Variant, rename_all: Option<RenameRule>) -> Result<String> {
+    let name = match meta::serde_rename(&variant.attrs)? {
+        Some(name) => name,
         None => rename_all.map_or_else(
             || variant.ident.to_string(),
             |rule| rule.apply_to_variant(variant.ident.to_string()),
         ),
     };
 
+    Ok(name)
+}
+
+/// Generates a `BsonSchema` for a single `enum` variant.
+fn variant_schema(
+    variant: Variant,
+    variant_name: &str,
+    tagging: &SerdeEnumTag,
+) -> Result<TokenStream> {
     match *tagging {
         SerdeEnumTag::Untagged => {
             impl_bson_schema_fields(&variant.attrs, variant.fields)
@@ -60,12 +287,12 @@ fn variant_schema(
             ref tag, ref content
         } => match variant.fields {
             Fields::Unit => adjacently_tagged_unit_variant_schema(
-                &variant_name,
+                variant_name,
                 tag,
             ),
             _ => adjacently_tagged_other_variant_schema(
                 &variant.attrs,
-                &variant_name,
+                variant_name,
                 tag,
                 content,
                 variant.fields,
@@ -73,21 +300,21 @@ fn variant_schema(
         },
         SerdeEnumTag::Internal(ref tag) => match variant.fields {
             Fields::Unit => internally_tagged_unit_variant_schema(
-                &variant_name,
+                variant_name,
                 tag,
             ),
             _ => internally_tagged_other_variant_schema(
                 &variant.attrs,
-                &variant_name,
+                variant_name,
                 tag,
                 variant.fields,
             ),
         },
         SerdeEnumTag::External => match variant.fields {
-            Fields::Unit => externally_tagged_unit_variant_schema(&variant_name),
+            Fields::Unit => externally_tagged_unit_variant_schema(variant_name),
             _ => externally_tagged_other_variant_schema(
                 &variant.attrs,
-                &variant_name,
+                variant_name,
                 variant.fields,
             ),
         },
@@ -98,7 +325,7 @@ fn variant_schema(
 /// if the containing enum is adjacently tagged.
 fn adjacently_tagged_unit_variant_schema(variant_name: &str, tag: &str) -> Result<TokenStream> {
     let tokens = quote! {
-        doc! {
+        ::bson::doc! {
             "type": "object",
             "additionalProperties": false,
             "required": [ #tag ],
@@ -111,7 +338,8 @@ fn adjacently_tagged_unit_variant_schema(variant_name: &str, tag: &str) -> Resul
 }
 
 /// Generates a schema for a non-unit (newtype, tuple, or struct) variant
-/// if the containing enum is adjacently tagged.
+/// if the containing enum is adjacently tagged. The doc comment of the
+/// variant describes the content.
 fn adjacently_tagged_other_variant_schema(
     attrs: &[Attribute],
     variant_name: &str,
@@ -119,16 +347,20 @@ fn adjacently_tagged_other_variant_schema(
     content: &str,
     fields: Fields,
 ) -> Result<TokenStream> {
-    let variant_schema = impl_bson_schema_fields(attrs, fields)?;
+    let variant_schema = extend_with_doc(impl_bson_schema_fields(attrs, fields)?, attrs);
     let tokens = quote! {
-        doc! {
-            "type": "object",
-            "additionalProperties": false,
-            "required": [ #tag, #content ],
-            "properties": {
-                #tag: { "enum": [ #variant_name ] },
-                #content: #variant_schema,
-            },
+        {
+            let variant_schema = #variant_schema;
+
+            ::bson::doc! {
+                "type": "object",
+                "additionalProperties": false,
+                "required": [ #tag, #content ],
+                "properties": {
+                    #tag: { "enum": [ #variant_name ] },
+                    #content: variant_schema,
+                },
+            }
         }
     };
     Ok(tokens)
@@ -141,6 +373,38 @@ fn internally_tagged_unit_variant_schema(variant_name: &str, tag: &str) -> Resul
     adjacently_tagged_unit_variant_schema(variant_name, tag)
 }
 
+/// The last path segments of standard types which are known to be
+/// neither structs, nor maps, nor enums, so that they can't hold the
+/// tag when wrapped in a newtype variant of an internally-tagged enum.
+const UNTAGGABLE_TYPES: &[&str] = &[
+    "bool", "char", "str", "String",
+    "i8", "i16", "i32", "i64", "i128", "isize",
+    "u8", "u16", "u32", "u64", "u128", "usize",
+    "f32", "f64",
+    "Option", "Vec", "VecDeque", "LinkedList",
+    "HashSet", "BTreeSet", "BinaryHeap",
+];
+
+/// Returns the name of `ty` if it is syntactically known to be
+/// incompatible with internal tagging. Other types, e.g. user-defined
+/// ones, might or might not be, so they are checked at runtime instead.
+fn untaggable_type_name(ty: &Type) -> Option<String> {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            path.path.segments.last().map(|pair| pair.into_value().ident.to_string()).filter(
+                |name| UNTAGGABLE_TYPES.contains(&name.as_str())
+            )
+        },
+        Type::Reference(ref reference) => untaggable_type_name(&reference.elem),
+        Type::Paren(ref paren) => untaggable_type_name(&paren.elem),
+        Type::Group(ref group) => untaggable_type_name(&group.elem),
+        Type::Tuple(ref tuple) if !tuple.elems.is_empty() => Some(String::from("tuple")),
+        Type::Array(_) => Some(String::from("array")),
+        Type::Slice(_) => Some(String::from("slice")),
+        _ => None,
+    }
+}
+
 /// Generates a schema for a non-unit (newtype or struct)
 /// variant if the containing enum is internally tagged.
 fn internally_tagged_other_variant_schema(
@@ -149,6 +413,18 @@ fn internally_tagged_other_variant_schema(
     tag: &str,
     fields: Fields,
 ) -> Result<TokenStream> {
+    if let Fields::Unnamed(ref unnamed) = fields {
+        let untaggable = unnamed.unnamed.iter().next().and_then(|field| untaggable_type_name(&field.ty));
+
+        if let Some(type_name) = untaggable {
+            let message = format!(
+                "newtype variant `{}` around `{}` can't be internally tagged by `{}`",
+                variant, type_name, tag
+            );
+            return Err(Error::new(message));
+        }
+    }
+
     let tag_extra = TagExtra { tag, variant };
 
     impl_bson_schema_fields_extra(attrs, fields, tag_extra.into())
@@ -158,7 +434,7 @@ fn internally_tagged_other_variant_schema(
 /// if the containing enum is externally tagged.
 fn externally_tagged_unit_variant_schema(variant_name: &str) -> Result<TokenStream> {
     let tokens = quote! {
-        doc! {
+        ::bson::doc! {
             "enum": [ #variant_name ],
         }
     };
@@ -166,22 +442,31 @@ fn externally_tagged_unit_variant_schema(variant_name: &str) -> Result<TokenStre
 }
 
 /// Generates a schema for a non-unit (newtype, tuple, or struct)
-/// variant if the containing enum is externally tagged.
+/// variant if the containing enum is externally tagged. The object must
+/// have exactly one key, the name of the variant, even if additional
+/// properties were to be allowed. The doc comment of the variant describes
+/// the value of that key.
 fn externally_tagged_other_variant_schema(
     attrs: &[Attribute],
     variant_name: &str,
     fields: Fields,
 ) -> Result<TokenStream> {
-    let variant_schema = impl_bson_schema_fields(attrs, fields)?;
+    let variant_schema = extend_with_doc(impl_bson_schema_fields(attrs, fields)?, attrs);
 
     let tokens = quote! {
-        doc! {
-            "type": "object",
-            "additionalProperties": false,
-            "required": [ #variant_name ],
-            "properties": {
-                #variant_name: #variant_schema
-            },
+        {
+            let variant_schema = #variant_schema;
+
+            ::bson::doc! {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": [ #variant_name ],
+                "properties": {
+                    #variant_name: variant_schema
+                },
+            }
         }
     };
     Ok(tokens)
@@ -1,12 +1,14 @@
 //! Common part of codegen for `struct`s and `enum` variants.
 
-use syn::{ Attribute, Field, Fields, MetaNameValue };
+use std::convert::TryFrom;
+use syn::{ Attribute, Field, Fields, Meta, MetaNameValue, Type, PathArguments, GenericArgument };
 use syn::punctuated::{ Punctuated, Pair };
+use syn::spanned::Spanned;
 use syn::token::Comma;
-use proc_macro2::TokenStream;
-use case::RenameRule;
+use proc_macro2::{ Span, TokenStream };
 use error::{ Error, Result };
-use meta;
+use meta::{ self, Number };
+use keys;
 
 /// Describes the extra field corresponding to an internally-tagged enum's tag.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,6 +31,10 @@ pub fn impl_bson_schema_fields_extra(
     fields: Fields,
     extra: Option<TagExtra>
 ) -> Result<TokenStream> {
+    for field in &fields {
+        check_attr_conflicts(field)?;
+    }
+
     match fields {
         Fields::Named(fields) => {
             impl_bson_schema_named_fields(attrs, fields.named, extra)
@@ -43,121 +49,591 @@ pub fn impl_bson_schema_fields_extra(
     }
 }
 
+/// Pairs of `#[magnet(...)]` arguments which make no sense on the same
+/// field, along with the reason. `*` stands for any other argument.
+const CONFLICTING_ATTRS: &[(&str, &str, &str)] = &[
+    ("min_incl", "min_excl", "a field can only have one lower bound"),
+    ("max_incl", "max_excl", "a field can only have one upper bound"),
+    ("values_min_incl", "values_min_excl", "values can only have one lower bound"),
+    ("values_max_incl", "values_max_excl", "values can only have one upper bound"),
+    ("skip",     "*",        "a skipped field is not part of the schema"),
+    ("required", "optional", "a field is either required or optional"),
+    ("map_entries", "entry_pairs", "a field is either a map or a list of pairs"),
+];
+
+/// Checks that no two `#[magnet(...)]` arguments of a field conflict,
+/// according to `CONFLICTING_ATTRS`. The error points to the latter one.
+fn check_attr_conflicts(field: &Field) -> Result<()> {
+    let keys = meta::magnet_keys(&field.attrs);
+
+    for (index, &(ref key, span)) in keys.iter().enumerate() {
+        for (prev_key, _) in &keys[..index] {
+            let conflict = CONFLICTING_ATTRS.iter().find(|&&(lhs, rhs, _)| {
+                let matches = |pattern: &str, other: &str| pattern == other || (pattern == "*" && other != lhs);
+                (lhs == prev_key && matches(rhs, key)) || (lhs == key && matches(rhs, prev_key))
+            });
+
+            if let Some(&(_, _, reason)) = conflict {
+                let message = format!(
+                    "`#[magnet({})]` conflicts with `#[magnet({})]`: {}", key, prev_key, reason
+                );
+                return Err(Error::spanned(span, message));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Implements `BsonSchema` for a `struct` or variant with named fields.
+/// Skipped fields are left out, and optional ones are not `required`, as
+/// decided by `field_presence()`. The `required` array is omitted if it
+/// would be empty, because some MongoDB versions reject empty arrays there.
+/// The generated code is a block expression, so it must not be used
+/// directly as a value within `doc!`, which would treat it as a document.
 fn impl_bson_schema_named_fields(
     attrs: &[Attribute],
     fields: Punctuated<Field, Comma>,
     extra: Option<TagExtra>,
 ) -> Result<TokenStream> {
-    let properties = &field_names(attrs, &fields)?;
-    let defs: Vec<_> = fields.iter().map(field_def).collect::<Result<_>>()?;
-    let tokens = if let Some(TagExtra { tag, variant }) = extra {
-        quote! {
-            doc! {
-                "type": "object",
-                "additionalProperties": false,
-                "required": [ #tag, #(#properties,)* ],
-                "properties": {
-                    #tag: { "enum": [ #variant ] },
-                    #(#properties: #defs,)*
-                },
-            }
+    let container_default = meta::has_serde_default(attrs)?;
+    let mut included = Vec::with_capacity(fields.len());
+    let mut presences = Vec::with_capacity(fields.len());
+
+    for field in &fields {
+        let presence = field_presence(field, container_default)?;
+
+        if presence != Presence::Skipped {
+            included.push(field);
+            presences.push(presence);
         }
+    }
+
+    let names = &field_names(attrs, &included)?;
+    let required_names: &Vec<_> = &names
+        .iter()
+        .zip(&presences)
+        .filter(|&(_, &presence)| presence == Presence::Required)
+        .map(|(name, _)| name)
+        .collect();
+    let defs: Vec<_> = included
+        .iter()
+        .map(|field| field_def(field).map(|def| extend_with_doc(def, &field.attrs)))
+        .collect::<Result<_>>()?;
+
+    // The document is built one statement per property rather than by a
+    // single `doc!` invocation, which would exceed the recursion limit of
+    // the macro expander for large structs, and would be slow to compile.
+    let (required, tag_property) = if let Some(TagExtra { tag, variant }) = extra {
+        let required = quote! {
+            schema.insert(
+                "required",
+                ::magnet_schema::support::unique_required(&[ #tag, #(#required_names,)* ]),
+            );
+        };
+        let tag_property = quote! {
+            properties.insert(#tag, ::bson::doc! { "enum": [ #variant ] });
+        };
+        (required, tag_property)
+    } else if required_names.is_empty() {
+        (quote!{}, quote!{})
     } else {
-        quote! {
-            doc! {
+        let required = quote! {
+            schema.insert(
+                "required",
+                ::magnet_schema::support::unique_required(&[ #(#required_names,)* ]),
+            );
+        };
+        (required, quote!{})
+    };
+
+    let tokens = quote! {
+        {
+            let mut schema = ::bson::doc! {
                 "type": "object",
-                "additionalProperties": false,
-                "required": [ #(#properties,)* ],
-                "properties": {
-                    #(#properties: #defs,)*
-                },
-            }
+                "additionalProperties": ctx.options().additional_properties,
+            };
+            #required
+
+            let mut properties = ::bson::Document::new();
+            #tag_property
+            #(properties.insert(#names, #defs);)*
+
+            schema.insert("properties", properties);
+            schema
         }
     };
 
     Ok(tokens)
 }
 
-/// Generates code for the value part of a key-value pair in a schema,
-/// corresponding to a single named struct field.
-/// TODO(H2CO3): check if field is numeric if bounded?
-fn field_def(field: &Field) -> Result<TokenStream> {
-    let ty = &field.ty;
-    let min_incl = meta::magnet_name_value(&field.attrs, "min_incl")?;
-    let min_excl = meta::magnet_name_value(&field.attrs, "min_excl")?;
-    let max_incl = meta::magnet_name_value(&field.attrs, "max_incl")?;
-    let max_excl = meta::magnet_name_value(&field.attrs, "max_excl")?;
-    let lower = bounds_from_meta(min_incl, min_excl)?;
-    let upper = bounds_from_meta(max_incl, max_excl)?;
+/// Whether a named field is part of the schema, and if so, whether it
+/// has to be present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Presence {
+    /// The field is never serialized.
+    Skipped,
+    /// The field is listed under `required`.
+    Required,
+    /// The field may be missing.
+    Optional,
+}
+
+/// Decides the presence of a named field. In decreasing order of
+/// precedence: skipped fields are left out; `#[magnet(required)]`
+/// fields are required; `#[magnet(optional)]` and `#[serde(default)]`
+/// fields, and every field of a `#[serde(default)]` container (if
+/// `container_default` is `true`), are optional; and any other field
+/// is required. Requiring a skipped field is an error.
+fn field_presence(field: &Field, container_default: bool) -> Result<Presence> {
+    let required = meta::has_magnet_word(&field.attrs, "required")?;
+
+    if is_skipped(field)? {
+        return if required {
+            let span = meta::magnet_keys(&field.attrs)
+                .into_iter()
+                .find(|(key, _)| key == "required")
+                .map_or_else(|| field_span(field), |(_, span)| span);
+            Err(Error::spanned(span, "`#[magnet(required)]` can't be applied to a skipped field"))
+        } else {
+            Ok(Presence::Skipped)
+        };
+    }
+
+    if required {
+        Ok(Presence::Required)
+    } else if container_default
+        || meta::has_magnet_word(&field.attrs, "optional")?
+        || meta::has_serde_default(&field.attrs)? {
+        Ok(Presence::Optional)
+    } else {
+        Ok(Presence::Required)
+    }
+}
+
+/// Wraps the code generating a schema so that the doc comment in `attrs`,
+/// if any, becomes the description of the schema. How much of it is kept
+/// is decided at runtime, based on the `MAGNET_DESCRIPTION_MODE` constant
+/// emitted by `description_mode()`, and on the options in `ctx`.
+pub fn extend_with_doc(tokens: TokenStream, attrs: &[Attribute]) -> TokenStream {
+    match meta::doc_comment(attrs) {
+        Some(doc) => quote! {
+            ::magnet_schema::support::extend_schema_with_doc_in(
+                #tokens,
+                #doc,
+                MAGNET_DESCRIPTION_MODE,
+                ctx.options(),
+            )
+        },
+        None => tokens,
+    }
+}
+
+/// Generates the declaration of the `MAGNET_DESCRIPTION_MODE` constant,
+/// based on the `#[magnet(doc = "...")]` attribute of the type.
+pub fn description_mode(attrs: &[Attribute]) -> Result<TokenStream> {
+    let mode = match meta::magnet_name_value(attrs, "doc")? {
+        None => quote!{ Full },
+        Some(nv) => match meta::value_as_str(&nv)?.as_str() {
+            "full" => quote!{ Full },
+            "first_line" => quote!{ FirstLine },
+            "skip" => quote!{ Skip },
+            _ => return Err(Error::spanned(
+                nv.lit.span(),
+                "`doc` must be one of \"full\", \"first_line\", or \"skip\""
+            )),
+        },
+    };
 
     Ok(quote! {
-        ::magnet_schema::support::extend_schema_with_bounds(
-            <#ty as ::magnet_schema::BsonSchema>::bson_schema(),
-            ::magnet_schema::support::Bounds {
-                lower: #lower,
-                upper: #upper,
-            },
-        )
+        #[allow(dead_code)]
+        const MAGNET_DESCRIPTION_MODE: ::magnet_schema::support::DescriptionMode =
+            ::magnet_schema::support::DescriptionMode::#mode;
     })
 }
 
-/// Parses meta attrs into quoted `Bound`s.
-fn bounds_from_meta(incl: Option<MetaNameValue>, excl: Option<MetaNameValue>) -> Result<TokenStream> {
-    // Inclusive takes precedence over exclusive (form a union).
-    // TODO(H2CO3): this could be the other way around (when both
-    // inclusive and exclusive bounds specified, form an intersection)
-    // -- I'm not sure, which one makes more sense? Or maybe an error?
-    if let Some(nv) = incl {
-        let value = meta::value_as_num(&nv)?;
+/// Wraps the code generating the schema of a type so that the version given
+/// by its `#[magnet(version = N)]` attribute, if any, is recorded in it.
+pub fn extend_with_version(tokens: TokenStream, attrs: &[Attribute]) -> Result<TokenStream> {
+    let nv = match meta::magnet_name_value(attrs, "version")? {
+        Some(nv) => nv,
+        None => return Ok(tokens),
+    };
+
+    match meta::value_as_num(&nv)? {
+        Number::Int(version) => Ok(quote! {
+            ::magnet_schema::versioned::with_version(#tokens, #version)
+        }),
+        Number::Float(_) => Err(Error::spanned(nv.lit.span(), "`version` must be an integer")),
+    }
+}
+
+/// Wraps the code generating the schema of a type so that the values
+/// referred to by its `#[magnet(examples_from = "...")]` attributes, if
+/// any, are appended to its `"examples"`, in the order of appearance.
+pub fn extend_with_examples(tokens: TokenStream, attrs: &[Attribute]) -> Result<TokenStream> {
+    meta::magnet_metas(attrs, "examples_from").into_iter().try_fold(tokens, |schema, example| {
+        let path = match example {
+            Meta::NameValue(ref nv) => meta::value_as_path(nv)?,
+            ref other => return Err(Error::spanned(
+                other.span(),
+                "attribute must have form `#[magnet(examples_from = \"...\")]`"
+            )),
+        };
 
         Ok(quote! {
-            ::magnet_schema::support::Bound::Inclusive(#value)
+            ::magnet_schema::support::extend_schema_with_example(
+                #schema,
+                ::magnet_schema::support::ExampleSource::into_example(
+                    ::std::clone::Clone::clone(&#path)
+                ),
+            )
         })
-    } else if let Some(nv) = excl {
-        let value = meta::value_as_num(&nv)?;
+    })
+}
 
-        Ok(quote! {
-            ::magnet_schema::support::Bound::Exclusive(#value)
+/// Returns `true` if a named field never appears in the serialized
+/// document, because of `#[serde(skip)]`, `#[serde(skip_serializing)]`
+/// or `#[magnet(skip)]`.
+pub fn is_skipped(field: &Field) -> Result<bool> {
+    Ok(
+        meta::has_serde_word(&field.attrs, "skip")?
+        || meta::has_serde_word(&field.attrs, "skip_serializing")?
+        || meta::has_magnet_word(&field.attrs, "skip")?
+    )
+}
+
+/// Generates code for the value part of a key-value pair in a schema,
+/// corresponding to a single named struct field. Errors without a more
+/// precise location point to the field.
+fn field_def(field: &Field) -> Result<TokenStream> {
+    field_def_unspanned(field).map_err(|error| error.with_span(field_span(field)))
+}
+
+/// Returns the location of a field for error reporting: its name,
+/// or its type if it's unnamed.
+fn field_span(field: &Field) -> Span {
+    field.ident.as_ref().map_or_else(|| field.ty.span(), |ident| ident.span())
+}
+
+/// Implementation of `field_def()`. The bounds are only applied at
+/// runtime if there are any, otherwise the schema of the field's type
+/// is used as-is. Similarly, `extend_with_doc()` leaves the code alone
+/// if there's no doc comment, so an unannotated field costs nothing
+/// beyond generating the schema of its type. Bounds on the field itself
+/// are applied before bounds on its values (`values_min_incl`, etc.),
+/// which only make sense for maps and collections. They are applied while
+/// the field is described as a map, i.e. after `map_entries` rewrites its
+/// schema, but before `entry_pairs` does. Constraints on the items of an
+/// array (`min_items`, `items_regex`, etc.) come last.
+/// TODO(H2CO3): check if field is numeric if bounded?
+fn field_def_unspanned(field: &Field) -> Result<TokenStream> {
+    let base = base_schema(field)?;
+    let own_bounds = bounds_from_attrs(&field.attrs, "")?;
+    let value_bounds = bounds_from_attrs(&field.attrs, "values_")?;
+    let format = format_from_attrs(&field.attrs)?;
+    let map_entries = meta::has_magnet_word(&field.attrs, "map_entries")?;
+    let entry_pairs = meta::has_magnet_word(&field.attrs, "entry_pairs")?;
+    let items = items_from_attrs(&field.attrs)?;
+    let items_regex = meta::magnet_name_value(&field.attrs, "items_regex")?
+        .map(|nv| meta::value_as_str(&nv))
+        .transpose()?;
+
+    // unbounded on both ends: `extend_schema_with_bounds()` would be a no-op
+    if own_bounds.is_none()
+        && value_bounds.is_none()
+        && format.is_none()
+        && !map_entries
+        && !entry_pairs
+        && items.is_none()
+        && items_regex.is_none() {
+        return Ok(base);
+    }
+
+    let to_map = if map_entries {
+        Some(quote! {
+            schema = ::magnet_schema::support::try_map_from_entries(schema)?;
         })
     } else {
-        Ok(quote! {
-            ::magnet_schema::support::Bound::Unbounded
+        None
+    };
+    let to_pairs = if entry_pairs {
+        Some(quote! {
+            schema = ::magnet_schema::support::try_entries_from_map(schema)?;
         })
+    } else {
+        None
+    };
+
+    let extend_own = own_bounds.map(|bounds| quote! {
+        schema = ::magnet_schema::support::try_extend_schema_with_bounds_in(
+            schema, #bounds, dialect
+        )?;
+    });
+    let extend_values = value_bounds.map(|bounds| quote! {
+        schema = ::magnet_schema::support::try_extend_values_with_bounds_in(
+            schema, #bounds, dialect
+        )?;
+    });
+
+    let extend_format = format.map(|name| quote! {
+        schema = ::magnet_schema::support::try_extend_schema_with_format(schema, #name)?;
+    });
+
+    let extend_items = items.map(|tokens| quote! {
+        schema = #tokens?;
+    });
+    let extend_items_regex = items_regex.map(|pattern| quote! {
+        schema = ::magnet_schema::support::try_extend_items_with_regex(schema, #pattern)?;
+    });
+
+    Ok(quote! {
+        ctx.try_generate(|ctx| {
+            let dialect = ctx.options().bounds_dialect;
+            let mut schema = #base;
+            #to_map
+            #extend_own
+            #extend_values
+            #extend_format
+            #to_pairs
+            #extend_items
+            #extend_items_regex
+            Ok(schema)
+        })
+    })
+}
+
+/// Generates the schema of a field before applying bounds: that of its
+/// type, or the result of the function given by `#[magnet(with = "...")]`.
+/// The latter is mandatory for trait objects, which have no schema.
+fn base_schema(field: &Field) -> Result<TokenStream> {
+    let ty = &field.ty;
+
+    if let Some(nv) = meta::magnet_name_value(&field.attrs, "with")? {
+        let path = meta::value_as_path(&nv)?;
+        return Ok(quote! { #path() });
+    }
+
+    if let Some(span) = trait_object_span(ty) {
+        return Err(Error::spanned(
+            span,
+            "trait objects have no schema, add `#[magnet(with = \"...\")]` to the field"
+        ));
+    }
+
+    Ok(quote! {
+        <#ty as ::magnet_schema::BsonSchema>::bson_schema_with(ctx)
+    })
+}
+
+/// Returns the location of the first `dyn Trait` within a type, if any,
+/// e.g. in `Box<dyn Trait>` or `Vec<Arc<dyn Trait>>`.
+fn trait_object_span(ty: &Type) -> Option<Span> {
+    match *ty {
+        Type::TraitObject(ref object) => Some(object.span()),
+        Type::Slice(ref slice) => trait_object_span(&slice.elem),
+        Type::Array(ref array) => trait_object_span(&array.elem),
+        Type::Ptr(ref ptr) => trait_object_span(&ptr.elem),
+        Type::Reference(ref reference) => trait_object_span(&reference.elem),
+        Type::Paren(ref paren) => trait_object_span(&paren.elem),
+        Type::Group(ref group) => trait_object_span(&group.elem),
+        Type::Tuple(ref tuple) => tuple.elems.iter().filter_map(trait_object_span).next(),
+        Type::Path(ref path) => {
+            path.path.segments.iter().filter_map(|segment| match segment.arguments {
+                PathArguments::AngleBracketed(ref args) => args.args.iter().filter_map(|arg| match *arg {
+                    GenericArgument::Type(ref inner) => trait_object_span(inner),
+                    _ => None,
+                }).next(),
+                _ => None,
+            }).next()
+        },
+        _ => None,
     }
 }
 
-/// Returns an iterator over the potentially-`#magnet[rename(...)]`d
-/// fields of a struct or variant with named fields.
-fn field_names(attrs: &[Attribute], fields: &Punctuated<Field, Comma>) -> Result<Vec<String>> {
-    let rename_all_str = meta::serde_name_value(attrs, "rename_all")?;
-    let rename_all: Option<RenameRule> = match rename_all_str {
-        Some(s) => Some(meta::value_as_str(&s)?.parse()?),
-        None => None,
+/// The names of the string formats of `#[magnet(format = "...")]`. These must
+/// be kept in sync with `magnet_schema::support::patterns::FORMATS`.
+const FORMATS: &[&str] = &[
+    "email", "hostname", "ipv4", "ipv6", "uri", "uuid", "date", "date-time", "objectid-hex",
+];
+
+/// Parses the `#[magnet(format = "...")]` attribute of a field, if any.
+/// An unknown format is an error, which lists the known ones.
+fn format_from_attrs(attrs: &[Attribute]) -> Result<Option<String>> {
+    let nv = match meta::magnet_name_value(attrs, "format")? {
+        Some(nv) => nv,
+        None => return Ok(None),
     };
+    let name = meta::value_as_str(&nv)?;
 
-    let iter = fields.iter().map(|field| {
-        let name = field.ident.as_ref().ok_or_else(
-            || Error::new("no name for named field?!")
-        )?;
+    if FORMATS.contains(&name.as_str()) {
+        Ok(Some(name))
+    } else {
+        let message = format!(
+            "unknown format `{}`, expected one of: {}", name, FORMATS.join(", ")
+        );
+        Err(Error::spanned(nv.lit.span(), message))
+    }
+}
+
+/// Parses the `min_items`, `max_items` and `unique_items` attributes of a
+/// field into a call to `try_extend_schema_with_items()`, or `None` if
+/// there are none of them.
+fn items_from_attrs(attrs: &[Attribute]) -> Result<Option<TokenStream>> {
+    let length = |key: &str| -> Result<Option<usize>> {
+        let nv = match meta::magnet_name_value(attrs, key)? {
+            Some(nv) => nv,
+            None => return Ok(None),
+        };
 
-        if meta::magnet_name_value(&field.attrs, "rename")?.is_some() {
-            return Err(Error::new("`#[magnet(rename = \"...\")]` no longer exists"))
+        match meta::value_as_num(&nv)? {
+            Number::Int(value) => usize::try_from(value).map(Some).map_err(|_| Error::spanned(
+                nv.lit.span(), format!("`{}` must not be negative", key)
+            )),
+            Number::Float(_) => Err(Error::spanned(
+                nv.lit.span(), format!("`{}` must be an integer", key)
+            )),
         }
+    };
+    let min_items = length("min_items")?;
+    let max_items = length("max_items")?;
+    let unique_items = meta::has_magnet_word(attrs, "unique_items")?;
+
+    if let (Some(min), Some(max)) = (min_items, max_items) {
+        if min > max {
+            return Err(Error::new("`min_items` must not exceed `max_items`"));
+        }
+    }
+
+    if min_items.is_none() && max_items.is_none() && !unique_items {
+        return Ok(None);
+    }
+
+    let quote_length = |bound: Option<usize>| match bound {
+        Some(value) => quote!{ Some(#value) },
+        None => quote!{ None },
+    };
+    let min_tokens = quote_length(min_items);
+    let max_tokens = quote_length(max_items);
 
-        let rename = meta::serde_name_value(&field.attrs, "rename")?;
-        let name = match rename {
-            Some(nv) => meta::value_as_str(&nv)?,
+    Ok(Some(quote! {
+        ::magnet_schema::support::try_extend_schema_with_items(
+            schema, #min_tokens, #max_tokens, #unique_items
+        )
+    }))
+}
+
+/// Parses the `{prefix}min_incl`, `{prefix}min_excl`, `{prefix}max_incl`
+/// and `{prefix}max_excl` attributes of a field into a `Bounds` expression,
+/// or `None` if there are none of them.
+fn bounds_from_attrs(attrs: &[Attribute], prefix: &str) -> Result<Option<TokenStream>> {
+    let attr = |name: &str| meta::magnet_name_value(attrs, &format!("{}{}", prefix, name));
+    let lower = bound_from_meta(attr("min_incl")?, attr("min_excl")?)?;
+    let upper = bound_from_meta(attr("max_incl")?, attr("max_excl")?)?;
+
+    if let (Some((min, min_is_excl)), Some((max, max_is_excl))) = (lower, upper) {
+        if min >= max && (min > max || min_is_excl || max_is_excl) {
+            return Err(Error::new("lower bound must not exceed upper bound"));
+        }
+    }
+
+    if lower.is_none() && upper.is_none() {
+        return Ok(None);
+    }
+
+    let lower_tokens = quote_bound(lower);
+    let upper_tokens = quote_bound(upper);
+
+    Ok(Some(quote! {
+        ::magnet_schema::support::Bounds {
+            lower: #lower_tokens,
+            upper: #upper_tokens,
+        }
+    }))
+}
+
+/// Parses meta attrs into a bound and a flag indicating whether it's exclusive.
+fn bound_from_meta(incl: Option<MetaNameValue>, excl: Option<MetaNameValue>) -> Result<Option<(Number, bool)>> {
+    // Specifying both is rejected by `check_attr_conflicts()`.
+    if let Some(nv) = incl {
+        meta::value_as_num(&nv).map(|value| Some((value, false)))
+    } else if let Some(nv) = excl {
+        meta::value_as_num(&nv).map(|value| Some((value, true)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Quotes a parsed bound as a `Bound`. Integer bounds stay integers.
+fn quote_bound(bound: Option<(Number, bool)>) -> TokenStream {
+    let quote_value = |number| match number {
+        Number::Int(value) => quote! {
+            ::magnet_schema::support::BoundValue::Int(#value)
+        },
+        Number::Float(value) => quote! {
+            ::magnet_schema::support::BoundValue::Double(#value)
+        },
+    };
+
+    match bound {
+        Some((number, false)) => {
+            let value = quote_value(number);
+            quote! {
+                ::magnet_schema::support::Bound::Inclusive(#value)
+            }
+        },
+        Some((number, true)) => {
+            let value = quote_value(number);
+            quote! {
+                ::magnet_schema::support::Bound::Exclusive(#value)
+            }
+        },
+        None => quote! {
+            ::magnet_schema::support::Bound::Unbounded
+        },
+    }
+}
+
+/// Returns the potentially-`#[serde(rename(...))]`d names of the fields
+/// of a struct or variant with named fields. Two fields ending up with
+/// the same name is an error, and so is a name that MongoDB doesn't allow,
+/// unless the struct, variant or field is marked `#[magnet(relaxed_keys)]`.
+pub fn field_names(attrs: &[Attribute], fields: &[&Field]) -> Result<Vec<String>> {
+    let relaxed = meta::has_magnet_word(attrs, "relaxed_keys")?;
+    let rename_all = meta::serde_rename_all(attrs)?;
+    let mut names: Vec<String> = Vec::with_capacity(fields.len());
+
+    for field in fields {
+        let ident = field.ident.as_ref().ok_or_else(
+            || Error::spanned(field.ty.span(), "no name for named field?!")
+        )?;
+
+        // like serde, strip the `r#` prefix of raw identifiers
+        let unraw = ident.to_string().trim_start_matches("r#").to_owned();
+        let name = match meta::serde_rename(&field.attrs)? {
+            Some(name) => name,
             None => rename_all.map_or_else(
-                || name.to_string(),
-                |rule| rule.apply_to_field(name.to_string()),
+                || unraw.clone(),
+                |rule| rule.apply_to_field(unraw.clone()),
             ),
         };
 
-        Ok(name)
-    });
+        if let Some(prev_index) = names.iter().position(|prev_name| *prev_name == name) {
+            let prev_ident = fields[prev_index].ident.as_ref().map(ToString::to_string).unwrap_or_default();
+            let message = format!(
+                "fields `{}` and `{}` are both named `{}` in the schema", prev_ident, ident, name
+            );
+            return Err(Error::spanned(ident.span(), message));
+        }
 
-    iter.collect()
+        let field_relaxed = relaxed || meta::has_magnet_word(&field.attrs, "relaxed_keys")?;
+        let origin = format!("field `{}`", ident);
+        keys::check_key(&name, &origin, ident.span(), field_relaxed)?;
+
+        names.push(name);
+    }
+
+    Ok(names)
 }
 
 /// Implements `BsonSchema` for a tuple `struct` or variant,
@@ -167,7 +643,7 @@ fn impl_bson_schema_indexed_fields(
     extra: Option<TagExtra>,
 ) -> Result<TokenStream> {
     if extra.is_some() && fields.len() != 1 {
-        return Err(Error::new("internal tagging not usable with tuple variant"))
+        return Err(Error::spanned(fields.span(), "internal tagging not usable with tuple variant"))
     }
 
     match fields.pop().map(Pair::into_value) {
@@ -175,14 +651,16 @@ fn impl_bson_schema_indexed_fields(
         Some(field) => match fields.len() {
             0 => {
                 // 1 field, aka newtype - just delegate to the field's type
-                let def = field_def(&field)?;
+                let def = extend_with_doc(field_def(&field)?, &field.attrs);
                 let tokens = if let Some(TagExtra { tag, variant }) = extra {
                     quote! {
-                        ::magnet_schema::support::extend_schema_with_tag(
-                            #def,
-                            #tag,
-                            #variant,
-                        )
+                        ctx.try_generate(|ctx| {
+                            ::magnet_schema::support::try_extend_schema_with_tag(
+                                #def,
+                                #tag,
+                                #variant,
+                            )
+                        })
                     }
                 } else {
                     def
@@ -195,14 +673,18 @@ fn impl_bson_schema_indexed_fields(
 
                 let defs: Vec<_> = fields
                     .iter()
-                    .map(field_def)
+                    .map(|item| field_def(item).map(|def| extend_with_doc(def, &item.attrs)))
                     .collect::<Result<_>>()?;
 
                 Ok(quote! {
-                    doc! {
-                        "type": "array",
-                        "additionalItems": false,
-                        "items": [ #(#defs,)* ],
+                    {
+                        let items: Vec<::bson::Bson> = vec![ #(::bson::Bson::from(#defs),)* ];
+
+                        ::bson::doc! {
+                            "type": "array",
+                            "additionalItems": false,
+                            "items": items,
+                        }
                     }
                 })
             },
@@ -212,5 +694,5 @@ fn impl_bson_schema_indexed_fields(
 
 /// Implements `BsonSchema` for a unit `struct` or variant with no fields.
 fn impl_bson_schema_unit_field() -> Result<TokenStream> {
-    Ok(quote!{ <() as ::magnet_schema::BsonSchema>::bson_schema() })
+    Ok(quote!{ <() as ::magnet_schema::BsonSchema>::bson_schema_with(ctx) })
 }
@@ -0,0 +1,50 @@
+//! Code generation for `BsonSchema::schema_name()`.
+
+use std::collections::HashSet;
+use syn::{ Attribute, Ident, Generics };
+use proc_macro2::TokenStream;
+use error::Result;
+use meta;
+
+/// Generates the body of `schema_name()`. The name is the value of the
+/// `#[magnet(schema_name = "...")]` attribute if present. Otherwise, it's
+/// the name of the type, followed by the schema names of its type
+/// arguments, if any, in angle brackets, e.g. `Tree<Option<i32>>`. Type
+/// arguments not in `bounded` aren't required to implement `BsonSchema`,
+/// so they are represented by their Rust type name instead.
+pub fn impl_schema_name(
+    attrs: &[Attribute],
+    ty: &Ident,
+    generics: &Generics,
+    bounded: &HashSet<Ident>,
+) -> Result<TokenStream> {
+    if let Some(nv) = meta::magnet_name_value(attrs, "schema_name")? {
+        let name = meta::value_as_str(&nv)?;
+        return Ok(quote!{ String::from(#name) });
+    }
+
+    let name = ty.to_string();
+    let args: Vec<_> = generics
+        .type_params()
+        .map(|param| {
+            let ident = &param.ident;
+
+            if bounded.contains(ident) {
+                quote!{ <#ident as ::magnet_schema::BsonSchema>::schema_name() }
+            } else {
+                quote!{ String::from(::std::any::type_name::<#ident>()) }
+            }
+        })
+        .collect();
+
+    if args.is_empty() {
+        return Ok(quote!{ String::from(#name) });
+    }
+
+    Ok(quote! {
+        let args: &[String] = &[
+            #(#args,)*
+        ];
+        format!("{}<{}>", #name, args.join(", "))
+    })
+}
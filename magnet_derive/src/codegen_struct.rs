@@ -1,11 +1,122 @@
 //! Code generation for `struct`s.
 
-use syn::{ DataStruct, Attribute };
+use syn::{ DataStruct, Attribute, AttrStyle, Field, Fields, Ident, WherePredicate };
+use syn::spanned::Spanned;
 use proc_macro2::TokenStream;
 use error::Result;
-use codegen_field::impl_bson_schema_fields;
+use codegen_field::{ impl_bson_schema_fields, extend_with_doc, field_names, is_skipped };
+use meta;
 
-/// Implements `BsonSchema` for a `struct`.
+/// The `#[magnet(...)]` arguments which constrain the schema of a field.
+/// On a newtype `struct`, they apply to its only field, just as if they
+/// were given on the field itself.
+pub const FORWARDED_ATTRS: &[&str] = &[
+    "min_incl", "min_excl", "max_incl", "max_excl",
+    "values_min_incl", "values_min_excl", "values_max_incl", "values_max_excl",
+    "format", "map_entries", "entry_pairs",
+    "unique_items", "min_items", "max_items", "items_regex",
+];
+
+/// Implements `BsonSchema` for a `struct`. Its doc comment becomes
+/// the description of the schema.
 pub fn impl_bson_schema_struct(attrs: Vec<Attribute>, ast: DataStruct) -> Result<TokenStream> {
-    impl_bson_schema_fields(&attrs, ast.fields)
+    let mut fields = ast.fields;
+
+    if let Fields::Unnamed(ref mut unnamed) = fields {
+        if unnamed.unnamed.len() == 1 {
+            unnamed.unnamed.iter_mut().for_each(|field| forward_attrs(&attrs, field));
+        }
+    }
+
+    let tokens = impl_bson_schema_fields(&attrs, fields)?;
+    Ok(extend_with_doc(tokens, &attrs))
+}
+
+/// Implements the methods of `BsonSchemaKey` for a newtype `struct` by
+/// delegating to its field, so that e.g. `struct UserId(String)` can be
+/// the key of a map. Also returns the bound requiring the field itself to
+/// be a key. It is higher-ranked, so that it's only checked when the impl
+/// is used: a newtype over e.g. a `Vec` is fine, as long as it's not a key.
+/// Other `struct`s can't be map keys, so `None` is returned for them.
+pub fn impl_bson_schema_key_newtype(ast: &DataStruct) -> Result<Option<(TokenStream, WherePredicate)>> {
+    let field = match ast.fields {
+        Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => &unnamed.unnamed[0],
+        Fields::Named(_) | Fields::Unnamed(_) | Fields::Unit => return Ok(None),
+    };
+
+    if is_skipped(field)? {
+        return Ok(None);
+    }
+
+    let ty = &field.ty;
+    let methods = quote! {
+        fn key_pattern() -> Option<String> {
+            <#ty as ::magnet_schema::BsonSchemaKey>::key_pattern()
+        }
+
+        fn known_keys() -> Option<Vec<String>> {
+            <#ty as ::magnet_schema::BsonSchemaKey>::known_keys()
+        }
+    };
+    let predicate = parse_quote! {
+        for<'__magnet_key> #ty: ::magnet_schema::BsonSchemaKey
+    };
+
+    Ok(Some((methods, predicate)))
+}
+
+/// Appends the `FORWARDED_ATTRS` of a newtype `struct` to the attributes
+/// of its field, each as a separate `#[magnet(...)]` attribute. The field
+/// then rejects conflicting ones, e.g. two different `min_items`.
+fn forward_attrs(attrs: &[Attribute], field: &mut Field) {
+    for key in FORWARDED_ATTRS {
+        for meta in meta::magnet_metas(attrs, key) {
+            let span = meta.span();
+
+            field.attrs.push(Attribute {
+                pound_token: Default::default(),
+                style: AttrStyle::Outer,
+                bracket_token: Default::default(),
+                path: Ident::new("magnet", span).into(),
+                tts: quote_spanned!{ span => (#meta) },
+                is_sugared_doc: false,
+            });
+        }
+    }
+}
+
+/// Generates the `FIELD_<NAME>` constants requested by `#[magnet(field_names)]`,
+/// one for each named field in the schema, holding its name after renaming,
+/// or `None` if the attribute is absent. `check_magnet_attrs()` has already
+/// rejected it on any other kind of `struct`.
+pub fn impl_field_name_consts(attrs: &[Attribute], ast: &DataStruct) -> Result<Option<TokenStream>> {
+    if !meta::has_magnet_word(attrs, "field_names")? {
+        return Ok(None);
+    }
+
+    let mut included = Vec::new();
+
+    if let Fields::Named(ref named) = ast.fields {
+        for field in &named.named {
+            if !is_skipped(field)? {
+                included.push(field);
+            }
+        }
+    }
+
+    let names = field_names(attrs, &included)?;
+    let consts = included.iter().zip(&names).filter_map(|(field, name)| {
+        let ident = field.ident.as_ref()?;
+        let ident_str = ident.to_string();
+        let field_name = ident_str.trim_start_matches("r#");
+        let const_ident = Ident::new(&format!("FIELD_{}", field_name.to_uppercase()), ident.span());
+        let doc = format!("The name of the field `{}` in documents.", field_name);
+
+        Some(quote! {
+            #[doc = #doc]
+            pub const #const_ident: &'static str = #name;
+        })
+    });
+
+    Ok(Some(quote!{ #(#consts)* }))
 }
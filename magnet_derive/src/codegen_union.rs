@@ -5,6 +5,6 @@ use proc_macro2::TokenStream;
 use error::{ Error, Result };
 
 /// Implements `BsonSchema` for a `union`.
-pub fn impl_bson_schema_union(_: Vec<Attribute>, _: DataUnion) -> Result<TokenStream> {
-    Err(Error::new("`BsonSchema` can't be implemented for unions"))
+pub fn impl_bson_schema_union(_: Vec<Attribute>, ast: DataUnion) -> Result<TokenStream> {
+    Err(Error::spanned(ast.union_token.0, "`BsonSchema` can't be implemented for unions"))
 }
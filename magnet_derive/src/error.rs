@@ -1,4 +1,26 @@
 //! Errors potentially happening while `#[derive]`ing `BsonSchema`.
+//!
+//! Errors are reported using `compile_error!()`, pointing to the offending
+//! attribute, field, or variant. For instance, the derive accepts this:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! #[macro_use]
+//! extern crate magnet_derive;
+//! extern crate magnet_schema;
+//!
+//! #[derive(BsonSchema)]
+//! struct Percentage {
+//!     #[magnet(min_incl = "0", max_incl = "100")]
+//!     value: f64,
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! An inverted range, e.g. `min_incl = "100", max_incl = "0"`, is instead
+//! rejected with an error pointing to the field. The messages and spans of
+//! this error and all the others are checked by the UI tests in `tests/ui`.
 
 use std::fmt;
 use std::error;
@@ -7,6 +29,7 @@ use std::ops::Deref;
 use std::string::FromUtf8Error;
 use std::num::{ ParseIntError, ParseFloatError };
 use syn::synom::ParseError;
+use proc_macro2::{ Span, TokenStream };
 
 /// Convenience type alias for a result that holds a `magnet_derive::Error` value.
 pub type Result<T> = result::Result<T, Error>;
@@ -18,6 +41,10 @@ pub struct Error {
     message: String,
     /// The underlying error, if any.
     cause: Option<Box<error::Error>>,
+    /// The location of the offending piece of code, if known.
+    span: Option<Span>,
+    /// A related location, e.g. of a conflicting attribute, with a message.
+    note: Option<(Span, String)>,
 }
 
 impl Error {
@@ -26,6 +53,45 @@ impl Error {
         Error {
             message: message.into(),
             cause: None,
+            span: None,
+            note: None,
+        }
+    }
+
+    /// Creates an `Error` instance with the specified message,
+    /// pointing to the code at `span`.
+    pub fn spanned<T: Into<String>>(span: Span, message: T) -> Self {
+        Error::new(message).with_span(span)
+    }
+
+    /// Points the error to the code at `span`, unless it already points
+    /// somewhere. This way, the innermost, most precise location wins.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = self.span.or(Some(span));
+        self
+    }
+
+    /// Adds a second location to the error, e.g. that of an earlier,
+    /// conflicting attribute, which is reported along with `message`.
+    pub fn with_note<T: Into<String>>(mut self, span: Span, message: T) -> Self {
+        self.note = Some((span, message.into()));
+        self
+    }
+
+    /// Converts the error into a `compile_error!()` invocation, so that
+    /// it's reported as a regular diagnostic at the offending location.
+    /// Errors without a location point to the whole `#[derive]`. A note,
+    /// if any, is reported as a separate diagnostic at its own location.
+    pub fn to_compile_error(&self) -> TokenStream {
+        let span = self.span.unwrap_or_else(Span::call_site);
+        let message = self.to_string();
+        let note = self.note.as_ref().map(|&(note_span, ref note_message)| quote_spanned! {note_span=>
+            compile_error!(#note_message);
+        });
+
+        quote_spanned! {span=>
+            compile_error!(#message);
+            #note
         }
     }
 }
@@ -54,6 +120,8 @@ impl From<ParseError> for Error {
         Error {
             message: String::from("could not parse derive input"),
             cause: Some(Box::new(error)),
+            span: None,
+            note: None,
         }
     }
 }
@@ -63,6 +131,8 @@ impl From<FromUtf8Error> for Error {
         Error {
             message: String::from("byte string is not valid UTF-8"),
             cause: Some(Box::new(error)),
+            span: None,
+            note: None,
         }
     }
 }
@@ -72,6 +142,8 @@ impl From<ParseIntError> for Error {
         Error {
             message: String::from("string is not a valid integer"),
             cause: Some(Box::new(error)),
+            span: None,
+            note: None,
         }
     }
 }
@@ -81,6 +153,8 @@ impl From<ParseFloatError> for Error {
         Error {
             message: String::from("string is not valid floating-point"),
             cause: Some(Box::new(error)),
+            span: None,
+            note: None,
         }
     }
 }
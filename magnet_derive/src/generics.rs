@@ -1,22 +1,32 @@
 //! Parse and extend generic bounds.
 
+use std::collections::HashSet;
 use syn::{
     Generics, ImplGenerics, TypeGenerics, GenericParam,
     WhereClause, WherePredicate, PredicateType,
     TypeParamBound, TraitBound, TraitBoundModifier, TypePath,
-    Ident, Path, PathSegment,
+    Ident, Path, PathSegment, Data, Fields, Type,
 };
 use syn::punctuated::Punctuated;
 use syn::token::{ Colon2, Add };
-use proc_macro2::Span;
+use quote::ToTokens;
+use proc_macro2::{ Span, TokenStream, TokenTree, Spacing };
+use codegen_field::is_skipped;
+use meta;
+use error::{ Error, Result };
 
 /// Helper for extending generics with the `: BsonSchema` trait bound.
 #[allow(clippy::stutter)]
 pub trait GenericsExt: Sized {
     /// The first return value is the `impl` generic parameter list on the left.
+    /// It keeps the declared bounds, but leaves off the defaults of type
+    /// parameters, which aren't allowed on `impl`s; they stay on the type
+    /// itself, so e.g. `Generic<'a, T, U = u32>` gets `impl<'a, T, U>`.
     /// The second one is just the list of names of type and lifetime arguments.
     /// The third one is the augmented `where` clause -- the whole point.
-    fn split_and_augment_for_impl(&self) -> (
+    /// Only the type parameters in `bounded` get the `BsonSchema` bound,
+    /// regardless of whether they have a default.
+    fn split_and_augment_for_impl(&self, bounded: &SchemaBounds) -> (
         ImplGenerics,
         TypeGenerics,
         Option<WhereClause>,
@@ -24,11 +34,12 @@ pub trait GenericsExt: Sized {
 }
 
 impl GenericsExt for Generics {
-    fn split_and_augment_for_impl(&self) -> (
+    fn split_and_augment_for_impl(&self, bounded: &SchemaBounds) -> (
         ImplGenerics,
         TypeGenerics,
         Option<WhereClause>,
     ) {
+        // `ImplGenerics` prints type parameters without their defaults
         let (impl_generics, type_generics, where_clause) = self.split_for_impl();
         let mut where_clause = where_clause.cloned().unwrap_or(WhereClause {
             where_token: Default::default(),
@@ -37,7 +48,10 @@ impl GenericsExt for Generics {
 
         where_clause.predicates.extend(self.params
                                        .iter()
-                                       .filter_map(where_predicate));
+                                       .filter_map(|param| where_predicate(param, &bounded.params)));
+        where_clause.predicates.extend(bounded.projections
+                                       .iter()
+                                       .map(|ty| bson_schema_predicate(ty.clone())));
 
         let where_clause = if where_clause.predicates.is_empty() {
             None
@@ -73,10 +87,11 @@ fn bson_schema_type_bounds() -> Punctuated<TypeParamBound, Add> {
     vec![bound].into_iter().collect()
 }
 
-/// Returns a predicate for a `where` clause iff the generic param is a type.
-fn where_predicate(param: &GenericParam) -> Option<WherePredicate> {
+/// Returns a predicate for a `where` clause iff the generic param is
+/// a type which is in the set of `bounded` parameters.
+fn where_predicate(param: &GenericParam, bounded: &HashSet<Ident>) -> Option<WherePredicate> {
     let type_param = match *param {
-        GenericParam::Type(ref ty) => ty,
+        GenericParam::Type(ref ty) if bounded.contains(&ty.ident) => ty,
         _ => return None,
     };
 
@@ -93,14 +108,232 @@ fn where_predicate(param: &GenericParam) -> Option<WherePredicate> {
         },
     };
 
-    let p = WherePredicate::Type(
+    Some(bson_schema_predicate(bounded_ty.into()))
+}
+
+/// Returns the predicate `bounded_ty: ::magnet_schema::BsonSchema`.
+fn bson_schema_predicate(bounded_ty: Type) -> WherePredicate {
+    WherePredicate::Type(
         PredicateType {
             lifetimes: None,
-            bounded_ty: bounded_ty.into(),
+            bounded_ty,
             colon_token: Default::default(),
             bounds: bson_schema_type_bounds(),
         }
-    );
+    )
+}
+
+/// The types which need the `BsonSchema` bound in the generated `impl`.
+#[derive(Default)]
+pub struct SchemaBounds {
+    /// The type parameters occurring in the types of fields by themselves.
+    pub params: HashSet<Ident>,
+    /// The associated types of type parameters occurring in the types of
+    /// fields, e.g. `T::Payload` or `<T as Config>::Payload`. These get the
+    /// bound themselves, since `T` isn't necessarily a `BsonSchema`.
+    pub projections: Vec<Type>,
+}
+
+/// Returns the type parameters and associated types which need the
+/// `BsonSchema` bound, i.e. those which occur in the types of fields
+/// that are part of the schema, apart from the type argument of
+/// `PhantomData`. The check is purely syntactic, hence conservative:
+/// any other occurrence of the name of a parameter, e.g. in a macro
+/// invocation or in a generic associated type like `T::Output<U>`,
+/// keeps the bound on the parameter.
+///
+/// The parameters in `excluded`, i.e. those listed by the attribute
+/// `#[magnet(no_bound(...))]`, never get a bound, and neither do their
+/// associated types. It's an error to exclude an unknown parameter.
+pub fn bounded_type_params(
+    generics: &Generics,
+    data: &Data,
+    excluded: &[Ident],
+) -> Result<SchemaBounds> {
+    let mut params: HashSet<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+
+    for ident in excluded {
+        if !params.remove(ident) {
+            let message = format!("`{}` is not a type parameter of this type", ident);
+            return Err(Error::spanned(ident.span(), message));
+        }
+    }
+
+    let mut usage = ParamUsage {
+        params: &params,
+        used: HashSet::new(),
+        projections: Vec::new(),
+    };
+
+    match *data {
+        Data::Struct(ref s) => usage.scan_fields(&s.fields)?,
+        Data::Enum(ref e) => for variant in &e.variants {
+            usage.scan_fields(&variant.fields)?;
+        },
+        Data::Union(_) => return Ok(SchemaBounds { params, projections: Vec::new() }),
+    }
+
+    let mut seen = HashSet::new();
+    let mut projections = Vec::with_capacity(usage.projections.len());
+
+    for tokens in usage.projections {
+        if seen.insert(tokens.to_string()) {
+            projections.push(syn::parse2::<Type>(tokens)?);
+        }
+    }
+
+    Ok(SchemaBounds { params: usage.used, projections })
+}
+
+/// Records which type parameters occur in the types of fields.
+#[derive(Debug)]
+struct ParamUsage<'a> {
+    /// The names of the type parameters of the deriving type.
+    params: &'a HashSet<Ident>,
+    /// The parameters occurring outside `PhantomData` and projections.
+    used: HashSet<Ident>,
+    /// The tokens of the associated types of parameters, e.g. `T::Payload`.
+    projections: Vec<TokenStream>,
+}
+
+impl<'a> ParamUsage<'a> {
+    /// Scans the types of the given fields, except for skipped named
+    /// fields, and fields with `#[magnet(with = "...")]`, since the schema
+    /// doesn't depend on the types of those.
+    fn scan_fields(&mut self, fields: &Fields) -> Result<()> {
+        for field in fields {
+            if field.ident.is_some() && is_skipped(field)? {
+                continue;
+            }
+
+            if meta::magnet_name_value(&field.attrs, "with")?.is_some() {
+                continue;
+            }
+
+            self.scan(field.ty.clone().into_token_stream());
+        }
+
+        Ok(())
+    }
+
+    /// Scans the tokens of a type, recursing into delimited groups,
+    /// ignoring the angle-bracketed arguments of `PhantomData`, and
+    /// recording associated types of parameters as projections.
+    fn scan(&mut self, tokens: TokenStream) {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut index = 0;
+
+        while index < trees.len() {
+            if let Some(end) = self.projection_end(&trees, index) {
+                self.projections.push(trees[index..end].iter().cloned().collect());
+                index = end;
+                continue;
+            }
+
+            match trees[index] {
+                TokenTree::Group(ref group) => self.scan(group.stream()),
+                TokenTree::Ident(ref ident) => {
+                    if ident == "PhantomData" && is_punct(trees.get(index + 1), '<') {
+                        index = skip_angle_bracketed_args(&trees, index + 2);
+                        continue;
+                    } else if self.params.contains(ident) {
+                        self.used.insert(ident.clone());
+                    }
+                },
+                _ => {},
+            }
+
+            index += 1;
+        }
+    }
+
+    /// If an associated type of a type parameter, i.e. `T::Assoc` or
+    /// `<T as Trait>::Assoc`, starts at `start`, returns the index right
+    /// after it. Returns `None` if it's followed by generic arguments,
+    /// since the bound on `T` might be needed for those.
+    fn projection_end(&self, trees: &[TokenTree], start: usize) -> Option<usize> {
+        let is_param = |tree: Option<&TokenTree>| match tree {
+            Some(TokenTree::Ident(ident)) => self.params.contains(ident),
+            _ => false,
+        };
+
+        // `T` in the middle of a path, e.g. `module::T::Assoc`, isn't the parameter
+        if start > 0 && is_punct(trees.get(start - 1), ':') {
+            return None;
+        }
+
+        let path_start = if is_param(trees.get(start)) {
+            start + 1
+        } else if is_punct(trees.get(start), '<')
+               && is_param(trees.get(start + 1))
+               && is_ident(trees.get(start + 2), "as") {
+            skip_angle_bracketed_args(trees, start + 3)
+        } else {
+            return None;
+        };
+
+        let mut end = path_start;
+
+        while is_punct(trees.get(end), ':')
+              && is_punct(trees.get(end + 1), ':')
+              && is_ident_tree(trees.get(end + 2)) {
+            end += 3;
+        }
+
+        if end == path_start || is_punct(trees.get(end), '<') || is_punct(trees.get(end), ':') {
+            None
+        } else {
+            Some(end)
+        }
+    }
+}
+
+/// Returns `true` if `tree` is the punctuation character `ch`.
+fn is_punct(tree: Option<&TokenTree>, ch: char) -> bool {
+    match tree {
+        Some(TokenTree::Punct(punct)) => punct.as_char() == ch,
+        _ => false,
+    }
+}
+
+/// Returns `true` if `tree` is the identifier (or keyword) `name`.
+fn is_ident(tree: Option<&TokenTree>, name: &str) -> bool {
+    match tree {
+        Some(TokenTree::Ident(ident)) => ident == name,
+        _ => false,
+    }
+}
+
+/// Returns `true` if `tree` is any identifier.
+fn is_ident_tree(tree: Option<&TokenTree>) -> bool {
+    matches!(tree, Some(TokenTree::Ident(_)))
+}
+
+/// Returns the index right after the `>` closing an angle bracket whose
+/// `<` precedes `start`. The `>` of a `->` doesn't count.
+fn skip_angle_bracketed_args(trees: &[TokenTree], start: usize) -> usize {
+    let mut depth = 0_usize;
+    let mut after_arrow_head = false;
+
+    for (index, tree) in trees.iter().enumerate().skip(start) {
+        let mut is_arrow_head = false;
+
+        if let TokenTree::Punct(ref punct) = *tree {
+            match punct.as_char() {
+                '<' => depth += 1,
+                '>' if !after_arrow_head => {
+                    if depth == 0 {
+                        return index + 1;
+                    }
+                    depth -= 1;
+                },
+                '-' => is_arrow_head = punct.spacing() == Spacing::Joint,
+                _ => {},
+            }
+        }
+
+        after_arrow_head = is_arrow_head;
+    }
 
-    Some(p)
+    trees.len()
 }
@@ -0,0 +1,31 @@
+//! Checking property names against MongoDB's rules for field names.
+
+use proc_macro2::Span;
+use error::{ Error, Result };
+
+/// Checks that `name` can be used as a field name in a document stored
+/// by MongoDB. Before 5.0, names starting with `$` or containing `.` are
+/// rejected (or misinterpreted by validators), so they're an error unless
+/// `relaxed` is set, which corresponds to `#[magnet(relaxed_keys)]`.
+/// `origin` describes where the name comes from, e.g. "field `x`".
+pub fn check_key(name: &str, origin: &str, span: Span, relaxed: bool) -> Result<()> {
+    if relaxed {
+        return Ok(());
+    }
+
+    let problem = if name.starts_with('$') {
+        "starts with `$`"
+    } else if name.contains('.') {
+        "contains `.`"
+    } else {
+        return Ok(());
+    };
+
+    let message = format!(
+        "key `{}` of {} {}, which MongoDB doesn't allow in field names before 5.0; \
+         add `#[magnet(relaxed_keys)]` to use it anyway",
+        name, origin, problem
+    );
+
+    Err(Error::spanned(span, message))
+}
@@ -31,6 +31,7 @@
 
 #[macro_use]
 extern crate quote;
+#[macro_use]
 extern crate syn;
 extern crate proc_macro;
 extern crate proc_macro2;
@@ -38,47 +39,224 @@ extern crate proc_macro2;
 mod tag;
 mod case;
 mod meta;
+mod keys;
 mod error;
 mod generics;
+mod codegen_name;
 mod codegen_field;
 mod codegen_struct;
 mod codegen_enum;
 mod codegen_union;
 
 use proc_macro::TokenStream;
-use syn::{ DeriveInput, Data };
-use error::Result;
+use syn::{ DeriveInput, Data, Fields, Generics, WhereClause };
+use error::{ Error, Result };
+use meta::Position;
+use tag::SerdeEnumTag;
 use generics::GenericsExt;
+use codegen_name::impl_schema_name;
 use codegen_struct::*;
 use codegen_enum::*;
 use codegen_union::*;
 
 /// The top-level entry point of this proc-macro. Only here to be exported
-/// and to handle `Result::Err` return values by `panic!()`ing.
+/// and to turn `Result::Err` return values into `compile_error!()`s.
 #[proc_macro_derive(BsonSchema, attributes(magnet))]
 pub fn derive_bson_schema(input: TokenStream) -> TokenStream {
-    impl_bson_schema(input).unwrap_or_else(|error| panic!("{}", error))
+    impl_bson_schema(input).unwrap_or_else(|error| error.to_compile_error().into())
 }
 
 /// Implements `BsonSchema` for a given type based on its
 /// recursively contained types in fields or variants.
 fn impl_bson_schema(input: TokenStream) -> Result<TokenStream> {
     let parsed_ast: DeriveInput = syn::parse(input)?;
+    check_magnet_attrs(&parsed_ast)?;
+
     let ty = parsed_ast.ident;
-    let impl_ast = match parsed_ast.data {
+    let excluded = meta::magnet_word_list(&parsed_ast.attrs, "no_bound")?;
+    let bounded = generics::bounded_type_params(&parsed_ast.generics, &parsed_ast.data, &excluded)?;
+    let name_ast = impl_schema_name(&parsed_ast.attrs, &ty, &parsed_ast.generics, &bounded.params)?;
+    let mode_ast = codegen_field::description_mode(&parsed_ast.attrs)?;
+    let container_attrs = parsed_ast.attrs.clone();
+    let field_consts = match parsed_ast.data {
+        Data::Struct(ref s) => impl_field_name_consts(&parsed_ast.attrs, s)?,
+        Data::Enum(_) | Data::Union(_) => None,
+    };
+    let key_impl = match parsed_ast.data {
+        Data::Enum(ref e) => impl_bson_schema_key(&parsed_ast.attrs, e)?.map(|methods| (methods, None)),
+        Data::Struct(ref s) => impl_bson_schema_key_newtype(s)?.map(|(methods, bound)| (methods, Some(bound))),
+        Data::Union(_) => None,
+    };
+    let unversioned_ast = match parsed_ast.data {
         Data::Struct(s) => impl_bson_schema_struct(parsed_ast.attrs, s)?,
         Data::Enum(e) => impl_bson_schema_enum(parsed_ast.attrs, e)?,
         Data::Union(u) => impl_bson_schema_union(parsed_ast.attrs, u)?,
     };
+    let exemplified_ast = codegen_field::extend_with_examples(unversioned_ast, &container_attrs)?;
+    let impl_ast = codegen_field::extend_with_version(exemplified_ast, &container_attrs)?;
     let generics = parsed_ast.generics;
-    let (impl_gen, ty_gen, where_cls) = generics.split_and_augment_for_impl();
+    let (impl_gen, ty_gen, where_cls) = generics.split_and_augment_for_impl(&bounded);
+    let schema_ast = impl_bson_schema_static(&generics);
+    let consts_ast = field_consts.map(|consts| {
+        let (plain_impl_gen, plain_ty_gen, plain_where_cls) = generics.split_for_impl();
+
+        quote! {
+            impl #plain_impl_gen #ty #plain_ty_gen #plain_where_cls {
+                #consts
+            }
+        }
+    });
+    let key_ast = key_impl.map(|(methods, bound)| {
+        let mut key_where_cls = where_cls.clone();
+
+        if let Some(predicate) = bound {
+            key_where_cls
+                .get_or_insert_with(|| WhereClause {
+                    where_token: Default::default(),
+                    predicates: Default::default(),
+                })
+                .predicates
+                .push(predicate);
+        }
+
+        quote! {
+            impl #impl_gen ::magnet_schema::BsonSchemaKey for #ty #ty_gen #key_where_cls {
+                #methods
+            }
+        }
+    });
     let generated = quote! {
         impl #impl_gen ::magnet_schema::BsonSchema for #ty #ty_gen #where_cls {
-            fn bson_schema() -> ::bson::Document {
-                #impl_ast
+            #schema_ast
+
+            fn try_bson_schema() -> Result<::bson::Document, ::magnet_schema::SchemaError> {
+                ::magnet_schema::try_root_schema::<Self>()
+            }
+
+            fn bson_schema_with(
+                ctx: &mut ::magnet_schema::SchemaContext
+            ) -> ::bson::Document {
+                // `::bson::doc!` and `bson!` invoke each other by their bare
+                // names, which the crate using the derive needn't import
+                #[allow(unused_imports)]
+                use ::bson::{ bson, doc };
+
+                #mode_ast
+
+                ctx.define_type::<Self, _>(&Self::schema_name(), |ctx| {
+                    #impl_ast
+                })
+            }
+
+            fn schema_name() -> String {
+                #name_ast
             }
         }
+
+        #key_ast
+
+        #consts_ast
     };
 
     Ok(generated.into())
 }
+
+/// Implements `bson_schema()` and `bson_schema_cached()`. The schema of a
+/// non-generic type is computed only once, and it's stored in a `static`
+/// of its own. A `static` can't depend on the generic parameters of the
+/// surrounding `impl`, so the schema of a generic type is built anew upon
+/// every call to `bson_schema()`, and `bson_schema_cached()` falls back
+/// to the shared, type-indexed cache.
+fn impl_bson_schema_static(generics: &Generics) -> proc_macro2::TokenStream {
+    if !generics.params.is_empty() {
+        return quote! {
+            fn bson_schema() -> ::bson::Document {
+                Self::try_bson_schema().unwrap_or_else(|error| panic!("{}", error))
+            }
+        };
+    }
+
+    quote! {
+        fn bson_schema() -> ::bson::Document {
+            Self::bson_schema_cached().clone()
+        }
+
+        fn bson_schema_cached() -> &'static ::bson::Document {
+            static SCHEMA: ::std::sync::OnceLock<::bson::Document> = ::std::sync::OnceLock::new();
+
+            SCHEMA.get_or_init(|| {
+                Self::try_bson_schema().unwrap_or_else(|error| panic!("{}", error))
+            })
+        }
+    }
+}
+
+/// Checks the `#[magnet(...)]` attributes of the type, and those of its
+/// variants and fields, before any code is generated.
+fn check_magnet_attrs(ast: &DeriveInput) -> Result<()> {
+    /// Checks the attributes of every field.
+    fn check_fields(fields: &Fields) -> Result<()> {
+        fields.iter().try_for_each(|field| meta::check_magnet_attrs(&field.attrs, Position::Field))
+    }
+
+    meta::check_magnet_attrs(&ast.attrs, Position::Container)?;
+
+    let has_tag = match ast.data {
+        Data::Enum(_) => SerdeEnumTag::from_attrs(&ast.attrs)?.tag().is_some(),
+        Data::Struct(_) | Data::Union(_) => false,
+    };
+
+    let keys = meta::magnet_keys(&ast.attrs);
+
+    if let Some(&(_, span)) = keys.iter().find(|&(key, _)| key == "tag_summary") {
+        if !has_tag {
+            return Err(Error::spanned(
+                span,
+                "`#[magnet(tag_summary)]` can only be applied to internally or adjacently tagged enums"
+            ));
+        }
+    }
+
+    if let Some(&(_, span)) = keys.iter().find(|&(key, _)| key == "field_names") {
+        let is_named = match ast.data {
+            Data::Struct(ref s) => match s.fields {
+                Fields::Named(_) => true,
+                Fields::Unnamed(_) | Fields::Unit => false,
+            },
+            Data::Enum(_) | Data::Union(_) => false,
+        };
+
+        if !is_named {
+            return Err(Error::spanned(
+                span,
+                "`#[magnet(field_names)]` can only be applied to structs with named fields"
+            ));
+        }
+    }
+
+    let is_newtype = match ast.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Unnamed(ref fields) => fields.unnamed.len() == 1,
+            Fields::Named(_) | Fields::Unit => false,
+        },
+        Data::Enum(_) | Data::Union(_) => false,
+    };
+
+    if !is_newtype {
+        if let Some(&(ref key, span)) = keys.iter().find(|&(key, _)| FORWARDED_ATTRS.contains(&key.as_str())) {
+            return Err(Error::spanned(
+                span,
+                format!("`#[magnet({})]` can only be applied to fields and newtype structs", key)
+            ));
+        }
+    }
+
+    match ast.data {
+        Data::Struct(ref s) => check_fields(&s.fields),
+        Data::Enum(ref e) => e.variants.iter().try_for_each(|variant| {
+            meta::check_magnet_attrs(&variant.attrs, Position::Variant)?;
+            check_fields(&variant.fields)
+        }),
+        Data::Union(_) => Ok(()),
+    }
+}
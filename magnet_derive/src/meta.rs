@@ -1,55 +1,268 @@
 //! Helper functions for retrieving and parsing meta attributes.
 
-use std::f64;
-use syn::{ Attribute, Meta, NestedMeta, MetaNameValue, Lit };
+use std::str::FromStr;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use syn::{ self, Attribute, Meta, NestedMeta, MetaNameValue, Lit, Ident, Path };
+use syn::spanned::Spanned;
+use proc_macro2::Span;
 use error::{ Error, Result };
+use case::RenameRule;
 
-/// Returns the inner, `...` part of the first `#[name(...)]` attribute
-/// with the specified name (like `#[magnet(key ( = "value")?)]`).
-/// TODO(H2CO3): check for duplicate arguments and bail out with an error
-fn meta(attrs: &[Attribute], name: &str, key: &str) -> Option<Meta> {
+/// Returns the inner, `...` parts of every `#[name(...)]` attribute with
+/// the specified name and key (like `#[magnet(key ( = "value")?)]`), from
+/// all attributes of an item, in the order of appearance.
+fn metas(attrs: &[Attribute], name: &str, key: &str) -> Vec<Meta> {
     attrs.iter().filter_map(|attr| {
-        let meta_list = match attr.interpret_meta()? {
-            Meta::List(list) => {
-                if list.ident == name {
-                    list
-                } else {
-                    return None;
-                }
-            },
-            _ => return None,
+        match attr.interpret_meta()? {
+            Meta::List(list) => if list.ident == name { Some(list) } else { None },
+            _ => None,
+        }
+    })
+    .flat_map(|meta_list| meta_list.nested.into_iter())
+    .filter_map(|nested_meta| match nested_meta {
+        NestedMeta::Meta(meta) => if meta.name() == key { Some(meta) } else { None },
+        NestedMeta::Literal(_) => None,
+    })
+    .collect()
+}
+
+/// Returns every `#[magnet(key ...)]` argument, in the order of appearance.
+pub fn magnet_metas(attrs: &[Attribute], key: &str) -> Vec<Meta> {
+    metas(attrs, "magnet", key)
+}
+
+/// Like `metas()`, but expects at most one occurrence of the key. Repeating
+/// it with exactly the same value is harmless, but any other duplicate, e.g.
+/// `#[magnet(min_incl = "1")]` and `#[magnet(min_incl = "2")]`, is an error,
+/// pointing to both occurrences, rather than silently using either of them.
+fn meta(attrs: &[Attribute], name: &str, key: &str) -> Result<Option<Meta>> {
+    let mut all = metas(attrs, name, key).into_iter();
+    let first = match all.next() {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    let first_tokens = quote!(#first).to_string();
+
+    for other in all {
+        if quote!(#other).to_string() != first_tokens {
+            let message = format!("conflicting `#[{}({})]` arguments", name, key);
+            return Err(
+                Error::spanned(other.span(), message)
+                    .with_note(first.span(), format!("`#[{}({})]` first given here", name, key))
+            );
+        }
+    }
+
+    Ok(Some(first))
+}
+
+/// The kinds of items `#[magnet(...)]` attributes can be applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// The `struct` or `enum` itself.
+    Container,
+    /// A named or unnamed field of a `struct` or variant.
+    Field,
+    /// A variant of an `enum`.
+    Variant,
+}
+
+impl Position {
+    /// Describes the position in error messages.
+    fn description(self) -> &'static str {
+        match self {
+            Position::Container => "a struct or enum",
+            Position::Field     => "a field",
+            Position::Variant   => "a variant",
+        }
+    }
+}
+
+/// The syntactic forms of arguments of `#[magnet(...)]` attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Form {
+    /// `#[magnet(key)]`
+    Word,
+    /// `#[magnet(key = "value")]`
+    NameValue,
+    /// `#[magnet(key(...))]`
+    List,
+}
+
+/// The recognized `#[magnet(...)]` arguments, along with their expected
+/// form, and the positions where they are allowed.
+const MAGNET_KEYS: &[(&str, Form, &[Position])] = &[
+    ("schema_name", Form::NameValue, &[Position::Container]),
+    ("doc",         Form::NameValue, &[Position::Container]),
+    ("tag_summary", Form::Word,      &[Position::Container]),
+    ("version",     Form::NameValue, &[Position::Container]),
+    ("examples_from", Form::NameValue, &[Position::Container]),
+    ("field_names", Form::Word,      &[Position::Container]),
+    ("relaxed_keys", Form::Word,     &[Position::Container, Position::Variant, Position::Field]),
+    ("no_bound",    Form::List,      &[Position::Container]),
+    ("min_incl",    Form::NameValue, &[Position::Container, Position::Field]),
+    ("min_excl",    Form::NameValue, &[Position::Container, Position::Field]),
+    ("max_incl",    Form::NameValue, &[Position::Container, Position::Field]),
+    ("max_excl",    Form::NameValue, &[Position::Container, Position::Field]),
+    ("values_min_incl", Form::NameValue, &[Position::Container, Position::Field]),
+    ("values_min_excl", Form::NameValue, &[Position::Container, Position::Field]),
+    ("values_max_incl", Form::NameValue, &[Position::Container, Position::Field]),
+    ("values_max_excl", Form::NameValue, &[Position::Container, Position::Field]),
+    ("skip",        Form::Word,      &[Position::Field]),
+    ("with",        Form::NameValue, &[Position::Field]),
+    ("format",      Form::NameValue, &[Position::Container, Position::Field]),
+    ("map_entries", Form::Word,      &[Position::Container, Position::Field]),
+    ("entry_pairs", Form::Word,      &[Position::Container, Position::Field]),
+    ("unique_items", Form::Word,     &[Position::Container, Position::Field]),
+    ("min_items",   Form::NameValue, &[Position::Container, Position::Field]),
+    ("max_items",   Form::NameValue, &[Position::Container, Position::Field]),
+    ("items_regex", Form::NameValue, &[Position::Container, Position::Field]),
+    ("required",    Form::Word,      &[Position::Field]),
+    ("optional",    Form::Word,      &[Position::Field]),
+];
+
+/// Checks that every `#[magnet(...)]` attribute in `attrs` is recognized,
+/// is allowed at `position`, and has the right form. Errors point to the
+/// offending argument, and suggest the intended key in case of a typo.
+pub fn check_magnet_attrs(attrs: &[Attribute], position: Position) -> Result<()> {
+    for attr in attrs {
+        if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "magnet" {
+            continue;
+        }
+
+        let list = match attr.interpret_meta() {
+            Some(Meta::List(list)) => list,
+            _ => return Err(Error::spanned(
+                attr.span(),
+                "attribute must have form `#[magnet(key = \"value\", ...)]`"
+            )),
         };
 
-        meta_list.nested.into_iter().filter_map(|nested_meta| {
-            let meta = match nested_meta {
-                NestedMeta::Meta(meta) => meta,
-                _ => return None,
-            };
+        for nested in &list.nested {
+            match *nested {
+                NestedMeta::Meta(ref meta) => check_magnet_arg(meta, position)?,
+                NestedMeta::Literal(ref literal) => return Err(Error::spanned(
+                    literal.span(),
+                    "expected `key = \"value\"`, found a literal"
+                )),
+            }
+        }
+    }
 
-            let ident = match meta.clone() {
-                Meta::Word(ident) => ident,
-                Meta::List(list) => list.ident,
-                Meta::NameValue(name_value) => name_value.ident,
+    Ok(())
+}
+
+/// Checks a single argument of a `#[magnet(...)]` attribute.
+fn check_magnet_arg(meta: &Meta, position: Position) -> Result<()> {
+    let (ident, form) = match *meta {
+        Meta::Word(ref ident) => (ident, Some(Form::Word)),
+        Meta::NameValue(ref nv) => (&nv.ident, Some(Form::NameValue)),
+        Meta::List(ref list) => (&list.ident, Some(Form::List)),
+    };
+    let key = ident.to_string();
+
+    if key == "rename" {
+        return Err(Error::spanned(
+            meta.span(),
+            "`#[magnet(rename = \"...\")]` no longer exists, use `#[serde(rename = \"...\")]`"
+        ));
+    }
+
+    let &(_, expected_form, positions) = match MAGNET_KEYS.iter().find(|&&(name, _, _)| name == key) {
+        Some(entry) => entry,
+        None => {
+            let candidates = MAGNET_KEYS
+                .iter()
+                .filter(|&&(_, _, positions)| positions.contains(&position))
+                .map(|&(name, _, _)| name);
+            let message = match closest_match(&key, candidates) {
+                Some(name) => format!("unknown attribute `#[magnet({})]`, did you mean `{}`?", key, name),
+                None => format!("unknown attribute `#[magnet({})]`", key),
             };
+            return Err(Error::spanned(ident.span(), message));
+        }
+    };
 
-            if ident == key {
-                Some(meta)
-            } else {
-                None
-            }
+    if !positions.contains(&position) {
+        let message = format!(
+            "`#[magnet({})]` can't be applied to {}", key, position.description()
+        );
+        return Err(Error::spanned(ident.span(), message));
+    }
+
+    if form != Some(expected_form) {
+        let message = match expected_form {
+            Form::Word => format!("attribute must have form `#[magnet({})]`", key),
+            Form::NameValue => format!("attribute must have form `#[magnet({} = \"...\")]`", key),
+            Form::List => format!("attribute must have form `#[magnet({}(...))]`", key),
+        };
+        return Err(Error::spanned(meta.span(), message));
+    }
+
+    Ok(())
+}
+
+/// Returns the keys of all `#[magnet(...)]` arguments in `attrs`, along
+/// with their locations, in the order of appearance.
+pub fn magnet_keys(attrs: &[Attribute]) -> Vec<(String, Span)> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr.interpret_meta()? {
+            Meta::List(ref list) if list.ident == "magnet" => Some(list.nested.clone()),
+            _ => None,
         })
-        .next()
-    })
-    .next()
+        .flat_map(|nested| nested.into_iter())
+        .filter_map(|nested| match nested {
+            NestedMeta::Meta(Meta::Word(ident)) => Some((ident.to_string(), ident.span())),
+            NestedMeta::Meta(Meta::List(list)) => Some((list.ident.to_string(), list.ident.span())),
+            NestedMeta::Meta(Meta::NameValue(nv)) => Some((nv.ident.to_string(), nv.ident.span())),
+            NestedMeta::Literal(_) => None,
+        })
+        .collect()
+}
+
+/// Finds the candidate most similar to `key`, if any is similar enough
+/// to be the intended spelling.
+fn closest_match<'a, I>(key: &str, candidates: I) -> Option<&'a str>
+    where I: IntoIterator<Item = &'a str>
+{
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= candidate.len() / 3)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs_chars: Vec<char> = rhs.chars().collect();
+    let mut prev_row: Vec<usize> = (0..=rhs_chars.len()).collect();
+
+    for (i, lhs_char) in lhs.chars().enumerate() {
+        let mut row = vec![i + 1];
+
+        for (j, &rhs_char) in rhs_chars.iter().enumerate() {
+            let substitution = prev_row[j] + if lhs_char == rhs_char { 0 } else { 1 };
+            let insertion = row[j] + 1;
+            let deletion = prev_row[j + 1] + 1;
+            row.push(substitution.min(insertion).min(deletion));
+        }
+
+        prev_row = row;
+    }
+
+    prev_row[rhs_chars.len()]
 }
 
 /// Search for an attribute, provided that it's a name-value pair.
 fn name_value(attrs: &[Attribute], name: &str, key: &str) -> Result<Option<MetaNameValue>> {
-    match meta(attrs, name, key) {
+    match meta(attrs, name, key)? {
         Some(Meta::NameValue(name_value)) => Ok(Some(name_value)),
-        Some(_) => {
+        Some(other) => {
             let msg = format!("attribute must have form `#[{}({} = \"...\")]`", name, key);
-            Err(Error::new(msg))
+            Err(Error::spanned(other.span(), msg))
         },
         None => Ok(None),
     }
@@ -57,11 +270,11 @@ fn name_value(attrs: &[Attribute], name: &str, key: &str) -> Result<Option<MetaN
 
 /// Search for an attribute, provided that it's a single word.
 fn has_meta_word(attrs: &[Attribute], name: &str, key: &str) -> Result<bool> {
-    match meta(attrs, name, key) {
+    match meta(attrs, name, key)? {
         Some(Meta::Word(_)) => Ok(true),
-        Some(_) => {
+        Some(other) => {
             let msg = format!("attribute must have form `#[{}({})]`", name, key);
-            Err(Error::new(msg))
+            Err(Error::spanned(other.span(), msg))
         },
         None => Ok(false),
     }
@@ -72,6 +285,29 @@ pub fn magnet_name_value(attrs: &[Attribute], key: &str) -> Result<Option<MetaNa
     name_value(attrs, "magnet", key)
 }
 
+/// Search for a `Magnet` attribute, provided that it's a single word.
+pub fn has_magnet_word(attrs: &[Attribute], key: &str) -> Result<bool> {
+    has_meta_word(attrs, "magnet", key)
+}
+
+/// Search for a `Magnet` attribute of the form `#[magnet(key(A, B, ...))]`,
+/// and return the identifiers in its argument list, which may be empty.
+pub fn magnet_word_list(attrs: &[Attribute], key: &str) -> Result<Vec<Ident>> {
+    let list = match meta(attrs, "magnet", key)? {
+        Some(Meta::List(list)) => list,
+        Some(other) => {
+            let msg = format!("attribute must have form `#[magnet({}(...))]`", key);
+            return Err(Error::spanned(other.span(), msg));
+        },
+        None => return Ok(Vec::new()),
+    };
+
+    list.nested.iter().map(|nested| match *nested {
+        NestedMeta::Meta(Meta::Word(ref ident)) => Ok(ident.clone()),
+        ref other => Err(Error::spanned(other.span(), "expected an identifier")),
+    }).collect()
+}
+
 /// Search for a `Serde` attribute, provided that it's a name-value pair.
 pub fn serde_name_value(attrs: &[Attribute], key: &str) -> Result<Option<MetaNameValue>> {
     name_value(attrs, "serde", key)
@@ -82,40 +318,226 @@ pub fn has_serde_word(attrs: &[Attribute], key: &str) -> Result<bool> {
     has_meta_word(attrs, "serde", key)
 }
 
+/// Checks for `#[serde(default)]` or `#[serde(default = "path")]`. Either
+/// form makes serde fill in missing fields, so the value itself isn't
+/// inspected.
+pub fn has_serde_default(attrs: &[Attribute]) -> Result<bool> {
+    match meta(attrs, "serde", "default")? {
+        Some(Meta::Word(_)) | Some(Meta::NameValue(_)) => Ok(true),
+        Some(other) => Err(Error::spanned(
+            other.span(),
+            "attribute must have form `#[serde(default)]` or `#[serde(default = \"...\")]`"
+        )),
+        None => Ok(false),
+    }
+}
+
+/// Returns the name an item is serialized under, as given by either
+/// `#[serde(rename = "...")]` or `#[serde(rename(serialize = "..."))]`.
+/// A `deserialize` name alone is ignored, because it's never stored.
+pub fn serde_rename(attrs: &[Attribute]) -> Result<Option<String>> {
+    serde_serialize_value(attrs, "rename").map(|value| value.map(|(name, _)| name))
+}
+
+/// Returns the rule for renaming fields or variants when serializing, as
+/// given by either `#[serde(rename_all = "...")]` or
+/// `#[serde(rename_all(serialize = "..."))]`.
+pub fn serde_rename_all(attrs: &[Attribute]) -> Result<Option<RenameRule>> {
+    match serde_serialize_value(attrs, "rename_all")? {
+        Some((rule, span)) => rule.parse().map(Some).map_err(|error: Error| error.with_span(span)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the value of a Serde argument that may be split into separate
+/// `serialize` and `deserialize` values, like `rename`, along with its
+/// location. Only the `serialize` value matters, because the other one is
+/// never stored. The two may be given in separate attributes, but two
+/// different `serialize` values are an error.
+fn serde_serialize_value(attrs: &[Attribute], key: &str) -> Result<Option<(String, Span)>> {
+    let mut result: Option<(String, Span)> = None;
+
+    for meta in metas(attrs, "serde", key) {
+        let value = match serialize_value(&meta, key)? {
+            Some(value) => value,
+            None => continue,
+        };
+
+        match result {
+            Some((ref prev, prev_span)) if *prev != value.0 => return Err(
+                Error::spanned(value.1, format!("conflicting `#[serde({})]` arguments", key))
+                    .with_note(prev_span, format!("`#[serde({})]` first given here", key))
+            ),
+            Some(_) => {},
+            None => result = Some(value),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns the `serialize` value in a single argument, i.e. either the
+/// `"..."` in `key = "..."` or in `key(serialize = "...")`, if any.
+fn serialize_value(meta: &Meta, key: &str) -> Result<Option<(String, Span)>> {
+    let list = match *meta {
+        Meta::NameValue(ref nv) => return value_as_str(nv).map(|value| Some((value, nv.lit.span()))),
+        Meta::List(ref list) => list,
+        Meta::Word(_) => return Err(Error::spanned(
+            meta.span(),
+            format!("attribute must have form `#[serde({} = \"...\")]`", key)
+        )),
+    };
+
+    for nested in &list.nested {
+        match *nested {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "serialize" => {
+                return value_as_str(nv).map(|value| Some((value, nv.lit.span())));
+            },
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "deserialize" => {},
+            ref other => return Err(Error::spanned(
+                other.span(),
+                "expected `serialize = \"...\"` or `deserialize = \"...\"`"
+            )),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the values of all `#[serde(alias = "...")]` attributes, along
+/// with their locations. Aliases are only accepted when deserializing.
+pub fn serde_aliases(attrs: &[Attribute]) -> Result<Vec<(String, Span)>> {
+    let mut aliases = Vec::new();
+
+    for attr in attrs {
+        let list = match attr.interpret_meta() {
+            Some(Meta::List(list)) => if list.ident == "serde" { list } else { continue },
+            _ => continue,
+        };
+
+        for nested in &list.nested {
+            match *nested {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "alias" => {
+                    aliases.push((value_as_str(nv)?, nv.lit.span()));
+                },
+                NestedMeta::Meta(ref meta) if meta.name() == "alias" => return Err(Error::spanned(
+                    meta.span(),
+                    "attribute must have form `#[serde(alias = \"...\")]`"
+                )),
+                _ => {},
+            }
+        }
+    }
+
+    Ok(aliases)
+}
+
+/// Returns the doc comment of an item, i.e. the values of all of its
+/// `#[doc = "..."]` attributes, one line each. A single leading space is
+/// stripped from every line, so that indentation, e.g. in Markdown code
+/// blocks, is preserved. Returns `None` if the item is undocumented.
+pub fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match attr.interpret_meta()? {
+            Meta::NameValue(MetaNameValue { ref ident, lit: Lit::Str(ref string), .. }) if ident == "doc" => {
+                Some(string.value())
+            },
+            _ => None,
+        })
+        .flat_map(|value| {
+            // unlike `lines()`, this yields an empty line for an empty `///`
+            value
+                .split('\n')
+                .map(|line| line.strip_prefix(' ').unwrap_or(line).trim_end().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let first = lines.iter().position(|line| !line.is_empty())?;
+    let last = lines.iter().rposition(|line| !line.is_empty())?;
+
+    Some(lines[first..=last].join("\n"))
+}
+
 /// Extracts a string value from an attribute value.
 /// Returns `Err` if the value is not a `LitStr` nor a valid UTF-8 `LitByteStr`.
 pub fn value_as_str(nv: &MetaNameValue) -> Result<String> {
-    match nv.lit {
+    let result = match nv.lit {
         Lit::Str(ref string) => Ok(string.value()),
         Lit::ByteStr(ref string) => String::from_utf8(string.value()).map_err(Into::into),
         _ => Err(Error::new("attribute value must be a valid UTF-8 string")),
+    };
+
+    result.map_err(|error| error.with_span(nv.lit.span()))
+}
+
+/// A numeric attribute value. Integers are kept as such, rather than
+/// being converted to floating-point, so that they are stored as `long`s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    /// An integer literal, or a string that parses as one.
+    Int(i64),
+    /// A floating-point literal, or a string that parses as one.
+    Float(f64),
+}
+
+impl PartialOrd for Number {
+    #[allow(clippy::cast_precision_loss)]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let as_f64 = |number| match number {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        };
+
+        match (*self, *other) {
+            (Number::Int(lhs), Number::Int(rhs)) => Some(lhs.cmp(&rhs)),
+            (lhs, rhs) => as_f64(lhs).partial_cmp(&as_f64(rhs)),
+        }
+    }
+}
+
+impl FromStr for Number {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse() {
+            Ok(value) => Ok(Number::Int(value)),
+            Err(_) => s.parse().map(Number::Float).map_err(Into::into),
+        }
     }
 }
 
-/// Extracts a floating-point value from an attribute value.
-/// Returns an `Err` if the literal is not a valid floating-point
-/// number or integer, and not a string that could be parsed as one.
-#[allow(clippy::cast_precision_loss)]
-pub fn value_as_num(nv: &MetaNameValue) -> Result<f64> {
-    match nv.lit {
-        Lit::Float(ref lit) => Ok(lit.value()),
+/// Extracts a number from an attribute value. Returns an `Err` if the
+/// literal is not a valid floating-point number or integer, and not a
+/// string that could be parsed as one, or if it's an integer too large
+/// for an `i64`.
+pub fn value_as_num(nv: &MetaNameValue) -> Result<Number> {
+    let result = match nv.lit {
+        Lit::Float(ref lit) => Ok(Number::Float(lit.value())),
         Lit::Int(ref lit) => {
-            // Check if `f64` can represent this `u64`,
-            // so the conversion would be lossless.
-            let value = lit.value();
-            let max_exact = 1 << f64::MANTISSA_DIGITS;
-            if value < max_exact {
-                Ok(value as f64)
-            } else {
-                Err(Error::new("Integer can't be exactly represented by `f64`"))
-            }
+            i64::try_from(lit.value())
+                .map(Number::Int)
+                .map_err(|_| Error::new("integer doesn't fit into an `i64`"))
         },
-        Lit::Str(ref string) => string.value().parse().map_err(Into::into),
+        Lit::Str(ref string) => string.value().parse(),
         Lit::ByteStr(ref string) => {
             String::from_utf8(string.value())
                 .map_err(Into::into)
-                .and_then(|s| s.parse().map_err(Into::into))
+                .and_then(|s| s.parse())
         },
         _ => Err(Error::new("attribute value must be a number")),
-    }
+    };
+
+    result.map_err(|error| error.with_span(nv.lit.span()))
 }
+
+/// Parses a string attribute value as a path, e.g. that of a function.
+/// Errors point to the literal.
+pub fn value_as_path(nv: &MetaNameValue) -> Result<Path> {
+    syn::parse_str(&value_as_str(nv)?).map_err(|_| Error::spanned(
+        nv.lit.span(),
+        "attribute value must be a path, e.g. `module::function`"
+    ))
+}
+
@@ -1,8 +1,10 @@
 //! Types and functions for dealing with Serde `enum` representation conventions.
 
 use syn::Attribute;
+use syn::spanned::Spanned;
 use error::Result;
 use meta;
+use keys;
 
 /// Represents Serde's `enum` tagging convention.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,13 +30,19 @@ pub enum SerdeEnumTag {
 
 impl SerdeEnumTag {
     /// Attempts to parse some attributes into a Serde enum tagging convention.
+    /// The `tag` and `content` keys must be valid MongoDB field names, unless
+    /// the attributes include `#[magnet(relaxed_keys)]`.
     /// TODO(H2CO3): check for conflicting tags? (Serde is supposed to do that!)
     pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
-        let conv = if let Some(tag) = meta::serde_name_value(attrs, "tag")? {
-            let tag = meta::value_as_str(&tag)?;
+        let relaxed = meta::has_magnet_word(attrs, "relaxed_keys")?;
 
-            if let Some(content) = meta::serde_name_value(attrs, "content")? {
-                let content = meta::value_as_str(&content)?;
+        let conv = if let Some(tag_nv) = meta::serde_name_value(attrs, "tag")? {
+            let tag = meta::value_as_str(&tag_nv)?;
+            keys::check_key(&tag, "the tag", tag_nv.lit.span(), relaxed)?;
+
+            if let Some(content_nv) = meta::serde_name_value(attrs, "content")? {
+                let content = meta::value_as_str(&content_nv)?;
+                keys::check_key(&content, "the content", content_nv.lit.span(), relaxed)?;
 
                 SerdeEnumTag::Adjacent { tag, content }
             } else {
@@ -48,6 +56,15 @@ impl SerdeEnumTag {
 
         Ok(conv)
     }
+
+    /// Returns the key under which the name of the variant is stored
+    /// in the same document as its contents, if there is such a key.
+    pub fn tag(&self) -> Option<&str> {
+        match *self {
+            SerdeEnumTag::Internal(ref tag) | SerdeEnumTag::Adjacent { ref tag, .. } => Some(tag),
+            SerdeEnumTag::External | SerdeEnumTag::Untagged => None,
+        }
+    }
 }
 
 impl Default for SerdeEnumTag {
@@ -0,0 +1,9 @@
+extern crate trybuild;
+
+/// Checks the messages and the spans of the errors reported by the derive.
+/// Run with `TRYBUILD=overwrite` to update the expected `.stderr` files.
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+struct Percentage {
+    #[magnet(max_incl = "100", max_excl = "100")]
+    value: f64,
+}
+
+fn main() {}
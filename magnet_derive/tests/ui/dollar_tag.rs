@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[serde(tag = "$type")]
+enum Shape {
+    Circle { radius: f64 },
+    Square { side: f64 },
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+struct Point {
+    #[serde(rename = "a.b")]
+    x: f64,
+}
+
+fn main() {}
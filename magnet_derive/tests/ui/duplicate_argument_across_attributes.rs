@@ -0,0 +1,13 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+struct Percentage {
+    #[magnet(min_incl = "0")]
+    #[magnet(min_incl = "1")]
+    value: i32,
+}
+
+fn main() {}
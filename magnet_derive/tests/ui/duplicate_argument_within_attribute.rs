@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+#[magnet(schema_name = "Foo", schema_name = "Bar")]
+struct Foo {
+    value: i32,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+extern crate bson;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+struct User {
+    user_id: u32,
+    #[serde(rename = "user_id")]
+    uid: u32,
+}
+
+fn main() {}
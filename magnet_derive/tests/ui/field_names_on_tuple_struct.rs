@@ -0,0 +1,10 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+#[magnet(field_names)]
+struct Point(f64, f64);
+
+fn main() {}
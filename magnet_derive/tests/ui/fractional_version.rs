@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+#[magnet(version = 1.5)]
+struct Account {
+    owner: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+#[magnet(min_items = "-1")]
+struct Tags(Vec<String>);
+
+fn main() {}
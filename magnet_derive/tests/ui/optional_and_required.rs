@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+struct Account {
+    #[magnet(optional, required)]
+    id: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+enum Color {
+    #[magnet(rename = "red")]
+    Red,
+}
+
+fn main() {}
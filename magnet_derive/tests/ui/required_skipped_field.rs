@@ -0,0 +1,15 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, BsonSchema)]
+struct Account {
+    #[serde(skip)]
+    #[magnet(required)]
+    id: String,
+}
+
+fn main() {}
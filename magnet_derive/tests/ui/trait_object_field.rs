@@ -0,0 +1,13 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+trait Payload {}
+
+#[derive(BsonSchema)]
+struct Message {
+    payload: Box<dyn Payload>,
+}
+
+fn main() {}
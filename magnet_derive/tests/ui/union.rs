@@ -0,0 +1,12 @@
+extern crate bson;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(BsonSchema)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
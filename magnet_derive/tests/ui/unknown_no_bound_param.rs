@@ -0,0 +1,15 @@
+extern crate bson;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[magnet(no_bound(U))]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn main() {}
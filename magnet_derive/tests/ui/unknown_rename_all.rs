@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[serde(rename_all = "Title Case")]
+struct Book {
+    page_count: u32,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+extern crate bson;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[serde(tag = "kind")]
+enum Measurement {
+    Count(u32),
+}
+
+fn main() {}
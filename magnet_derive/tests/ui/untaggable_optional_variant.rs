@@ -0,0 +1,20 @@
+extern crate bson;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[serde(tag = "kind")]
+enum Measurement {
+    Reading(Option<Reading>),
+}
+
+#[derive(Serialize, BsonSchema)]
+struct Reading {
+    value: f64,
+}
+
+fn main() {}
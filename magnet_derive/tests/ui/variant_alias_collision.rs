@@ -0,0 +1,17 @@
+extern crate bson;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate magnet_derive;
+extern crate magnet_schema;
+
+#[derive(Serialize, BsonSchema)]
+#[serde(tag = "status")]
+enum Status {
+    #[serde(alias = "Disabled")]
+    Inactive,
+    Disabled,
+}
+
+fn main() {}
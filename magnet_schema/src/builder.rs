@@ -0,0 +1,297 @@
+//! Builders for assembling schemas in manual `BsonSchema` impls.
+//!
+//! They produce the same documents as `#[derive(BsonSchema)]` does for
+//! equivalent types, down to the order of the keywords, so manual and
+//! derived schemas can be mixed freely.
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate bson;
+//! # extern crate magnet_schema;
+//! # use bson::Document;
+//! # use magnet_schema::BsonSchema;
+//! # use magnet_schema::builder::{ ObjectSchema, ArraySchema, StringSchema };
+//! # fn main() {
+//! struct Article {
+//!     title: String,
+//!     tags: Vec<String>,
+//!     summary: Option<String>,
+//! }
+//!
+//! impl BsonSchema for Article {
+//!     fn bson_schema() -> Document {
+//!         ObjectSchema::new()
+//!             .description("A blog post.")
+//!             .property("title", StringSchema::new().min_length(1).build())
+//!             .property("tags", ArraySchema::new(String::bson_schema()).unique_items().build())
+//!             .optional_property("summary", Option::<String>::bson_schema())
+//!             .build()
+//!     }
+//! }
+//!
+//! assert_eq!(Article::bson_schema(), doc!{
+//!     "type": "object",
+//!     "additionalProperties": false,
+//!     "required": ["title", "tags"],
+//!     "properties": {
+//!         "title": { "type": "string", "minLength": 1_i64 },
+//!         "tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+//!         "summary": { "type": ["string", "null"] },
+//!     },
+//!     "description": "A blog post.",
+//! });
+//! # }
+//! ```
+
+use bson::Document;
+use support::{ self, patterns };
+use SchemaOptions;
+
+/// Builds the schema of an object, i.e. of a `struct` with named fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSchema {
+    /// The schemas of the properties, in the order they were added.
+    properties: Document,
+    /// The names of the required properties.
+    required: Vec<String>,
+    /// Whether properties not listed are allowed.
+    additional_properties: bool,
+    /// The description of the object.
+    description: Option<String>,
+}
+
+impl ObjectSchema {
+    /// Creates the schema of an object without properties. Whether other
+    /// properties are allowed is decided by the default `SchemaOptions`.
+    pub fn new() -> Self {
+        ObjectSchema::default()
+    }
+
+    /// Adds a required property, like a field without attributes. A
+    /// property of the same name is replaced.
+    pub fn property(self, name: &str, schema: Document) -> Self {
+        self.optional_property(name, schema).required(name)
+    }
+
+    /// Adds a property which may be missing, like a field with
+    /// `#[magnet(optional)]` or `#[serde(default)]`. A property of
+    /// the same name is replaced, but it stays required if it was.
+    pub fn optional_property(mut self, name: &str, schema: Document) -> Self {
+        self.properties.insert(name, schema);
+        self
+    }
+
+    /// Marks a property as required. Names are listed only once.
+    pub fn required(mut self, name: &str) -> Self {
+        if !self.required.iter().any(|required| required == name) {
+            self.required.push(String::from(name));
+        }
+        self
+    }
+
+    /// Sets whether properties not listed are allowed.
+    pub fn allow_extra(mut self, allowed: bool) -> Self {
+        self.additional_properties = allowed;
+        self
+    }
+
+    /// Sets the description of the object.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// Returns the schema. `required` is left out if there are no
+    /// required properties, as MongoDB rejects empty arrays there.
+    pub fn build(self) -> Document {
+        let mut schema = doc! {
+            "type": "object",
+            "additionalProperties": self.additional_properties,
+        };
+        let required: Vec<&str> = self.required.iter().map(String::as_str).collect();
+
+        schema = support::extend_schema_with_required(schema, &required);
+        schema.insert("properties", self.properties);
+
+        match self.description {
+            Some(description) => support::extend_schema_with_doc(schema, &description),
+            None => schema,
+        }
+    }
+}
+
+impl Default for ObjectSchema {
+    fn default() -> Self {
+        ObjectSchema {
+            properties: Document::new(),
+            required: Vec::new(),
+            additional_properties: SchemaOptions::default().additional_properties,
+            description: None,
+        }
+    }
+}
+
+/// Builds the schema of an array whose items share the same schema,
+/// e.g. that of a `Vec<T>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArraySchema {
+    /// The schema of each item.
+    items: Document,
+    /// The minimal number of items.
+    min_items: Option<usize>,
+    /// The maximal number of items.
+    max_items: Option<usize>,
+    /// Whether the items must be distinct.
+    unique_items: bool,
+    /// The description of the array.
+    description: Option<String>,
+}
+
+impl ArraySchema {
+    /// Creates the schema of an array of items with the schema `items`.
+    pub fn new(items: Document) -> Self {
+        ArraySchema {
+            items,
+            min_items: None,
+            max_items: None,
+            unique_items: false,
+            description: None,
+        }
+    }
+
+    /// Sets the minimal number of items, like `#[magnet(min_items = "...")]`.
+    pub fn min_items(mut self, min: usize) -> Self {
+        self.min_items = Some(min);
+        self
+    }
+
+    /// Sets the maximal number of items, like `#[magnet(max_items = "...")]`.
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.max_items = Some(max);
+        self
+    }
+
+    /// Requires the items to be distinct, like `#[magnet(unique_items)]`.
+    pub fn unique_items(mut self) -> Self {
+        self.unique_items = true;
+        self
+    }
+
+    /// Sets the description of the array.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// Returns the schema.
+    ///
+    /// # Panics
+    ///
+    /// If the minimal number of items exceeds the maximal one.
+    pub fn build(self) -> Document {
+        let array = doc! {
+            "type": "array",
+            "items": self.items,
+        };
+        let schema = support::try_extend_schema_with_items(
+            array, self.min_items, self.max_items, self.unique_items
+        ).unwrap_or_else(|error| panic!("{}", error));
+
+        match self.description {
+            Some(description) => support::extend_schema_with_doc(schema, &description),
+            None => schema,
+        }
+    }
+}
+
+/// Builds the schema of a string.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StringSchema {
+    /// The regular expression the string must match, and its format name.
+    pattern: Option<(String, Option<&'static str>)>,
+    /// The minimal length, in characters.
+    min_length: Option<usize>,
+    /// The maximal length, in characters.
+    max_length: Option<usize>,
+    /// The description of the string.
+    description: Option<String>,
+}
+
+impl StringSchema {
+    /// Creates the schema of an arbitrary string.
+    pub fn new() -> Self {
+        StringSchema::default()
+    }
+
+    /// Requires the string to match a regular expression. It replaces
+    /// the pattern of a format.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some((String::from(pattern), None));
+        self
+    }
+
+    /// Requires the string to be of a named format, like
+    /// `#[magnet(format = "...")]`. It replaces the pattern.
+    ///
+    /// # Panics
+    ///
+    /// If the format is not one of `support::patterns::FORMATS`.
+    pub fn format(mut self, name: &str) -> Self {
+        let &(format, pattern) = patterns::FORMATS
+            .iter()
+            .find(|&&(format, _)| format == name)
+            .unwrap_or_else(|| panic!("unknown string format `{}`", name));
+
+        self.pattern = Some((String::from(pattern), Some(format)));
+        self
+    }
+
+    /// Sets the minimal length of the string.
+    pub fn min_length(mut self, min: usize) -> Self {
+        self.min_length = Some(min);
+        self
+    }
+
+    /// Sets the maximal length of the string.
+    pub fn max_length(mut self, max: usize) -> Self {
+        self.max_length = Some(max);
+        self
+    }
+
+    /// Sets the description of the string.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// Returns the schema.
+    pub fn build(self) -> Document {
+        let mut schema = doc!{ "type": "string" };
+
+        if let Some((pattern, format)) = self.pattern {
+            schema.insert("pattern", pattern);
+
+            if let Some(name) = format {
+                schema.insert("format", name);
+            }
+        }
+
+        #[allow(clippy::cast_possible_wrap)]
+        let lengths = [
+            ("minLength", self.min_length.map(|min| min as i64)),
+            ("maxLength", self.max_length.map(|max| max as i64)),
+        ];
+
+        for &(key, length) in &lengths {
+            if let Some(value) = length {
+                schema.insert(key, value);
+            }
+        }
+
+        match self.description {
+            Some(description) => support::extend_schema_with_doc(schema, &description),
+            None => schema,
+        }
+    }
+}
+
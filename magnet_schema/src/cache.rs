@@ -0,0 +1,34 @@
+//! Process-wide memoization of schemas, keyed by the type they describe.
+
+use std::any::TypeId;
+use std::sync::Mutex;
+use std::collections::BTreeMap;
+use bson::Document;
+use BsonSchema;
+
+/// Schemas computed so far. They are leaked deliberately, so that
+/// references to them can be handed out for the rest of the program.
+static SCHEMAS: Mutex<BTreeMap<TypeId, &'static Document>> = Mutex::new(BTreeMap::new());
+
+/// Returns the cached schema of `T`, computing it upon the first call.
+///
+/// The lock isn't held while the schema is being computed, so that
+/// `bson_schema()` implementations may themselves use the cache. If two
+/// threads race to compute the same schema, only one of them is kept.
+pub fn cached_schema<T>() -> &'static Document where T: ?Sized + BsonSchema + 'static {
+    let id = TypeId::of::<T>();
+
+    if let Some(&schema) = lock().get(&id) {
+        return schema;
+    }
+
+    let schema = T::bson_schema();
+
+    lock().entry(id).or_insert_with(|| Box::leak(Box::new(schema)))
+}
+
+/// Locks the cache. A poisoned lock is recovered from, since the cache
+/// is never left in an inconsistent state by a panicking thread.
+fn lock() -> ::std::sync::MutexGuard<'static, BTreeMap<TypeId, &'static Document>> {
+    SCHEMAS.lock().unwrap_or_else(::std::sync::PoisonError::into_inner)
+}
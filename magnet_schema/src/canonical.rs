@@ -0,0 +1,92 @@
+//! Canonical forms and fingerprints of schemas, for detecting whether
+//! two schemas are logically the same, e.g. whether the validator of a
+//! deployed collection is out of date.
+//!
+//! The canonical form of a schema has the keys of every subschema sorted,
+//! and so are the arrays whose order is not significant: `required`,
+//! `enum`, `type`, `bsonType`, `anyOf`, `oneOf` and `allOf`. The values
+//! listed in an `enum` are otherwise left intact, since the order of keys
+//! is significant when MongoDB compares documents.
+
+use std::cmp::Ordering;
+use bson::{ self, Bson, Document };
+
+/// The keywords whose value is an array in which order doesn't matter.
+const UNORDERED_KEYWORDS: &[&str] = &[
+    "required", "enum", "type", "bsonType", "anyOf", "oneOf", "allOf",
+];
+
+/// Returns the canonical form of `schema`.
+pub fn canonicalize(schema: &Document) -> Document {
+    let mut entries: Vec<_> = schema.iter().collect();
+    entries.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.clone(), canonicalize_value(key, value)))
+        .collect()
+}
+
+/// Returns the canonical form of the value of the keyword `key`.
+fn canonicalize_value(key: &str, value: &Bson) -> Bson {
+    match *value {
+        Bson::Document(ref doc) => canonicalize(doc).into(),
+        Bson::Array(ref array) => {
+            let mut items: Vec<Bson> = if key == "enum" {
+                array.clone()
+            } else {
+                array.iter().map(|item| canonicalize_value("", item)).collect()
+            };
+
+            if UNORDERED_KEYWORDS.contains(&key) {
+                items.sort_by(compare_values);
+            }
+
+            items.into()
+        },
+        ref other => other.clone(),
+    }
+}
+
+/// An arbitrary but consistent total order of BSON values,
+/// based on their binary encoding.
+fn compare_values(lhs: &Bson, rhs: &Bson) -> Ordering {
+    encode_value(lhs).cmp(&encode_value(rhs))
+}
+
+/// Encodes a single BSON value as a document with an empty key.
+fn encode_value(value: &Bson) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut doc = Document::new();
+    doc.insert_bson(String::new(), value.clone());
+
+    // values that can't be encoded all compare equal; they don't occur in
+    // schemas generated by this crate anyway, and the order remains stable.
+    bson::encode_document(&mut buf, &doc).map_or_else(|_| Vec::new(), |()| buf)
+}
+
+/// Computes a 64-bit fingerprint of a schema which only depends on its
+/// canonical form, so logically identical schemas have the same
+/// fingerprint regardless of the order in which their keys were
+/// generated. The fingerprint is the 64-bit FNV-1a hash of the BSON
+/// encoding of the canonical form, so it's stable across platforms,
+/// Rust versions and runs of the program, and it can be stored in the
+/// database alongside the validator.
+///
+/// Numbers are hashed along with their BSON type, so e.g. `1` as an
+/// `int` and `1.0` as a `double` result in different fingerprints.
+pub fn fingerprint(schema: &Document) -> u64 {
+    /// The FNV-1a 64-bit offset basis.
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    /// The FNV-1a 64-bit prime.
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut buf = Vec::new();
+
+    bson::encode_document(&mut buf, &canonicalize(schema))
+        .expect("a schema document can always be encoded");
+
+    buf.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,129 @@
+//! Building MongoDB commands which install a type's schema as the
+//! validator of a collection.
+
+use bson::Document;
+use recursive::flatten_refs;
+use BsonSchema;
+
+/// Determines how strictly MongoDB applies validation rules to existing
+/// documents during updates. Corresponds to the `validationLevel` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ValidationLevel {
+    /// No validation for inserts or updates.
+    Off,
+    /// Validation of all inserts and updates. The default.
+    #[default]
+    Strict,
+    /// Validation of inserts, and of updates to existing valid documents.
+    Moderate,
+}
+
+impl ValidationLevel {
+    /// Returns the string representation expected by MongoDB.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidationLevel::Off      => "off",
+            ValidationLevel::Strict   => "strict",
+            ValidationLevel::Moderate => "moderate",
+        }
+    }
+}
+
+/// Determines what MongoDB does with invalid documents.
+/// Corresponds to the `validationAction` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ValidationAction {
+    /// Reject documents that violate the validation rules. The default.
+    #[default]
+    Error,
+    /// Accept invalid documents, but log a warning about them.
+    Warn,
+}
+
+impl ValidationAction {
+    /// Returns the string representation expected by MongoDB.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidationAction::Error => "error",
+            ValidationAction::Warn  => "warn",
+        }
+    }
+}
+
+/// Builder for `create` and `collMod` commands that set up
+/// the schema of a type as the validator of a collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValidatorOptions {
+    /// The `validationLevel` of the collection.
+    pub level: ValidationLevel,
+    /// The `validationAction` of the collection.
+    pub action: ValidationAction,
+    /// How many times recursive `$ref`s are inlined by `flatten_refs()`,
+    /// as MongoDB doesn't support `$ref` in validators.
+    pub recursion_limit: usize,
+}
+
+impl ValidatorOptions {
+    /// Creates options with MongoDB's defaults: strict validation with errors.
+    pub fn new() -> Self {
+        ValidatorOptions::default()
+    }
+
+    /// Sets the validation level.
+    pub fn level(mut self, level: ValidationLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets the validation action.
+    pub fn action(mut self, action: ValidationAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Sets how deeply recursive types are expanded.
+    pub fn recursion_limit(mut self, recursion_limit: usize) -> Self {
+        self.recursion_limit = recursion_limit;
+        self
+    }
+
+    /// Returns a `create` command for a new collection named `collection`,
+    /// with the schema of `T` as its validator.
+    pub fn create_collection_command<T>(&self, collection: &str) -> Document
+        where T: ?Sized + BsonSchema
+    {
+        self.command::<T>("create", collection)
+    }
+
+    /// Returns a `collMod` command for the existing collection named
+    /// `collection`, replacing its validator with the schema of `T`.
+    pub fn coll_mod_command<T>(&self, collection: &str) -> Document
+        where T: ?Sized + BsonSchema
+    {
+        self.command::<T>("collMod", collection)
+    }
+
+    /// Returns a command named `command_name`, with the validator options.
+    fn command<T>(&self, command_name: &str, collection: &str) -> Document
+        where T: ?Sized + BsonSchema
+    {
+        let schema = flatten_refs(T::bson_schema(), self.recursion_limit);
+
+        doc! {
+            command_name: collection,
+            "validator": { "$jsonSchema": schema },
+            "validationLevel": self.level.as_str(),
+            "validationAction": self.action.as_str(),
+        }
+    }
+}
+
+impl Default for ValidatorOptions {
+    fn default() -> Self {
+        ValidatorOptions {
+            level: ValidationLevel::default(),
+            action: ValidationAction::default(),
+            recursion_limit: 8,
+        }
+    }
+}
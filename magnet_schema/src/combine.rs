@@ -0,0 +1,121 @@
+//! Building schemas out of other schemas.
+//!
+//! The `all_of()`, `any_of()`, `one_of()` and `not()` functions wrap
+//! their arguments in the corresponding keyword. Arguments consisting
+//! solely of the same keyword are inlined, so that composition doesn't
+//! result in needless nesting: `any_of(&[any_of(&[a, b]), c])` is the
+//! same as `any_of(&[a, b, c])`. (This is not done for `one_of()`, since
+//! "exactly one" is not associative.)
+//!
+//! `merge()` combines two object schemas into one, which is usually
+//! more readable than `all_of()`, and it's what MongoDB error messages
+//! are clearest about.
+
+use bson::{ Bson, Document };
+
+/// Returns a schema matching values that match all of `schemas`.
+pub fn all_of(schemas: &[Document]) -> Document {
+    doc!{ "allOf": flatten("allOf", schemas) }
+}
+
+/// Returns a schema matching values that match at least one of `schemas`.
+pub fn any_of(schemas: &[Document]) -> Document {
+    doc!{ "anyOf": flatten("anyOf", schemas) }
+}
+
+/// Returns a schema matching values that match exactly one of `schemas`.
+pub fn one_of(schemas: &[Document]) -> Document {
+    doc!{ "oneOf": schemas.iter().cloned().map(Bson::from).collect::<Vec<_>>() }
+}
+
+/// Returns a schema matching values that don't match `schema`.
+/// Double negation is eliminated.
+pub fn not(schema: &Document) -> Document {
+    match only_keyword(schema, "not") {
+        Some(Bson::Document(inner)) => inner.clone(),
+        _ => doc!{ "not": schema.clone() },
+    }
+}
+
+/// Deep-merges `overlay` into `base`. The rules are as follows:
+///
+/// * `properties`, `patternProperties` and `definitions` are merged
+///   key by key; subschemas present in both are merged recursively.
+/// * `required` becomes the union of the two lists, without duplicates.
+///   It is omitted if the union is empty.
+/// * For every other keyword, the value in `overlay` replaces the one
+///   in `base`, if any.
+///
+/// For example, merging `{ "properties": { "age": { "minimum": 18 } } }`
+/// into the schema of a struct tightens the constraint on the `age` field
+/// while leaving its type and every other field untouched.
+pub fn merge(base: Document, overlay: Document) -> Document {
+    let mut result = base;
+
+    for (key, value) in overlay {
+        let merged = match (key.as_str(), result.remove(&key), value) {
+            ("properties", Some(Bson::Document(lhs)), Bson::Document(rhs)) |
+            ("patternProperties", Some(Bson::Document(lhs)), Bson::Document(rhs)) |
+            ("definitions", Some(Bson::Document(lhs)), Bson::Document(rhs)) => {
+                Bson::Document(merge_subschemas(lhs, rhs))
+            },
+            ("required", Some(Bson::Array(mut lhs)), Bson::Array(rhs)) => {
+                for name in rhs {
+                    if !lhs.contains(&name) {
+                        lhs.push(name);
+                    }
+                }
+                Bson::Array(lhs)
+            },
+            (_, _, other) => other,
+        };
+
+        result.insert_bson(key, merged);
+    }
+
+    if result.get_array("required").is_ok_and(Vec::is_empty) {
+        result.remove("required");
+    }
+
+    result
+}
+
+/// Merges two maps of named subschemas.
+fn merge_subschemas(base: Document, overlay: Document) -> Document {
+    let mut result = base;
+
+    for (name, value) in overlay {
+        let merged = match (result.remove(&name), value) {
+            (Some(Bson::Document(lhs)), Bson::Document(rhs)) => Bson::Document(merge(lhs, rhs)),
+            (_, other) => other,
+        };
+
+        result.insert_bson(name, merged);
+    }
+
+    result
+}
+
+/// Collects `schemas` into an array, inlining the elements of those
+/// which consist of nothing but an array under `keyword`.
+fn flatten(keyword: &str, schemas: &[Document]) -> Vec<Bson> {
+    let mut result = Vec::with_capacity(schemas.len());
+
+    for schema in schemas {
+        match only_keyword(schema, keyword) {
+            Some(Bson::Array(items)) => result.extend(items.iter().cloned()),
+            _ => result.push(schema.clone().into()),
+        }
+    }
+
+    result
+}
+
+/// Returns the value of `keyword` if `schema` has no other keys.
+fn only_keyword<'a>(schema: &'a Document, keyword: &str) -> Option<&'a Bson> {
+    if schema.len() == 1 {
+        schema.get(keyword)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,153 @@
+//! Rewriting schemas for older MongoDB versions.
+//!
+//! `$jsonSchema` implements most of JSON Schema draft-04, but it rejects
+//! validators containing keywords it doesn't know, and some releases are
+//! stricter than others. `downlevel()` rewrites a schema so that it only
+//! uses what a given `CompatLevel` understands. Schemas generated with the
+//! `compat_level` option set are passed through it automatically.
+//!
+//! At `CompatLevel::Mongo42` and below:
+//!
+//! * `$schema`, `$id`, `id`, `$comment`, `default`, `examples`, `format`,
+//!   `readOnly`, `writeOnly`, `contentEncoding` and `contentMediaType` are
+//!   removed. They are annotations, so this doesn't change what is valid.
+//! * `const` is rewritten as a single-item `enum`.
+//! * `"integer"` in `type` is rewritten as `bsonType: ["int", "long"]`.
+//! * Numeric `exclusiveMinimum` and `exclusiveMaximum` are rewritten as
+//!   boolean flags next to `minimum` and `maximum`.
+//! * `contains`, `propertyNames`, `if`, `then` and `else` are removed. This
+//!   makes the schema more permissive, since MongoDB can't express them.
+//!
+//! At `CompatLevel::Mongo36`, additionally:
+//!
+//! * Empty `required` arrays are removed, since 3.6 and 4.0 reject them.
+//! * `uniqueItems` is removed, since it has been reported to be rejected by
+//!   some 3.6 deployments. This makes the schema more permissive.
+//!
+//! `$ref` and `definitions` are left alone; use `flatten_refs()` on the
+//! root schema to inline them first.
+
+use bson::{ Bson, Document };
+use config::CompatLevel;
+use convert::to_bounds_dialect;
+use support::BoundsDialect;
+
+/// Annotations and other keywords which `$jsonSchema` doesn't support,
+/// and which are removed below `CompatLevel::Latest`.
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "$schema", "$id", "id", "$comment", "default", "examples", "format",
+    "readOnly", "writeOnly", "contentEncoding", "contentMediaType",
+    "contains", "propertyNames", "if", "then", "else",
+];
+
+/// Rewrites `schema` so that it's accepted by the MongoDB versions of
+/// `level`, as documented at the module level. `CompatLevel::Latest`
+/// leaves the schema unchanged.
+pub fn downlevel(schema: Document, level: CompatLevel) -> Document {
+    match level {
+        CompatLevel::Latest => schema,
+        CompatLevel::Mongo42 | CompatLevel::Mongo36 => {
+            downlevel_doc(to_bounds_dialect(schema, BoundsDialect::Boolean), level)
+        },
+    }
+}
+
+/// Implementation of `downlevel()`, for a single (sub)schema.
+fn downlevel_doc(schema: Document, level: CompatLevel) -> Document {
+    let mut doc: Document = schema
+        .into_iter()
+        .filter(|(key, _)| !UNSUPPORTED_KEYWORDS.contains(&key.as_str()))
+        .map(|(key, value)| {
+            let converted = downlevel_subschemas(&key, value, level);
+            (key, converted)
+        })
+        .collect();
+
+    if let Some(value) = doc.remove("const") {
+        doc.insert("enum", vec![value]);
+    }
+
+    downlevel_integer_type(&mut doc);
+
+    if level == CompatLevel::Mongo36 {
+        doc.remove("uniqueItems");
+
+        if doc.get_array("required").is_ok_and(Vec::is_empty) {
+            doc.remove("required");
+        }
+    }
+
+    doc
+}
+
+/// Recursively rewrites the subschemas under the keyword `key`, if any.
+fn downlevel_subschemas(key: &str, subschemas: Bson, level: CompatLevel) -> Bson {
+    match (key, subschemas) {
+        ("properties", Bson::Document(doc)) |
+        ("patternProperties", Bson::Document(doc)) |
+        ("definitions", Bson::Document(doc)) |
+        ("dependencies", Bson::Document(doc)) => {
+            let converted: Document = doc
+                .into_iter()
+                .map(|(name, subschema)| (name, downlevel_subschema(subschema, level)))
+                .collect();
+
+            converted.into()
+        },
+        ("items", Bson::Array(array)) |
+        ("anyOf", Bson::Array(array)) |
+        ("oneOf", Bson::Array(array)) |
+        ("allOf", Bson::Array(array)) => {
+            array.into_iter().map(|item| downlevel_subschema(item, level)).collect::<Vec<_>>().into()
+        },
+        ("items", value) |
+        ("additionalItems", value) |
+        ("additionalProperties", value) |
+        ("not", value) => downlevel_subschema(value, level),
+        (_, value) => value,
+    }
+}
+
+/// Rewrites a value if it's a schema document, otherwise returns it unchanged.
+fn downlevel_subschema(value: Bson, level: CompatLevel) -> Bson {
+    match value {
+        Bson::Document(doc) => downlevel_doc(doc, level).into(),
+        other => other,
+    }
+}
+
+/// Replaces `"integer"` in `type` by `bsonType: ["int", "long"]`, which
+/// `$jsonSchema` doesn't support. Other JSON types are moved to `bsonType`
+/// as well, since `type` and `bsonType` can't be combined.
+fn downlevel_integer_type(doc: &mut Document) {
+    let types: Vec<String> = match doc.get("type") {
+        Some(Bson::String(ty)) => vec![ty.clone()],
+        Some(Bson::Array(array)) => array.iter().filter_map(|ty| ty.as_str().map(String::from)).collect(),
+        _ => return,
+    };
+
+    if !types.iter().any(|ty| ty == "integer") {
+        return;
+    }
+
+    let mut bson_types: Vec<Bson> = Vec::with_capacity(types.len() + 1);
+
+    for ty in &types {
+        let aliases: &[&str] = match ty.as_str() {
+            "integer" => &["int", "long"],
+            "boolean" => &["bool"],
+            other => &[other][..],
+        };
+
+        for &alias in aliases {
+            let item = Bson::from(alias);
+
+            if !bson_types.contains(&item) {
+                bson_types.push(item);
+            }
+        }
+    }
+
+    doc.remove("type");
+    doc.insert("bsonType", bson_types);
+}
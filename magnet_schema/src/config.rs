@@ -0,0 +1,254 @@
+//! Options affecting schema generation globally, as opposed to
+//! attributes, which only affect a single type or field.
+
+use support::BoundsDialect;
+
+/// Options for generating schemas, passed to
+/// `BsonSchema::bson_schema_with_options()`. The `Default`
+/// value results in the same schemas as `bson_schema()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SchemaOptions {
+    /// Whether structs allow properties other than their fields.
+    /// `false` by default, i.e. `"additionalProperties": false`.
+    pub additional_properties: bool,
+    /// How the schema of `Option<T>` describes `null`.
+    pub nullability: Nullability,
+    /// How exclusive bounds of numeric fields are expressed.
+    pub bounds_dialect: BoundsDialect,
+    /// How `uuid::Uuid` is represented in documents.
+    pub uuid_representation: UuidRepresentation,
+    /// How `()`, unit structs and unit variants are described.
+    pub unit_representation: UnitRepresentation,
+    /// Whether derived schemas carry the doc comments of types, fields and
+    /// variants as their `"description"`. `true` by default.
+    pub include_descriptions: bool,
+    /// How `u64`, and `usize` on 64-bit targets, are described.
+    pub u64_policy: U64Policy,
+    /// Which BSON types `f32` and `f64` accept.
+    pub float_policy: FloatPolicy,
+    /// Whether named types, e.g. derived ones, other than the root type are
+    /// described only once, under `definitions`, and referred to by `$ref`s.
+    /// `false` by default, since MongoDB doesn't support `$ref`s.
+    pub share_definitions: bool,
+    /// The MongoDB versions the schema has to be accepted by.
+    pub compat_level: CompatLevel,
+    /// What happens to property and definition names which MongoDB doesn't
+    /// allow as field names, i.e. ones starting with `$` or containing `.`.
+    pub key_policy: KeyPolicy,
+    /// Whether types are described by `type`, by `bsonType`, or by whichever
+    /// is more natural for each of them. The latter by default.
+    pub key_style: KeyStyle,
+}
+
+impl Default for SchemaOptions {
+    fn default() -> Self {
+        SchemaOptions {
+            additional_properties: false,
+            nullability: Nullability::default(),
+            bounds_dialect: BoundsDialect::default(),
+            uuid_representation: UuidRepresentation::default(),
+            unit_representation: UnitRepresentation::default(),
+            include_descriptions: true,
+            u64_policy: U64Policy::default(),
+            float_policy: FloatPolicy::default(),
+            share_definitions: false,
+            compat_level: CompatLevel::default(),
+            key_policy: KeyPolicy::default(),
+            key_style: KeyStyle::default(),
+        }
+    }
+}
+
+impl SchemaOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        SchemaOptions::default()
+    }
+
+    /// Sets whether structs allow properties other than their fields.
+    pub fn additional_properties(mut self, allowed: bool) -> Self {
+        self.additional_properties = allowed;
+        self
+    }
+
+    /// Sets how the schema of `Option<T>` describes `null`.
+    pub fn nullability(mut self, nullability: Nullability) -> Self {
+        self.nullability = nullability;
+        self
+    }
+
+    /// Sets how exclusive bounds of numeric fields are expressed.
+    pub fn bounds_dialect(mut self, dialect: BoundsDialect) -> Self {
+        self.bounds_dialect = dialect;
+        self
+    }
+
+    /// Sets how `uuid::Uuid` is represented in documents.
+    pub fn uuid_representation(mut self, representation: UuidRepresentation) -> Self {
+        self.uuid_representation = representation;
+        self
+    }
+
+    /// Sets how `()`, unit structs and unit variants are described.
+    pub fn unit_representation(mut self, representation: UnitRepresentation) -> Self {
+        self.unit_representation = representation;
+        self
+    }
+
+    /// Sets whether derived schemas carry descriptions.
+    pub fn include_descriptions(mut self, include: bool) -> Self {
+        self.include_descriptions = include;
+        self
+    }
+
+    /// Sets how `u64`, and `usize` on 64-bit targets, are described.
+    pub fn u64_policy(mut self, policy: U64Policy) -> Self {
+        self.u64_policy = policy;
+        self
+    }
+
+    /// Sets which BSON types `f32` and `f64` accept.
+    pub fn float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Sets whether repeated named types are shared by means of `$ref`s.
+    pub fn share_definitions(mut self, share: bool) -> Self {
+        self.share_definitions = share;
+        self
+    }
+
+    /// Sets the MongoDB versions the schema has to be accepted by.
+    pub fn compat_level(mut self, level: CompatLevel) -> Self {
+        self.compat_level = level;
+        self
+    }
+
+    /// Sets what happens to names which MongoDB doesn't allow as field names.
+    pub fn key_policy(mut self, policy: KeyPolicy) -> Self {
+        self.key_policy = policy;
+        self
+    }
+
+    /// Sets which keyword describes the types of values.
+    pub fn key_style(mut self, style: KeyStyle) -> Self {
+        self.key_style = style;
+        self
+    }
+}
+
+/// The ways the schema of `Option<T>` can describe `null`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Nullability {
+    /// Add `"null"` to the `type` or `bsonType` of `T`, e.g.
+    /// `{ "type": ["string", "null"] }`, if possible. The default.
+    #[default]
+    TypeArray,
+    /// Add a `{ "type": "null" }` alternative, e.g.
+    /// `{ "anyOf": [{ "type": "string" }, { "type": "null" }] }`.
+    AnyOf,
+}
+
+/// The ways a `uuid::Uuid` may be stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UuidRepresentation {
+    /// A hyphenated, hexadecimal string, as produced by `Serialize`. The default.
+    #[default]
+    String,
+    /// Binary data, e.g. when stored as a BSON UUID.
+    Binary,
+}
+
+/// The ways the schema of the unit type `()` may describe its values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UnitRepresentation {
+    /// `null` or an empty array, i.e.
+    /// `{ "type": ["array", "null"], "maxItems": 0 }`. The default.
+    #[default]
+    Permissive,
+    /// Only `null`, which is how both `bson` and `serde_json`
+    /// serialize `()`, i.e. `{ "type": "null" }`.
+    Null,
+}
+
+/// The ways of describing `u64` (and `usize` on 64-bit targets), whose
+/// range exceeds that of the largest BSON integer type, `long` (`i64`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum U64Policy {
+    /// An `int` or a `long` between 0 and `i64::MAX`, i.e. the values which
+    /// the `bson` crate can store without loss. Larger values are rejected
+    /// by the validator, even though they are valid in Rust. The default.
+    #[default]
+    ClampToI64Max,
+    /// An `int` or a `long` without bounds, for data written by converting
+    /// `u64` to `i64` using a wrapping cast (`as i64`), which represents
+    /// values above `i64::MAX` as negative numbers. Since every `long` is
+    /// the image of some `u64`, there are no bounds to express.
+    AllowNegativeWraparound,
+    /// A `decimal` (Decimal128), which can store every `u64` exactly,
+    /// i.e. `{ "bsonType": "decimal" }`. The bounds are omitted, because
+    /// they can't be expressed as decimals in a schema.
+    AsDecimal,
+}
+
+/// The BSON types accepted where a floating-point number is expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FloatPolicy {
+    /// A `double`, an `int` or a `long`, i.e.
+    /// `{ "bsonType": ["double", "int", "long"] }`. Integers are accepted
+    /// because they deserialize into floats just fine, and other drivers
+    /// (or the shell) commonly store whole numbers as such. The default.
+    #[default]
+    AcceptIntegers,
+    /// A `double` only, i.e. `{ "bsonType": "double" }`, for data which is
+    /// known to be normalized.
+    StrictDouble,
+}
+
+/// The oldest MongoDB version which generated schemas have to be accepted by.
+/// See the `compat` module for the rewrites performed at each level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CompatLevel {
+    /// MongoDB 3.6 and 4.0, the first versions supporting `$jsonSchema`.
+    Mongo36,
+    /// MongoDB 4.2 and later, without any keywords `$jsonSchema` lacks.
+    Mongo42,
+    /// No rewriting at all; the schema is left as generated. The default.
+    #[default]
+    Latest,
+}
+
+/// What to do with property names, and names of `definitions`, that start
+/// with `$` or contain `.`, which MongoDB only allows in field names since 5.0.
+/// Derived types already reject such names at compile time, unless they are
+/// marked `#[magnet(relaxed_keys)]`; this applies to the whole schema, e.g.
+/// to hand-written and flattened properties, when it's generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum KeyPolicy {
+    /// Fail with `SchemaError::IllegalKey`.
+    Error,
+    /// Log a warning and keep the name. The warning goes through the `log`
+    /// crate if the `log` feature is enabled, and to stderr otherwise.
+    Warn,
+    /// Keep the name silently. The default.
+    #[default]
+    Allow,
+}
+
+/// Which keyword describes the types of values: the standard `type`, or
+/// MongoDB's `bsonType`. A type is only rewritten from one keyword to the
+/// other if the result accepts exactly the same values, so e.g. `objectId`
+/// and `int` stay `bsonType`s even with `KeyStyle::TypeKey`. See
+/// `convert::to_key_style()` for the mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum KeyStyle {
+    /// `type` for JSON types, e.g. strings and objects, and `bsonType`
+    /// for the rest, e.g. `int` and `objectId`. The default.
+    #[default]
+    Mixed,
+    /// `type` wherever possible.
+    TypeKey,
+    /// `bsonType` everywhere.
+    BsonTypeKey,
+}
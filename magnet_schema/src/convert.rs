@@ -0,0 +1,404 @@
+//! Conversion of MongoDB-flavored schemas to standard JSON Schema (draft-07).
+//!
+//! The MongoDB `$jsonSchema` dialect differs from the standard in that it
+//! uses the `bsonType` keyword for BSON-specific types (`int`, `long`,
+//! `objectId`, `date`, etc.), and in that its `exclusiveMinimum` and
+//! `exclusiveMaximum` keywords are booleans modifying `minimum` and
+//! `maximum`, as opposed to being numeric bounds themselves.
+
+use bson::{ Bson, Document };
+use support::BoundsDialect;
+use config::KeyStyle;
+
+/// The URI of the JSON Schema draft-07 meta-schema.
+pub const JSON_SCHEMA_DRAFT_07: &str = "http://json-schema.org/draft-07/schema#";
+
+/// Pattern for the 24-digit hexadecimal string representation of an `ObjectId`.
+const OBJECT_ID_PATTERN: &str = "^[0-9a-fA-F]{24}$";
+
+/// Converts a MongoDB-flavored schema into a standard JSON Schema (draft-07)
+/// document. The result is annotated with the top-level `$schema` keyword.
+pub fn to_json_schema(schema: Document) -> Document {
+    let mut doc = doc!{ "$schema": JSON_SCHEMA_DRAFT_07 };
+
+    for (key, value) in to_json_subschema(schema) {
+        doc.insert_bson(key, value);
+    }
+
+    doc
+}
+
+/// Like `to_json_schema()`, but doesn't insert the `$schema` keyword,
+/// so the result is suitable for embedding into other schemas.
+pub fn to_json_subschema(schema: Document) -> Document {
+    let mut doc: Document = schema
+        .into_iter()
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value, to_json_subschema);
+            (key, converted)
+        })
+        .collect();
+
+    convert_bson_type(&mut doc);
+    convert_exclusive_bound(&mut doc, "minimum", "exclusiveMinimum");
+    convert_exclusive_bound(&mut doc, "maximum", "exclusiveMaximum");
+
+    doc
+}
+
+/// Recursively rewrites every `exclusiveMinimum` and `exclusiveMaximum` in
+/// a schema so that they follow the given convention, leaving everything
+/// else intact. MongoDB only understands `BoundsDialect::Boolean`, while
+/// JSON Schema draft-06 and later require `BoundsDialect::Numeric`.
+pub fn to_bounds_dialect(schema: Document, dialect: BoundsDialect) -> Document {
+    match dialect {
+        BoundsDialect::Boolean => to_boolean_bounds(schema),
+        BoundsDialect::Numeric => to_numeric_bounds(schema),
+    }
+}
+
+/// Implementation of `to_bounds_dialect(_, BoundsDialect::Boolean)`.
+fn to_boolean_bounds(schema: Document) -> Document {
+    let mut doc: Document = schema
+        .into_iter()
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value, to_boolean_bounds);
+            (key, converted)
+        })
+        .collect();
+
+    convert_numeric_bound(&mut doc, "minimum", "exclusiveMinimum");
+    convert_numeric_bound(&mut doc, "maximum", "exclusiveMaximum");
+
+    doc
+}
+
+/// Implementation of `to_bounds_dialect(_, BoundsDialect::Numeric)`.
+fn to_numeric_bounds(schema: Document) -> Document {
+    let mut doc: Document = schema
+        .into_iter()
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value, to_numeric_bounds);
+            (key, converted)
+        })
+        .collect();
+
+    convert_exclusive_bound(&mut doc, "minimum", "exclusiveMinimum");
+    convert_exclusive_bound(&mut doc, "maximum", "exclusiveMaximum");
+
+    doc
+}
+
+/// The BSON types which together are the same as the JSON `number` type.
+const NUMBER_BSON_TYPES: &[&str] = &["double", "int", "long", "decimal"];
+
+/// Recursively rewrites the `type` and `bsonType` keywords of a schema
+/// according to `style`, leaving everything else intact.
+///
+/// With `KeyStyle::TypeKey`, a `bsonType` becomes a `type` if each of its
+/// BSON types has a JSON equivalent: `string`, `object`, `array`, `bool`
+/// (`boolean`), `null`, or the four numeric types `double`, `int`, `long`
+/// and `decimal` together (`number`). With `KeyStyle::BsonTypeKey`, every
+/// `type` becomes a `bsonType`, the other way around; the JSON `integer`
+/// becomes `int` and `long`. Schemas having both keywords, or types not
+/// listed here, are left alone.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate bson;
+/// # extern crate magnet_schema;
+/// # use magnet_schema::KeyStyle;
+/// # use magnet_schema::convert::to_key_style;
+/// # fn main() {
+/// let schema = doc!{ "bsonType": ["string", "null"] };
+/// assert_eq!(to_key_style(schema, KeyStyle::TypeKey), doc!{ "type": ["string", "null"] });
+///
+/// let schema = doc!{ "type": "number" };
+/// assert_eq!(
+///     to_key_style(schema, KeyStyle::BsonTypeKey),
+///     doc!{ "bsonType": ["double", "int", "long", "decimal"] }
+/// );
+///
+/// let schema = doc!{ "bsonType": "objectId" };
+/// assert_eq!(to_key_style(schema.clone(), KeyStyle::TypeKey), schema);
+/// # }
+/// ```
+pub fn to_key_style(schema: Document, style: KeyStyle) -> Document {
+    match style {
+        KeyStyle::Mixed => schema,
+        KeyStyle::TypeKey => to_type_key(schema),
+        KeyStyle::BsonTypeKey => to_bson_type_key(schema),
+    }
+}
+
+/// Implementation of `to_key_style(_, KeyStyle::TypeKey)`.
+fn to_type_key(schema: Document) -> Document {
+    let has_both = schema.contains_key("type") && schema.contains_key("bsonType");
+
+    schema
+        .into_iter()
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value, to_type_key);
+
+            match json_types(&converted) {
+                Some(types) if key == "bsonType" && !has_both => (String::from("type"), types),
+                _ => (key, converted),
+            }
+        })
+        .collect()
+}
+
+/// Implementation of `to_key_style(_, KeyStyle::BsonTypeKey)`.
+fn to_bson_type_key(schema: Document) -> Document {
+    let has_both = schema.contains_key("type") && schema.contains_key("bsonType");
+
+    schema
+        .into_iter()
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value, to_bson_type_key);
+
+            match bson_types(&converted) {
+                Some(types) if key == "type" && !has_both => (String::from("bsonType"), types),
+                _ => (key, converted),
+            }
+        })
+        .collect()
+}
+
+/// Returns the names in the value of a `type` or `bsonType` keyword, or
+/// `None` if it's neither a string nor an array of strings.
+fn type_names(value: &Bson) -> Option<Vec<&str>> {
+    match *value {
+        Bson::String(ref name) => Some(vec![name.as_str()]),
+        Bson::Array(ref array) => array.iter().map(Bson::as_str).collect(),
+        _ => None,
+    }
+}
+
+/// Converts a list of type names to the value of a `type` or `bsonType`
+/// keyword: the only name itself, or an array of them.
+fn type_value(mut names: Vec<&str>) -> Bson {
+    if names.len() == 1 {
+        Bson::from(names.remove(0))
+    } else {
+        names.into_iter().map(Bson::from).collect::<Vec<_>>().into()
+    }
+}
+
+/// Returns the JSON equivalent of the value of a `bsonType` keyword, if
+/// it accepts exactly the same values.
+fn json_types(value: &Bson) -> Option<Bson> {
+    let names = type_names(value)?;
+    let mut types = Vec::with_capacity(names.len());
+    let has_number = NUMBER_BSON_TYPES.iter().all(|number| names.contains(number));
+
+    for name in names {
+        let json_type = match name {
+            "string" | "object" | "array" | "null" => name,
+            "bool" => "boolean",
+            _ if has_number && NUMBER_BSON_TYPES.contains(&name) => "number",
+            _ => return None,
+        };
+
+        if !types.contains(&json_type) {
+            types.push(json_type);
+        }
+    }
+
+    Some(type_value(types))
+}
+
+/// Returns the BSON equivalent of the value of a `type` keyword.
+fn bson_types(value: &Bson) -> Option<Bson> {
+    let names = type_names(value)?;
+    let mut types = Vec::with_capacity(names.len());
+
+    for name in names {
+        let bson_types: &[&str] = match name {
+            "string" | "object" | "array" | "null" => &[name],
+            "boolean" => &["bool"],
+            "number" => NUMBER_BSON_TYPES,
+            "integer" => &["int", "long"],
+            _ => return None,
+        };
+
+        for &bson_type in bson_types {
+            if !types.contains(&bson_type) {
+                types.push(bson_type);
+            }
+        }
+    }
+
+    Some(type_value(types))
+}
+
+/// Recursively converts the subschemas under the keyword `key`, if any.
+fn convert_subschemas(key: &str, subschemas: Bson, convert: fn(Document) -> Document) -> Bson {
+    match (key, subschemas) {
+        ("properties", Bson::Document(doc)) |
+        ("patternProperties", Bson::Document(doc)) |
+        ("definitions", Bson::Document(doc)) => {
+            let converted: Document = doc
+                .into_iter()
+                .map(|(name, subschema)| (name, convert_subschema(subschema, convert)))
+                .collect();
+
+            converted.into()
+        },
+        ("items", Bson::Array(array)) |
+        ("anyOf", Bson::Array(array)) |
+        ("oneOf", Bson::Array(array)) |
+        ("allOf", Bson::Array(array)) => {
+            array.into_iter().map(|item| convert_subschema(item, convert)).collect::<Vec<_>>().into()
+        },
+        ("items", value) |
+        ("additionalItems", value) |
+        ("additionalProperties", value) |
+        ("not", value) => convert_subschema(value, convert),
+        (_, value) => value,
+    }
+}
+
+/// Converts a value if it's a schema document, otherwise returns it unchanged.
+fn convert_subschema(value: Bson, convert: fn(Document) -> Document) -> Bson {
+    match value {
+        Bson::Document(doc) => convert(doc).into(),
+        other => other,
+    }
+}
+
+/// Maps a BSON type alias to a standard JSON type and an optional format.
+/// Returns `None` if the BSON type has no reasonable JSON representation.
+fn json_type_and_format(bson_type: &str) -> Option<(&'static str, Option<&'static str>)> {
+    let pair = match bson_type {
+        "double"     => ("number", Some("double")),
+        "decimal"    => ("number", None),
+        "number"     => ("number", None),
+        "int"        => ("integer", Some("int32")),
+        "long"       => ("integer", Some("int64")),
+        "timestamp"  => ("integer", None),
+        "bool"       => ("boolean", None),
+        "boolean"    => ("boolean", None),
+        "string"     => ("string", None),
+        "javascript" => ("string", None),
+        "objectId"   => ("string", None),
+        "date"       => ("string", Some("date-time")),
+        "binData"    => ("string", Some("byte")),
+        "regex"      => ("string", Some("regex")),
+        "object"     => ("object", None),
+        "array"      => ("array", None),
+        "null"       => ("null", None),
+        _ => return None,
+    };
+
+    Some(pair)
+}
+
+/// Replaces the `bsonType` keyword with the equivalent standard `type`
+/// keyword, adding a `format` or a `pattern` where it is unambiguous.
+fn convert_bson_type(doc: &mut Document) {
+    let mut bson_types = Vec::new();
+
+    for key in &["type", "bsonType"] {
+        match doc.remove(key) {
+            Some(Bson::String(name)) => bson_types.push(name),
+            Some(Bson::Array(array)) => bson_types.extend(array.into_iter().filter_map(
+                |item| match item {
+                    Bson::String(name) => Some(name),
+                    _ => None,
+                }
+            )),
+            Some(other) => {
+                // not something we understand, leave it alone
+                doc.insert_bson(key.to_string(), other);
+                return;
+            },
+            None => {},
+        }
+    }
+
+    if bson_types.is_empty() {
+        return;
+    }
+
+    let mut types = Vec::new();
+    let mut formats = Vec::new();
+
+    for bson_type in &bson_types {
+        let (json_type, format) = match json_type_and_format(bson_type) {
+            Some(pair) => pair,
+            None => return, // can't express this, so don't constrain the type at all
+        };
+
+        if !types.contains(&json_type) {
+            types.push(json_type);
+        }
+
+        if json_type != "null" && !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+
+    // JSON `number` subsumes `integer`, so e.g. the `["double", "int", "long"]`
+    // of floats becomes a plain `number` without a format.
+    if types.contains(&"number") && types.contains(&"integer") {
+        types.retain(|&json_type| json_type != "integer");
+        formats.clear();
+        formats.push(None);
+    }
+
+    if types.len() == 1 {
+        doc.insert("type", types[0]);
+    } else {
+        doc.insert("type", types.into_iter().map(Bson::from).collect::<Vec<_>>());
+    }
+
+    if formats.len() == 1 && !doc.contains_key("format") {
+        if let Some(format) = formats[0] {
+            doc.insert("format", format);
+        }
+    }
+
+    let is_object_id = bson_types.iter().any(|t| t == "objectId")
+                       &&
+                       bson_types.iter().all(|t| t == "objectId" || t == "null");
+
+    if is_object_id && !doc.contains_key("pattern") {
+        doc.insert("pattern", OBJECT_ID_PATTERN);
+    }
+}
+
+/// Converts a MongoDB-style boolean `exclusiveMinimum` or `exclusiveMaximum`
+/// to its draft-07 equivalent, which holds the bound itself.
+fn convert_exclusive_bound(doc: &mut Document, bound_key: &str, exclusive_key: &str) {
+    match doc.remove(exclusive_key) {
+        Some(Bson::Boolean(true)) => {
+            if let Some(bound) = doc.remove(bound_key) {
+                doc.insert_bson(exclusive_key.into(), bound);
+            }
+        },
+        Some(Bson::Boolean(false)) | None => {},
+        Some(bound) => {
+            // already in draft-07 form
+            doc.insert_bson(exclusive_key.into(), bound);
+        },
+    }
+}
+
+/// Converts a draft-06+ numeric `exclusiveMinimum` or `exclusiveMaximum`
+/// to the MongoDB (and draft-04) form, which is a boolean flag modifying
+/// `minimum` or `maximum`. If both an inclusive and an exclusive bound are
+/// present, the exclusive one takes precedence.
+fn convert_numeric_bound(doc: &mut Document, bound_key: &str, exclusive_key: &str) {
+    match doc.remove(exclusive_key) {
+        Some(Bson::Boolean(flag)) => {
+            // already in boolean form
+            doc.insert(exclusive_key, flag);
+        },
+        Some(bound) => {
+            doc.insert_bson(bound_key.into(), bound);
+            doc.insert(exclusive_key, true);
+        },
+        None => {},
+    }
+}
@@ -0,0 +1,146 @@
+//! Collecting the schemas of several named types into a single document.
+
+use std::fmt;
+use std::error::Error;
+use std::any::TypeId;
+use std::collections::HashMap;
+use bson::{ Bson, Document };
+use recursive::SchemaContext;
+use error::SchemaError;
+use convert;
+use BsonSchema;
+
+/// Collects the schemas of types into a `definitions` map keyed by their
+/// `schema_name()`, e.g. for exporting the whole surface of an API at once.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    /// The types registered so far, by name, for detecting collisions.
+    types: HashMap<String, TypeId>,
+    /// The schemas of the registered types and their recursive dependencies.
+    definitions: Document,
+}
+
+impl SchemaRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        SchemaRegistry::default()
+    }
+
+    /// Adds the schema of `T` to the definitions. Registering the same type
+    /// more than once is a no-op, but registering two different types with
+    /// the same schema name is an error. So is registering a type whose
+    /// dependencies have a definition different from the one already
+    /// registered under the same name. Errors while generating the schema
+    /// of `T` are reported as well, and nothing is registered then.
+    pub fn register<T>(&mut self) -> Result<&mut Self, RegistryError>
+        where T: ?Sized + BsonSchema + 'static
+    {
+        let name = T::schema_name();
+        let type_id = TypeId::of::<T>();
+
+        match self.types.get(&name) {
+            Some(&id) if id == type_id => return Ok(self),
+            Some(_) => return Err(NameCollisionError { name }.into()),
+            None => {},
+        }
+
+        let mut ctx = SchemaContext::new();
+        let schema = T::bson_schema_with(&mut ctx);
+        let mut finished = ctx.try_finish(schema)?;
+
+        // definitions of recursive types reachable from `T`, then `T` itself
+        let mut definitions = match finished.remove("definitions") {
+            Some(Bson::Document(definitions)) => definitions,
+            _ => Document::new(),
+        };
+        definitions.insert(name.clone(), finished);
+
+        for (key, value) in &definitions {
+            if self.definitions.get(key).is_some_and(|existing| existing != value) {
+                return Err(NameCollisionError { name: key.clone() }.into());
+            }
+        }
+
+        for (key, value) in definitions {
+            self.definitions.insert_bson(key, value);
+        }
+
+        self.types.insert(name, type_id);
+
+        Ok(self)
+    }
+
+    /// Returns the definitions collected so far.
+    pub fn definitions(&self) -> &Document {
+        &self.definitions
+    }
+
+    /// Returns a document of the form `{ "definitions": { ... } }`.
+    pub fn into_document(self) -> Document {
+        doc!{ "definitions": self.definitions }
+    }
+
+    /// Like `into_document()`, but converts the definitions
+    /// to standard JSON Schema (draft-07) first.
+    pub fn into_json_schema(self) -> Document {
+        convert::to_json_schema(self.into_document())
+    }
+}
+
+/// Two different types registered in a `SchemaRegistry` have the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameCollisionError {
+    /// The schema name shared by the colliding types.
+    pub name: String,
+}
+
+impl fmt::Display for NameCollisionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "schema name `{}` is used by more than one type", self.name)
+    }
+}
+
+impl Error for NameCollisionError {
+    fn description(&self) -> &str {
+        "schema name is used by more than one type"
+    }
+}
+
+/// The reason why a type couldn't be added to a `SchemaRegistry`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+    /// Another type has already been registered under the same name.
+    NameCollision(NameCollisionError),
+    /// The schema of the type couldn't be generated.
+    Schema(SchemaError),
+}
+
+impl From<NameCollisionError> for RegistryError {
+    fn from(error: NameCollisionError) -> Self {
+        RegistryError::NameCollision(error)
+    }
+}
+
+impl From<SchemaError> for RegistryError {
+    fn from(error: SchemaError) -> Self {
+        RegistryError::Schema(error)
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegistryError::NameCollision(ref error) => write!(f, "{}", error),
+            RegistryError::Schema(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for RegistryError {
+    fn description(&self) -> &str {
+        match *self {
+            RegistryError::NameCollision(_) => "schema name is used by more than one type",
+            RegistryError::Schema(_) => "schema couldn't be generated",
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! Comparing schemas, e.g. in order to find out whether a change to a
+//! type would make documents already in the database invalid.
+//!
+//! Changes are located using JSON pointers into the *new* schema (or the
+//! old one, for removed parts). Key order, as well as the order of the
+//! `required`, `enum` and `type` arrays, is insignificant.
+
+use std::fmt;
+use std::cmp::Ordering;
+use bson::{ Bson, Document };
+
+/// Keywords which don't affect validation.
+const ANNOTATIONS: &[&str] = &["title", "description", "default", "examples", "$comment", "$schema"];
+
+/// Compares an old and a new version of a schema.
+pub fn diff(old: &Document, new: &Document) -> SchemaDiff {
+    let mut changes = Vec::new();
+    diff_at(old, new, "", &mut changes);
+    SchemaDiff { changes }
+}
+
+/// The list of changes between two versions of a schema.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SchemaDiff {
+    /// The individual changes, in no particular order.
+    pub changes: Vec<Change>,
+}
+
+impl SchemaDiff {
+    /// Returns `true` if the two schemas are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns `true` if some documents valid under the old schema
+    /// may be invalid under the new one.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    /// Returns the changes which may invalidate existing documents.
+    pub fn breaking_changes(&self) -> impl Iterator<Item=&Change> {
+        self.changes.iter().filter(|change| change.breaking)
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return f.write_str("no changes");
+        }
+
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            change.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single change between two versions of a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// JSON pointer to the changed subschema; empty for the root.
+    pub path: String,
+    /// What has changed.
+    pub kind: ChangeKind,
+    /// Whether documents valid under the old schema may become invalid.
+    pub breaking: bool,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}: {}",
+            if self.breaking { "breaking" } else { "compatible" },
+            if self.path.is_empty() { "/" } else { &self.path },
+            self.kind,
+        )
+    }
+}
+
+/// The kinds of changes between two versions of a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// A new property was added to an object.
+    PropertyAdded {
+        /// The name of the property.
+        name: String,
+        /// Whether the property is required.
+        required: bool,
+    },
+    /// A property was removed from an object.
+    PropertyRemoved {
+        /// The name of the property.
+        name: String,
+    },
+    /// An existing property became required.
+    RequiredAdded(String),
+    /// An existing property became optional.
+    RequiredRemoved(String),
+    /// The set of allowed types changed. An empty list means any type.
+    TypeChanged {
+        /// Types allowed by the old schema.
+        old: Vec<String>,
+        /// Types allowed by the new schema.
+        new: Vec<String>,
+    },
+    /// A numeric or length bound (`minimum`, `maximum`, `minItems`,
+    /// or `maxItems`) was added, removed, or changed.
+    BoundChanged {
+        /// The name of the bound.
+        keyword: String,
+        /// The old value of the bound, if any.
+        old: Option<Bson>,
+        /// The new value of the bound, if any.
+        new: Option<Bson>,
+    },
+    /// Values were added to an `enum`.
+    EnumValuesAdded(Vec<Bson>),
+    /// Values were removed from an `enum`.
+    EnumValuesRemoved(Vec<Bson>),
+    /// The `additionalProperties` keyword changed.
+    AdditionalPropertiesChanged {
+        /// The old value, if any.
+        old: Option<Bson>,
+        /// The new value, if any.
+        new: Option<Bson>,
+    },
+    /// A new alternative was added to an `anyOf`.
+    AlternativeAdded(usize),
+    /// An alternative was removed from an `anyOf`.
+    AlternativeRemoved(usize),
+    /// Some other keyword was added.
+    KeywordAdded(String),
+    /// Some other keyword was removed.
+    KeywordRemoved(String),
+    /// The value of some other keyword changed.
+    KeywordChanged(String),
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChangeKind::PropertyAdded { ref name, required } => write!(
+                f, "{} property `{}` added", if required { "required" } else { "optional" }, name
+            ),
+            ChangeKind::PropertyRemoved { ref name } => write!(f, "property `{}` removed", name),
+            ChangeKind::RequiredAdded(ref name) => write!(f, "property `{}` became required", name),
+            ChangeKind::RequiredRemoved(ref name) => write!(f, "property `{}` became optional", name),
+            ChangeKind::TypeChanged { ref old, ref new } => write!(
+                f, "type changed from {} to {}", type_list(old), type_list(new)
+            ),
+            ChangeKind::BoundChanged { ref keyword, ref old, ref new } => write!(
+                f, "{} changed from {} to {}", keyword, optional_value(old), optional_value(new)
+            ),
+            ChangeKind::EnumValuesAdded(ref values) => write!(f, "enum values added: {}", value_list(values)),
+            ChangeKind::EnumValuesRemoved(ref values) => write!(f, "enum values removed: {}", value_list(values)),
+            ChangeKind::AdditionalPropertiesChanged { ref old, ref new } => write!(
+                f, "additionalProperties changed from {} to {}", optional_value(old), optional_value(new)
+            ),
+            ChangeKind::AlternativeAdded(index) => write!(f, "alternative #{} added", index + 1),
+            ChangeKind::AlternativeRemoved(index) => write!(f, "alternative #{} removed", index + 1),
+            ChangeKind::KeywordAdded(ref keyword) => write!(f, "`{}` added", keyword),
+            ChangeKind::KeywordRemoved(ref keyword) => write!(f, "`{}` removed", keyword),
+            ChangeKind::KeywordChanged(ref keyword) => write!(f, "`{}` changed", keyword),
+        }
+    }
+}
+
+/// Compares two (sub)schemas found at `path`.
+fn diff_at(old: &Document, new: &Document, path: &str, changes: &mut Vec<Change>) {
+    let mut push = |kind, breaking| changes.push(Change { path: path.to_owned(), kind, breaking });
+
+    diff_types(old, new, &mut push);
+    diff_enums(old, new, &mut push);
+
+    for &(keyword, lower) in &[("minimum", true), ("maximum", false), ("minItems", true), ("maxItems", false)] {
+        diff_bound(old, new, keyword, lower, &mut push);
+    }
+
+    diff_properties(old, new, path, changes);
+    diff_additional_properties(old, new, path, changes);
+    diff_alternatives(old, new, path, changes);
+
+    for keyword in &["items", "additionalItems", "not"] {
+        if let (Some(Bson::Document(old_sub)), Some(Bson::Document(new_sub))) = (old.get(keyword), new.get(keyword)) {
+            diff_at(old_sub, new_sub, &child_path(path, &[keyword]), changes);
+        }
+    }
+
+    if let (Ok(old_defs), Ok(new_defs)) = (old.get_document("definitions"), new.get_document("definitions")) {
+        for (name, value) in new_defs {
+            if let (Ok(old_def), Bson::Document(new_def)) = (old_defs.get_document(name), value) {
+                diff_at(old_def, new_def, &child_path(path, &["definitions", name]), changes);
+            }
+        }
+    }
+
+    diff_other_keywords(old, new, path, changes);
+}
+
+/// Compares the `type` and `bsonType` keywords.
+fn diff_types<F>(old: &Document, new: &Document, push: &mut F) where F: FnMut(ChangeKind, bool) {
+    let old_types = types(old);
+    let new_types = types(new);
+
+    if !same_set(&old_types, &new_types) {
+        let widened = new_types.is_empty()
+                      ||
+                      !old_types.is_empty() && old_types.iter().all(|t| new_types.contains(t));
+
+        push(ChangeKind::TypeChanged { old: old_types, new: new_types }, !widened);
+    }
+}
+
+/// Compares the `enum` keyword.
+fn diff_enums<F>(old: &Document, new: &Document, push: &mut F) where F: FnMut(ChangeKind, bool) {
+    let (old_values, new_values) = match (old.get_array("enum"), new.get_array("enum")) {
+        (Ok(old_values), Ok(new_values)) => (old_values, new_values),
+        _ => return, // reported as an added or removed keyword
+    };
+
+    let added: Vec<_> = new_values.iter().filter(|v| !contains(old_values, v)).cloned().collect();
+    let removed: Vec<_> = old_values.iter().filter(|v| !contains(new_values, v)).cloned().collect();
+
+    if !added.is_empty() {
+        push(ChangeKind::EnumValuesAdded(added), false);
+    }
+    if !removed.is_empty() {
+        push(ChangeKind::EnumValuesRemoved(removed), true);
+    }
+}
+
+/// Compares a lower or an upper bound, taking the corresponding
+/// `exclusiveMinimum` or `exclusiveMaximum` into account.
+fn diff_bound<F>(
+    old: &Document,
+    new: &Document,
+    keyword: &str,
+    lower: bool,
+    push: &mut F,
+) where F: FnMut(ChangeKind, bool) {
+    let old_bound = old.get(keyword);
+    let new_bound = new.get(keyword);
+    let exclusive_key = match keyword {
+        "minimum" => "exclusiveMinimum",
+        "maximum" => "exclusiveMaximum",
+        _ => "",
+    };
+    let old_exclusive = old.get(exclusive_key) == Some(&Bson::Boolean(true));
+    let new_exclusive = new.get(exclusive_key) == Some(&Bson::Boolean(true));
+
+    let narrowed = match (old_bound, new_bound) {
+        (None, None) => return,
+        (Some(_), None) => false,
+        (None, Some(_)) => true,
+        (Some(old_value), Some(new_value)) => match compare_numbers(new_value, old_value) {
+            Some(Ordering::Equal) => {
+                if old_exclusive == new_exclusive {
+                    return;
+                }
+                new_exclusive
+            },
+            Some(Ordering::Greater) => lower,
+            Some(Ordering::Less) => !lower,
+            None => true,
+        },
+    };
+
+    push(ChangeKind::BoundChanged {
+        keyword: keyword.to_owned(),
+        old: old_bound.cloned(),
+        new: new_bound.cloned(),
+    }, narrowed);
+}
+
+/// Compares the `properties` and `required` keywords.
+fn diff_properties(old: &Document, new: &Document, path: &str, changes: &mut Vec<Change>) {
+    let empty = Document::new();
+    let old_props = old.get_document("properties").unwrap_or(&empty);
+    let new_props = new.get_document("properties").unwrap_or(&empty);
+    let old_required = required(old);
+    let new_required = required(new);
+    let new_closed = new.get("additionalProperties") == Some(&Bson::Boolean(false));
+    let mut push = |kind, breaking| changes.push(Change { path: path.to_owned(), kind, breaking });
+
+    for name in new_props.keys() {
+        let is_required = new_required.contains(&name.as_str());
+
+        if !old_props.contains_key(name) {
+            push(ChangeKind::PropertyAdded { name: name.clone(), required: is_required }, is_required);
+        } else if is_required && !old_required.contains(&name.as_str()) {
+            push(ChangeKind::RequiredAdded(name.clone()), true);
+        }
+    }
+
+    for name in old_props.keys() {
+        if !new_props.contains_key(name) {
+            push(ChangeKind::PropertyRemoved { name: name.clone() }, new_closed);
+        } else if old_required.contains(&name.as_str()) && !new_required.contains(&name.as_str()) {
+            push(ChangeKind::RequiredRemoved(name.clone()), false);
+        }
+    }
+
+    for (name, value) in new_props {
+        if let (Ok(old_prop), Bson::Document(new_prop)) = (old_props.get_document(name), value) {
+            diff_at(old_prop, new_prop, &child_path(path, &["properties", name]), changes);
+        }
+    }
+}
+
+/// Compares the `additionalProperties` keyword.
+fn diff_additional_properties(old: &Document, new: &Document, path: &str, changes: &mut Vec<Change>) {
+    let old_value = old.get("additionalProperties");
+    let new_value = new.get("additionalProperties");
+
+    match (old_value, new_value) {
+        (Some(Bson::Document(old_sub)), Some(Bson::Document(new_sub))) => {
+            diff_at(old_sub, new_sub, &child_path(path, &["additionalProperties"]), changes);
+        },
+        _ => if !same_value(old_value, new_value) {
+            let permissive = new_value.is_none() || new_value == Some(&Bson::Boolean(true));
+            let was_closed = old_value == Some(&Bson::Boolean(false));
+
+            changes.push(Change {
+                path: path.to_owned(),
+                kind: ChangeKind::AdditionalPropertiesChanged {
+                    old: old_value.cloned(),
+                    new: new_value.cloned(),
+                },
+                breaking: !permissive && !was_closed,
+            });
+        },
+    }
+}
+
+/// Compares the alternatives of an `anyOf` pairwise.
+fn diff_alternatives(old: &Document, new: &Document, path: &str, changes: &mut Vec<Change>) {
+    let (old_branches, new_branches) = match (old.get_array("anyOf"), new.get_array("anyOf")) {
+        (Ok(old_branches), Ok(new_branches)) => (old_branches, new_branches),
+        _ => return, // reported as an added or removed keyword
+    };
+
+    for (i, pair) in old_branches.iter().zip(new_branches).enumerate() {
+        if let (Bson::Document(old_sub), Bson::Document(new_sub)) = pair {
+            diff_at(old_sub, new_sub, &child_path(path, &["anyOf", &i.to_string()]), changes);
+        }
+    }
+
+    for i in new_branches.len()..old_branches.len() {
+        changes.push(Change { path: path.to_owned(), kind: ChangeKind::AlternativeRemoved(i), breaking: true });
+    }
+
+    for i in old_branches.len()..new_branches.len() {
+        changes.push(Change { path: path.to_owned(), kind: ChangeKind::AlternativeAdded(i), breaking: false });
+    }
+}
+
+/// Compares keywords without more specific handling. Added keywords are
+/// assumed to be new constraints, removed keywords are assumed to relax
+/// constraints, while modified keywords are conservatively assumed to
+/// be breaking. Annotations never are.
+fn diff_other_keywords(old: &Document, new: &Document, path: &str, changes: &mut Vec<Change>) {
+    let handled = |key: &str| match key {
+        "type" | "bsonType" | "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" |
+        "minItems" | "maxItems" | "properties" | "required" | "additionalProperties" => true,
+        "enum" | "anyOf" | "definitions" => old.contains_key(key) && new.contains_key(key),
+        "items" | "additionalItems" | "not" => old.get_document(key).is_ok() && new.get_document(key).is_ok(),
+        _ => false,
+    };
+    let mut push = |kind, key: &str, breaking| changes.push(Change {
+        path: path.to_owned(),
+        kind,
+        breaking: breaking && !ANNOTATIONS.contains(&key),
+    });
+
+    for (key, new_value) in new {
+        if handled(key) {
+            continue;
+        }
+
+        match old.get(key) {
+            None => push(ChangeKind::KeywordAdded(key.clone()), key, true),
+            Some(old_value) => if !same_value(Some(old_value), Some(new_value)) {
+                push(ChangeKind::KeywordChanged(key.clone()), key, true)
+            },
+        }
+    }
+
+    for key in old.keys() {
+        if !handled(key) && !new.contains_key(key) {
+            push(ChangeKind::KeywordRemoved(key.clone()), key, false);
+        }
+    }
+}
+
+/// Returns the list of types allowed by `type` and `bsonType`.
+fn types(schema: &Document) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for key in &["type", "bsonType"] {
+        match schema.get(key) {
+            Some(Bson::String(name)) => names.push(name.clone()),
+            Some(Bson::Array(array)) => names.extend(array.iter().filter_map(Bson::as_str).map(String::from)),
+            _ => {},
+        }
+    }
+
+    names
+}
+
+/// Returns the names of the required properties.
+fn required(schema: &Document) -> Vec<&str> {
+    schema.get_array("required").map_or_else(
+        |_| Vec::new(),
+        |names| names.iter().filter_map(Bson::as_str).collect()
+    )
+}
+
+/// Compares two lists of types as sets.
+fn same_set(lhs: &[String], rhs: &[String]) -> bool {
+    lhs.iter().all(|t| rhs.contains(t)) && rhs.iter().all(|t| lhs.contains(t))
+}
+
+/// Checks whether an array contains a value, ignoring key order in documents.
+fn contains(array: &[Bson], value: &Bson) -> bool {
+    array.iter().any(|item| same_value(Some(item), Some(value)))
+}
+
+/// Compares two values, ignoring the order of keys in documents.
+/// The order of arrays is significant, except for `required`,
+/// `enum` and `type`-like arrays, which are compared as sets.
+fn same_value(lhs: Option<&Bson>, rhs: Option<&Bson>) -> bool {
+    match (lhs, rhs) {
+        (Some(Bson::Document(lhs_doc)), Some(Bson::Document(rhs_doc))) => {
+            lhs_doc.len() == rhs_doc.len() && lhs_doc.iter().all(|(key, value)| {
+                match (value, rhs_doc.get(key)) {
+                    (Bson::Array(lhs_arr), Some(Bson::Array(rhs_arr))) if is_set_keyword(key) => {
+                        lhs_arr.len() == rhs_arr.len() && lhs_arr.iter().all(|item| contains(rhs_arr, item))
+                    },
+                    (_, other) => same_value(Some(value), other),
+                }
+            })
+        },
+        (Some(Bson::Array(lhs_arr)), Some(Bson::Array(rhs_arr))) => {
+            lhs_arr.len() == rhs_arr.len()
+            &&
+            lhs_arr.iter().zip(rhs_arr).all(|(l, r)| same_value(Some(l), Some(r)))
+        },
+        _ => lhs == rhs,
+    }
+}
+
+/// Checks whether the array value of a keyword is a set.
+fn is_set_keyword(key: &str) -> bool {
+    key == "required" || key == "enum" || key == "type" || key == "bsonType"
+}
+
+/// Compares two numbers, exactly if both of them are integers.
+#[allow(clippy::cast_precision_loss)]
+fn compare_numbers(lhs: &Bson, rhs: &Bson) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (&Bson::I32(x), &Bson::I32(y)) => Some(x.cmp(&y)),
+        (&Bson::I64(x), &Bson::I64(y)) => Some(x.cmp(&y)),
+        (&Bson::I32(x), &Bson::I64(y)) => Some(i64::from(x).cmp(&y)),
+        (&Bson::I64(x), &Bson::I32(y)) => Some(x.cmp(&i64::from(y))),
+        _ => lhs.as_f64().or_else(|| lhs.as_i64().map(|x| x as f64))?.partial_cmp(
+            &rhs.as_f64().or_else(|| rhs.as_i64().map(|y| y as f64))?
+        ),
+    }
+}
+
+/// Appends reference tokens to a JSON pointer, escaping `~` and `/`.
+fn child_path(path: &str, tokens: &[&str]) -> String {
+    tokens.iter().fold(path.to_owned(), |acc, token| {
+        format!("{}/{}", acc, token.replace('~', "~0").replace('/', "~1"))
+    })
+}
+
+/// Formats a list of types; an empty list means "any type".
+fn type_list(types: &[String]) -> String {
+    if types.is_empty() {
+        String::from("any")
+    } else {
+        types.join("|")
+    }
+}
+
+/// Formats a list of values.
+fn value_list(values: &[Bson]) -> String {
+    values.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats a value that may be missing.
+fn optional_value(value: &Option<Bson>) -> String {
+    value.as_ref().map_or_else(|| String::from("none"), ToString::to_string)
+}
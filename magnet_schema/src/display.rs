@@ -0,0 +1,258 @@
+//! Human-readable summaries of schemas, for debugging and documentation.
+//!
+//! Each property is described on a line of its own, in the form
+//! `name: type [bounds] (required)`. Nullable types are marked with a
+//! trailing `?`, and the properties of nested objects as well as the
+//! alternatives of enums are listed below their parent, indented.
+
+use std::fmt;
+use bson::{ Bson, Document };
+
+/// Returns the summary of `schema` as a `String`.
+pub fn explain(schema: &Document) -> String {
+    Explain(schema).to_string()
+}
+
+/// Wrapper around a schema, `Display`ing a summary of it.
+#[derive(Debug, Clone, Copy)]
+pub struct Explain<'a>(pub &'a Document);
+
+impl<'a> fmt::Display for Explain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&head(self.0))?;
+        write_body(f, self.0, 0)?;
+
+        if let Ok(definitions) = self.0.get_document("definitions") {
+            f.write_str("\n\ndefinitions:")?;
+
+            for (name, definition) in definitions {
+                if let Bson::Document(ref schema) = *definition {
+                    write_line(f, 1, name, schema, false)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a property or a labeled alternative on a new line.
+fn write_line(
+    f: &mut fmt::Formatter,
+    indent: usize,
+    label: &str,
+    schema: &Document,
+    required: bool,
+) -> fmt::Result {
+    write!(f, "\n{:width$}{}: {}", "", label, head(schema), width = indent * 2)?;
+
+    if required {
+        f.write_str(" (required)")?;
+    }
+
+    write_body(f, schema, indent)
+}
+
+/// Writes the indented list of properties, map values, or alternatives
+/// of a schema, if any.
+fn write_body(f: &mut fmt::Formatter, schema: &Document, indent: usize) -> fmt::Result {
+    if let Ok(items) = schema.get_document("items") {
+        return write_body(f, items, indent);
+    }
+
+    if let Ok(branches) = schema.get_array("anyOf") {
+        for (i, branch) in branches.iter().enumerate() {
+            match *branch {
+                Bson::Document(ref doc) if !is_null_only(doc) => {
+                    write_line(f, indent + 1, &format!("- {}", branch_label(doc, i)), doc, false)?;
+                },
+                _ => {},
+            }
+        }
+
+        return Ok(());
+    }
+
+    match (schema.get_document("properties").ok(), schema.get_document("additionalProperties").ok()) {
+        (None, Some(values)) => write_body(f, values, indent),
+        (None, None) => Ok(()),
+        (Some(properties), values) => {
+            let required = schema.get_array("required").map_or(&[][..], Vec::as_slice);
+
+            f.write_str(" \u{2192} {")?;
+
+            for (name, property) in properties {
+                if let Bson::Document(ref doc) = *property {
+                    let is_required = required.contains(&Bson::from(name.as_str()));
+                    write_line(f, indent + 1, name, doc, is_required)?;
+                }
+            }
+
+            if let Some(value_schema) = values {
+                write_line(f, indent + 1, "*", value_schema, false)?;
+            }
+
+            write!(f, "\n{:width$}}}", "", width = indent * 2)
+        },
+    }
+}
+
+/// Returns the one-line summary of a schema: its type(s), nullability,
+/// and constraints. The element types of arrays and the value types
+/// of maps are summarized too.
+fn head(schema: &Document) -> String {
+    let mut types: Vec<&str> = Vec::new();
+
+    for key in &["type", "bsonType"] {
+        match schema.get(key) {
+            Some(Bson::String(name)) => types.push(name),
+            Some(Bson::Array(names)) => types.extend(names.iter().filter_map(Bson::as_str)),
+            _ => {},
+        }
+    }
+
+    let mut nullable = types.contains(&"null") && types.len() > 1;
+    types.retain(|&name| name != "null" || !nullable);
+
+    if let Ok(branches) = schema.get_array("anyOf") {
+        nullable |= branches.iter().any(|branch| branch.as_document().is_some_and(is_null_only));
+    }
+
+    let mut summary = if let Some(target) = schema.get("$ref").and_then(Bson::as_str) {
+        format!("ref {}", target.trim_start_matches("#/definitions/"))
+    } else if !types.is_empty() {
+        types.join("|")
+    } else if schema.contains_key("anyOf") {
+        String::from("one of")
+    } else if let Ok(values) = schema.get_array("enum") {
+        join(values, " | ")
+    } else if schema.get("not").and_then(Bson::as_document).is_some_and(Document::is_empty) {
+        String::from("nothing")
+    } else {
+        String::from("any")
+    };
+
+    if nullable {
+        summary.push('?');
+    }
+
+    if !types.is_empty() {
+        if let Ok(values) = schema.get_array("enum") {
+            summary.push_str(&format!(" {{{}}}", join(values, ", ")));
+        }
+    }
+
+    match (schema.get("items"), schema.get_document("properties"), schema.get("additionalProperties")) {
+        (Some(Bson::Document(items)), _, _) => {
+            summary.push_str(" of ");
+            summary.push_str(&head(items));
+        },
+        (Some(Bson::Array(items)), _, _) => {
+            let heads: Vec<_> = items.iter().filter_map(Bson::as_document).map(head).collect();
+            summary.push_str(&format!(" ({})", heads.join(", ")));
+        },
+        (_, Err(_), Some(Bson::Document(values))) => {
+            summary.push_str(" of ");
+            summary.push_str(&head(values));
+        },
+        _ => {},
+    }
+
+    if let Some(bounds) = bounds(schema) {
+        summary.push(' ');
+        summary.push_str(&bounds);
+    }
+
+    if let Some(length) = length(schema) {
+        summary.push(' ');
+        summary.push_str(&length);
+    }
+
+    if schema.get("uniqueItems") == Some(&Bson::Boolean(true)) {
+        summary.push_str(" unique");
+    }
+
+    if let Ok(pattern) = schema.get_str("pattern") {
+        summary.push_str(&format!(" /{}/", pattern));
+    }
+
+    summary
+}
+
+/// Describes the `minimum` and `maximum` of a schema as an interval.
+fn bounds(schema: &Document) -> Option<String> {
+    let min = schema.get("minimum");
+    let max = schema.get("maximum");
+
+    if min.is_none() && max.is_none() {
+        return None;
+    }
+
+    let open_min = schema.get("exclusiveMinimum") == Some(&Bson::Boolean(true));
+    let open_max = schema.get("exclusiveMaximum") == Some(&Bson::Boolean(true));
+
+    Some(format!(
+        "{}{}, {}{}",
+        if open_min || min.is_none() { '(' } else { '[' },
+        min.map_or_else(|| String::from("-inf"), ToString::to_string),
+        max.map_or_else(|| String::from("inf"), ToString::to_string),
+        if open_max || max.is_none() { ')' } else { ']' },
+    ))
+}
+
+/// Describes the `minItems` and `maxItems` of a schema.
+fn length(schema: &Document) -> Option<String> {
+    match (schema.get("minItems"), schema.get("maxItems")) {
+        (Some(min), Some(max)) if min == max => Some(format!("length {}", min)),
+        (Some(min), Some(max)) => Some(format!("length {}..{}", min, max)),
+        (Some(min), None) => Some(format!("length >= {}", min)),
+        (None, Some(max)) => Some(format!("length <= {}", max)),
+        (None, None) => None,
+    }
+}
+
+/// Returns a label for an alternative of an `anyOf`: the name of the
+/// variant if it can be found, or its 1-based index otherwise.
+fn branch_label(branch: &Document, index: usize) -> String {
+    // unit variant of an externally-tagged enum
+    if let Ok(values) = branch.get_array("enum") {
+        if let [Bson::String(ref name)] = values.as_slice() {
+            return name.clone();
+        }
+    }
+
+    if let Ok(properties) = branch.get_document("properties") {
+        // internally- or adjacently-tagged variant
+        for (_, property) in properties {
+            let tag = property
+                .as_document()
+                .and_then(|doc| doc.get_array("enum").ok())
+                .filter(|tags| tags.len() == 1)
+                .and_then(|tags| tags[0].as_str());
+
+            if let Some(name) = tag {
+                return name.to_owned();
+            }
+        }
+
+        // externally-tagged variant
+        if branch.get("additionalProperties") == Some(&Bson::Boolean(false)) && properties.len() == 1 {
+            if let Some(key) = properties.keys().next() {
+                return key.clone();
+            }
+        }
+    }
+
+    format!("#{}", index + 1)
+}
+
+/// Checks whether a schema is the `{ "type": "null" }` alternative
+/// added to enums wrapped in an `Option`.
+fn is_null_only(schema: &Document) -> bool {
+    schema.len() == 1 && schema.get_str("type") == Ok("null")
+}
+
+/// Formats and joins a list of BSON values.
+fn join(values: &[Bson], separator: &str) -> String {
+    values.iter().map(ToString::to_string).collect::<Vec<_>>().join(separator)
+}
@@ -0,0 +1,68 @@
+//! Errors that can occur while generating a schema, reported by
+//! `BsonSchema::try_bson_schema()` instead of panicking.
+
+use std::fmt;
+use std::error::Error;
+use support::Bounds;
+
+/// The reason why the schema of a type couldn't be generated.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    /// The contents of an internally-tagged newtype variant can't hold the
+    /// tag, because they describe neither a struct, nor a map, nor an enum.
+    UntaggableVariant {
+        /// The name of the tag field.
+        tag: String,
+        /// The name of the variant.
+        variant: String,
+    },
+    /// The schema requires a feature that isn't supported yet, e.g. an
+    /// internally-tagged newtype variant around an enum.
+    Unsupported(String),
+    /// The bounds of a number leave no valid values, taking into account
+    /// the range of its type.
+    EmptyRange(Bounds),
+    /// A schema which is expected to have a specific structure doesn't.
+    MalformedSchema(String),
+    /// A property or definition name starts with `$` or contains `.`, and
+    /// the `key_policy` option is `KeyPolicy::Error`.
+    IllegalKey {
+        /// The offending name.
+        key: String,
+        /// JSON pointer to the schema declaring the name.
+        path: String,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemaError::UntaggableVariant { ref tag, ref variant } => write!(
+                f,
+                "newtype variant `{}` tagged by `{}` doesn't describe a struct, a map, or an enum",
+                variant, tag
+            ),
+            SchemaError::Unsupported(ref what) => write!(f, "{} not yet supported", what),
+            SchemaError::EmptyRange(ref bounds) => write!(f, "bounds {:?} leave no valid values", bounds),
+            SchemaError::MalformedSchema(ref message) => write!(f, "malformed schema: {}", message),
+            SchemaError::IllegalKey { ref key, ref path } => write!(
+                f,
+                "key `{}` at `{}` starts with `$` or contains `.`, which MongoDB doesn't allow",
+                key, path
+            ),
+        }
+    }
+}
+
+impl Error for SchemaError {
+    fn description(&self) -> &str {
+        match *self {
+            SchemaError::UntaggableVariant { .. } => "internally-tagged newtype variant can't hold the tag",
+            SchemaError::Unsupported(_) => "schema feature not yet supported",
+            SchemaError::EmptyRange(_) => "bounds leave no valid values",
+            SchemaError::MalformedSchema(_) => "malformed schema",
+            SchemaError::IllegalKey { .. } => "illegal property name",
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Writing schemas as JSON, e.g. for consumption by other services
+//! or validators outside of MongoDB.
+//!
+//! BSON values are mapped to plain JSON, not MongoDB Extended JSON:
+//! integers and doubles both become JSON numbers, and the few BSON-only
+//! types which might occur in a schema are represented by strings or
+//! numbers, so that any JSON parser can read the output.
+//!
+//! The exported schemas are MongoDB-flavored, just like the output of
+//! `bson_schema()`. Use the `convert` module to turn them into standard
+//! JSON Schema before exporting, if needed.
+//!
+//! The keywords of exported schemas are ordered by
+//! `support::reorder_canonical()`, so exporting the same schema always
+//! results in the same output, e.g. in files checked into version control,
+//! regardless of the order in which the keywords were generated. The
+//! JSON is written straight from the reordered document, so the order is
+//! kept even though `serde_json::Map` sorts its keys by default.
+
+use std::io;
+use std::fs::{ self, File };
+use std::path::Path;
+use bson::{ Bson, Document };
+use serde::{ Serialize, Serializer };
+use serde_json::{ self, Map, Number, Value };
+use definitions::SchemaRegistry;
+use support::reorder_canonical;
+use BsonSchema;
+
+/// Converts a BSON document to a plain JSON object. The order of its keys
+/// is only kept if the `preserve_order` feature of `serde_json` is enabled.
+pub fn to_json_value(doc: &Document) -> Value {
+    let object: Map<String, Value> = doc
+        .iter()
+        .map(|(key, value)| (key.clone(), bson_to_json(value)))
+        .collect();
+
+    Value::Object(object)
+}
+
+/// Converts a single BSON value to plain JSON.
+fn bson_to_json(value: &Bson) -> Value {
+    match *value {
+        Bson::FloatingPoint(x) => Number::from_f64(x).map_or(Value::Null, Value::Number),
+        Bson::String(ref s) |
+        Bson::JavaScriptCode(ref s) |
+        Bson::JavaScriptCodeWithScope(ref s, _) |
+        Bson::Symbol(ref s) |
+        Bson::RegExp(ref s, _) => Value::String(s.clone()),
+        Bson::Array(ref array) => Value::Array(array.iter().map(bson_to_json).collect()),
+        Bson::Document(ref doc) => to_json_value(doc),
+        Bson::Boolean(b) => Value::Bool(b),
+        Bson::Null => Value::Null,
+        Bson::I32(n) => Value::from(n),
+        Bson::I64(n) | Bson::TimeStamp(n) => Value::from(n),
+        Bson::Binary(_, ref bytes) => Value::Array(bytes.iter().map(|&b| Value::from(b)).collect()),
+        Bson::ObjectId(ref oid) => Value::String(oid.to_hex()),
+        Bson::UtcDatetime(ref date) => Value::String(date.to_rfc3339()),
+    }
+}
+
+/// Serializes a BSON document as plain JSON, like `to_json_value()`,
+/// but keeping the order of its keys, and those of its subdocuments.
+struct OrderedJson<'a>(&'a Document);
+
+impl<'a> Serialize for OrderedJson<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, OrderedJsonValue(value))))
+    }
+}
+
+/// Serializes a BSON value as plain JSON, keeping the order of the keys
+/// of documents within it.
+struct OrderedJsonValue<'a>(&'a Bson);
+
+impl<'a> Serialize for OrderedJsonValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self.0 {
+            Bson::Document(ref doc) => OrderedJson(doc).serialize(serializer),
+            Bson::Array(ref array) => serializer.collect_seq(array.iter().map(OrderedJsonValue)),
+            ref value => bson_to_json(value).serialize(serializer),
+        }
+    }
+}
+
+/// Returns the schema of `T` as a JSON string, optionally pretty-printed.
+pub fn to_json_string<T: ?Sized + BsonSchema>(pretty: bool) -> String {
+    let schema = reorder_canonical(&T::bson_schema());
+    let json = if pretty {
+        serde_json::to_string_pretty(&OrderedJson(&schema))
+    } else {
+        serde_json::to_string(&OrderedJson(&schema))
+    };
+
+    json.expect("a document with string keys can always be serialized as JSON")
+}
+
+/// Writes the pretty-printed JSON schema of `T` to `writer`.
+pub fn write_json<T: ?Sized + BsonSchema, W: io::Write>(writer: W) -> io::Result<()> {
+    write_document(&T::bson_schema(), writer)
+}
+
+/// Writes the definitions of all types in a registry to `writer`, as a
+/// pretty-printed JSON document of the form `{ "definitions": { ... } }`.
+pub fn write_registry_json<W: io::Write>(registry: &SchemaRegistry, writer: W) -> io::Result<()> {
+    write_document(&doc!{ "definitions": registry.definitions().clone() }, writer)
+}
+
+/// Writes the schema of every type in a registry to a separate file in the
+/// directory `dir`, which is created if it doesn't exist yet. The files are
+/// named after the schema names, with characters other than ASCII letters,
+/// digits, `-` and `_` replaced by `_`, followed by the `.json` extension.
+///
+/// Since the `$ref`s in schemas of recursive types point into a common
+/// `definitions` map, those are better exported by `write_registry_json()`.
+pub fn write_json_files<P: AsRef<Path>>(registry: &SchemaRegistry, dir: P) -> io::Result<()> {
+    let dir_path = dir.as_ref();
+    fs::create_dir_all(dir_path)?;
+
+    for (name, value) in registry.definitions() {
+        let schema = match *value {
+            Bson::Document(ref doc) => doc,
+            _ => continue,
+        };
+        let file_name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+
+        write_document(schema, File::create(dir_path.join(file_name + ".json"))?)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a pretty-printed schema to `writer`, followed by a newline.
+fn write_document<W: io::Write>(doc: &Document, mut writer: W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, &OrderedJson(&reorder_canonical(doc)))?;
+    writer.write_all(b"\n")
+}
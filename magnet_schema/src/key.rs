@@ -0,0 +1,161 @@
+//! Schemas of the keys of maps.
+//!
+//! Map keys are always serialized as strings, so the most that can be
+//! said about them in a schema is which strings are allowed. This is
+//! expressed as a regular expression, which map schemas then use as the
+//! name of their (only) entry under `patternProperties`. If there are only
+//! finitely many possible keys, they are listed under `properties` instead.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::borrow::Cow;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr };
+use bson::oid::ObjectId;
+use support::{ self, patterns };
+
+/// Types which can be used as the keys of maps, i.e. whose values are
+/// serialized as strings. `#[derive(BsonSchema)]` implements it for
+/// externally-tagged `enum`s consisting of unit variants only, allowing
+/// just the names of the variants, and for newtype `struct`s whose field
+/// is a key, allowing what the field allows. Other key types, e.g. ones
+/// serialized through their `Display` impl, need an impl of their own,
+/// which may be empty, allowing any string.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate bson;
+/// extern crate magnet_schema;
+///
+/// use std::collections::HashMap;
+/// use magnet_schema::{ BsonSchema, BsonSchemaKey };
+///
+/// #[derive(PartialEq, Eq, Hash)]
+/// struct Sku(String);
+///
+/// impl BsonSchemaKey for Sku {
+///     fn key_pattern() -> Option<String> {
+///         Some(String::from("^[A-Z]{3}-[0-9]{4}$"))
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(HashMap::<Sku, u32>::bson_schema(), doc!{
+///         "type": "object",
+///         "patternProperties": {
+///             "^[A-Z]{3}-[0-9]{4}$": u32::bson_schema(),
+///         },
+///         "additionalProperties": false,
+///     });
+/// }
+/// ```
+#[allow(clippy::module_name_repetitions)]
+pub trait BsonSchemaKey {
+    /// Returns an anchored regular expression matching the string form of
+    /// every value of this type, if only some strings are valid. The
+    /// default implementation returns `None`, allowing any string.
+    fn key_pattern() -> Option<String> {
+        None
+    }
+
+    /// Returns every possible string form of the values of this type, if
+    /// there are finitely many of them, e.g. the names of the variants of
+    /// a unit-only enum. Maps with such keys are then described by an
+    /// explicit list of properties, which is more precise than a pattern.
+    /// The default implementation returns `None`.
+    fn known_keys() -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Keys which may be any string.
+macro_rules! impl_bson_schema_key_string {
+    ($($ty:ty,)*) => {$(
+        impl BsonSchemaKey for $ty {}
+    )*}
+}
+
+impl_bson_schema_key_string! {
+    str,
+    String,
+    Box<str>,
+    Rc<str>,
+    Arc<str>,
+}
+
+impl BsonSchemaKey for Cow<'_, str> {}
+
+impl<T> BsonSchemaKey for &T where T: ?Sized + BsonSchemaKey {
+    fn key_pattern() -> Option<String> {
+        T::key_pattern()
+    }
+
+    fn known_keys() -> Option<Vec<String>> {
+        T::known_keys()
+    }
+}
+
+/// Keys whose string form matches a fixed pattern.
+macro_rules! impl_bson_schema_key_pattern {
+    ($($ty:ty => $pattern:expr,)*) => {$(
+        impl BsonSchemaKey for $ty {
+            fn key_pattern() -> Option<String> {
+                Some(String::from($pattern))
+            }
+        }
+    )*}
+}
+
+impl_bson_schema_key_pattern! {
+    char  => r"^[\s\S]$",
+    u8    => "^[0-9]+$",
+    u16   => "^[0-9]+$",
+    u32   => "^[0-9]+$",
+    u64   => "^[0-9]+$",
+    usize => "^[0-9]+$",
+    i8    => "^-?[0-9]+$",
+    i16   => "^-?[0-9]+$",
+    i32   => "^-?[0-9]+$",
+    i64   => "^-?[0-9]+$",
+    isize => "^-?[0-9]+$",
+    IpAddr   => support::IP_ADDR_PATTERN,
+    Ipv4Addr => support::IPV4_ADDR_PATTERN,
+    Ipv6Addr => support::IPV6_ADDR_PATTERN,
+    ObjectId => patterns::OBJECT_ID_HEX_PATTERN,
+}
+
+impl BsonSchemaKey for bool {
+    fn key_pattern() -> Option<String> {
+        Some(String::from("^(?:true|false)$"))
+    }
+
+    fn known_keys() -> Option<Vec<String>> {
+        Some(vec![String::from("true"), String::from("false")])
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl_bson_schema_key_pattern! {
+    ::uuid::Uuid => support::UUID_PATTERN,
+}
+
+#[cfg(feature = "url")]
+impl_bson_schema_key_pattern! {
+    ::url::Url => support::URL_PATTERN,
+}
+
+#[cfg(feature = "semver")]
+impl_bson_schema_key_pattern! {
+    ::semver::Version => support::SEMVER_PATTERN,
+}
+
+#[cfg(feature = "mime")]
+impl_bson_schema_key_pattern! {
+    ::mime::Mime => support::MIME_PATTERN,
+}
+
+#[cfg(feature = "ipnet")]
+impl_bson_schema_key_pattern! {
+    ::ipnet::IpNet   => support::IP_NET_PATTERN,
+    ::ipnet::Ipv4Net => support::IPV4_NET_PATTERN,
+    ::ipnet::Ipv6Net => support::IPV6_NET_PATTERN,
+}
@@ -5,7 +5,7 @@
 //! which allows types to easily implement JSON schema validation for
 //! use with MongoDB.
 //!
-//! The trait defines a single function, `bson_schema()`, that returns
+//! The trait's central function, `bson_schema()`, returns
 //! a BSON `Document` describing the validation schema of the type based
 //! on its fields (for `struct`s and tuples), variants (for `enum`s), or
 //! elements/entries (for array- and map-like types).
@@ -50,6 +50,115 @@
 //! }
 //! ```
 //!
+//! The generated code refers to the macros of `bson` by their full path,
+//! so they needn't be imported with `#[macro_use]` or `use bson::doc;`.
+//! Only the `bson` crate itself must be available:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate serde_derive;
+//! extern crate serde;
+//! extern crate bson;
+//! #[macro_use]
+//! extern crate magnet_derive;
+//! extern crate magnet_schema;
+//!
+//! use magnet_schema::BsonSchema;
+//!
+//! #[derive(BsonSchema)]
+//! struct Account {
+//!     #[magnet(min_incl = "1")]
+//!     id: u64,
+//!     owner: (String, Option<String>),
+//!     events: Vec<Event>,
+//!     status: Status,
+//! }
+//!
+//! #[derive(BsonSchema, Serialize)]
+//! #[serde(tag = "kind", content = "data")]
+//! enum Event {
+//!     Opened { at: u64 },
+//!     Closed,
+//! }
+//!
+//! #[derive(BsonSchema)]
+//! enum Status {
+//!     Active,
+//!     Suspended(String),
+//! }
+//!
+//! fn main() {
+//!     let schema = Account::bson_schema();
+//!     assert!(schema.get_document("properties").unwrap().contains_key("events"));
+//! }
+//! ```
+//!
+//! ## Recursive Types
+//!
+//! Schemas of self-referential types are generated with the help of a
+//! `SchemaContext`: recursive occurrences of a type are replaced by a
+//! `$ref` into the `definitions` of the root schema. Since MongoDB doesn't
+//! support `$ref`, such schemas should be passed through `flatten_refs()`
+//! before being used as a collection validator.
+//!
+//! ## Map Keys
+//!
+//! The keys of `HashMap`s and `BTreeMap`s must implement `BsonSchemaKey`,
+//! which describes the strings they serialize to, so that map schemas can
+//! constrain their keys through `patternProperties` or an explicit list of
+//! `properties`. Note that this is a breaking change: previously, any key
+//! implementing `ToString` was accepted, and was allowed to be any string.
+//! The blanket impl that would preserve this can't coexist with the more
+//! precise impls for integers, enums, etc. without specialization, so to
+//! migrate, write an empty impl for such key types. It uses the default
+//! methods of the trait, which place no constraints on the keys, exactly
+//! like before:
+//!
+//! ```rust
+//! # extern crate magnet_schema;
+//! # use std::fmt;
+//! # use std::collections::HashMap;
+//! # use magnet_schema::{ BsonSchema, BsonSchemaKey };
+//! #[derive(PartialEq, Eq, Hash)]
+//! struct Name(String);
+//!
+//! impl fmt::Display for Name {
+//!     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+//!         f.write_str(&self.0)
+//!     }
+//! }
+//!
+//! impl BsonSchemaKey for Name {}
+//!
+//! # fn main() {
+//! let schema = HashMap::<Name, u32>::bson_schema();
+//! assert_eq!(schema.get_document("patternProperties").ok(), None);
+//! # }
+//! ```
+//!
+//! Unit-only `enum`s and newtype `struct`s deriving `BsonSchema` get an
+//! impl of `BsonSchemaKey` automatically.
+//!
+//! ## Descriptions
+//!
+//! The doc comments of `struct`s, of their fields, and of `enum`s and their
+//! variants become the `"description"` of the corresponding schemas. The
+//! descriptions of the fields of tuple structs end up in `"items"`. The
+//! description of a variant is attached to its payload: that is the value
+//! under the name of the variant if the enum is externally tagged, or under
+//! `content` if it's adjacently tagged. Unit variants, and the variants of
+//! internally tagged or untagged enums, have no separate payload, so their
+//! description is attached to their branch in `anyOf`. Undocumented unit
+//! variants don't need branches of their own: an enum consisting only of
+//! them is described by a single flat schema, e.g. `{ "enum": ["Red",
+//! "Green"] }` if it's externally tagged, and those of internally or
+//! adjacently tagged enums share a single branch.
+//!
+//! The `#[magnet(doc = "...")]` attribute of a type controls how much of
+//! its own doc comment and those of its fields and variants is used:
+//! `"full"` (the default), `"first_line"`, or `"skip"`. Descriptions can
+//! also be omitted globally using `SchemaOptions::include_descriptions()`.
+//!
 //! ## Custom Attributes
 //!
 //! * `#[serde(rename = "new_name")]`: Magnet will respect Serde's field/variant
@@ -57,6 +166,23 @@
 //!
 //! * `#[serde(rename_all = "rename_rule")]`: it will also respect Serde's
 //!   `rename_all` rule.
+//!   An explicit `rename` of a field or variant takes precedence over it.
+//!   Of `#[serde(rename(serialize = "...", deserialize = "..."))]`, only the
+//!   `serialize` name is used, as it's the one stored in the database.
+//!
+//! * `#[serde(alias = "...")]`: aliases are only accepted when deserializing,
+//!   so the schema (e.g. the `enum` of a tag) only lists canonical names.
+//!   An alias of a variant which is the name of another one is an error.
+//!
+//! * `#[serde(borrow)]` (or `#[serde(borrow = "'a")]`) only affects deserialization,
+//!   so it's accepted, and borrowed fields get the schema of the referenced type:
+//!   `&'a str` and `Cow<'a, str>` are strings. `&'a [u8]` is an array of integers,
+//!   since that's how serde writes it; for fields written as binary, e.g. by
+//!   `#[serde(with = "serde_bytes")]`, use `#[magnet(with = "...")]`.
+//!
+//! * `#[serde(default)]` (or `#[serde(default = "...")]`) on a `struct`: serde
+//!   fills in every missing field, so none of them is `required`, except for
+//!   those marked `#[magnet(required)]`.
 //!
 //! * `#[magnet(min_incl = "-1337")]` &mdash; enforces an inclusive minimum for fields of numeric types
 //!
@@ -66,6 +192,93 @@
 //!
 //! * `#[magnet(max_excl = "64")]` &mdash; enforces an exclusive "maximum" (supremum) for fields of numeric types
 //!
+//! * `#[magnet(values_min_incl = "0", values_max_excl = "1")]` (and `values_min_excl`,
+//!   `values_max_incl`) &mdash; the same bounds, applied to the values of a map or the
+//!   elements of a set or other collection. The schema of any other type is an error.
+//!
+//! * `#[magnet(schema_name = "Name")]` &mdash; on a `struct` or `enum`, overrides the
+//!   name returned by `schema_name()`, under which the type appears in `definitions`
+//!
+//! * `#[magnet(version = 2)]` &mdash; on a `struct` or `enum`, records the version of its
+//!   schema as a `"$comment"`. See the `versioned` module for reading it back, and for
+//!   accepting several versions at once during a migration.
+//!
+//! * `#[magnet(examples_from = "path::to::EXAMPLE")]` &mdash; on a `struct` or `enum`,
+//!   appends an example to the `"examples"` of its schema. The path refers to a `const`
+//!   or `static` of type `Bson` or `Document`, or to a function returning something
+//!   convertible to `Bson`, e.g. a `Document`. It can be given more than once.
+//!
+//! * `#[magnet(field_names)]` &mdash; on a `struct` with named fields, also generates a
+//!   `pub const FIELD_<NAME>: &'static str` for every field in the schema, e.g.
+//!   `FIELD_CREATED_AT` for `created_at`, holding its name after renaming, for use
+//!   in queries.
+//!
+//! * `#[magnet(no_bound(T, ...))]` &mdash; on a generic `struct` or `enum`, keeps the
+//!   derived `impl` from requiring `T: BsonSchema` (or a bound on its associated types),
+//!   for when the types of fields implement `BsonSchema` regardless of `T`
+//!
+//! * `#[magnet(tag_summary)]` &mdash; on an internally or adjacently tagged `enum`,
+//!   also lists every possible value of the tag at the top level, next to the
+//!   `anyOf` of the variants, which makes validation errors easier to read
+//!
+//! * `#[magnet(relaxed_keys)]` &mdash; on a `struct`, variant, or field, allows
+//!   property names (after renaming) that start with `$` or contain `.`, and on
+//!   an `enum`, such `tag` and `content` keys and variant names. MongoDB only
+//!   accepts these in field names since 5.0, so they are a compile-time error by
+//!   default. Names which are only known at runtime, e.g. those of hand-written
+//!   schemas, are checked according to the `key_policy` option instead.
+//!
+//! * `#[serde(default)]` (or `#[serde(default = "...")]`) on a field: the field
+//!   is not `required`.
+//!
+//! * `#[magnet(optional)]` &mdash; leaves a named field out of the `required` array
+//!
+//! * `#[magnet(required)]` &mdash; keeps a named field in the `required` array
+//!   even if it's made optional by any of the above. It can't be combined with
+//!   `#[magnet(optional)]`, nor with skipping the field.
+//!
+//! * `#[magnet(format = "email")]` &mdash; constrains a string field to a named
+//!   format: one of `email`, `hostname`, `ipv4`, `ipv6`, `uri`, `uuid`, `date`,
+//!   `date-time` and `objectid-hex`. Both the `"pattern"` from
+//!   `support::patterns`, which MongoDB checks, and the standard `"format"`
+//!   keyword are added. An unknown format is a compile-time error.
+//!
+//! * `#[magnet(map_entries)]` &mdash; describes a list of key-value pairs, e.g. a
+//!   `Vec<(K, V)>`, as a document mapping keys to values, for fields which a serde
+//!   helper serializes that way. Conversely, `#[magnet(entry_pairs)]` describes a
+//!   map as an array of `[key, value]` pairs. Bounds on values apply to the values
+//!   of the map in both cases. Any other schema is an error at the time the schema
+//!   is generated. See `support::try_map_from_entries()` and
+//!   `support::try_entries_from_map()`.
+//!
+//! * `#[magnet(min_items = "1", max_items = "10")]` and `#[magnet(unique_items)]`
+//!   &mdash; constrain the length of an array, and require its items to be unique.
+//!   `#[magnet(items_regex = "^[a-z]+$")]` constrains the items to strings matching
+//!   a regular expression. Bounds on the items are given by `values_min_incl`, etc.
+//!   A schema which doesn't describe an array is an error at the time the schema
+//!   is generated. See `support::try_extend_schema_with_items()` and
+//!   `support::try_extend_items_with_regex()`.
+//!
+//! * On a newtype `struct`, i.e. a tuple struct with a single field, any of the
+//!   above attributes constraining the schema of a field (bounds, `format`,
+//!   `map_entries`, `entry_pairs`, and those of items) can also be given on the
+//!   `struct` itself, e.g. `#[magnet(unique_items)] struct Tags(Vec<String>);`.
+//!   They apply to the field, as if they were given on it. They are an error on
+//!   any other `struct` or `enum`.
+//!
+//! * The above attributes can also be given on the unnamed fields of tuple structs and
+//!   tuple variants, whatever the tagging of the `enum`. Each one constrains the entry
+//!   of `"items"` at the position of its field, e.g. in
+//!   `Clamp(#[magnet(min_incl = "0")] f64, #[magnet(max_incl = "1")] f64)`.
+//!
+//! * `#[magnet(with = "path::to::function")]` &mdash; uses the schema returned by
+//!   a `fn() -> bson::Document` instead of that of the field's type, which then
+//!   doesn't need to implement `BsonSchema`. This is required for fields whose
+//!   type contains a trait object, e.g. `Box<dyn Trait>`.
+//!
+//! * `#[magnet(skip)]` &mdash; leaves a named field out of the schema. Fields marked
+//!   `#[serde(skip)]` or `#[serde(skip_serializing)]` are left out automatically.
+//!
 //! ## Development Roadmap
 //!
 //! * `[x]` Define `BsonSchema` trait
@@ -73,7 +286,8 @@
 //! * `[x]` `impl BsonSchema` for most primitives/`std::` types
 //!
 //! * `[x]` Cargo `feature`s for implementing `BsonSchema` for "atomic"
-//!   types in foreign crates, for instance, `url::Url` and `uuid::Uuid`.
+//!   types in foreign crates, for instance, `url::Url`, `uuid::Uuid`,
+//!   and `bytes::Bytes`.
 //!
 //! * `[x]` `#[derive(BsonSchema)]` on regular, named-field structs
 //!
@@ -102,12 +316,13 @@
 //! * `[x]` Respect more `#[serde(...)]` attributes, for example: `rename`,
 //!   `rename_all`
 //!
-//! * `[ ]` Respect more `#[serde(...)]` attributes, for example: `default`,
+//! * `[x]` Respect more `#[serde(...)]` attributes, for example: `default`,
 //!   `skip`, `skip_serializing`, `skip_deserializing`
 //!
 //! * `[x]` Handle generic types in proc-macro derive
 //!
-//! * `[ ]` Standard (non-MongoDB-specific) JSON schema support (approach?)
+//! * `[x]` Standard (non-MongoDB-specific) JSON schema support: the
+//!   `json_schema()` method and the `convert` module
 //!
 //! * `[x]` unit tests
 //!
@@ -179,16 +394,44 @@
 
 #[macro_use]
 extern crate bson;
+extern crate serde;
+extern crate serde_json;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
 #[cfg(feature = "url")]
 extern crate url;
 #[cfg(feature = "uuid")]
 extern crate uuid;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "either")]
+extern crate either;
+#[cfg(feature = "semver")]
+extern crate semver;
+#[cfg(feature = "mime")]
+extern crate mime;
+#[cfg(feature = "ordered-float")]
+extern crate ordered_float;
+#[cfg(feature = "ordered-float")]
+extern crate num_traits;
+#[cfg(feature = "ipnet")]
+extern crate ipnet;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "mongodb")]
+extern crate mongodb;
 
 use std::{ u8, u16, u32, u64, usize, i8, i16, i32, i64, isize };
 use std::ffi::{ OsStr, OsString };
 use std::path::{ Path, PathBuf };
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, Shutdown };
+use std::cmp::Ordering;
+use std::num::FpCategory;
+use std::any::type_name;
 use std::marker::PhantomData;
-use std::hash::{ Hash, BuildHasher };
 use std::borrow::Cow;
 use std::rc::Rc;
 use std::ops::{ Range, RangeInclusive };
@@ -200,16 +443,170 @@ use std::collections::{
     VecDeque, BinaryHeap,
     LinkedList,
 };
-use bson::{ Bson, Document };
+use bson::{ Bson, Document, UtcDateTime, TimeStamp };
 use bson::oid::ObjectId;
 
-#[doc(hidden)]
 pub mod support;
+pub mod convert;
+pub mod compat;
+pub mod openapi;
+pub mod display;
+pub mod diff;
+pub mod combine;
+pub mod canonical;
+pub mod stats;
+pub mod optimize;
+pub mod config;
+pub mod recursive;
+pub mod definitions;
+pub mod collection;
+pub mod mongo;
+pub mod validate;
+pub mod testing;
+pub mod sample;
+pub mod export;
+pub mod error;
+pub mod key;
+pub mod builder;
+pub mod versioned;
+#[cfg(feature = "chrono")]
+pub mod time;
+mod cache;
+
+pub use recursive::{
+    SchemaContext, root_schema, root_schema_with_options,
+    try_root_schema, try_root_schema_with_options, flatten_refs,
+};
+pub use error::SchemaError;
+pub use key::BsonSchemaKey;
+pub use config::{ SchemaOptions, Nullability, UnitRepresentation, UuidRepresentation, U64Policy, FloatPolicy, CompatLevel, KeyPolicy, KeyStyle };
+pub use definitions::{ SchemaRegistry, RegistryError, NameCollisionError };
+pub use collection::{ ValidatorOptions, ValidationLevel, ValidationAction };
+pub use support::{ Bound, Bounds, BoundValue };
+pub use canonical::{ canonicalize, fingerprint };
+#[cfg(feature = "chrono")]
+pub use time::Iso8601Duration;
 
 /// Types which can be expressed/validated by a MongoDB-flavored JSON schema.
 pub trait BsonSchema {
     /// Returns a BSON document describing the MongoDB-flavored schema of this type.
     fn bson_schema() -> Document;
+
+    /// Like `bson_schema()`, but returns an error instead of panicking if
+    /// the schema can't be generated, e.g. because an internally-tagged
+    /// newtype variant wraps a type which can't hold the tag. The default
+    /// implementation simply wraps the result of `bson_schema()`, which
+    /// is correct for types whose schema generation can't fail.
+    /// `#[derive(BsonSchema)]` implements it in terms of `try_root_schema()`,
+    /// and `bson_schema()` in terms of `try_bson_schema()`.
+    fn try_bson_schema() -> Result<Document, SchemaError> {
+        Ok(Self::bson_schema())
+    }
+
+    /// Returns the schema of this type within a `SchemaContext`, which
+    /// makes it possible to describe recursive types using `$ref`s.
+    ///
+    /// The default implementation ignores the context and simply calls
+    /// `bson_schema()`, which is correct for non-generic, non-recursive
+    /// types. Types containing other types should override this method,
+    /// passing on the context to their constituents, and they should
+    /// implement `bson_schema()` in terms of `root_schema::<Self>()`.
+    /// `#[derive(BsonSchema)]` does all of this automatically.
+    fn bson_schema_with(_: &mut SchemaContext) -> Document {
+        Self::bson_schema()
+    }
+
+    /// Returns the schema of this type, generated according to `options`.
+    ///
+    /// The default implementation passes the options down to the
+    /// constituent types by means of `bson_schema_with()`, and for types
+    /// which don't override the latter, it's the same as `bson_schema()`.
+    fn bson_schema_with_options(options: &SchemaOptions) -> Document {
+        root_schema_with_options::<Self>(options)
+    }
+
+    /// Returns a name identifying this type, used as the key of its
+    /// schema among `definitions`. The default implementation returns
+    /// the unqualified name of the type, e.g. `Vec<Option<i32>>`.
+    fn schema_name() -> String {
+        support::unqualified_type_name(type_name::<Self>())
+    }
+
+    /// Returns a standard (draft-07) JSON Schema describing this type, for
+    /// consumers other than MongoDB. By default, it is obtained by converting
+    /// the result of `bson_schema()` using `convert::to_json_schema()`.
+    fn json_schema() -> Document {
+        convert::to_json_schema(Self::bson_schema())
+    }
+
+    /// Returns the result of `bson_schema()`, computing it only once per
+    /// type and process. Useful in hot code paths, e.g. when validating
+    /// every incoming request. The cache is thread-safe.
+    ///
+    /// `#[derive(BsonSchema)]` overrides this method for non-generic types,
+    /// storing the schema in a dedicated `static` instead of the shared
+    /// cache, and it also implements `bson_schema()` by cloning the result.
+    /// The schemas of generic types are built upon every `bson_schema()`
+    /// call, since a `static` can't depend on type parameters.
+    fn bson_schema_cached() -> &'static Document where Self: 'static {
+        cache::cached_schema::<Self>()
+    }
+}
+
+/// An object-safe counterpart of `BsonSchema`, for when the type whose
+/// schema is needed is only known at runtime, e.g. when iterating over a
+/// heterogeneous collection of values. It is implemented for every type
+/// that implements `BsonSchema`.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate bson;
+/// #[macro_use]
+/// extern crate magnet_derive;
+/// extern crate magnet_schema;
+///
+/// use magnet_schema::BsonSchemaDyn;
+///
+/// #[derive(BsonSchema)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// #[derive(BsonSchema)]
+/// struct Post {
+///     title: String,
+///     likes: u32,
+/// }
+///
+/// fn main() {
+///     let collections: Vec<(&str, Box<dyn BsonSchemaDyn>)> = vec![
+///         ("users", Box::new(User { name: String::from("admin") })),
+///         ("posts", Box::new(Post { title: String::new(), likes: 0 })),
+///     ];
+///
+///     for &(collection, ref prototype) in &collections {
+///         let schema = prototype.bson_schema_dyn();
+///         assert!(schema.contains_key("properties"));
+///         println!("{} ({}): {}", collection, prototype.schema_name_dyn(), schema);
+///     }
+/// }
+/// ```
+pub trait BsonSchemaDyn {
+    /// Returns the result of `BsonSchema::bson_schema()` for the type of `self`.
+    fn bson_schema_dyn(&self) -> Document;
+
+    /// Returns the result of `BsonSchema::schema_name()` for the type of `self`.
+    fn schema_name_dyn(&self) -> String;
+}
+
+impl<T> BsonSchemaDyn for T where T: ?Sized + BsonSchema {
+    fn bson_schema_dyn(&self) -> Document {
+        T::bson_schema()
+    }
+
+    fn schema_name_dyn(&self) -> String {
+        T::schema_name()
+    }
 }
 
 /////////////////////////////
@@ -242,7 +639,6 @@ impl_bson_schema_int! {
     u8 :  u8::MIN =>  u8::MAX;
     u16: u16::MIN => u16::MAX;
     u32: u32::MIN => u32::MAX;
-    u64: u64::MIN => i64::MAX; // !!! must not overflow i64
     i8 :  i8::MIN =>  i8::MAX;
     i16: i16::MIN => i16::MAX;
     i32: i32::MIN => i32::MAX;
@@ -262,15 +658,28 @@ impl BsonSchema for usize {
     }
 }
 
+/// The maximum is `i64::MAX` by default, since larger values don't fit
+/// into any BSON integer type. See `U64Policy` for the alternatives.
+impl BsonSchema for u64 {
+    fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        support::u64_schema(ctx.options().u64_policy)
+    }
+}
+
 /// Do **NOT** assume `sizeof(usize) <= sizeof(u64)`!!!
+/// Like `u64`, this is subject to the `U64Policy` in effect.
 #[cfg(target_pointer_width = "64")]
 impl BsonSchema for usize {
     fn bson_schema() -> Document {
-        doc! {
-            "bsonType": ["int", "long"],
-            "minimum": usize::MIN as i64,
-            "maximum": isize::MAX as i64,
-        }
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        support::u64_schema(ctx.options().u64_policy)
     }
 }
 
@@ -289,11 +698,17 @@ impl BsonSchema for isize {
     }
 }
 
+/// The range of `f32` isn't expressed as bounds, because infinities
+/// and NaN are valid `f32` values too, and they would be rejected.
 macro_rules! impl_bson_schema_float {
     ($($ty:ident,)*) => {$(
         impl BsonSchema for $ty {
             fn bson_schema() -> Document {
-                doc!{ "type": "number" }
+                root_schema::<Self>()
+            }
+
+            fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+                support::float_schema(ctx.options().float_policy)
             }
         }
     )*}
@@ -324,6 +739,46 @@ impl_bson_schema_string! {
     PathBuf,
 }
 
+/// Strings constrained by a validation regex.
+macro_rules! impl_bson_schema_pattern {
+    ($($ty:ty => $pattern:expr,)*) => {$(
+        impl BsonSchema for $ty {
+            fn bson_schema() -> Document {
+                doc! {
+                    "type": "string",
+                    "pattern": $pattern,
+                }
+            }
+        }
+    )*}
+}
+
+impl_bson_schema_pattern! {
+    IpAddr   => support::IP_ADDR_PATTERN,
+    Ipv4Addr => support::IPV4_ADDR_PATTERN,
+    Ipv6Addr => support::IPV6_ADDR_PATTERN,
+}
+
+/// Fieldless `std` enums, described by the names of their variants, like
+/// derived externally-tagged enums. `serde` doesn't implement `Serialize`
+/// for them, but a `#[serde(remote = "...")]` mirror which keeps the names
+/// of the variants serializes them as these strings.
+macro_rules! impl_bson_schema_unit_enum {
+    ($($ty:ty => [$($name:expr),*],)*) => {$(
+        impl BsonSchema for $ty {
+            fn bson_schema() -> Document {
+                support::string_enum_schema(&[$($name),*])
+            }
+        }
+    )*}
+}
+
+impl_bson_schema_unit_enum! {
+    Ordering   => ["Less", "Equal", "Greater"],
+    Shutdown   => ["Read", "Write", "Both"],
+    FpCategory => ["Nan", "Infinite", "Zero", "Subnormal", "Normal"],
+}
+
 ///////////////////////////////
 // Built-in parametric types //
 ///////////////////////////////
@@ -332,20 +787,32 @@ impl<'a, T> BsonSchema for &'a T where T: ?Sized + BsonSchema {
     fn bson_schema() -> Document {
         T::bson_schema()
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
 }
 
 impl<'a, T> BsonSchema for &'a mut T where T: ?Sized + BsonSchema {
     fn bson_schema() -> Document {
         T::bson_schema()
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
 }
 
 /// TODO(H2CO3): maybe specialize as binary for `[u8]`?
 impl<T> BsonSchema for [T] where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
@@ -353,13 +820,17 @@ impl<T> BsonSchema for [T] where T: BsonSchema {
 macro_rules! impl_bson_schema_array {
     ($($size:expr,)*) => {$(
         impl<T> BsonSchema for [T; $size] where T: BsonSchema {
-            #[allow(trivial_numeric_casts)]
             fn bson_schema() -> Document {
+                root_schema::<Self>()
+            }
+
+            #[allow(trivial_numeric_casts)]
+            fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
                 doc! {
                     "type": "array",
                     "minItems": $size as i64,
                     "maxItems": $size as i64,
-                    "items": T::bson_schema(),
+                    "items": T::bson_schema_with(ctx),
                 }
             }
         }
@@ -389,16 +860,27 @@ impl BsonSchema for () {
             "maxItems": 0_i64,
         }
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        match ctx.options().unit_representation {
+            UnitRepresentation::Permissive => Self::bson_schema(),
+            UnitRepresentation::Null => doc!{ "type": "null" },
+        }
+    }
 }
 
 macro_rules! impl_bson_schema_tuple {
     ($($ty:ident),*) => {
         impl<$($ty),*> BsonSchema for ($($ty),*) where $($ty: BsonSchema),* {
             fn bson_schema() -> Document {
+                root_schema::<Self>()
+            }
+
+            fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
                 doc! {
                     "type": "array",
                     "additionalItems": false,
-                    "items": [$($ty::bson_schema()),*],
+                    "items": [$($ty::bson_schema_with(ctx)),*],
                 }
             }
         }
@@ -426,16 +908,24 @@ impl_bson_schema_tuple!{ A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P }
 ///////////////////////////////////////
 
 /// TODO(H2CO3): maybe specialize for `Cow<[u8]>` as binary?
-impl<'a, T> BsonSchema for Cow<'a, T> where T: ?Sized + Clone + BsonSchema {
+impl<'a, T> BsonSchema for Cow<'a, T> where T: ?Sized + ToOwned + BsonSchema {
     fn bson_schema() -> Document {
         T::bson_schema()
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
 }
 
 impl<T> BsonSchema for Cell<T> where T: BsonSchema {
     fn bson_schema() -> Document {
         T::bson_schema()
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
 }
 
 macro_rules! impl_bson_schema_unsized {
@@ -444,6 +934,10 @@ macro_rules! impl_bson_schema_unsized {
             fn bson_schema() -> Document {
                 T::bson_schema()
             }
+
+            fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+                T::bson_schema_with(ctx)
+            }
         }
     )*}
 }
@@ -460,137 +954,144 @@ impl_bson_schema_unsized! {
 /// TODO(H2CO3): maybe specialize for `Vec<u8>` as binary?
 impl<T> BsonSchema for Vec<T> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
 impl<T> BsonSchema for VecDeque<T> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
 impl<T> BsonSchema for LinkedList<T> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
-impl<T> BsonSchema for BinaryHeap<T> where T: BsonSchema + Ord {
+impl<T> BsonSchema for BinaryHeap<T> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
 impl<T> BsonSchema for Option<T> where T: BsonSchema {
     fn bson_schema() -> Document {
-        let mut doc = T::bson_schema();
-        let null_bson_str = Bson::from("null");
-        let (type_key, old_type_spec) = match doc.remove("type") {
-            Some(spec) => ("type", spec),
-            None => match doc.remove("bsonType") {
-                Some(spec) => ("bsonType", spec),
-                None => {
-                    // type wasn't directly constrained;
-                    // as a last resort, check if it's an `enum`.
-                    if let Some(&mut Bson::Array(ref mut array)) = doc.get_mut("anyOf") {
-                        array.push(bson!({ "type": null_bson_str }));
-                    }
-                    return doc;
-                }
-            }
-        };
-        let new_type_spec = match old_type_spec {
-            Bson::String(_) => vec![
-                old_type_spec,
-                null_bson_str,
-            ],
-            Bson::Array(mut array) => {
-                // duplicate type strings are a schema error :(
-                if !array.iter().any(|item| item == &null_bson_str) {
-                    array.push(null_bson_str);
-                }
-
-                array
-            },
-            _ => panic!("invalid schema: `{}` isn't a string or array: {:?}",
-                        type_key, old_type_spec.element_type()),
-        };
+        root_schema::<Self>()
+    }
 
-        doc.insert(type_key, new_type_spec);
-        doc
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        let schema = T::bson_schema_with(ctx);
+        support::make_nullable(schema, ctx.options().nullability)
     }
 }
 
-impl<T, H> BsonSchema for HashSet<T, H>
-    where T: BsonSchema + Eq + Hash,
-          H: BuildHasher
-{
+impl<T, H> BsonSchema for HashSet<T, H> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
             "uniqueItems": true,
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
-impl<T> BsonSchema for BTreeSet<T> where T: BsonSchema + Ord {
+impl<T> BsonSchema for BTreeSet<T> where T: BsonSchema {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "array",
             "uniqueItems": true,
-            "items": T::bson_schema(),
+            "items": T::bson_schema_with(ctx),
         }
     }
 }
 
 impl<K, V, H> BsonSchema for HashMap<K, V, H>
-    where K: ToString + Eq + Hash,
-          V: BsonSchema,
-          H: BuildHasher
+    where K: BsonSchemaKey,
+          V: BsonSchema
 {
     fn bson_schema() -> Document {
-        doc! {
-            "type": "object",
-            "additionalProperties": V::bson_schema(),
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        let value_schema = V::bson_schema_with(ctx);
+
+        match K::known_keys() {
+            Some(keys) => support::exhaustive_map_schema(&keys, value_schema, false),
+            None => support::map_schema(K::key_pattern(), value_schema),
         }
     }
 }
 
 impl<K, V> BsonSchema for BTreeMap<K, V>
-    where K: ToString + Ord,
+    where K: BsonSchemaKey,
           V: BsonSchema
 {
     fn bson_schema() -> Document {
-        doc! {
-            "type": "object",
-            "additionalProperties": V::bson_schema(),
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        let value_schema = V::bson_schema_with(ctx);
+
+        match K::known_keys() {
+            Some(keys) => support::exhaustive_map_schema(&keys, value_schema, false),
+            None => support::map_schema(K::key_pattern(), value_schema),
         }
     }
 }
 
 impl<T: BsonSchema> BsonSchema for Range<T> {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "object",
             "additionalProperties": false,
             "required": ["start", "end"],
             "properties": {
-                "start": T::bson_schema(),
-                "end":   T::bson_schema(),
+                "start": T::bson_schema_with(ctx),
+                "end":   T::bson_schema_with(ctx),
             },
         }
     }
@@ -598,13 +1099,17 @@ impl<T: BsonSchema> BsonSchema for Range<T> {
 
 impl<T: BsonSchema> BsonSchema for RangeInclusive<T> {
     fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
         doc! {
             "type": "object",
             "additionalProperties": false,
             "required": ["start", "end"],
             "properties": {
-                "start": T::bson_schema(),
-                "end":   T::bson_schema(),
+                "start": T::bson_schema_with(ctx),
+                "end":   T::bson_schema_with(ctx),
             },
         }
     }
@@ -615,6 +1120,10 @@ impl<T> BsonSchema for PhantomData<T> {
         // it's just a unit struct
         <() as BsonSchema>::bson_schema()
     }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        <() as BsonSchema>::bson_schema_with(ctx)
+    }
 }
 
 ////////////////////////////////////////////////////////
@@ -633,12 +1142,38 @@ impl BsonSchema for ObjectId {
     }
 }
 
+/// A `Bson` value may be anything, so its schema doesn't constrain it at all.
+impl BsonSchema for Bson {
+    fn bson_schema() -> Document {
+        Document::new()
+    }
+}
+
+/// Like `Bson`, a JSON `Value` may be anything.
+impl BsonSchema for serde_json::Value {
+    fn bson_schema() -> Document {
+        Document::new()
+    }
+}
+
+impl BsonSchema for UtcDateTime {
+    fn bson_schema() -> Document {
+        doc!{ "bsonType": "date" }
+    }
+}
+
+impl BsonSchema for TimeStamp {
+    fn bson_schema() -> Document {
+        doc!{ "bsonType": "timestamp" }
+    }
+}
+
 #[cfg(feature = "url")]
 impl BsonSchema for url::Url {
     fn bson_schema() -> Document {
         doc! {
             "type": "string",
-            // TODO(H2CO3): validation regex pattern?
+            "pattern": support::URL_PATTERN,
         }
     }
 }
@@ -648,7 +1183,122 @@ impl BsonSchema for uuid::Uuid {
     fn bson_schema() -> Document {
         doc! {
             "type": "string",
-            "pattern": "^[[:xdigit:]]{8}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{12}$",
+            "pattern": support::UUID_PATTERN,
+        }
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        match ctx.options().uuid_representation {
+            UuidRepresentation::String => Self::bson_schema(),
+            UuidRepresentation::Binary => doc!{ "bsonType": "binData" },
         }
     }
 }
+
+#[cfg(feature = "bytes")]
+impl BsonSchema for bytes::Bytes {
+    fn bson_schema() -> Document {
+        doc!{ "bsonType": "binData" }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl BsonSchema for bytes::BytesMut {
+    fn bson_schema() -> Document {
+        doc!{ "bsonType": "binData" }
+    }
+}
+
+/// `Either` is serialized as an externally-tagged `enum`,
+/// with the variant names `Left` and `Right`.
+#[cfg(feature = "either")]
+impl<L, R> BsonSchema for either::Either<L, R>
+    where L: BsonSchema,
+          R: BsonSchema
+{
+    fn bson_schema() -> Document {
+        root_schema::<Self>()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        doc! {
+            "anyOf": [
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "minProperties": 1,
+                    "maxProperties": 1,
+                    "required": ["Left"],
+                    "properties": {
+                        "Left": L::bson_schema_with(ctx),
+                    },
+                },
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "minProperties": 1,
+                    "maxProperties": 1,
+                    "required": ["Right"],
+                    "properties": {
+                        "Right": R::bson_schema_with(ctx),
+                    },
+                },
+            ]
+        }
+    }
+}
+
+#[cfg(feature = "semver")]
+impl BsonSchema for semver::Version {
+    fn bson_schema() -> Document {
+        doc! {
+            "type": "string",
+            "pattern": support::SEMVER_PATTERN,
+        }
+    }
+}
+
+#[cfg(feature = "mime")]
+impl BsonSchema for mime::Mime {
+    fn bson_schema() -> Document {
+        doc! {
+            "type": "string",
+            "pattern": support::MIME_PATTERN,
+        }
+    }
+}
+
+#[cfg(feature = "ordered-float")]
+impl<T> BsonSchema for ordered_float::OrderedFloat<T>
+    where T: BsonSchema + num_traits::Float
+{
+    fn bson_schema() -> Document {
+        T::bson_schema()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
+}
+
+/// Note that `$jsonSchema` has no way of expressing that a number
+/// must not be NaN, so this schema is as permissive as that of `T`.
+#[cfg(feature = "ordered-float")]
+impl<T> BsonSchema for ordered_float::NotNan<T>
+    where T: BsonSchema + num_traits::Float
+{
+    fn bson_schema() -> Document {
+        T::bson_schema()
+    }
+
+    fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+        T::bson_schema_with(ctx)
+    }
+}
+
+#[cfg(feature = "ipnet")]
+impl_bson_schema_pattern! {
+    ipnet::IpNet   => support::IP_NET_PATTERN,
+    ipnet::Ipv4Net => support::IPV4_NET_PATTERN,
+    ipnet::Ipv6Net => support::IPV6_NET_PATTERN,
+}
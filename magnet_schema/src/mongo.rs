@@ -0,0 +1,251 @@
+//! Installing and inspecting the validators of collections in a live
+//! database, on top of the commands built by `ValidatorOptions`.
+//!
+//! Database access is abstracted away by the `CommandRunner` trait, so
+//! that this crate doesn't depend on any particular version of a MongoDB
+//! driver. It is implemented for closures, so adapting a driver is usually
+//! a one-liner that forwards the command to the driver's equivalent of
+//! `Database::run_command()`, and converts the reply back to a `Document`.
+//! Replies are checked for `ok`, so the runner may return them as-is even
+//! if the command failed on the server.
+//!
+//! With the `mongodb` feature, `CommandRunner` is also implemented for the
+//! synchronous `mongodb::sync::Database` of the official driver, which uses
+//! a newer version of `bson`. Documents are converted between the two by
+//! way of their binary encoding, see `to_driver_document()` and
+//! `from_driver_document()`.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! extern crate magnet_schema;
+//!
+//! use bson::Document;
+//! use magnet_schema::ValidatorOptions;
+//! use magnet_schema::mongo::ensure_validator;
+//!
+//! fn main() {
+//!     let mut commands = Vec::new();
+//!     let mut runner = |command: Document| -> Result<Document, String> {
+//!         commands.push(command);
+//!         // pretend that the database has no collections yet
+//!         Ok(doc!{ "cursor": { "firstBatch": [] }, "ok": 1.0 })
+//!     };
+//!
+//!     ensure_validator::<String, _>(&mut runner, "names", &ValidatorOptions::new()).unwrap();
+//!
+//!     assert!(commands[0].contains_key("listCollections"));
+//!     assert_eq!(commands[1].get_str("create").unwrap(), "names");
+//! }
+//! ```
+
+use std::fmt;
+use std::error::Error;
+use bson::{ Bson, Document };
+use collection::ValidatorOptions;
+use BsonSchema;
+
+/// Something that can run a command against a database and return its
+/// reply, e.g. a thin wrapper around a driver's database handle.
+pub trait CommandRunner {
+    /// The error returned when the command can't be run.
+    type Error;
+
+    /// Runs `command` and returns the reply of the server.
+    fn run_command(&mut self, command: Document) -> Result<Document, Self::Error>;
+}
+
+impl<F, E> CommandRunner for F where F: FnMut(Document) -> Result<Document, E> {
+    type Error = E;
+
+    fn run_command(&mut self, command: Document) -> Result<Document, Self::Error> {
+        self(command)
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl CommandRunner for mongodb::sync::Database {
+    type Error = DriverError;
+
+    fn run_command(&mut self, command: Document) -> Result<Document, Self::Error> {
+        let reply = mongodb::sync::Database::run_command(self, to_driver_document(&command)?, None)
+            .map_err(DriverError::Driver)?;
+
+        from_driver_document(&reply)
+    }
+}
+
+/// Converts a document to the version of `bson` used by the `mongodb` driver.
+#[cfg(feature = "mongodb")]
+pub fn to_driver_document(doc: &Document) -> Result<mongodb::bson::Document, DriverError> {
+    let mut bytes = Vec::new();
+
+    bson::encode_document(&mut bytes, doc).map_err(DriverError::Encoding)?;
+    mongodb::bson::Document::from_reader(bytes.as_slice()).map_err(|error| DriverError::Driver(error.into()))
+}
+
+/// Converts a document from the version of `bson` used by the `mongodb`
+/// driver. Fails for values that this version doesn't support, e.g. ones
+/// of type `decimal`.
+#[cfg(feature = "mongodb")]
+pub fn from_driver_document(doc: &mongodb::bson::Document) -> Result<Document, DriverError> {
+    let mut bytes = Vec::new();
+
+    doc.to_writer(&mut bytes).map_err(|error| DriverError::Driver(error.into()))?;
+    bson::decode_document(&mut bytes.as_slice()).map_err(DriverError::Decoding)
+}
+
+/// Installs the schema of `T` as the validator of the collection named
+/// `collection`, along with the validation level and action in `options`.
+/// If the collection doesn't exist, it's created with a `create` command,
+/// otherwise its validator is replaced using `collMod`.
+pub fn ensure_validator<T, R>(
+    runner: &mut R,
+    collection: &str,
+    options: &ValidatorOptions,
+) -> Result<(), CommandError<R::Error>>
+    where T: ?Sized + BsonSchema,
+          R: ?Sized + CommandRunner
+{
+    let command = if collection_info(runner, collection)?.is_some() {
+        options.coll_mod_command::<T>(collection)
+    } else {
+        options.create_collection_command::<T>(collection)
+    };
+
+    run_checked(runner, command).map(drop)
+}
+
+/// Returns the `$jsonSchema` of the validator of the collection named
+/// `collection`, e.g. for comparing it against the schema of a type using
+/// `diff::diff()`. Returns `None` if the collection doesn't exist, or if
+/// it doesn't have a `$jsonSchema` validator.
+pub fn fetch_validator<R>(
+    runner: &mut R,
+    collection: &str,
+) -> Result<Option<Document>, CommandError<R::Error>>
+    where R: ?Sized + CommandRunner
+{
+    let info = collection_info(runner, collection)?;
+    let schema = info.as_ref().and_then(|entry| {
+        entry.get_document("options").ok()?.get_document("validator").ok()?.get_document("$jsonSchema").ok()
+    });
+
+    Ok(schema.cloned())
+}
+
+/// Returns the `listCollections` entry describing `collection`, if it exists.
+fn collection_info<R>(
+    runner: &mut R,
+    collection: &str,
+) -> Result<Option<Document>, CommandError<R::Error>>
+    where R: ?Sized + CommandRunner
+{
+    let reply = run_checked(runner, doc!{
+        "listCollections": 1,
+        "filter": { "name": collection },
+    })?;
+    let batch = reply
+        .get_document("cursor")
+        .and_then(|cursor| cursor.get_array("firstBatch"))
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let info = batch.iter().find_map(|entry| match *entry {
+        Bson::Document(ref doc) if doc.get_str("name") == Ok(collection) => Some(doc.clone()),
+        _ => None,
+    });
+
+    Ok(info)
+}
+
+/// Runs `command`, and turns a reply indicating failure into an error.
+fn run_checked<R>(runner: &mut R, command: Document) -> Result<Document, CommandError<R::Error>>
+    where R: ?Sized + CommandRunner
+{
+    let reply = runner.run_command(command).map_err(CommandError::Runner)?;
+    let ok = match reply.get("ok") {
+        Some(&Bson::FloatingPoint(value)) => value != 0.0,
+        Some(&Bson::I32(value)) => value != 0,
+        Some(&Bson::I64(value)) => value != 0,
+        Some(&Bson::Boolean(value)) => value,
+        _ => false,
+    };
+
+    if ok {
+        Ok(reply)
+    } else {
+        Err(CommandError::Failed {
+            code: reply.get_i32("code").ok(),
+            message: reply.get_str("errmsg").unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+/// The reason why a command couldn't be carried out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError<E> {
+    /// The runner couldn't run the command, e.g. because of a network error.
+    Runner(E),
+    /// The server ran the command, but its reply doesn't have a true `ok`.
+    Failed {
+        /// The `code` of the reply, if any.
+        code: Option<i32>,
+        /// The `errmsg` of the reply, or an empty string if there's none.
+        message: String,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for CommandError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Runner(ref error) => write!(f, "can't run command: {}", error),
+            CommandError::Failed { code: Some(code), ref message } => {
+                write!(f, "command failed with code {}: {}", code, message)
+            },
+            CommandError::Failed { code: None, ref message } => write!(f, "command failed: {}", message),
+        }
+    }
+}
+
+impl<E: Error> Error for CommandError<E> {
+    fn description(&self) -> &str {
+        match *self {
+            CommandError::Runner(_) => "can't run command",
+            CommandError::Failed { .. } => "command failed",
+        }
+    }
+}
+
+/// The reason why a command couldn't be run by a `mongodb::sync::Database`.
+#[cfg(feature = "mongodb")]
+#[derive(Debug)]
+pub enum DriverError {
+    /// The driver failed, e.g. because the server couldn't be reached.
+    Driver(mongodb::error::Error),
+    /// The command couldn't be encoded.
+    Encoding(bson::EncoderError),
+    /// The reply couldn't be decoded.
+    Decoding(bson::DecoderError),
+}
+
+#[cfg(feature = "mongodb")]
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DriverError::Driver(ref error) => write!(f, "driver error: {}", error),
+            DriverError::Encoding(ref error) => write!(f, "can't encode command: {}", error),
+            DriverError::Decoding(ref error) => write!(f, "can't decode reply: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl Error for DriverError {
+    fn description(&self) -> &str {
+        match *self {
+            DriverError::Driver(_) => "driver error",
+            DriverError::Encoding(_) => "can't encode command",
+            DriverError::Decoding(_) => "can't decode reply",
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Conversion of MongoDB-flavored schemas to OpenAPI 3.0 Schema Objects.
+//!
+//! OpenAPI 3.0 uses a restricted and extended subset of JSON Schema:
+//! there is no `null` type (`nullable: true` is used instead), `type`
+//! must be a single string, `items` must be a single schema, and the
+//! `exclusiveMinimum` and `exclusiveMaximum` keywords are booleans.
+//! The conversion also prefers `oneOf` over `anyOf` for enums whose
+//! variants are provably mutually exclusive, and derives the `int32`
+//! or `int64` format of integers from their range.
+//!
+//! OpenAPI 3.1 is fully compatible with JSON Schema, so the output of
+//! `convert::to_json_subschema()` can be used for it directly.
+//!
+//! `$ref`s are left untouched. Schemas of recursive types should be
+//! flattened using `flatten_refs()` before conversion.
+
+use std::convert::TryFrom;
+use bson::{ Bson, Document };
+use convert::to_json_subschema;
+
+/// Converts a MongoDB-flavored schema into an OpenAPI 3.0 Schema Object.
+pub fn to_openapi_schema(doc: &Document) -> Document {
+    openapi_subschema(to_json_subschema(doc.clone()))
+}
+
+/// Converts a standard JSON (sub)schema into an OpenAPI 3.0 Schema Object.
+fn openapi_subschema(schema: Document) -> Document {
+    let mut doc: Document = convert_pattern_properties(schema)
+        .into_iter()
+        .filter(|(key, _)| key != "additionalItems")
+        .map(|(key, value)| {
+            let converted = convert_subschemas(&key, value);
+            (key, converted)
+        })
+        .collect();
+
+    convert_null_branches(&mut doc);
+    convert_type(&mut doc);
+    convert_exclusive_bound(&mut doc, "minimum", "exclusiveMinimum");
+    convert_exclusive_bound(&mut doc, "maximum", "exclusiveMaximum");
+    convert_integer_format(&mut doc);
+    convert_tuple_items(&mut doc);
+    convert_exclusive_branches(&mut doc);
+
+    doc
+}
+
+/// Removes `patternProperties`, which OpenAPI 3.0 doesn't support. The
+/// properties it matched would then be rejected by `additionalProperties:
+/// false`, as is the case with maps with restricted keys, so the subschema
+/// of a single pattern becomes that of `additionalProperties`. If there are
+/// several patterns, any additional properties are allowed instead. Either
+/// way, the restriction on the names of the properties is lost.
+fn convert_pattern_properties(mut doc: Document) -> Document {
+    let patterns = match doc.remove("patternProperties") {
+        Some(Bson::Document(patterns)) => patterns,
+        _ => return doc,
+    };
+
+    if doc.get("additionalProperties") == Some(&Bson::Boolean(false)) {
+        let mut subschemas = patterns.into_iter().map(|(_, subschema)| subschema);
+
+        match (subschemas.next(), subschemas.next()) {
+            (Some(subschema), None) => doc.insert("additionalProperties", subschema),
+            _ => doc.remove("additionalProperties"),
+        };
+    }
+
+    doc
+}
+
+/// Recursively converts the subschemas under the keyword `key`, if any.
+fn convert_subschemas(key: &str, subschemas: Bson) -> Bson {
+    match (key, subschemas) {
+        ("properties", Bson::Document(doc)) |
+        ("definitions", Bson::Document(doc)) => {
+            let converted: Document = doc
+                .into_iter()
+                .map(|(name, subschema)| (name, convert_subschema(subschema)))
+                .collect();
+
+            converted.into()
+        },
+        ("items", Bson::Array(array)) |
+        ("anyOf", Bson::Array(array)) |
+        ("oneOf", Bson::Array(array)) |
+        ("allOf", Bson::Array(array)) => {
+            array.into_iter().map(convert_subschema).collect::<Vec<_>>().into()
+        },
+        ("items", value) |
+        ("additionalProperties", value) |
+        ("not", value) => convert_subschema(value),
+        (_, value) => value,
+    }
+}
+
+/// Converts a value if it's a schema document, otherwise returns it unchanged.
+fn convert_subschema(value: Bson) -> Bson {
+    match value {
+        Bson::Document(doc) => openapi_subschema(doc).into(),
+        other => other,
+    }
+}
+
+/// Replaces `{ "type": "null" }` alternatives of an `anyOf` (which is how
+/// an `Option` of an enum is described) with `nullable: true`. Since the
+/// subschemas are already converted, these appear as `null`-only schemas.
+fn convert_null_branches(doc: &mut Document) {
+    let null_only = null_only_schema();
+    let had_null = match doc.get_mut("anyOf") {
+        Some(Bson::Array(branches)) => {
+            let len = branches.len();
+            branches.retain(|branch| branch != &null_only);
+            branches.len() < len
+        },
+        _ => false,
+    };
+
+    if had_null {
+        doc.insert("nullable", true);
+    }
+}
+
+/// The OpenAPI 3.0 representation of a schema only matching `null`.
+fn null_only_schema() -> Bson {
+    bson!({ "nullable": true, "enum": [null] })
+}
+
+/// Replaces an array-valued `type` by a single type, expressing `null`
+/// using `nullable` and multiple types using `anyOf`.
+fn convert_type(doc: &mut Document) {
+    let mut types = match doc.remove("type") {
+        Some(Bson::Array(types)) => types,
+        Some(Bson::String(ref t)) if t == "null" => vec![Bson::from("null")],
+        Some(other) => {
+            doc.insert_bson("type".into(), other);
+            return;
+        },
+        None => return,
+    };
+
+    let len = types.len();
+    types.retain(|t| t != &Bson::from("null"));
+
+    if types.len() < len {
+        doc.insert("nullable", true);
+    }
+
+    match types.len() {
+        0 => {
+            doc.insert("enum", vec![Bson::Null]);
+        },
+        1 => {
+            doc.insert_bson("type".into(), types.remove(0));
+        },
+        _ => if !doc.contains_key("anyOf") {
+            let branches: Vec<Bson> = types.into_iter().map(|t| bson!({ "type": t })).collect();
+            doc.insert("anyOf", branches);
+        },
+    }
+}
+
+/// Converts a draft-07 numeric `exclusiveMinimum` or `exclusiveMaximum`
+/// back to the boolean form used by OpenAPI 3.0.
+fn convert_exclusive_bound(doc: &mut Document, bound_key: &str, exclusive_key: &str) {
+    match doc.remove(exclusive_key) {
+        Some(Bson::Boolean(flag)) => {
+            doc.insert(exclusive_key, flag);
+        },
+        Some(bound) => {
+            doc.insert_bson(bound_key.into(), bound);
+            doc.insert(exclusive_key, true);
+        },
+        None => {},
+    }
+}
+
+/// Adds the `int32` or `int64` format to an integer schema
+/// without one, based on its `minimum` and `maximum`.
+fn convert_integer_format(doc: &mut Document) {
+    if doc.get_str("type") != Ok("integer") || doc.contains_key("format") {
+        return;
+    }
+
+    let bound = |key| match doc.get(key) {
+        Some(&Bson::I32(value)) => Some(i64::from(value)),
+        Some(&Bson::I64(value)) => Some(value),
+        _ => None,
+    };
+    let fits_i32 = match (bound("minimum"), bound("maximum")) {
+        (Some(min), Some(max)) => min >= i64::from(i32::MIN) && max <= i64::from(i32::MAX),
+        _ => false,
+    };
+
+    doc.insert("format", if fits_i32 { "int32" } else { "int64" });
+}
+
+/// Replaces the array form of `items`, which describes tuples, with the
+/// single-schema form, constraining the length of the array instead.
+fn convert_tuple_items(doc: &mut Document) {
+    let items = match doc.remove("items") {
+        Some(Bson::Array(items)) => items,
+        Some(other) => {
+            doc.insert_bson("items".into(), other);
+            return;
+        },
+        None => return,
+    };
+
+    let len = i64::try_from(items.len()).unwrap_or(i64::MAX);
+
+    if !doc.contains_key("minItems") {
+        doc.insert("minItems", len);
+    }
+    if !doc.contains_key("maxItems") {
+        doc.insert("maxItems", len);
+    }
+
+    let mut distinct_items: Vec<Bson> = Vec::with_capacity(items.len());
+
+    for item in items {
+        if !distinct_items.contains(&item) {
+            distinct_items.push(item);
+        }
+    }
+
+    let item_schema = match distinct_items.len() {
+        0 => return,
+        1 => distinct_items.remove(0),
+        _ => bson!({ "anyOf": distinct_items }),
+    };
+
+    doc.insert_bson("items".into(), item_schema);
+}
+
+/// Renames `anyOf` to `oneOf` if at most one of its alternatives can
+/// ever match, which is the case for tagged enums.
+fn convert_exclusive_branches(doc: &mut Document) {
+    let exclusive = match doc.get("anyOf") {
+        Some(Bson::Array(branches)) => branches_are_exclusive(branches),
+        _ => false,
+    };
+
+    if exclusive && !doc.contains_key("oneOf") {
+        if let Some(branches) = doc.remove("anyOf") {
+            doc.insert_bson("oneOf".into(), branches);
+        }
+    }
+}
+
+/// Checks whether a list of alternatives describes the variants of an
+/// externally, internally, or adjacently tagged enum. Unit variants of
+/// externally tagged enums are described by string `enum`s, which never
+/// match the objects describing the other variants.
+fn branches_are_exclusive(branches: &[Bson]) -> bool {
+    let mut values = Vec::new();
+    let mut objects = Vec::new();
+
+    for branch in branches {
+        let doc = match *branch {
+            Bson::Document(ref doc) => doc,
+            _ => return false,
+        };
+
+        if doc.len() == 1 {
+            if let Ok(variants) = doc.get_array("enum") {
+                if variants.iter().any(|v| values.contains(v) || v.as_str().is_none()) {
+                    return false;
+                }
+                values.extend(variants.iter().cloned());
+                continue;
+            }
+        }
+
+        if doc.get_str("type") != Ok("object") {
+            return false;
+        }
+
+        objects.push(doc);
+    }
+
+    objects_are_externally_tagged(&objects) || objects_are_internally_tagged(&objects)
+}
+
+/// Checks whether every object has exactly one, distinct, mandatory key,
+/// and no other keys.
+fn objects_are_externally_tagged(objects: &[&Document]) -> bool {
+    let mut keys = Vec::new();
+
+    for object in objects {
+        let key = match object.get_array("required") {
+            Ok(required) if required.len() == 1 => &required[0],
+            _ => return false,
+        };
+
+        if object.get("additionalProperties") != Some(&Bson::Boolean(false)) || keys.contains(&key) {
+            return false;
+        }
+
+        keys.push(key);
+    }
+
+    true
+}
+
+/// Checks whether every object has the same mandatory tag property,
+/// with a distinct single allowed value.
+fn objects_are_internally_tagged(objects: &[&Document]) -> bool {
+    let tag_value = |object: &Document, tag: &str| -> Option<Bson> {
+        let required = object.get_array("required").ok()?;
+        let property = object.get_document("properties").ok()?.get_document(tag).ok()?;
+
+        match property.get_array("enum") {
+            Ok(values) if values.len() == 1 && required.contains(&Bson::from(tag)) => {
+                Some(values[0].clone())
+            },
+            _ => None,
+        }
+    };
+
+    let first = match objects.first() {
+        Some(first) => first,
+        None => return true,
+    };
+    let candidates = first.get_document("properties").map(Document::keys);
+
+    candidates.into_iter().flatten().any(|tag| {
+        let mut values = Vec::new();
+
+        objects.iter().all(|object| match tag_value(object, tag) {
+            Some(value) if !values.contains(&value) => {
+                values.push(value);
+                true
+            },
+            _ => false,
+        })
+    })
+}
+
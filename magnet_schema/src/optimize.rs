@@ -0,0 +1,225 @@
+//! Making schemas smaller without changing which documents they accept,
+//! e.g. before sending them to MongoDB in a `collMod` command.
+//!
+//! `minimize()` removes:
+//!
+//! * Keywords equal to their default value, e.g. `exclusiveMinimum: false`,
+//!   `additionalProperties: true`, `uniqueItems: false`, `minItems: 0`, or an
+//!   empty `required` array.
+//! * `additionalItems` next to `items` which is not a list of schemas, or
+//!   no `items` at all, since it's ignored then.
+//! * `minimum` and `maximum` which can't exclude any value, because the
+//!   only numeric BSON types allowed are `int` and `long`, and the bound is
+//!   the smallest or largest `long`, e.g. those of `i64`.
+//! * Repeated, identical branches of `anyOf` and `allOf`. (Those of `oneOf`
+//!   are kept, since repeating a branch makes it fail.) Branches are
+//!   compared regardless of the order of their keys.
+//!
+//! Generated schemas are not minimized by default, since e.g. the bounds of
+//! `i64` document the range of the type, even if they are redundant.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! extern crate magnet_schema;
+//!
+//! use magnet_schema::BsonSchema;
+//! use magnet_schema::optimize::minimize;
+//!
+//! fn main() {
+//!     assert_eq!(minimize(&i64::bson_schema()), doc!{ "bsonType": ["int", "long"] });
+//! }
+//! ```
+
+use bson::{ Bson, Document };
+use canonical::canonicalize;
+
+/// Keywords which can be removed if their value is the given default.
+/// Numbers are compared by value, regardless of their BSON type.
+const DEFAULT_VALUES: &[(&str, DefaultValue)] = &[
+    ("exclusiveMinimum",     DefaultValue::Bool(false)),
+    ("exclusiveMaximum",     DefaultValue::Bool(false)),
+    ("additionalProperties", DefaultValue::Bool(true)),
+    ("additionalItems",      DefaultValue::Bool(true)),
+    ("uniqueItems",          DefaultValue::Bool(false)),
+    ("minItems",             DefaultValue::Zero),
+    ("minProperties",        DefaultValue::Zero),
+    ("minLength",            DefaultValue::Zero),
+    ("required",             DefaultValue::Empty),
+    ("properties",           DefaultValue::Empty),
+    ("patternProperties",    DefaultValue::Empty),
+    ("dependencies",         DefaultValue::Empty),
+    ("definitions",          DefaultValue::Empty),
+];
+
+/// The default value of a keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefaultValue {
+    /// A boolean.
+    Bool(bool),
+    /// The number 0.
+    Zero,
+    /// An empty array or document.
+    Empty,
+}
+
+impl DefaultValue {
+    /// Returns `true` if `value` is this default value. A subschema of
+    /// `additionalProperties` or `additionalItems` which is an empty
+    /// document accepts everything, just like `true`.
+    fn matches(self, value: &Bson) -> bool {
+        match (self, value) {
+            (DefaultValue::Bool(expected), &Bson::Boolean(actual)) => expected == actual,
+            (DefaultValue::Bool(true), Bson::Document(doc)) => doc.is_empty(),
+            (DefaultValue::Zero, &Bson::I32(number)) => number == 0,
+            (DefaultValue::Zero, &Bson::I64(number)) => number == 0,
+            (DefaultValue::Zero, &Bson::FloatingPoint(number)) => number == 0.0,
+            (DefaultValue::Empty, Bson::Array(array)) => array.is_empty(),
+            (DefaultValue::Empty, Bson::Document(doc)) => doc.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Returns an equivalent, but potentially smaller version of `schema`,
+/// as documented at the module level.
+pub fn minimize(schema: &Document) -> Document {
+    let mut doc: Document = schema
+        .iter()
+        .map(|(key, value)| (key.clone(), minimize_subschemas(key, value)))
+        .collect();
+
+    remove_redundant_bounds(&mut doc);
+    remove_ignored_additional_items(&mut doc);
+
+    for &(keyword, default) in DEFAULT_VALUES {
+        if doc.get(keyword).is_some_and(|value| default.matches(value)) {
+            doc.remove(keyword);
+        }
+    }
+
+    doc
+}
+
+/// Minimizes the subschemas under the keyword `key`, if any.
+fn minimize_subschemas(key: &str, value: &Bson) -> Bson {
+    match (key, value) {
+        ("properties", Bson::Document(doc)) |
+        ("patternProperties", Bson::Document(doc)) |
+        ("definitions", Bson::Document(doc)) |
+        ("dependencies", Bson::Document(doc)) => {
+            let minimized: Document = doc
+                .iter()
+                .map(|(name, subschema)| (name.clone(), minimize_subschema(subschema)))
+                .collect();
+
+            minimized.into()
+        },
+        ("anyOf", Bson::Array(array)) |
+        ("allOf", Bson::Array(array)) => {
+            let mut branches: Vec<Bson> = Vec::with_capacity(array.len());
+            let mut canonical_branches: Vec<Bson> = Vec::with_capacity(array.len());
+
+            for branch in array {
+                let minimized = minimize_subschema(branch);
+                let canonical = match minimized {
+                    Bson::Document(ref doc) => canonicalize(doc).into(),
+                    ref other => other.clone(),
+                };
+
+                if !canonical_branches.contains(&canonical) {
+                    canonical_branches.push(canonical);
+                    branches.push(minimized);
+                }
+            }
+
+            branches.into()
+        },
+        ("items", Bson::Array(array)) |
+        ("oneOf", Bson::Array(array)) => {
+            array.iter().map(minimize_subschema).collect::<Vec<_>>().into()
+        },
+        ("items", _) |
+        ("additionalItems", _) |
+        ("additionalProperties", _) |
+        ("not", _) => minimize_subschema(value),
+        _ => value.clone(),
+    }
+}
+
+/// Minimizes a value if it's a schema document, otherwise returns it unchanged.
+fn minimize_subschema(value: &Bson) -> Bson {
+    match *value {
+        Bson::Document(ref doc) => minimize(doc).into(),
+        ref other => other.clone(),
+    }
+}
+
+/// Removes `minimum` and `maximum` if they are the bounds of `long`, and
+/// only `int` and `long` numbers are allowed, i.e. they can't exclude any
+/// value. An exclusive bound, on the other hand, does exclude one.
+#[allow(clippy::cast_precision_loss)]
+fn remove_redundant_bounds(doc: &mut Document) {
+    if !only_integers(doc) {
+        return;
+    }
+
+    // a boolean `exclusiveMinimum: true` makes `minimum` exclude itself,
+    // whereas a numeric one is a separate bound, which is left alone
+    let is_inclusive = |schema: &Document, exclusive_key: &str| {
+        schema.get(exclusive_key) != Some(&Bson::Boolean(true))
+    };
+
+    let min_redundant = match doc.get("minimum") {
+        Some(&Bson::I64(min)) => min == i64::MIN,
+        Some(&Bson::FloatingPoint(min)) => min <= i64::MIN as f64,
+        _ => false,
+    };
+
+    if min_redundant && is_inclusive(doc, "exclusiveMinimum") {
+        doc.remove("minimum");
+    }
+
+    let max_redundant = match doc.get("maximum") {
+        Some(&Bson::I64(max)) => max == i64::MAX,
+        Some(&Bson::FloatingPoint(max)) => max >= i64::MAX as f64,
+        _ => false,
+    };
+
+    if max_redundant && is_inclusive(doc, "exclusiveMaximum") {
+        doc.remove("maximum");
+    }
+}
+
+/// Returns `true` if the `bsonType` of a schema only allows `int` and
+/// `long` among the numeric types, and there is no `type` which could
+/// allow others. Non-numeric types don't matter, since bounds don't
+/// apply to them.
+fn only_integers(doc: &Document) -> bool {
+    /// Numeric types, other than `int` and `long`, and their aliases.
+    const OTHER_NUMBERS: &[&str] = &["double", "decimal", "number"];
+
+    if doc.contains_key("type") {
+        return false;
+    }
+
+    match doc.get("bsonType") {
+        Some(Bson::String(ty)) => !OTHER_NUMBERS.contains(&ty.as_str()),
+        Some(Bson::Array(types)) => types.iter().all(|ty| match *ty {
+            Bson::String(ref name) => !OTHER_NUMBERS.contains(&name.as_str()),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Removes `additionalItems` unless `items` is an array of schemas,
+/// because it has no effect otherwise.
+fn remove_ignored_additional_items(doc: &mut Document) {
+    match doc.get("items") {
+        Some(&Bson::Array(_)) => {},
+        _ => {
+            doc.remove("additionalItems");
+        },
+    }
+}
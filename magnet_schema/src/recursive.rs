@@ -0,0 +1,386 @@
+//! Schema generation for recursive types, by means of `$ref` and `definitions`.
+//!
+//! A self-referential type, e.g. `struct Tree { children: Vec<Tree> }`,
+//! would make schema generation recurse forever. A `SchemaContext` keeps
+//! track of the types whose schema is currently being generated, and when
+//! such a type is encountered again, it emits a `$ref` pointing into the
+//! `definitions` of the root schema instead of recursing.
+//!
+//! The same mechanism allows generating the schema of a type that occurs
+//! more than once, e.g. in `Vec<(Option<Big>, Option<Big>)>`, only once,
+//! and referring to it everywhere, if the `share_definitions` option is set.
+//!
+//! MongoDB's `$jsonSchema` doesn't support `$ref`, so `flatten_refs()` is
+//! provided for inlining references up to a certain depth.
+
+use std::any::type_name;
+use std::collections::{ HashSet, HashMap };
+use bson::{ Bson, Document };
+use config::SchemaOptions;
+use compat;
+use convert;
+use support;
+use error::SchemaError;
+use BsonSchema;
+
+/// The prefix of `$ref` JSON pointers emitted by `SchemaContext`.
+const DEFINITIONS_PREFIX: &str = "#/definitions/";
+
+/// Keeps track of in-progress and already-defined named schemas while
+/// generating the schema of a (potentially recursive) type.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaContext {
+    /// The identities of the types whose schema is currently being generated.
+    in_progress: HashSet<String>,
+    /// The identities of in-progress types which have been referred to recursively.
+    referenced: HashSet<String>,
+    /// The key of the definition of each type encountered, by identity.
+    keys: HashMap<String, String>,
+    /// The definitions collected so far, keyed by type name.
+    definitions: Document,
+    /// Options affecting the generated schemas.
+    options: SchemaOptions,
+    /// The first error encountered while generating schemas, if any.
+    error: Option<SchemaError>,
+}
+
+impl SchemaContext {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        SchemaContext::default()
+    }
+
+    /// Creates an empty context, generating schemas according to `options`.
+    pub fn with_options(options: SchemaOptions) -> Self {
+        SchemaContext {
+            options,
+            ..SchemaContext::default()
+        }
+    }
+
+    /// Returns the options in effect.
+    pub fn options(&self) -> &SchemaOptions {
+        &self.options
+    }
+
+    /// Returns the schema of `T`, generated within this context.
+    pub fn schema_for<T: ?Sized + BsonSchema>(&mut self) -> Document {
+        T::bson_schema_with(self)
+    }
+
+    /// Generates the schema of a named type using the `generate` callback.
+    ///
+    /// If a schema with the same name is already being generated (i.e. the
+    /// type is recursive), or it has already been added to the definitions,
+    /// then a `$ref` to it is returned, and `generate` is not called.
+    ///
+    /// Otherwise, the schema is generated and returned inline. If it turns out
+    /// to be recursive, it is also added to the definitions, so that the `$ref`s
+    /// within it can be resolved.
+    ///
+    /// If the `share_definitions` option is set, then the schemas of named
+    /// types other than the root one are always added to the definitions,
+    /// and every occurrence of them is a `$ref`.
+    pub fn define<F>(&mut self, name: &str, generate: F) -> Document
+        where F: FnOnce(&mut Self) -> Document
+    {
+        self.define_as(name, name, generate)
+    }
+
+    /// Like `define()`, but tells types apart by `T` itself rather than by
+    /// `name`, so that different types with the same name, e.g. `a::Node`
+    /// and `b::Node`, don't end up sharing a definition. The first of them
+    /// keeps `name` as the key of its definition, and the others are keyed
+    /// by their fully qualified type name. This is what the derive uses.
+    pub fn define_type<T, F>(&mut self, name: &str, generate: F) -> Document
+        where T: ?Sized,
+              F: FnOnce(&mut Self) -> Document
+    {
+        self.define_as(type_name::<T>(), name, generate)
+    }
+
+    /// Generates the schema of the type identified by `id`, which is
+    /// defined under the key `name` unless another type has claimed it.
+    fn define_as<F>(&mut self, id: &str, name: &str, generate: F) -> Document
+        where F: FnOnce(&mut Self) -> Document
+    {
+        let key = self.definition_key(id, name);
+
+        if self.in_progress.contains(id) {
+            self.referenced.insert(id.to_owned());
+            return Self::reference(&key);
+        }
+
+        if self.definitions.contains_key(&key) {
+            return Self::reference(&key);
+        }
+
+        let is_root = self.in_progress.is_empty();
+
+        self.in_progress.insert(id.to_owned());
+        let schema = generate(self);
+        self.in_progress.remove(id);
+
+        if self.referenced.remove(id) {
+            self.definitions.insert(key, schema.clone());
+        } else if self.options.share_definitions && !is_root {
+            let reference = Self::reference(&key);
+            self.definitions.insert(key, schema);
+            return reference;
+        }
+
+        schema
+    }
+
+    /// Returns the key of the definition of the type identified by `id`.
+    /// The key is claimed when the type is first encountered: it is `name`
+    /// if that is still free, and `id` otherwise.
+    fn definition_key(&mut self, id: &str, name: &str) -> String {
+        if let Some(key) = self.keys.get(id) {
+            return key.clone();
+        }
+
+        let mut key = name.to_owned();
+        let mut suffix = 1;
+
+        if self.keys.values().any(|claimed| *claimed == key) {
+            key = id.to_owned();
+        }
+
+        while self.keys.values().any(|claimed| *claimed == key) {
+            suffix += 1;
+            key = format!("{}~{}", id, suffix);
+        }
+
+        self.keys.insert(id.to_owned(), key.clone());
+        key
+    }
+
+    /// Returns a `$ref` schema pointing to the definition of the named type.
+    pub fn reference(name: &str) -> Document {
+        doc!{ "$ref": format!("{}{}", DEFINITIONS_PREFIX, encode_pointer_token(name)) }
+    }
+
+    /// Returns the definitions collected so far.
+    pub fn definitions(&self) -> &Document {
+        &self.definitions
+    }
+
+    /// Generates a schema using the fallible `generate` callback. If it
+    /// fails, the error is recorded (unless an earlier one has already
+    /// been recorded), and an empty schema is returned in place of the
+    /// erroneous one, so that the generation of the rest of the schema
+    /// can go on. The error is eventually reported by `try_finish()`.
+    pub fn try_generate<F>(&mut self, generate: F) -> Document
+        where F: FnOnce(&mut Self) -> Result<Document, SchemaError>
+    {
+        generate(self).unwrap_or_else(|error| {
+            if self.error.is_none() {
+                self.error = Some(error);
+            }
+            Document::new()
+        })
+    }
+
+    /// Returns the first error recorded by `try_generate()`, if any.
+    pub fn error(&self) -> Option<&SchemaError> {
+        self.error.as_ref()
+    }
+
+    /// Consumes the context, and attaches the collected definitions, if
+    /// any, to the given root `schema` under the `definitions` key. The
+    /// result is then rewritten according to the `key_style` and the
+    /// `compat_level` options.
+    ///
+    /// # Panics
+    ///
+    /// If an error has been recorded by `try_generate()`.
+    /// See `try_finish()` for a fallible version.
+    pub fn finish(self, schema: Document) -> Document {
+        self.try_finish(schema).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `finish()`, but returns the first recorded error, if any,
+    /// instead of panicking.
+    pub fn try_finish(self, mut schema: Document) -> Result<Document, SchemaError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        if !self.definitions.is_empty() {
+            schema.insert("definitions", self.definitions);
+        }
+
+        support::check_keys(&schema, self.options.key_policy)?;
+
+        let styled = convert::to_key_style(schema, self.options.key_style);
+
+        Ok(compat::downlevel(styled, self.options.compat_level))
+    }
+}
+
+/// Escapes a name for use as a JSON pointer token within a URI fragment,
+/// so that generic schema names like `Tree<i32>` can be referred to.
+fn encode_pointer_token(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+
+    for ch in name.chars() {
+        match ch {
+            '~' => encoded.push_str("~0"),
+            '/' => encoded.push_str("~1"),
+            'A'..='Z' | 'a'..='z' | '0'..='9' |
+            '-' | '.' | '_' | '!' | '$' | '&' | '\'' | '(' | ')' |
+            '*' | '+' | ',' | ';' | '=' | ':' | '@' => encoded.push(ch),
+            _ => {
+                let mut buf = [0; 4];
+                for byte in ch.encode_utf8(&mut buf).bytes() {
+                    encoded.push_str(&format!("%{:02X}", byte));
+                }
+            },
+        }
+    }
+
+    encoded
+}
+
+/// Generates the schema of `T` in a fresh context, and attaches
+/// the definitions of recursive types, if any, to the result.
+pub fn root_schema<T: ?Sized + BsonSchema>() -> Document {
+    root_schema_with_options::<T>(&SchemaOptions::default())
+}
+
+/// Like `root_schema()`, but generates the schema according to `options`.
+pub fn root_schema_with_options<T: ?Sized + BsonSchema>(options: &SchemaOptions) -> Document {
+    let mut ctx = SchemaContext::with_options(*options);
+    let schema = T::bson_schema_with(&mut ctx);
+    ctx.finish(schema)
+}
+
+/// Like `root_schema()`, but returns an error instead of panicking if
+/// the schema of `T` can't be generated.
+pub fn try_root_schema<T: ?Sized + BsonSchema>() -> Result<Document, SchemaError> {
+    try_root_schema_with_options::<T>(&SchemaOptions::default())
+}
+
+/// Like `root_schema_with_options()`, but returns an error instead of
+/// panicking if the schema of `T` can't be generated.
+pub fn try_root_schema_with_options<T: ?Sized + BsonSchema>(options: &SchemaOptions) -> Result<Document, SchemaError> {
+    let mut ctx = SchemaContext::with_options(*options);
+    let schema = T::bson_schema_with(&mut ctx);
+    ctx.try_finish(schema)
+}
+
+/// Inlines the `$ref`s of a root schema using its `definitions`, and removes
+/// the `definitions` themselves, so that the result is usable with MongoDB.
+///
+/// Recursive references, i.e. ones to a definition which is already being
+/// inlined, are expanded at most `depth_limit` times along any path. Other
+/// references, e.g. the ones emitted by the `share_definitions` option for
+/// types which merely occur more than once, are always inlined. Deeper
+/// recursive references are replaced with a schema that rejects every
+/// value, i.e. `{ "not": {} }`, so that unvalidated data can't slip through.
+/// References not pointing into the `definitions` are left alone.
+pub fn flatten_refs(mut schema: Document, depth_limit: usize) -> Document {
+    let definitions = match schema.remove("definitions") {
+        Some(Bson::Document(definitions)) => definitions,
+        Some(other) => {
+            schema.insert_bson("definitions".into(), other);
+            return schema;
+        },
+        None => return schema,
+    };
+
+    // the root schema of a recursive type is also one of the definitions
+    let root = definitions.iter().find(|&(_, definition)| match *definition {
+        Bson::Document(ref doc) => *doc == schema,
+        _ => false,
+    });
+    let mut expansion = Expansion {
+        definitions: &definitions,
+        path: root
+            .map(|(name, _)| format!("{}{}", DEFINITIONS_PREFIX, encode_pointer_token(name)))
+            .into_iter()
+            .collect(),
+        depth_limit,
+    };
+
+    flatten_doc(schema, &mut expansion)
+}
+
+/// Looks up the definition a `$ref` JSON pointer refers to within the
+/// `definitions` of a root schema. Returns `None` if the pointer doesn't
+/// point into the `definitions`, or if there's no such definition.
+pub fn resolve_ref<'a>(pointer: &str, definitions: &'a Document) -> Option<&'a Document> {
+    if !pointer.starts_with(DEFINITIONS_PREFIX) {
+        return None;
+    }
+
+    let token = &pointer[DEFINITIONS_PREFIX.len()..];
+
+    definitions.iter().find(|&(name, _)| encode_pointer_token(name) == token).and_then(
+        |(_, value)| match *value {
+            Bson::Document(ref definition) => Some(definition),
+            _ => None,
+        }
+    )
+}
+
+/// The definitions being inlined along the current path, for telling
+/// recursive references apart from merely shared ones.
+struct Expansion<'a> {
+    /// The `definitions` of the root schema.
+    definitions: &'a Document,
+    /// The `$ref` pointers whose definitions are being inlined.
+    path: Vec<String>,
+    /// How many more times a reference already on `path` may be inlined.
+    depth_limit: usize,
+}
+
+/// Inlines the `$ref`s in a (sub)schema document. A reference to a
+/// definition which is already being inlined is recursive, and only
+/// these count towards the depth limit.
+fn flatten_doc(doc: Document, expansion: &mut Expansion) -> Document {
+    let target = doc
+        .get_str("$ref")
+        .ok()
+        .and_then(|pointer| resolve_ref(pointer, expansion.definitions).map(|def| (pointer, def)));
+
+    let (pointer, definition) = match target {
+        Some((pointer, definition)) => (pointer.to_owned(), definition.clone()),
+        None => return doc
+            .into_iter()
+            .map(|(key, value)| (key, flatten_bson(value, expansion)))
+            .collect(),
+    };
+    let is_recursive = expansion.path.contains(&pointer);
+
+    if is_recursive {
+        if expansion.depth_limit == 0 {
+            return doc!{ "not": {} };
+        }
+        expansion.depth_limit -= 1;
+    }
+
+    expansion.path.push(pointer);
+    let flattened = flatten_doc(definition, expansion);
+    expansion.path.pop();
+
+    if is_recursive {
+        expansion.depth_limit += 1;
+    }
+
+    flattened
+}
+
+/// Inlines the `$ref`s in any subschemas contained in a BSON value.
+fn flatten_bson(value: Bson, expansion: &mut Expansion) -> Bson {
+    match value {
+        Bson::Document(doc) => flatten_doc(doc, expansion).into(),
+        Bson::Array(array) => array
+            .into_iter()
+            .map(|item| flatten_bson(item, expansion))
+            .collect::<Vec<_>>()
+            .into(),
+        other => other,
+    }
+}
@@ -0,0 +1,406 @@
+//! Generating sample values that satisfy a schema, e.g. for seeding test
+//! databases or for illustrating the shape of documents in examples.
+//!
+//! The output is deterministic: the same schema always results in the
+//! same sample. Samples are as small as possible while still satisfying
+//! the schema:
+//!
+//! * Only `required` properties are included.
+//! * The first value of an `enum` is used.
+//! * Numbers are the midpoint of their bounds, or `0` if unbounded.
+//! * Strings are the shortest allowed by `minLength`. The patterns in
+//!   the `support` module are recognized, and an example matching them
+//!   is used. Other patterns aren't taken into account.
+//! * Arrays contain as few items as `minItems` allows.
+//! * The first alternative of an `anyOf` or `oneOf` is used, except that
+//!   alternatives referring to recursive types are avoided if possible.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! extern crate magnet_schema;
+//!
+//! use magnet_schema::sample::sample_from;
+//!
+//! fn main() {
+//!     let schema = doc!{
+//!         "type": "object",
+//!         "required": ["name", "level"],
+//!         "properties": {
+//!             "name": { "type": "string", "minLength": 3 },
+//!             "level": { "bsonType": "int", "minimum": 1, "maximum": 9 },
+//!             "nick": { "type": "string" },
+//!         },
+//!     };
+//!
+//!     assert_eq!(sample_from(&schema), bson!({ "name": "aaa", "level": 5 }));
+//! }
+//! ```
+
+use std::convert::TryFrom;
+use bson::{ Bson, Document };
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use combine::merge;
+use recursive::resolve_ref;
+use support;
+use BsonSchema;
+
+/// The nesting depth beyond which `$ref`s are no longer followed, and
+/// `null` is preferred wherever allowed, so that sampling terminates
+/// even for recursive types which can't be instantiated finitely.
+const MAX_DEPTH: usize = 32;
+
+/// Patterns for which the sample string is known, with the sample itself.
+const PATTERN_SAMPLES: &[(&str, &str)] = &[
+    (support::SEMVER_PATTERN, "0.0.0"),
+    (support::MIME_PATTERN, "text/plain"),
+    (support::URL_PATTERN, "http://localhost"),
+    (support::HTTP_URL_PATTERN, "http://localhost"),
+    (support::IPV4_ADDR_PATTERN, "0.0.0.0"),
+    (support::IPV6_ADDR_PATTERN, "::"),
+    (support::IP_ADDR_PATTERN, "0.0.0.0"),
+    (support::IPV4_NET_PATTERN, "0.0.0.0/0"),
+    (support::IPV6_NET_PATTERN, "::/0"),
+    (support::IP_NET_PATTERN, "0.0.0.0/0"),
+    (support::UUID_PATTERN, "00000000-0000-0000-0000-000000000000"),
+];
+
+/// Returns a sample value that satisfies the schema of `T`.
+#[allow(clippy::module_name_repetitions)]
+pub fn sample<T: ?Sized + BsonSchema>() -> Bson {
+    sample_from(&T::bson_schema())
+}
+
+/// Returns a sample value that satisfies `schema`. If `schema` is the root
+/// of a recursive schema, then `$ref`s are resolved using its `definitions`.
+#[allow(clippy::module_name_repetitions)]
+pub fn sample_from(schema: &Document) -> Bson {
+    let mut sampler = Sampler {
+        definitions: schema.get_document("definitions").ok(),
+        depth: 0,
+    };
+
+    sampler.sample(schema)
+}
+
+/// Keeps track of the state of sample generation.
+#[derive(Debug)]
+struct Sampler<'a> {
+    /// The definitions of the root schema, if any.
+    definitions: Option<&'a Document>,
+    /// The nesting depth of the schema currently being sampled.
+    depth: usize,
+}
+
+impl<'a> Sampler<'a> {
+    /// Samples a subschema one level deeper than the current one.
+    fn nested(&mut self, schema: &Document) -> Bson {
+        self.depth += 1;
+        let value = self.sample(schema);
+        self.depth -= 1;
+        value
+    }
+
+    /// Returns whether sampling has gone too deep.
+    fn too_deep(&self) -> bool {
+        self.depth >= MAX_DEPTH
+    }
+
+    /// Samples a (sub)schema.
+    fn sample(&mut self, schema: &Document) -> Bson {
+        if let Ok(pointer) = schema.get_str("$ref") {
+            let target = self.definitions.and_then(|defs| resolve_ref(pointer, defs));
+
+            return match target {
+                Some(definition) if !self.too_deep() => self.nested(definition),
+                _ => Bson::Null,
+            };
+        }
+
+        if let Ok(values) = schema.get_array("enum") {
+            let preferred = if self.too_deep() {
+                values.iter().find(|value| **value == Bson::Null)
+            } else {
+                None
+            };
+
+            return preferred.or_else(|| values.first()).cloned().unwrap_or(Bson::Null);
+        }
+
+        if let Ok(branches) = schema.get_array("anyOf").or_else(|_| schema.get_array("oneOf")) {
+            return match self.choose_branch(branches) {
+                Some(branch) => self.nested(branch),
+                None => Bson::Null,
+            };
+        }
+
+        if let Ok(branches) = schema.get_array("allOf") {
+            let mut rest = schema.clone();
+            rest.remove("allOf");
+
+            let merged = branches
+                .iter()
+                .filter_map(Bson::as_document)
+                .cloned()
+                .fold(rest, merge);
+
+            return self.sample(&merged);
+        }
+
+        let types = schema_types(schema);
+
+        match self.choose_type(&types) {
+            "object" => self.sample_object(schema),
+            "array" => self.sample_array(schema),
+            "string" => sample_string(schema),
+            "int" | "long" | "integer" => sample_integer(schema, &types),
+            "number" | "double" => Bson::FloatingPoint(sample_double(schema)),
+            "bool" | "boolean" => Bson::Boolean(false),
+            "objectId" => Bson::ObjectId(ObjectId::with_bytes([0; 12])),
+            "date" => Bson::from_extended_document(doc!{ "$date": { "$numberLong": 0_i64 } }),
+            "timestamp" => Bson::TimeStamp(0),
+            "binData" => Bson::Binary(BinarySubtype::Generic, Vec::new()),
+            "regex" => Bson::RegExp(String::new(), String::new()),
+            "javascript" => Bson::JavaScriptCode(String::new()),
+            "symbol" => Bson::Symbol(String::new()),
+            _ => Bson::Null,
+        }
+    }
+
+    /// Chooses the alternative of an `anyOf` or `oneOf` to sample. This is
+    /// the first one without any `$ref`s, which might lead to deep or even
+    /// infinite recursion, or the first one if every alternative has some.
+    fn choose_branch<'b>(&self, branches: &'b [Bson]) -> Option<&'b Document> {
+        let mut docs = branches.iter().filter_map(Bson::as_document);
+        let accepts_null = |doc: &&Document| {
+            doc.get_str("type") == Ok("null") || doc.get_str("bsonType") == Ok("null")
+        };
+
+        if self.too_deep() {
+            if let Some(branch) = docs.clone().find(accepts_null) {
+                return Some(branch);
+            }
+        }
+
+        docs.clone().find(|doc| !contains_ref(doc)).or_else(|| docs.next())
+    }
+
+    /// Chooses the type to generate a value of: the first one that
+    /// can be sampled, or `null` if there is none.
+    fn choose_type<'b>(&self, types: &[&'b str]) -> &'b str {
+        const SAMPLED: &[&str] = &[
+            "object", "array", "string",
+            "int", "long", "integer", "number", "double",
+            "bool", "boolean",
+            "objectId", "date", "timestamp", "binData",
+            "regex", "javascript", "symbol",
+        ];
+
+        if self.too_deep() && types.contains(&"null") {
+            return "null";
+        }
+
+        types.iter().cloned().find(|ty| SAMPLED.contains(ty)).unwrap_or("null")
+    }
+
+    /// Generates a document containing the `required` properties.
+    fn sample_object(&mut self, schema: &Document) -> Bson {
+        let empty = Document::new();
+        let properties = schema.get_document("properties").unwrap_or(&empty);
+        let additional = schema.get_document("additionalProperties").unwrap_or(&empty);
+        let required = schema.get_array("required").map(Vec::as_slice).unwrap_or_default();
+        let mut object = Document::new();
+
+        for name in required.iter().filter_map(Bson::as_str) {
+            let subschema = properties.get_document(name).unwrap_or(additional);
+            let value = self.nested(subschema);
+            object.insert(name, value);
+        }
+
+        Bson::Document(object)
+    }
+
+    /// Generates an array with as few items as allowed.
+    fn sample_array(&mut self, schema: &Document) -> Bson {
+        let empty = Document::new();
+        let min_items = schema
+            .get("minItems")
+            .and_then(as_i64)
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(0);
+
+        let items: Vec<&Document> = match schema.get("items") {
+            Some(Bson::Array(items)) => {
+                let additional = schema.get_document("additionalItems").unwrap_or(&empty);
+                let padding = min_items.saturating_sub(items.len());
+
+                items
+                    .iter()
+                    .map(|item| item.as_document().unwrap_or(&empty))
+                    .chain(std::iter::repeat_n(additional, padding))
+                    .collect()
+            },
+            Some(Bson::Document(item)) => vec![item; min_items],
+            _ => vec![&empty; min_items],
+        };
+
+        Bson::Array(items.into_iter().map(|item| self.nested(item)).collect())
+    }
+}
+
+/// Checks whether a schema refers to a definition anywhere within itself.
+fn contains_ref(schema: &Document) -> bool {
+    schema.iter().any(|(key, value)| match *value {
+        Bson::Document(ref doc) => contains_ref(doc),
+        Bson::Array(ref array) => array.iter().filter_map(Bson::as_document).any(contains_ref),
+        _ => key == "$ref",
+    })
+}
+
+/// Returns the list of types a schema allows, from either `type` or
+/// `bsonType`. If the type isn't constrained, it is inferred from the
+/// other keywords, if possible.
+fn schema_types(schema: &Document) -> Vec<&str> {
+    let spec = schema.get("type").or_else(|| schema.get("bsonType"));
+
+    match spec {
+        Some(Bson::String(ty)) => return vec![ty.as_str()],
+        Some(Bson::Array(types)) => return types.iter().filter_map(Bson::as_str).collect(),
+        _ => {},
+    }
+
+    let has_any = |keys: &[&str]| keys.iter().any(|key| schema.contains_key(key));
+
+    if has_any(&["properties", "required", "additionalProperties", "patternProperties"]) {
+        vec!["object"]
+    } else if has_any(&["items", "additionalItems", "minItems", "maxItems", "uniqueItems"]) {
+        vec!["array"]
+    } else if has_any(&["minLength", "maxLength", "pattern"]) {
+        vec!["string"]
+    } else if has_any(&["minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum"]) {
+        vec!["number"]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Generates the shortest string allowed by `minLength`, or an example of
+/// one of the well-known patterns.
+fn sample_string(schema: &Document) -> Bson {
+    if let Ok(pattern) = schema.get_str("pattern") {
+        if let Some(&(_, example)) = PATTERN_SAMPLES.iter().find(|&&(p, _)| p == pattern) {
+            return example.into();
+        }
+    }
+
+    let min_length = schema
+        .get("minLength")
+        .and_then(as_i64)
+        .and_then(|n| usize::try_from(n).ok())
+        .unwrap_or(0);
+
+    "a".repeat(min_length).into()
+}
+
+/// Generates the integer at the midpoint of the bounds of the schema. The
+/// result is an `int` if that's allowed and it fits, otherwise a `long`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn sample_integer(schema: &Document, types: &[&str]) -> Bson {
+    let to_integer = |value: &Bson, round: fn(f64) -> f64| match *value {
+        Bson::I32(n) => Some(i64::from(n)),
+        Bson::I64(n) => Some(n),
+        Bson::FloatingPoint(x) => Some(round(x) as i64),
+        _ => None,
+    };
+
+    let lower = lower_bound(schema).and_then(|(value, exclusive)| {
+        if exclusive {
+            to_integer(value, f64::floor).map(|n| n.saturating_add(1))
+        } else {
+            to_integer(value, f64::ceil)
+        }
+    });
+    let upper = upper_bound(schema).and_then(|(value, exclusive)| {
+        if exclusive {
+            to_integer(value, f64::ceil).map(|n| n.saturating_sub(1))
+        } else {
+            to_integer(value, f64::floor)
+        }
+    });
+
+    let value = match (lower, upper) {
+        (Some(min), Some(max)) => midpoint(min, max),
+        (Some(min), None) => min,
+        (None, Some(max)) => max,
+        (None, None) => 0,
+    };
+
+    let int_allowed = types.iter().any(|&ty| ty == "int" || ty == "integer" || ty == "number");
+
+    match i32::try_from(value) {
+        Ok(small) if int_allowed => Bson::I32(small),
+        _ => Bson::I64(value),
+    }
+}
+
+/// Returns the midpoint of two integers, rounded towards zero.
+fn midpoint(min: i64, max: i64) -> i64 {
+    let mid = (i128::from(min) + i128::from(max)) / 2;
+    i64::try_from(mid).unwrap_or(min)
+}
+
+/// Generates the floating-point number at the midpoint of the bounds of
+/// the schema, or one away from the only bound if it's half-open.
+fn sample_double(schema: &Document) -> f64 {
+    let lower = lower_bound(schema).and_then(|(value, exclusive)| as_f64(value).map(|x| (x, exclusive)));
+    let upper = upper_bound(schema).and_then(|(value, exclusive)| as_f64(value).map(|x| (x, exclusive)));
+
+    match (lower, upper) {
+        (Some((min, _)), Some((max, _))) => min / 2.0 + max / 2.0,
+        (Some((min, exclusive)), None) => if exclusive { min + 1.0 } else { min },
+        (None, Some((max, exclusive))) => if exclusive { max - 1.0 } else { max },
+        (None, None) => 0.0,
+    }
+}
+
+/// Returns the lower bound of a schema and whether it is exclusive.
+/// Both the boolean and the numeric form of `exclusiveMinimum` is handled.
+fn lower_bound(schema: &Document) -> Option<(&Bson, bool)> {
+    bound(schema, "minimum", "exclusiveMinimum")
+}
+
+/// Returns the upper bound of a schema and whether it is exclusive.
+/// Both the boolean and the numeric form of `exclusiveMaximum` is handled.
+fn upper_bound(schema: &Document) -> Option<(&Bson, bool)> {
+    bound(schema, "maximum", "exclusiveMaximum")
+}
+
+/// Common part of `lower_bound()` and `upper_bound()`.
+fn bound<'a>(schema: &'a Document, bound_key: &str, exclusive_key: &str) -> Option<(&'a Bson, bool)> {
+    match schema.get(exclusive_key) {
+        Some(&Bson::Boolean(exclusive)) => schema.get(bound_key).map(|value| (value, exclusive)),
+        Some(value) => Some((value, true)),
+        None => schema.get(bound_key).map(|value| (value, false)),
+    }
+}
+
+/// Returns the value of an integer, be it an `int` or a `long`.
+fn as_i64(value: &Bson) -> Option<i64> {
+    match *value {
+        Bson::I32(n) => Some(i64::from(n)),
+        Bson::I64(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Returns the value of any number as a floating-point value.
+#[allow(clippy::cast_precision_loss)]
+fn as_f64(value: &Bson) -> Option<f64> {
+    match *value {
+        Bson::FloatingPoint(x) => Some(x),
+        Bson::I32(n) => Some(f64::from(n)),
+        Bson::I64(n) => Some(n as f64),
+        _ => None,
+    }
+}
@@ -0,0 +1,143 @@
+//! Measuring the size and shape of schemas, e.g. in order to catch types
+//! whose schema grows too large for MongoDB before the server rejects it.
+//!
+//! A `collMod` or `create` command, along with the validator in it, must
+//! fit in a single BSON document of at most 16 MiB, nested at most 100
+//! levels deep. Schemas of deeply nested generic types, or of types which
+//! contain the same large type many times, may exceed these limits, since
+//! every occurrence of a type is described in full.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! extern crate magnet_schema;
+//!
+//! use magnet_schema::stats::{ measure, assert_within_limits };
+//!
+//! fn main() {
+//!     let schema = doc!{
+//!         "anyOf": [
+//!             { "type": "string" },
+//!             { "type": "object", "properties": { "id": { "type": "string" } } },
+//!         ],
+//!     };
+//!     let stats = measure(&schema);
+//!
+//!     assert_eq!(stats.documents, 5);
+//!     assert_eq!(stats.max_depth, 5);
+//!     assert_eq!(stats.any_of_branches, 2);
+//!     assert_within_limits(&schema);
+//! }
+//! ```
+
+use std::fmt;
+use bson::{ self, Bson, Document };
+
+/// The maximal size of a BSON document accepted by MongoDB, in bytes.
+pub const MAX_DOCUMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// The maximal nesting depth of documents and arrays accepted by MongoDB.
+pub const MAX_NESTING_DEPTH: usize = 100;
+
+/// Statistics about a schema, as returned by `measure()`.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SchemaStats {
+    /// The size of the schema, serialized as BSON, in bytes.
+    pub byte_size: usize,
+    /// The nesting depth of documents and arrays, counting the schema itself
+    /// as 1. This is what MongoDB's nesting limit applies to.
+    pub max_depth: usize,
+    /// The number of documents, including the schema itself. Every
+    /// subschema is a document, but so are e.g. `properties`.
+    pub documents: usize,
+    /// The number of `anyOf` keywords.
+    pub any_of_count: usize,
+    /// The total number of branches of all `anyOf` keywords.
+    pub any_of_branches: usize,
+    /// The number of branches of the largest `anyOf`.
+    pub max_any_of_branches: usize,
+}
+
+impl SchemaStats {
+    /// Returns `true` if the schema fits within `MAX_DOCUMENT_SIZE` and
+    /// `MAX_NESTING_DEPTH`. The command containing the schema adds a little
+    /// overhead to both, so it may still be rejected if it's very close.
+    pub fn is_within_limits(&self) -> bool {
+        self.byte_size <= MAX_DOCUMENT_SIZE && self.max_depth <= MAX_NESTING_DEPTH
+    }
+}
+
+impl fmt::Display for SchemaStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes, {} documents, depth {}, {} anyOf with {} branches (at most {})",
+            self.byte_size, self.documents, self.max_depth,
+            self.any_of_count, self.any_of_branches, self.max_any_of_branches
+        )
+    }
+}
+
+/// Computes the statistics of `schema`.
+pub fn measure(schema: &Document) -> SchemaStats {
+    let mut buf = Vec::new();
+    // a schema that can't be encoded can't be sent to MongoDB either
+    let byte_size = bson::encode_document(&mut buf, schema).map_or(usize::MAX, |()| buf.len());
+    let mut stats = SchemaStats {
+        byte_size,
+        ..SchemaStats::default()
+    };
+
+    measure_document(schema, 1, &mut stats);
+
+    stats
+}
+
+/// Asserts that `schema` is within MongoDB's limits (see
+/// `SchemaStats::is_within_limits()`), e.g. in a test, and returns its
+/// statistics. Panics with the statistics otherwise.
+pub fn assert_within_limits(schema: &Document) -> SchemaStats {
+    let stats = measure(schema);
+
+    assert!(
+        stats.is_within_limits(),
+        "schema exceeds the limits of MongoDB ({} bytes, depth {}): {}",
+        MAX_DOCUMENT_SIZE, MAX_NESTING_DEPTH, stats
+    );
+
+    stats
+}
+
+/// Implementation of `measure()` for a document at nesting level `depth`.
+fn measure_document(doc: &Document, depth: usize, stats: &mut SchemaStats) {
+    stats.documents += 1;
+    stats.max_depth = stats.max_depth.max(depth);
+
+    for (key, value) in doc {
+        if let Bson::Array(ref branches) = *value {
+            if key == "anyOf" {
+                stats.any_of_count += 1;
+                stats.any_of_branches += branches.len();
+                stats.max_any_of_branches = stats.max_any_of_branches.max(branches.len());
+            }
+        }
+
+        measure_value(value, depth + 1, stats);
+    }
+}
+
+/// Implementation of `measure()` for a value at nesting level `depth`.
+fn measure_value(value: &Bson, depth: usize, stats: &mut SchemaStats) {
+    match *value {
+        Bson::Document(ref doc) => measure_document(doc, depth, stats),
+        Bson::Array(ref array) => {
+            stats.max_depth = stats.max_depth.max(depth);
+
+            for item in array {
+                measure_value(item, depth + 1, stats);
+            }
+        },
+        _ => {},
+    }
+}
@@ -1,22 +1,757 @@
-//! "Runtime" support for `magnet_derive` -- quasi-private functions.
+//! Building blocks for hand-written `BsonSchema` implementations.
+//! These are the same helpers that code generated by `magnet_derive`
+//! relies on, so using them results in schemas consistent with derived
+//! ones. They are:
+//!
+//! * Validation patterns for common string formats, e.g. `URL_PATTERN`.
+//! * The `patterns` of named string formats, and
+//!   `try_extend_schema_with_format()`, which adds them to a schema.
+//! * `Bound` and `Bounds`, along with `extend_schema_with_bounds()`, for
+//!   constraining numbers to a range. `Bounds` can be created from Rust
+//!   ranges, e.g. `Bounds::from(0..=100)`. Integer bounds are stored as
+//!   `long`s, and floating-point ones as `double`s. The values of maps and
+//!   the elements of sets are bounded by `try_extend_values_with_bounds_in()`.
+//! * `extend_schema_with_null()` and `make_nullable()`, for making a schema
+//!   nullable.
+//! * `string_enum_schema()` and `impl_string_enum_schema!`, for types which
+//!   are serialized as one of a few strings, e.g. enums of other crates.
+//! * `map_schema()` and `exhaustive_map_schema()`, for describing maps,
+//!   optionally with restricted keys.
+//! * `extend_schema_with_tag()`, for adding the tag of an internally-tagged
+//!   enum variant to the schema of its contents.
+//! * `extend_schema_with_required()`, for listing required properties, which
+//!   leaves out the `required` array if it would be empty.
+//! * `check_keys()`, for finding property names which MongoDB doesn't allow.
+//! * `extend_schema_with_doc()`, for describing a schema, e.g. using the
+//!   doc comment of the type.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! extern crate magnet_schema;
+//!
+//! use bson::Document;
+//! use magnet_schema::BsonSchema;
+//! use magnet_schema::support::{ Bounds, extend_schema_with_bounds, extend_schema_with_null };
+//!
+//! /// A probability, serialized as a floating-point number or `null` if unknown.
+//! struct Probability(Option<f64>);
+//!
+//! impl BsonSchema for Probability {
+//!     fn bson_schema() -> Document {
+//!         let bounds = Bounds::between(0.0, 1.0);
+//!         let schema = extend_schema_with_bounds(f64::bson_schema(), bounds);
+//!         extend_schema_with_null(schema)
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let schema = Probability::bson_schema();
+//!
+//!     assert_eq!(
+//!         schema.get_array("bsonType").unwrap(),
+//!         &vec!["double".into(), "int".into(), "long".into(), "null".into()]
+//!     );
+//!     assert_eq!(schema.get_f64("minimum").unwrap(), 0.0);
+//!     assert_eq!(schema.get_f64("maximum").unwrap(), 1.0);
+//! }
+//! ```
 
+use std::mem;
+use std::cmp::Ordering;
+use std::ops::{ Range, RangeInclusive, RangeFrom, RangeTo, RangeToInclusive, RangeFull };
 use bson::{ Bson, Document };
+use combine::any_of;
+use config::{ SchemaOptions, Nullability, U64Policy, FloatPolicy, KeyPolicy };
+use error::SchemaError;
 
-/// Describes a lower or upper bound.
-#[doc(hidden)]
+/// Anchored pattern matching a Semantic Versioning 2.0.0 version string.
+/// This is the official regular expression recommended at https://semver.org/.
+pub const SEMVER_PATTERN: &str = concat!(
+    r"^(0|[1-9]\d*)\.(0|[1-9]\d*)\.(0|[1-9]\d*)",
+    r"(?:-((?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*)(?:\.(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*))*))?",
+    r"(?:\+([0-9a-zA-Z-]+(?:\.[0-9a-zA-Z-]+)*))?$",
+);
+
+/// Anchored pattern matching an RFC 2045 media type: a `type/subtype`
+/// pair of tokens, optionally followed by `; name=value` parameters,
+/// where the value is either a token or a quoted string.
+pub const MIME_PATTERN: &str = concat!(
+    r"^[!#$%&'*+.^_`|~0-9A-Za-z-]+/[!#$%&'*+.^_`|~0-9A-Za-z-]+",
+    r#"(?:[ \t]*;[ \t]*[!#$%&'*+.^_`|~0-9A-Za-z-]+=(?:[!#$%&'*+.^_`|~0-9A-Za-z-]+|"(?:[^"\\]|\\.)*"))*$"#,
+);
+
+/// Anchored pattern matching an absolute URL: a scheme followed by `:` and
+/// anything but whitespace. This accepts every URL that `url::Url` does,
+/// including ones without an authority, e.g. `mailto:user@example.com`,
+/// `data:text/plain,hi` or `urn:isbn:0451450523`, but also some that it
+/// rejects, e.g. `http://`.
+pub const URL_PATTERN: &str = r"^[A-Za-z][A-Za-z0-9+.-]*:\S*$";
+
+/// Like `URL_PATTERN`, but stricter: the scheme must be followed by `://`
+/// and a non-empty authority (host, and optionally userinfo and port),
+/// then an optional path, query and fragment. URLs with an empty host, e.g.
+/// `file:///etc/passwd`, are rejected, so it's not used by any impl.
+pub const AUTHORITY_URL_PATTERN: &str = r"^[A-Za-z][A-Za-z0-9+.-]*://[^\s/?#]+(?:[/?#]\S*)?$";
+
+/// Like `AUTHORITY_URL_PATTERN`, but only accepts the `http` and `https` schemes.
+pub const HTTP_URL_PATTERN: &str = r"^[Hh][Tt][Tt][Pp][Ss]?://[^\s/?#]+(?:[/?#]\S*)?$";
+
+/// Anchored pattern matching a hyphenated, hexadecimal UUID.
+pub const UUID_PATTERN: &str =
+    "^[[:xdigit:]]{8}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{12}$";
+
+/// Unanchored pattern matching a dotted-decimal IPv4 address.
+macro_rules! ipv4_addr_pattern {
+    () => {
+        r"(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)(?:\.(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)){3}"
+    }
+}
+
+/// Unanchored pattern matching an IPv6 address in any of its textual
+/// forms: full, `::`-compressed, and with an embedded IPv4 suffix.
+macro_rules! ipv6_addr_pattern {
+    () => {
+        concat!(
+            "(?:",
+            r"(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}",
+            r"|(?:[0-9A-Fa-f]{1,4}:){6}", ipv4_addr_pattern!(),
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,7}:",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,6}:[0-9A-Fa-f]{1,4}",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,5}(?::[0-9A-Fa-f]{1,4}){1,2}",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,4}(?::[0-9A-Fa-f]{1,4}){1,3}",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,3}(?::[0-9A-Fa-f]{1,4}){1,4}",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,2}(?::[0-9A-Fa-f]{1,4}){1,5}",
+            r"|[0-9A-Fa-f]{1,4}:(?::[0-9A-Fa-f]{1,4}){1,6}",
+            r"|:(?:(?::[0-9A-Fa-f]{1,4}){1,7}|:)",
+            r"|(?:[0-9A-Fa-f]{1,4}:){1,5}:", ipv4_addr_pattern!(),
+            r"|::(?:[0-9A-Fa-f]{1,4}:){0,5}", ipv4_addr_pattern!(),
+            ")",
+        )
+    }
+}
+
+/// Unanchored pattern matching an IPv4 network prefix length, `/0` to `/32`.
+macro_rules! ipv4_prefix_pattern {
+    () => { r"/(?:3[0-2]|[12]?\d)" }
+}
+
+/// Unanchored pattern matching an IPv6 network prefix length, `/0` to `/128`.
+macro_rules! ipv6_prefix_pattern {
+    () => { r"/(?:12[0-8]|1[01]\d|[1-9]?\d)" }
+}
+
+/// Anchored pattern matching an IPv4 address.
+pub const IPV4_ADDR_PATTERN: &str = concat!("^", ipv4_addr_pattern!(), "$");
+
+/// Anchored pattern matching an IPv6 address.
+pub const IPV6_ADDR_PATTERN: &str = concat!("^", ipv6_addr_pattern!(), "$");
+
+/// Anchored pattern matching either an IPv4 or an IPv6 address.
+pub const IP_ADDR_PATTERN: &str = concat!(
+    "^(?:", ipv4_addr_pattern!(), "|", ipv6_addr_pattern!(), ")$"
+);
+
+/// Anchored pattern matching an IPv4 network in CIDR notation, e.g. `10.0.0.0/8`.
+pub const IPV4_NET_PATTERN: &str = concat!(
+    "^", ipv4_addr_pattern!(), ipv4_prefix_pattern!(), "$"
+);
+
+/// Anchored pattern matching an IPv6 network in CIDR notation, e.g. `fe80::/10`.
+pub const IPV6_NET_PATTERN: &str = concat!(
+    "^", ipv6_addr_pattern!(), ipv6_prefix_pattern!(), "$"
+);
+
+/// Anchored pattern matching either an IPv4 or an IPv6 network in CIDR notation.
+pub const IP_NET_PATTERN: &str = concat!(
+    "^(?:",
+    ipv4_addr_pattern!(), ipv4_prefix_pattern!(),
+    "|",
+    ipv6_addr_pattern!(), ipv6_prefix_pattern!(),
+    ")$"
+);
+
+/// Patterns of the named string formats of `#[magnet(format = "...")]`.
+/// The patterns avoid look-around and backreferences, so that they behave
+/// the same in MongoDB (PCRE) and in the `regex` crate used by `validate`.
+pub mod patterns {
+    pub use super::{ UUID_PATTERN, URL_PATTERN, IPV4_ADDR_PATTERN, IPV6_ADDR_PATTERN };
+
+    /// Anchored pattern matching an e-mail address, as accepted by the
+    /// HTML `<input type="email">` element. Quoted local parts are rejected.
+    pub const EMAIL_PATTERN: &str = concat!(
+        r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@",
+        r"[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?",
+        r"(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?)*$",
+    );
+
+    /// Anchored pattern matching a DNS host name made of RFC 1123 labels.
+    /// The total length of 253 characters is not enforced.
+    pub const HOSTNAME_PATTERN: &str = concat!(
+        r"^[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?",
+        r"(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?)*$",
+    );
+
+    /// Anchored pattern matching an RFC 3339 `full-date`, e.g. `2019-02-28`.
+    /// The number of days in the month is not checked.
+    pub const DATE_PATTERN: &str = r"^\d{4}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01])$";
+
+    /// Anchored pattern matching an RFC 3339 `date-time`, with a mandatory
+    /// offset, e.g. `2019-02-28T13:45:00.5+01:00`.
+    pub const DATE_TIME_PATTERN: &str = concat!(
+        r"^\d{4}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01])",
+        r"[Tt](?:[01]\d|2[0-3]):[0-5]\d:(?:[0-5]\d|60)(?:\.\d+)?",
+        r"(?:[Zz]|[+-](?:[01]\d|2[0-3]):[0-5]\d)$",
+    );
+
+    /// Anchored pattern matching the hexadecimal form of an `ObjectId`.
+    pub const OBJECT_ID_HEX_PATTERN: &str = "^[[:xdigit:]]{24}$";
+
+    /// Anchored pattern matching a non-negative ISO 8601 duration, e.g.
+    /// `P30D`, `PT1H30M` or `P2W`. At least one component is required, and
+    /// only the seconds may have a fraction. Weeks can't be combined with
+    /// other components.
+    #[cfg(feature = "chrono")]
+    pub const ISO8601_DURATION: &str = concat!(
+        r"^P(?:\d+W",
+        r"|(?:\d+Y(?:\d+M)?(?:\d+D)?|\d+M(?:\d+D)?|\d+D)",
+        r"(?:T(?:\d+H(?:\d+M)?(?:\d+(?:[.,]\d+)?S)?|\d+M(?:\d+(?:[.,]\d+)?S)?|\d+(?:[.,]\d+)?S))?",
+        r"|T(?:\d+H(?:\d+M)?(?:\d+(?:[.,]\d+)?S)?|\d+M(?:\d+(?:[.,]\d+)?S)?|\d+(?:[.,]\d+)?S))$",
+    );
+
+    /// The names of the supported formats, along with their patterns.
+    /// Where a standard JSON Schema format exists, the name is the same.
+    pub const FORMATS: &[(&str, &str)] = &[
+        ("email",        EMAIL_PATTERN),
+        ("hostname",     HOSTNAME_PATTERN),
+        ("ipv4",         IPV4_ADDR_PATTERN),
+        ("ipv6",         IPV6_ADDR_PATTERN),
+        ("uri",          URL_PATTERN),
+        ("uuid",         UUID_PATTERN),
+        ("date",         DATE_PATTERN),
+        ("date-time",    DATE_TIME_PATTERN),
+        ("objectid-hex", OBJECT_ID_HEX_PATTERN),
+    ];
+
+    /// Returns the pattern of the format called `name`, if it's supported.
+    pub fn for_format(name: &str) -> Option<&'static str> {
+        FORMATS.iter().find(|&&(format, _)| format == name).map(|&(_, pattern)| pattern)
+    }
+}
+
+/// Adds the `"pattern"` of the named string format `format` (see the
+/// `patterns` module) to a schema, along with the `"format"` keyword itself.
+/// MongoDB ignores the latter, but standard JSON Schema consumers use it.
+/// An unknown format is an error.
+pub fn try_extend_schema_with_format(mut schema: Document, format: &str) -> Result<Document, SchemaError> {
+    let pattern = patterns::for_format(format).ok_or_else(
+        || SchemaError::Unsupported(format!("string format `{}`", format))
+    )?;
+
+    schema.insert("pattern", pattern);
+    schema.insert("format", format);
+
+    Ok(schema)
+}
+
+/// Returns `true` if the `type` or `bsonType` of a schema allows arrays.
+fn allows_arrays(schema: &Document) -> bool {
+    ["type", "bsonType"].iter().any(|&key| match schema.get(key) {
+        Some(Bson::String(ty)) => ty == "array",
+        Some(Bson::Array(types)) => types.contains(&Bson::from("array")),
+        _ => false,
+    })
+}
+
+/// Constrains the length of an array, and optionally requires its items to
+/// be unique. This is what `#[magnet(min_items = "...")]`, `max_items` and
+/// `unique_items` do. A bound which the schema already has, e.g. because
+/// it describes a fixed-size array, is only replaced by a stricter one.
+/// The schema must allow arrays, otherwise it's a `MalformedSchema` error,
+/// and so is a minimal length exceeding the maximal one.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::try_extend_schema_with_items;
+/// # fn main() {
+/// let schema = try_extend_schema_with_items(Vec::<i32>::bson_schema(), Some(1), None, true).unwrap();
+///
+/// assert_eq!(schema.get_i64("minItems").unwrap(), 1);
+/// assert_eq!(schema.get_bool("uniqueItems").unwrap(), true);
+/// assert!(!schema.contains_key("maxItems"));
+/// # }
+/// ```
+pub fn try_extend_schema_with_items(
+    mut schema: Document,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    unique_items: bool,
+) -> Result<Document, SchemaError> {
+    if !allows_arrays(&schema) {
+        return Err(SchemaError::MalformedSchema(
+            String::from("constraints on items require the schema of an array")
+        ));
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    let lengths = [
+        (min_items.map(|min| min as i64), "minItems", Ordering::Greater),
+        (max_items.map(|max| max as i64), "maxItems", Ordering::Less),
+    ];
+
+    for &(length, key, stricter) in &lengths {
+        if let Some(new) = length {
+            let is_stricter = schema.get(key).and_then(Bson::as_i64).is_none_or(|old| new.cmp(&old) == stricter);
+
+            if is_stricter {
+                schema.insert(key, new);
+            }
+        }
+    }
+
+    if let (Some(min), Some(max)) = (
+        schema.get("minItems").and_then(Bson::as_i64),
+        schema.get("maxItems").and_then(Bson::as_i64),
+    ) {
+        if min > max {
+            return Err(SchemaError::MalformedSchema(
+                format!("`minItems` ({}) exceeds `maxItems` ({})", min, max)
+            ));
+        }
+    }
+
+    if unique_items {
+        schema.insert("uniqueItems", true);
+    }
+
+    Ok(schema)
+}
+
+/// Constrains the items of an array to strings matching the regular
+/// expression `pattern`. This is what `#[magnet(items_regex = "...")]`
+/// does. The schema must allow arrays, and its `items` must be a single
+/// schema, i.e. not that of a tuple, otherwise it's a `MalformedSchema`
+/// error. An existing pattern of the items is replaced.
+pub fn try_extend_items_with_regex(mut schema: Document, pattern: &str) -> Result<Document, SchemaError> {
+    let is_array = allows_arrays(&schema);
+
+    match schema.get_mut("items") {
+        Some(&mut Bson::Document(ref mut items)) if is_array => {
+            items.insert("pattern", pattern);
+        },
+        _ => return Err(SchemaError::MalformedSchema(
+            String::from("`items_regex` requires the schema of an array of strings")
+        )),
+    }
+
+    Ok(schema)
+}
+
+/// Strips the module paths from every path in a type name, as returned by
+/// `std::any::type_name()`. E.g. `alloc::vec::Vec<core::option::Option<i32>>`
+/// becomes `Vec<Option<i32>>`.
+pub fn unqualified_type_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut path_start = 0;
+    let mut chars = name.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            result.truncate(path_start);
+        } else {
+            result.push(ch);
+
+            if !ch.is_alphanumeric() && ch != '_' {
+                path_start = result.len();
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the names of required properties as a BSON array without
+/// duplicates, keeping the first occurrence of each name. Derived schemas
+/// build their `required` arrays using this function, since MongoDB
+/// rejects `required` arrays with duplicate items.
+///
+/// ```rust
+/// # extern crate bson;
+/// # extern crate magnet_schema;
+/// # use bson::Bson;
+/// # use magnet_schema::support::unique_required;
+/// # fn main() {
+/// let required = unique_required(&["kind", "name", "kind"]);
+/// assert_eq!(required, vec![Bson::from("kind"), Bson::from("name")]);
+/// # }
+/// ```
+pub fn unique_required(names: &[&str]) -> Vec<Bson> {
+    let mut result: Vec<Bson> = Vec::with_capacity(names.len());
+
+    for &name in names {
+        let item = Bson::from(name);
+
+        if !result.contains(&item) {
+            result.push(item);
+        }
+    }
+
+    result
+}
+
+/// Adds `names` to the `required` array of a schema, creating it if needed,
+/// without duplicates. If the resulting list is empty, the `required` key is
+/// removed instead, since MongoDB 3.6 and 4.0 reject empty `required` arrays.
+/// An existing `required` value which is not an array is left alone.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate bson;
+/// # extern crate magnet_schema;
+/// # use magnet_schema::support::extend_schema_with_required;
+/// # fn main() {
+/// let schema = doc!{ "type": "object", "required": [] };
+/// assert_eq!(extend_schema_with_required(schema, &[]), doc!{ "type": "object" });
+///
+/// let schema = doc!{ "type": "object", "required": ["id"] };
+/// assert_eq!(
+///     extend_schema_with_required(schema, &["name", "id"]),
+///     doc!{ "type": "object", "required": ["id", "name"] }
+/// );
+/// # }
+/// ```
+pub fn extend_schema_with_required(mut schema: Document, names: &[&str]) -> Document {
+    let mut required = match schema.remove("required") {
+        Some(Bson::Array(array)) => array,
+        Some(other) => {
+            schema.insert_bson(String::from("required"), other);
+            return schema;
+        },
+        None => Vec::new(),
+    };
+
+    for &name in names {
+        let item = Bson::from(name);
+
+        if !required.contains(&item) {
+            required.push(item);
+        }
+    }
+
+    if !required.is_empty() {
+        schema.insert("required", required);
+    }
+
+    schema
+}
+
+/// Checks the names of `properties` and `definitions` throughout `schema`
+/// against MongoDB's rules for field names, i.e. that they neither start
+/// with `$` nor contain `.`. Offending names are handled according to
+/// `policy`; with `KeyPolicy::Error`, the first one is returned as an
+/// `IllegalKey` error, along with the JSON pointer of its schema. The keys
+/// of `patternProperties` are regular expressions, not names, so they are
+/// not checked.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate bson;
+/// # extern crate magnet_schema;
+/// # use magnet_schema::{ KeyPolicy, SchemaError };
+/// # use magnet_schema::support::check_keys;
+/// # fn main() {
+/// let schema = doc!{
+///     "type": "object",
+///     "properties": {
+///         "location": {
+///             "type": "object",
+///             "properties": { "$lat": { "type": "number" } },
+///         },
+///     },
+/// };
+///
+/// assert_eq!(check_keys(&schema, KeyPolicy::Allow), Ok(()));
+/// assert_eq!(check_keys(&schema, KeyPolicy::Error), Err(SchemaError::IllegalKey {
+///     key: String::from("$lat"),
+///     path: String::from("/properties/location"),
+/// }));
+/// # }
+/// ```
+pub fn check_keys(schema: &Document, policy: KeyPolicy) -> Result<(), SchemaError> {
+    if policy == KeyPolicy::Allow {
+        return Ok(());
+    }
+
+    check_keys_at(schema, "", policy)
+}
+
+/// Reports a key not allowed by MongoDB when the policy is `KeyPolicy::Warn`.
+#[cfg(feature = "log")]
+fn warn_illegal_key(key: &str, path: &str) {
+    warn!("key `{}` at `{}` starts with `$` or contains `.`, which MongoDB doesn't allow", key, path);
+}
+
+/// Reports a key not allowed by MongoDB when the policy is `KeyPolicy::Warn`.
+#[cfg(not(feature = "log"))]
+fn warn_illegal_key(key: &str, path: &str) {
+    eprintln!("warning: key `{}` at `{}` starts with `$` or contains `.`, which MongoDB doesn't allow", key, path);
+}
+
+/// Implementation of `check_keys()`, for the (sub)schema at `path`.
+fn check_keys_at(schema: &Document, path: &str, policy: KeyPolicy) -> Result<(), SchemaError> {
+    for (keyword, value) in schema {
+        let keyword_path = format!("{}/{}", path, keyword);
+
+        match (keyword.as_str(), value) {
+            ("properties", Bson::Document(subschemas)) |
+            ("definitions", Bson::Document(subschemas)) => {
+                for (name, subschema) in subschemas {
+                    if name.starts_with('$') || name.contains('.') {
+                        match policy {
+                            KeyPolicy::Error => return Err(SchemaError::IllegalKey {
+                                key: name.clone(),
+                                path: path.into(),
+                            }),
+                            KeyPolicy::Warn => warn_illegal_key(name, path),
+                            KeyPolicy::Allow => {},
+                        }
+                    }
+
+                    if let Bson::Document(doc) = subschema {
+                        check_keys_at(doc, &format!("{}/{}", keyword_path, name), policy)?;
+                    }
+                }
+            },
+            ("patternProperties", Bson::Document(subschemas)) |
+            ("dependencies", Bson::Document(subschemas)) => {
+                for (name, subschema) in subschemas {
+                    if let Bson::Document(doc) = subschema {
+                        check_keys_at(doc, &format!("{}/{}", keyword_path, name), policy)?;
+                    }
+                }
+            },
+            ("items", Bson::Array(subschemas)) |
+            ("anyOf", Bson::Array(subschemas)) |
+            ("oneOf", Bson::Array(subschemas)) |
+            ("allOf", Bson::Array(subschemas)) => {
+                for (index, subschema) in subschemas.iter().enumerate() {
+                    if let Bson::Document(doc) = subschema {
+                        check_keys_at(doc, &format!("{}/{}", keyword_path, index), policy)?;
+                    }
+                }
+            },
+            ("items", Bson::Document(doc)) |
+            ("additionalItems", Bson::Document(doc)) |
+            ("additionalProperties", Bson::Document(doc)) |
+            ("not", Bson::Document(doc)) => check_keys_at(doc, &keyword_path, policy)?,
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds a `"description"` to a schema, replacing the existing one, if any.
+/// Derived schemas are described by the doc comments of the type, its
+/// fields and variants.
+pub fn extend_schema_with_doc(mut schema: Document, description: &str) -> Document {
+    schema.insert("description", description);
+    schema
+}
+
+/// Values which `#[magnet(examples_from = "path")]` can refer to: a `const`
+/// or `static` of type `Bson` or `Document`, or a function returning a value
+/// convertible to `Bson`, which is called when the schema is generated.
+pub trait ExampleSource {
+    /// Returns the example.
+    fn into_example(self) -> Bson;
+}
+
+impl ExampleSource for Bson {
+    fn into_example(self) -> Bson {
+        self
+    }
+}
+
+impl ExampleSource for Document {
+    fn into_example(self) -> Bson {
+        Bson::Document(self)
+    }
+}
+
+impl<F, T> ExampleSource for F where F: FnOnce() -> T, T: Into<Bson> {
+    fn into_example(self) -> Bson {
+        self().into()
+    }
+}
+
+/// Appends `example` to the `"examples"` array of a schema, creating it
+/// if needed. An existing `"examples"` value which is not an array is
+/// replaced, since it's not valid JSON Schema.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate bson;
+/// # extern crate magnet_schema;
+/// # use magnet_schema::support::extend_schema_with_example;
+/// # fn main() {
+/// let schema = extend_schema_with_example(doc!{ "type": "string" }, "foo".into());
+/// let schema = extend_schema_with_example(schema, "bar".into());
+///
+/// assert_eq!(schema, doc!{ "type": "string", "examples": ["foo", "bar"] });
+/// # }
+/// ```
+pub fn extend_schema_with_example(mut schema: Document, example: Bson) -> Document {
+    match schema.get_mut("examples") {
+        Some(&mut Bson::Array(ref mut examples)) => examples.push(example),
+        _ => {
+            schema.insert("examples", vec![example]);
+        },
+    }
+
+    schema
+}
+
+/// Specifies how much of a doc comment ends up in a description.
+/// Selected by the `#[magnet(doc = "...")]` attribute of a type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DescriptionMode {
+    /// The whole doc comment, set by `#[magnet(doc = "full")]`.
+    #[default]
+    Full,
+    /// Only the first line, i.e. the summary, set by `#[magnet(doc = "first_line")]`.
+    FirstLine,
+    /// No description at all, set by `#[magnet(doc = "skip")]`.
+    Skip,
+}
+
+/// Like `extend_schema_with_doc()`, but respects a description mode and
+/// `options.include_descriptions`. This is what derived schemas use.
+pub fn extend_schema_with_doc_in(
+    schema: Document,
+    description: &str,
+    mode: DescriptionMode,
+    options: &SchemaOptions,
+) -> Document {
+    if !options.include_descriptions {
+        return schema;
+    }
+
+    match mode {
+        DescriptionMode::Full => extend_schema_with_doc(schema, description),
+        DescriptionMode::FirstLine => {
+            let summary = description.lines().next().unwrap_or_default();
+            extend_schema_with_doc(schema, summary)
+        },
+        DescriptionMode::Skip => schema,
+    }
+}
+
+/// The value of a bound. Integers are kept as such, so that they are
+/// stored as `long`s, like the intrinsic bounds of integer types, and so
+/// that they can be larger than 2<sup>53</sup> without losing precision.
 #[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundValue {
+    /// An integer, stored as a `long`.
+    Int(i64),
+    /// A floating-point number, stored as a `double`.
+    Double(f64),
+}
+
+impl BoundValue {
+    /// Returns the value as an `f64`, which may be inexact for large integers.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn as_f64(self) -> f64 {
+        match self {
+            BoundValue::Int(value) => value as f64,
+            BoundValue::Double(value) => value,
+        }
+    }
+
+    /// Returns the value of a number of any type, or `None` if `value`
+    /// isn't a number.
+    pub fn from_bson(value: &Bson) -> Option<Self> {
+        match *value {
+            Bson::FloatingPoint(x) => Some(BoundValue::Double(x)),
+            Bson::I32(x) => Some(BoundValue::Int(i64::from(x))),
+            Bson::I64(x) => Some(BoundValue::Int(x)),
+            _ => None,
+        }
+    }
+}
+
+/// Integers are compared exactly, other numbers as `f64`s.
+impl PartialOrd for BoundValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (*self, *other) {
+            (BoundValue::Int(lhs), BoundValue::Int(rhs)) => Some(lhs.cmp(&rhs)),
+            (lhs, rhs) => lhs.as_f64().partial_cmp(&rhs.as_f64()),
+        }
+    }
+}
+
+impl From<f64> for BoundValue {
+    fn from(value: f64) -> Self {
+        BoundValue::Double(value)
+    }
+}
+
+impl From<i64> for BoundValue {
+    fn from(value: i64) -> Self {
+        BoundValue::Int(value)
+    }
+}
+
+impl From<i32> for BoundValue {
+    fn from(value: i32) -> Self {
+        BoundValue::Int(i64::from(value))
+    }
+}
+
+impl From<u32> for BoundValue {
+    fn from(value: u32) -> Self {
+        BoundValue::Int(i64::from(value))
+    }
+}
+
+impl From<BoundValue> for Bson {
+    fn from(bound: BoundValue) -> Self {
+        match bound {
+            BoundValue::Int(value) => Bson::I64(value),
+            BoundValue::Double(value) => Bson::FloatingPoint(value),
+        }
+    }
+}
+
+/// Describes a lower or upper bound.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Bound {
     /// The range is not bounded.
+    #[default]
     Unbounded,
     /// The range is bounded, the bound is in the range.
-    Inclusive(f64),
+    Inclusive(BoundValue),
     /// The range is bounded, the bound is not in the range.
-    Exclusive(f64),
+    Exclusive(BoundValue),
+}
+
+impl Bound {
+    /// Creates a bound which is in the range.
+    pub fn inclusive<T: Into<BoundValue>>(value: T) -> Self {
+        Bound::Inclusive(value.into())
+    }
+
+    /// Creates a bound which is not in the range.
+    pub fn exclusive<T: Into<BoundValue>>(value: T) -> Self {
+        Bound::Exclusive(value.into())
+    }
 }
 
 /// Describes both the lower and the upper bounds of a range.
-#[doc(hidden)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// The `Default` value is unbounded in both directions.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Bounds {
     /// The lower bound of the range.
     pub lower: Bound,
@@ -24,40 +759,308 @@ pub struct Bounds {
     pub upper: Bound,
 }
 
-/// Based on bounds parsed from attributes, generates minimum and maximum
-/// constraints and adds them to a JSON schema. Calls to this functions
-/// are to be made from `magnet_derive`'d, generated code only.
-#[doc(hidden)]
-pub fn extend_schema_with_bounds(mut schema: Document, bounds: Bounds) -> Document {
-    match bounds.lower {
-        Bound::Unbounded => {},
-        Bound::Inclusive(minimum) => {
-            schema.insert("minimum", minimum);
-            schema.insert("exclusiveMinimum", false);
-        },
-        Bound::Exclusive(minimum) => {
-            schema.insert("minimum", minimum);
-            schema.insert("exclusiveMinimum", true);
-        },
+impl Bounds {
+    /// Creates a range from its lower and upper bounds.
+    pub fn new(lower: Bound, upper: Bound) -> Self {
+        Bounds { lower, upper }
     }
 
-    match bounds.upper {
-        Bound::Unbounded => {},
-        Bound::Inclusive(maximum) => {
-            schema.insert("maximum", maximum);
-            schema.insert("exclusiveMaximum", false);
-        },
-        Bound::Exclusive(maximum) => {
-            schema.insert("maximum", maximum);
-            schema.insert("exclusiveMaximum", true);
-        },
+    /// Creates a range of numbers greater than or equal to `minimum`.
+    pub fn at_least<T: Into<BoundValue>>(minimum: T) -> Self {
+        Bounds::new(Bound::inclusive(minimum), Bound::Unbounded)
     }
 
-    schema
+    /// Creates a range of numbers less than or equal to `maximum`.
+    pub fn at_most<T: Into<BoundValue>>(maximum: T) -> Self {
+        Bounds::new(Bound::Unbounded, Bound::inclusive(maximum))
+    }
+
+    /// Creates a range of numbers between `minimum` and `maximum`,
+    /// both inclusive. The same as `Bounds::from(minimum..=maximum)`.
+    pub fn between<T: Into<BoundValue>>(minimum: T, maximum: T) -> Self {
+        Bounds::new(Bound::inclusive(minimum), Bound::inclusive(maximum))
+    }
+}
+
+/// Ranges of numbers convert to `Bounds` with the same ends, e.g. the
+/// half-open range `0.0..1.0` includes its start, but not its end.
+/// Integer ranges result in integer bounds.
+///
+/// ```rust
+/// # use magnet_schema::support::{ Bound, Bounds };
+/// assert_eq!(Bounds::from(0.0..1.0), Bounds::new(Bound::inclusive(0.0), Bound::exclusive(1.0)));
+/// assert_eq!(Bounds::from(0..=100), Bounds::between(0, 100));
+/// assert_eq!(Bounds::from(..), Bounds::default());
+/// ```
+macro_rules! impl_bounds_from_ranges {
+    ($($ty:ty,)*) => {$(
+        impl From<Range<$ty>> for Bounds {
+            fn from(range: Range<$ty>) -> Self {
+                Bounds::new(Bound::inclusive(range.start), Bound::exclusive(range.end))
+            }
+        }
+
+        impl From<RangeInclusive<$ty>> for Bounds {
+            fn from(range: RangeInclusive<$ty>) -> Self {
+                let (start, end) = range.into_inner();
+                Bounds::between(start, end)
+            }
+        }
+
+        impl From<RangeFrom<$ty>> for Bounds {
+            fn from(range: RangeFrom<$ty>) -> Self {
+                Bounds::at_least(range.start)
+            }
+        }
+
+        impl From<RangeTo<$ty>> for Bounds {
+            fn from(range: RangeTo<$ty>) -> Self {
+                Bounds::new(Bound::Unbounded, Bound::exclusive(range.end))
+            }
+        }
+
+        impl From<RangeToInclusive<$ty>> for Bounds {
+            fn from(range: RangeToInclusive<$ty>) -> Self {
+                Bounds::at_most(range.end)
+            }
+        }
+    )*}
+}
+
+impl_bounds_from_ranges! {
+    f64,
+    i64,
+    i32,
+    u32,
+}
+
+impl From<RangeFull> for Bounds {
+    fn from(_: RangeFull) -> Self {
+        Bounds::default()
+    }
+}
+
+/// The two conventions for expressing exclusive bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BoundsDialect {
+    /// `"minimum": x, "exclusiveMinimum": true`, as in JSON Schema draft-04.
+    /// This is the only form MongoDB understands, hence the default.
+    #[default]
+    Boolean,
+    /// `"exclusiveMinimum": x`, as in JSON Schema draft-06 and later.
+    Numeric,
+}
+
+/// Adds `minimum` and `maximum` constraints, along with the corresponding
+/// `exclusiveMinimum` and `exclusiveMaximum` flags, to a schema. Unbounded
+/// ends of the range don't add a constraint. If the schema already has a
+/// bound, e.g. because it describes a fixed-width integer type, the stricter
+/// one of the two is kept, so that the result is the intersection of the
+/// two ranges. This uses the boolean form of
+/// exclusive bounds, as required by MongoDB; see also
+/// `extend_schema_with_bounds_in()`.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::{ Bound, Bounds, extend_schema_with_bounds };
+/// # fn main() {
+/// let bounds = Bounds::new(Bound::exclusive(0.0), Bound::Unbounded);
+/// let schema = extend_schema_with_bounds(f64::bson_schema(), bounds);
+///
+/// assert_eq!(schema.get_f64("minimum").unwrap(), 0.0);
+/// assert_eq!(schema.get_bool("exclusiveMinimum").unwrap(), true);
+/// assert!(!schema.contains_key("maximum"));
+/// # }
+/// ```
+pub fn extend_schema_with_bounds(schema: Document, bounds: Bounds) -> Document {
+    extend_schema_with_bounds_in(schema, bounds, BoundsDialect::Boolean)
 }
 
-/// This function should not be used directly; calls to it are only generated by
-/// `magnet_derive` when emitting code for internally-tagged newtype variants.
+/// Like `extend_schema_with_bounds()`, but the representation of exclusive
+/// bounds is chosen by `dialect`.
+///
+/// # Panics
+///
+/// If the intersection of the bounds and those already in the schema is
+/// empty. See `try_extend_schema_with_bounds_in()` for a fallible version.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::{ Bound, Bounds, BoundsDialect, extend_schema_with_bounds_in };
+/// # fn main() {
+/// let bounds = Bounds::new(Bound::exclusive(0.0), Bound::inclusive(1.0));
+/// let schema = extend_schema_with_bounds_in(f64::bson_schema(), bounds, BoundsDialect::Numeric);
+///
+/// assert_eq!(schema.get_f64("exclusiveMinimum").unwrap(), 0.0);
+/// assert_eq!(schema.get_f64("maximum").unwrap(), 1.0);
+/// assert!(!schema.contains_key("minimum"));
+/// # }
+/// ```
+pub fn extend_schema_with_bounds_in(
+    schema: Document,
+    bounds: Bounds,
+    dialect: BoundsDialect,
+) -> Document {
+    try_extend_schema_with_bounds_in(schema, bounds, dialect).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Like `extend_schema_with_bounds_in()`, but returns an error instead of
+/// panicking if the bounds leave no valid values. This is what derived
+/// schemas use.
+pub fn try_extend_schema_with_bounds_in(
+    mut schema: Document,
+    bounds: Bounds,
+    dialect: BoundsDialect,
+) -> Result<Document, SchemaError> {
+    let ends = [
+        (bounds.lower, "minimum", "exclusiveMinimum", Ordering::Greater),
+        (bounds.upper, "maximum", "exclusiveMaximum", Ordering::Less),
+    ];
+
+    for &(bound, bound_key, exclusive_key, stricter) in &ends {
+        let (value, exclusive) = match bound {
+            Bound::Unbounded => continue,
+            Bound::Inclusive(value) => (value, false),
+            Bound::Exclusive(value) => (value, true),
+        };
+
+        // only replace the intrinsic bound of the type if ours is stricter
+        if let Some((old_value, old_exclusive)) = existing_bound(&schema, bound_key, exclusive_key) {
+            match value.partial_cmp(&old_value) {
+                Some(Ordering::Equal) if exclusive && !old_exclusive => {},
+                Some(ordering) if ordering == stricter => {},
+                _ => continue,
+            }
+        }
+
+        schema.remove(bound_key);
+        schema.remove(exclusive_key);
+
+        match dialect {
+            BoundsDialect::Boolean => {
+                schema.insert(bound_key, Bson::from(value));
+                schema.insert(exclusive_key, exclusive);
+            },
+            BoundsDialect::Numeric => {
+                schema.insert(if exclusive { exclusive_key } else { bound_key }, Bson::from(value));
+            },
+        }
+    }
+
+    let lower = existing_bound(&schema, "minimum", "exclusiveMinimum");
+    let upper = existing_bound(&schema, "maximum", "exclusiveMaximum");
+
+    if let (Some((min, min_exclusive)), Some((max, max_exclusive))) = (lower, upper) {
+        if min >= max && (min > max || min_exclusive || max_exclusive) {
+            return Err(SchemaError::EmptyRange(bounds));
+        }
+    }
+
+    Ok(schema)
+}
+
+/// Like `try_extend_schema_with_bounds_in()`, but constrains the values of a
+/// map or the elements of a set (or any other array), instead of the schema
+/// itself. This is what `#[magnet(values_min_incl = "...")]` and friends do.
+///
+/// The bounds are applied to `additionalProperties` if it is a schema, to
+/// every schema under `patternProperties`, and to `items` if it is a schema.
+/// Each alternative of an `anyOf`, e.g. that of a nullable map, is extended
+/// in the same way. Schemas which have none of these, for instance because
+/// they describe a struct, are rejected with `SchemaError::MalformedSchema`.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use std::collections::HashMap;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::{ Bounds, BoundsDialect, try_extend_values_with_bounds_in };
+/// # fn main() {
+/// let schema = try_extend_values_with_bounds_in(
+///     HashMap::<String, f64>::bson_schema(),
+///     Bounds::between(0.0, 1.0),
+///     BoundsDialect::Numeric,
+/// ).unwrap();
+/// let values = schema.get_document("additionalProperties").unwrap();
+///
+/// assert_eq!(values.get_f64("minimum").unwrap(), 0.0);
+/// assert_eq!(values.get_f64("maximum").unwrap(), 1.0);
+/// assert!(!schema.contains_key("minimum"));
+/// # }
+/// ```
+pub fn try_extend_values_with_bounds_in(
+    mut schema: Document,
+    bounds: Bounds,
+    dialect: BoundsDialect,
+) -> Result<Document, SchemaError> {
+    let mut found = false;
+
+    for &key in &["additionalProperties", "items"] {
+        if let Some(Bson::Document(values)) = schema.remove(key) {
+            schema.insert(key, try_extend_schema_with_bounds_in(values, bounds, dialect)?);
+            found = true;
+        }
+    }
+
+    if let Some(Bson::Document(patterns)) = schema.remove("patternProperties") {
+        let mut bounded = Document::new();
+
+        for (pattern, values) in patterns {
+            let bounded_values = match values {
+                Bson::Document(doc) => Bson::from(try_extend_schema_with_bounds_in(doc, bounds, dialect)?),
+                other => other,
+            };
+            bounded.insert(pattern, bounded_values);
+        }
+
+        schema.insert("patternProperties", bounded);
+        found = true;
+    }
+
+    if let Some(Bson::Array(alternatives)) = schema.remove("anyOf") {
+        let mut bounded = Vec::with_capacity(alternatives.len());
+
+        for alternative in alternatives {
+            // alternatives which aren't collections, e.g. `null`, are kept
+            match alternative {
+                Bson::Document(doc) => match try_extend_values_with_bounds_in(doc.clone(), bounds, dialect) {
+                    Ok(extended) => {
+                        bounded.push(Bson::from(extended));
+                        found = true;
+                    },
+                    Err(SchemaError::MalformedSchema(_)) => bounded.push(Bson::from(doc)),
+                    Err(error) => return Err(error),
+                },
+                other => bounded.push(other),
+            }
+        }
+
+        schema.insert("anyOf", bounded);
+    }
+
+    if found {
+        Ok(schema)
+    } else {
+        Err(SchemaError::MalformedSchema(
+            String::from("bounds on values require the schema of a map or a collection")
+        ))
+    }
+}
+
+/// Returns the lower or upper bound already present in a schema, in either
+/// dialect, along with a flag indicating whether it is exclusive.
+fn existing_bound(schema: &Document, bound_key: &str, exclusive_key: &str) -> Option<(BoundValue, bool)> {
+    match schema.get(exclusive_key) {
+        None | Some(&Bson::Boolean(false)) => BoundValue::from_bson(schema.get(bound_key)?).map(|v| (v, false)),
+        Some(&Bson::Boolean(true)) => BoundValue::from_bson(schema.get(bound_key)?).map(|v| (v, true)),
+        Some(value) => BoundValue::from_bson(value).map(|v| (v, true)),
+    }
+}
+
+/// Extends the schema of the contents of an internally-tagged newtype
+/// variant so that it also describes the tag, `tag: variant`. This is what
+/// `magnet_derive` emits for such variants.
 ///
 /// If the newtype schema comes from a struct, just extend its "required"
 /// and "properties" fields to include the tag and the variant name.
@@ -72,17 +1075,521 @@ pub fn extend_schema_with_bounds(mut schema: Document, bounds: Bounds) -> Docume
 /// add the tag and the variant name to each.
 /// TODO(H2CO3): implement me --- this scenario is yet to be handled.
 ///
-/// Every other case is considered an error.
-#[doc(hidden)]
+/// # Panics
+///
+/// Every other case is considered an error, and results in a panic.
+/// See `try_extend_schema_with_tag()` for a fallible version.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use std::collections::HashMap;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::extend_schema_with_tag;
+/// # fn main() {
+/// let schema = extend_schema_with_tag(HashMap::<String, i32>::bson_schema(), "kind", "Counts");
+/// let tag_schema = schema.get_document("properties").unwrap().get_document("kind").unwrap();
+///
+/// assert_eq!(schema.get_array("required").unwrap(), &vec!["kind".into()]);
+/// assert_eq!(tag_schema.get_array("enum").unwrap(), &vec!["Counts".into()]);
+/// # }
+/// ```
 pub fn extend_schema_with_tag(schema: Document, tag: &str, variant: &str) -> Document {
+    try_extend_schema_with_tag(schema, tag, variant).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Like `extend_schema_with_tag()`, but returns an error instead of
+/// panicking if the schema can't describe the tag. This is what derived
+/// schemas use.
+pub fn try_extend_schema_with_tag(schema: Document, tag: &str, variant: &str) -> Result<Document, SchemaError> {
     if schema_is_struct(&schema) {
         extend_struct_schema_with_tag(schema, tag, variant)
     } else if schema_is_map(&schema) {
-        extend_map_schema_with_tag(schema, tag, variant)
+        Ok(extend_map_schema_with_tag(schema, tag, variant))
     } else if schema_is_enum(&schema) {
         extend_enum_schema_with_tag(schema, tag, variant)
     } else {
-        panic!("newtype variant doesn't describe a struct, a map, or an enum?!")
+        Err(SchemaError::UntaggableVariant {
+            tag: tag.to_owned(),
+            variant: variant.to_owned(),
+        })
+    }
+}
+
+/// Returns the schema of `f32` and `f64` according to `policy`.
+pub fn float_schema(policy: FloatPolicy) -> Document {
+    match policy {
+        FloatPolicy::AcceptIntegers => doc! {
+            "bsonType": ["double", "int", "long"],
+        },
+        FloatPolicy::StrictDouble => doc! {
+            "bsonType": "double",
+        },
+    }
+}
+
+/// Returns the schema of `u64` according to `policy`. This is also used
+/// for `usize` on 64-bit targets.
+pub fn u64_schema(policy: U64Policy) -> Document {
+    match policy {
+        U64Policy::ClampToI64Max => doc! {
+            "bsonType": ["int", "long"],
+            "minimum": 0_i64,
+            "maximum": i64::MAX,
+        },
+        U64Policy::AllowNegativeWraparound => doc! {
+            "bsonType": ["int", "long"],
+        },
+        U64Policy::AsDecimal => doc! {
+            "bsonType": "decimal",
+        },
+    }
+}
+
+/// Returns the schema of a type serialized as one of the strings `values`,
+/// e.g. an enum from another crate, like `http::Method`. The schema is the
+/// same as the one derived for an externally-tagged enum of unit variants.
+/// See `impl_string_enum_schema!` for implementing `BsonSchema` this way.
+pub fn string_enum_schema(values: &[&str]) -> Document {
+    let items: Vec<Bson> = values.iter().map(|&value| Bson::from(value)).collect();
+    doc!{ "enum": items }
+}
+
+/// Implements `BsonSchema` for types serialized as one of a fixed set of
+/// strings, using `support::string_enum_schema()`. Due to coherence rules,
+/// a crate can only use it for its own types, e.g. newtypes around enums of
+/// other crates, or (with `#[serde(remote = "...")]`) local mirrors of them.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate bson;
+/// #[macro_use]
+/// extern crate magnet_schema;
+///
+/// use magnet_schema::BsonSchema;
+///
+/// /// Stands in for `log::Level`.
+/// struct Level;
+///
+/// impl_string_enum_schema! {
+///     Level => ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+/// }
+///
+/// fn main() {
+///     assert_eq!(
+///         Level::bson_schema(),
+///         doc!{ "enum": ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"] }
+///     );
+/// }
+/// ```
+#[macro_export]
+macro_rules! impl_string_enum_schema {
+    ($($ty:ty => [$($value:expr),* $(,)*]);* $(;)*) => {$(
+        impl $crate::BsonSchema for $ty {
+            fn bson_schema() -> ::bson::Document {
+                $crate::support::string_enum_schema(&[$($value),*])
+            }
+        }
+    )*}
+}
+
+/// Returns the schema of a type, constrained in the same way as a field of a
+/// derived struct would be by its `#[magnet(...)]` attributes, e.g. without
+/// having to define a wrapper type around it. The keys are:
+///
+/// * `min_incl`, `min_excl`, `max_incl`, `max_excl`: bounds on a number
+/// * `values_min_incl`, etc.: bounds on the values of a map or a collection
+/// * `format`: a named string format, see `support::patterns`
+/// * `description`: the description, as given by a doc comment
+/// * `min_items`, `max_items`: the length of an array
+///
+/// Like `BsonSchema::bson_schema()`, it uses the default `SchemaOptions`,
+/// and panics if the constraints are invalid, e.g. if the bounds leave no
+/// valid values, or if the format is unknown.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate bson;
+/// #[macro_use]
+/// extern crate magnet_schema;
+///
+/// fn main() {
+///     assert_eq!(
+///         schema_for!(Vec<String>, min_items = 1, description = "tags"),
+///         doc!{
+///             "type": "array",
+///             "items": { "type": "string" },
+///             "minItems": 1_i64,
+///             "description": "tags",
+///         }
+///     );
+/// }
+/// ```
+#[macro_export]
+macro_rules! schema_for {
+    ($ty:ty $(, $key:ident = $value:expr)* $(,)*) => {{
+        let options = $crate::SchemaOptions::default();
+        #[allow(unused_mut)]
+        let mut schema = <$ty as $crate::BsonSchema>::bson_schema();
+        $(schema = $crate::__schema_for_key!(schema, options, $key, $value);)*
+        schema
+    }}
+}
+
+/// Implementation detail of `schema_for!`: applies a single constraint.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __schema_for_key {
+    (@bounds $schema:ident, $options:ident, $extend:ident, $lower:expr, $upper:expr) => {
+        $crate::support::$extend(
+            $schema, $crate::support::Bounds::new($lower, $upper), $options.bounds_dialect
+        ).unwrap_or_else(|error| panic!("{}", error))
+    };
+    ($schema:ident, $options:ident, min_incl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_schema_with_bounds_in,
+            $crate::support::Bound::inclusive($value), $crate::support::Bound::Unbounded)
+    };
+    ($schema:ident, $options:ident, min_excl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_schema_with_bounds_in,
+            $crate::support::Bound::exclusive($value), $crate::support::Bound::Unbounded)
+    };
+    ($schema:ident, $options:ident, max_incl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_schema_with_bounds_in,
+            $crate::support::Bound::Unbounded, $crate::support::Bound::inclusive($value))
+    };
+    ($schema:ident, $options:ident, max_excl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_schema_with_bounds_in,
+            $crate::support::Bound::Unbounded, $crate::support::Bound::exclusive($value))
+    };
+    ($schema:ident, $options:ident, values_min_incl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_values_with_bounds_in,
+            $crate::support::Bound::inclusive($value), $crate::support::Bound::Unbounded)
+    };
+    ($schema:ident, $options:ident, values_min_excl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_values_with_bounds_in,
+            $crate::support::Bound::exclusive($value), $crate::support::Bound::Unbounded)
+    };
+    ($schema:ident, $options:ident, values_max_incl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_values_with_bounds_in,
+            $crate::support::Bound::Unbounded, $crate::support::Bound::inclusive($value))
+    };
+    ($schema:ident, $options:ident, values_max_excl, $value:expr) => {
+        $crate::__schema_for_key!(@bounds $schema, $options, try_extend_values_with_bounds_in,
+            $crate::support::Bound::Unbounded, $crate::support::Bound::exclusive($value))
+    };
+    ($schema:ident, $options:ident, format, $value:expr) => {
+        $crate::support::try_extend_schema_with_format($schema, $value)
+            .unwrap_or_else(|error| panic!("{}", error))
+    };
+    ($schema:ident, $options:ident, description, $value:expr) => {
+        $crate::support::extend_schema_with_doc_in(
+            $schema, $value, $crate::support::DescriptionMode::Full, &$options
+        )
+    };
+    ($schema:ident, $options:ident, min_items, $value:expr) => {{
+        let mut schema = $schema;
+        schema.insert("minItems", ($value) as i64);
+        schema
+    }};
+    ($schema:ident, $options:ident, max_items, $value:expr) => {{
+        let mut schema = $schema;
+        schema.insert("maxItems", ($value) as i64);
+        schema
+    }};
+    ($schema:ident, $options:ident, $key:ident, $value:expr) => {
+        compile_error!(concat!(
+            "unknown `schema_for!` key `", stringify!($key), "`, expected one of ",
+            "`min_incl`, `min_excl`, `max_incl`, `max_excl`, `values_min_incl`, ",
+            "`values_min_excl`, `values_max_incl`, `values_max_excl`, `format`, ",
+            "`description`, `min_items`, or `max_items`"
+        ))
+    };
+}
+
+/// Returns the schema of a map whose values are described by `value_schema`.
+/// If the keys of the map are restricted to the strings matching the regular
+/// expression `key_pattern`, then the values are described under
+/// `patternProperties` instead of `additionalProperties`, and no other keys
+/// are allowed. This is how `HashMap<K, V>` and `BTreeMap<K, V>` are
+/// implemented, with `key_pattern` coming from `BsonSchemaKey::key_pattern()`,
+/// unless the keys are known in advance (see `exhaustive_map_schema()`).
+pub fn map_schema(key_pattern: Option<String>, value_schema: Document) -> Document {
+    match key_pattern {
+        Some(pattern) => doc! {
+            "type": "object",
+            "patternProperties": {
+                pattern: value_schema,
+            },
+            "additionalProperties": false,
+        },
+        None => doc! {
+            "type": "object",
+            "additionalProperties": value_schema,
+        },
+    }
+}
+
+/// Returns the schema of a map whose keys are among `keys`, and whose values
+/// are described by `value_schema`. Every key is listed under `properties`,
+/// and no other keys are allowed. If `required` is `true`, then every key
+/// must also be present, e.g. for maps which are always fully populated.
+/// This is how maps are implemented if `BsonSchemaKey::known_keys()` of
+/// their key type returns `Some`, with `required` being `false`.
+pub fn exhaustive_map_schema(keys: &[String], value_schema: Document, required: bool) -> Document {
+    let properties: Document = keys
+        .iter()
+        .map(|key| (key.clone(), Bson::from(value_schema.clone())))
+        .collect();
+    let mut schema = doc! {
+        "type": "object",
+        "additionalProperties": false,
+    };
+
+    if required {
+        let names: Vec<&str> = keys.iter().map(String::as_str).collect();
+        schema = extend_schema_with_required(schema, &names);
+    }
+
+    schema.insert("properties", properties);
+    schema
+}
+
+/// Rewrites the schema of a list of key-value pairs, e.g. that of
+/// `Vec<(K, V)>`, into the schema of a map from `K` to `V`, as returned by
+/// `map_schema()`. This is what `#[magnet(map_entries)]` does, for fields
+/// serialized as a document by a serde helper. The `pattern` of the key
+/// schema, if any, constrains the keys of the map. A `description` is kept.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use std::collections::HashMap;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::try_map_from_entries;
+/// # fn main() {
+/// assert_eq!(
+///     try_map_from_entries(Vec::<(String, bool)>::bson_schema()).unwrap(),
+///     HashMap::<String, bool>::bson_schema()
+/// );
+/// # }
+/// ```
+pub fn try_map_from_entries(schema: Document) -> Result<Document, SchemaError> {
+    let malformed = || SchemaError::MalformedSchema(
+        String::from("`map_entries` requires the schema of a list of key-value pairs")
+    );
+
+    if schema.get("type") != Some(&Bson::from("array")) {
+        return Err(malformed());
+    }
+
+    let pair = match schema.get("items") {
+        Some(Bson::Document(pair)) => pair,
+        _ => return Err(malformed()),
+    };
+    let (key_schema, value_schema) = match pair.get("items") {
+        Some(Bson::Array(items)) if items.len() == 2 => match (&items[0], &items[1]) {
+            (Bson::Document(key), Bson::Document(value)) => (key, value.clone()),
+            _ => return Err(malformed()),
+        },
+        _ => return Err(malformed()),
+    };
+
+    let key_pattern = key_schema.get_str("pattern").ok().map(String::from);
+    let mut map = map_schema(key_pattern, value_schema);
+
+    if let Some(description) = schema.get("description") {
+        map.insert("description", description.clone());
+    }
+
+    Ok(map)
+}
+
+/// Rewrites the schema of a map, as returned by `map_schema()` or
+/// `exhaustive_map_schema()`, into the schema of a list of key-value pairs,
+/// like that of `Vec<(String, V)>`. This is what `#[magnet(entry_pairs)]`
+/// does, for maps serialized as a sequence of pairs by a serde helper.
+/// The keys are strings matching the pattern, or one of the known keys, of
+/// the map, if any. A `description` is kept.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use std::collections::BTreeMap;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::try_entries_from_map;
+/// # fn main() {
+/// assert_eq!(
+///     try_entries_from_map(BTreeMap::<String, i32>::bson_schema()).unwrap(),
+///     Vec::<(String, i32)>::bson_schema()
+/// );
+/// # }
+/// ```
+pub fn try_entries_from_map(schema: Document) -> Result<Document, SchemaError> {
+    let malformed = || SchemaError::MalformedSchema(
+        String::from("`entry_pairs` requires the schema of a map")
+    );
+
+    if schema.get("type") != Some(&Bson::from("object")) {
+        return Err(malformed());
+    }
+
+    let patterns = match schema.get("patternProperties") {
+        Some(Bson::Document(doc)) => Some(doc),
+        Some(_) => return Err(malformed()),
+        None => None,
+    };
+    let properties = match schema.get("properties") {
+        Some(Bson::Document(doc)) => Some(doc),
+        Some(_) => return Err(malformed()),
+        None => None,
+    };
+
+    let (key_schema, value_schema) = match (patterns, properties, schema.get("additionalProperties")) {
+        (Some(pattern_schemas), None, Some(&Bson::Boolean(false))) if pattern_schemas.len() == 1 => {
+            match pattern_schemas.iter().next() {
+                Some((pattern, Bson::Document(value))) => (
+                    doc!{ "type": "string", "pattern": pattern.as_str() },
+                    value.clone(),
+                ),
+                _ => return Err(malformed()),
+            }
+        },
+        (None, Some(known), Some(&Bson::Boolean(false))) => {
+            let keys: Vec<Bson> = known.keys().map(|key| Bson::from(key.as_str())).collect();
+
+            // every known key has the same value schema
+            match known.iter().next() {
+                Some((_, Bson::Document(value))) => (doc!{ "enum": keys }, value.clone()),
+                _ => return Err(malformed()),
+            }
+        },
+        (None, None, Some(Bson::Document(value))) => (doc!{ "type": "string" }, value.clone()),
+        _ => return Err(malformed()),
+    };
+
+    let mut entries = doc! {
+        "type": "array",
+        "items": {
+            "type": "array",
+            "additionalItems": false,
+            "items": [key_schema, value_schema],
+        },
+    };
+
+    if let Some(description) = schema.get("description") {
+        entries.insert("description", description.clone());
+    }
+
+    Ok(entries)
+}
+
+/// Makes a schema nullable, i.e. allows `null` in addition to the values
+/// it already describes. This is how `Option<T>` is implemented.
+///
+/// If the type of the schema is constrained by `type` or `bsonType`, then
+/// `"null"` is added to the allowed types. Otherwise, if it is an enum,
+/// then a `{ "type": "null" }` alternative is added to its `anyOf`.
+/// Furthermore, if the schema only allows the values listed under `enum`,
+/// then `null` is added to the list.
+///
+/// If none of these apply, e.g. because the schema is only a `$ref` or
+/// it is empty, then it's wrapped as `{ "anyOf": [schema, { "type": "null" }] }`.
+/// So is a schema whose `type` or `bsonType` is neither a string nor an
+/// array, e.g. one returned by a hand-written `BsonSchema` impl: that is
+/// invalid, but rejecting it is up to whoever uses the schema.
+///
+/// The operation is idempotent: applying it to a schema that already
+/// accepts `null` (e.g. that of an `Option<T>`) doesn't change it.
+///
+/// ```rust
+/// # extern crate magnet_schema;
+/// # use magnet_schema::BsonSchema;
+/// # use magnet_schema::support::extend_schema_with_null;
+/// # fn main() {
+/// let schema = extend_schema_with_null(String::bson_schema());
+/// assert_eq!(schema.get_array("type").unwrap(), &vec!["string".into(), "null".into()]);
+/// # }
+/// ```
+pub fn extend_schema_with_null(mut doc: Document) -> Document {
+    if let Some(&mut Bson::Array(ref mut values)) = doc.get_mut("enum") {
+        if !values.contains(&Bson::Null) {
+            values.push(Bson::Null);
+        }
+    }
+
+    // the type specification is updated in place, so that no intermediate
+    // values are allocated, and the order of the keys is preserved.
+    let type_key = if doc.contains_key("type") {
+        "type"
+    } else if doc.contains_key("bsonType") {
+        "bsonType"
+    } else if let Some(&mut Bson::Array(ref mut array)) = doc.get_mut("anyOf") {
+        // type wasn't directly constrained, but it's an `enum`.
+        if !array.iter().any(is_null_clause) {
+            array.push(doc!{ "type": "null" }.into());
+        }
+        return doc;
+    } else if doc.contains_key("enum") {
+        // `null` has already been added to the allowed values
+        return doc;
+    } else {
+        // as a last resort, allow either the original schema or `null`,
+        // so that e.g. `$ref`s and unconstrained schemas stay nullable too.
+        return any_of(&[doc, doc!{ "type": "null" }]);
+    };
+
+    match doc.get(type_key) {
+        Some(&Bson::String(_)) | Some(&Bson::Array(_)) => {},
+        _ => return any_of(&[doc, doc!{ "type": "null" }]),
+    }
+
+    match doc.get_mut(type_key) {
+        Some(&mut Bson::String(ref t)) if t == "null" => {},
+        Some(spec @ &mut Bson::String(_)) => {
+            let old_type_spec = mem::replace(spec, Bson::Null);
+            *spec = vec![old_type_spec, Bson::from("null")].into();
+        },
+        Some(&mut Bson::Array(ref mut array)) => {
+            // duplicate type strings are a schema error :(
+            if !array.iter().any(|item| item.as_str() == Some("null")) {
+                array.push(Bson::from("null"));
+            }
+        },
+        _ => unreachable!("`{}` is neither a string nor an array", type_key),
+    }
+
+    doc
+}
+
+/// Makes a schema nullable in the style requested by `nullability`.
+/// This is what `Option<T>` uses, and it's also suitable for a field
+/// that should accept `null` without its type being an `Option`.
+///
+/// `Nullability::TypeArray` is the same as `extend_schema_with_null()`.
+/// `Nullability::AnyOf` adds a `{ "type": "null" }` alternative to the
+/// schema using `combine::any_of()`. Both are idempotent.
+pub fn make_nullable(schema: Document, nullability: Nullability) -> Document {
+    match nullability {
+        Nullability::TypeArray => extend_schema_with_null(schema),
+        Nullability::AnyOf => {
+            let accepts_null = match schema.get("anyOf") {
+                Some(Bson::Array(branches)) => schema.len() == 1 && branches.iter().any(is_null_clause),
+                _ => false,
+            };
+
+            if accepts_null {
+                schema
+            } else {
+                any_of(&[schema, doc!{ "type": "null" }])
+            }
+        },
+    }
+}
+
+/// Checks whether an alternative of an `anyOf` is the one matching `null`.
+fn is_null_clause(branch: &Bson) -> bool {
+    match branch.as_document() {
+        Some(doc) => doc.len() == 1 && (
+            doc.get_str("type") == Ok("null") || doc.get_str("bsonType") == Ok("null")
+        ),
+        None => false,
     }
 }
 
@@ -96,14 +1603,15 @@ fn schema_is_struct(doc: &Document) -> bool {
     doc.get_document("properties").is_ok()
 }
 
-/// Check if a schema holds a dynamic set of keys.
+/// Check if a schema holds a dynamic set of keys, either arbitrary ones,
+/// or ones matching a pattern.
 /// Note: we could check for `"type"` being an array containing `"object"`
 /// as well, in case it's an `Option`, but internally-tagged newtype variants
 /// around `Option` aren't supported by Serde anyway.
 fn schema_is_map(doc: &Document) -> bool {
     doc.get_str("type") == Ok("object")
     &&
-    doc.get_document("additionalProperties").is_ok()
+    (doc.get_document("additionalProperties").is_ok() || doc.get_document("patternProperties").is_ok())
 }
 
 /// Check if a BSON schema describes an enum.
@@ -112,27 +1620,36 @@ fn schema_is_enum(doc: &Document) -> bool {
 }
 
 /// Extends a `struct`'s schema so that it describes an internally-tagged variant.
-fn extend_struct_schema_with_tag(mut schema: Document, tag: &str, variant: &str) -> Document {
+fn extend_struct_schema_with_tag(mut schema: Document, tag: &str, variant: &str) -> Result<Document, SchemaError> {
+    // `required` is omitted from the schemas of structs without fields
     let mut required = match schema.remove("required") {
         Some(Bson::Array(arr)) => arr,
-        Some(_) => panic!("`required` is not an array in struct schema?!"),
-        None => panic!("`required` key not found in struct schema?!"),
+        Some(_) => return Err(SchemaError::MalformedSchema(
+            String::from("`required` is not an array in struct schema")
+        )),
+        None => Vec::new(),
     };
     let mut properties = match schema.remove("properties") {
         Some(Bson::Document(doc)) => doc,
-        Some(_) => panic!("`properties` is not a document in struct schema?!"),
-        None => panic!("`properties` key not found in struct schema?!"),
+        Some(_) => return Err(SchemaError::MalformedSchema(
+            String::from("`properties` is not a document in struct schema")
+        )),
+        None => return Err(SchemaError::MalformedSchema(
+            String::from("`properties` key not found in struct schema")
+        )),
     };
 
-    // TODO(H2CO3): check for duplicate items and keys --
+    // TODO(H2CO3): check for duplicate keys --
     // however, Serde should catch them too, shouldn't it?
-    required.push(tag.into());
+    if !required.contains(&Bson::from(tag)) {
+        required.push(tag.into());
+    }
     properties.insert(tag, doc!{ "enum": [ variant ] });
 
     schema.insert("required", required);
     schema.insert("properties", properties);
 
-    schema
+    Ok(schema)
 }
 
 /// Extends a map's schema so that it describes an internally-tagged variant.
@@ -145,9 +1662,104 @@ fn extend_map_schema_with_tag(mut schema: Document, tag: &str, variant: &str) ->
 }
 
 /// Extends an `enum`'s schema so that it describes an internally-tagged variant.
-fn extend_enum_schema_with_tag(_schema: Document, _tag: &str, _variant: &str) -> Document {
+fn extend_enum_schema_with_tag(_schema: Document, _tag: &str, _variant: &str) -> Result<Document, SchemaError> {
     // TODO(H2CO3): recursively and transitively walk `anyOf` / `oneOf`
     // structure, until the leaves (struct or newtype-around-struct) are reached
     // or an error occurs (a non struct or newtype-around-struct type is found).
-    unimplemented!("internally-tagged newtype variants around enums are not yet supported")
+    Err(SchemaError::Unsupported(String::from("internally-tagged newtype variants around enums are")))
+}
+
+/// The order of keywords in `reorder_canonical()`: the type first, then
+/// the constraints, then the subschemas, and the annotations last.
+const KEYWORD_ORDER: &[&str] = &[
+    "$ref", "type", "bsonType", "enum",
+    "minimum", "exclusiveMinimum", "maximum", "exclusiveMaximum", "multipleOf",
+    "minLength", "maxLength", "pattern", "format",
+    "minItems", "maxItems", "uniqueItems",
+    "minProperties", "maxProperties", "required",
+    "properties", "patternProperties", "additionalProperties", "dependencies",
+    "items", "additionalItems",
+    "anyOf", "oneOf", "allOf", "not",
+    "definitions",
+    "title", "description",
+];
+
+/// Returns `schema` with the keywords of it and its subschemas in a fixed
+/// order, regardless of the order in which they were inserted, e.g. by
+/// `make_nullable()`: that of `KEYWORD_ORDER`, with any other keywords in
+/// alphabetical order, before the annotations (`title` and `description`).
+/// The names under `properties`, `definitions`, etc. keep their order, so
+/// the properties of a struct stay in the order of its fields. Nothing
+/// else, e.g. the order of `anyOf` branches or `enum` values, is changed.
+/// The `export` module writes schemas in this order.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate bson;
+/// extern crate magnet_schema;
+///
+/// use magnet_schema::support::reorder_canonical;
+///
+/// fn main() {
+///     let schema = doc!{ "description": "Age", "maximum": 150, "bsonType": "int" };
+///     let keys: Vec<_> = reorder_canonical(&schema).keys().cloned().collect();
+///
+///     assert_eq!(keys, ["bsonType", "maximum", "description"]);
+/// }
+/// ```
+pub fn reorder_canonical(schema: &Document) -> Document {
+    /// Unknown keywords come right before `title`.
+    fn rank(key: &str) -> (usize, &str) {
+        let annotations = KEYWORD_ORDER.len() - 2;
+
+        match KEYWORD_ORDER.iter().position(|&keyword| keyword == key) {
+            Some(index) if index < annotations => (index, ""),
+            Some(index) => (index + 1, ""),
+            None => (annotations, key),
+        }
+    }
+
+    let mut entries: Vec<_> = schema.iter().collect();
+    entries.sort_by(|lhs, rhs| rank(lhs.0).cmp(&rank(rhs.0)));
+
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.clone(), reorder_subschemas(key, value)))
+        .collect()
+}
+
+/// Reorders the subschemas under the keyword `key`, if any.
+fn reorder_subschemas(key: &str, value: &Bson) -> Bson {
+    match (key, value) {
+        ("properties", Bson::Document(doc)) |
+        ("patternProperties", Bson::Document(doc)) |
+        ("definitions", Bson::Document(doc)) |
+        ("dependencies", Bson::Document(doc)) => {
+            let reordered: Document = doc
+                .iter()
+                .map(|(name, subschema)| (name.clone(), reorder_subschema(subschema)))
+                .collect();
+
+            reordered.into()
+        },
+        ("items", Bson::Array(array)) |
+        ("anyOf", Bson::Array(array)) |
+        ("oneOf", Bson::Array(array)) |
+        ("allOf", Bson::Array(array)) => {
+            array.iter().map(reorder_subschema).collect::<Vec<_>>().into()
+        },
+        ("items", _) |
+        ("additionalItems", _) |
+        ("additionalProperties", _) |
+        ("not", _) => reorder_subschema(value),
+        _ => value.clone(),
+    }
+}
+
+/// Reorders a value if it's a schema document, otherwise returns it unchanged.
+fn reorder_subschema(value: &Bson) -> Bson {
+    match *value {
+        Bson::Document(ref doc) => reorder_canonical(doc).into(),
+        ref other => other.clone(),
+    }
 }
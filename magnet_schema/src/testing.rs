@@ -0,0 +1,153 @@
+//! Helpers for testing schemas and the types they describe:
+//!
+//! * `assert_valid()` checks that the serialized form of a value actually
+//!   satisfies the schema generated for its type. Note that `$ref`s are
+//!   not followed by the validator, so values nested under a recursive
+//!   reference are accepted unconditionally.
+//! * `schema_eq()` and the `assert_schema_eq!` and `assert_schema_ne!`
+//!   macros compare schemas without regard to the order of keys, which
+//!   is not significant in JSON Schema, but is kept by `Document`.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate bson;
+//! #[macro_use]
+//! extern crate magnet_schema;
+//!
+//! use magnet_schema::BsonSchema;
+//!
+//! fn main() {
+//!     assert_schema_eq!(
+//!         Option::<u8>::bson_schema(),
+//!         doc!{ "maximum": 255_i64, "minimum": 0_i64, "bsonType": ["int", "long", "null"] }
+//!     );
+//! }
+//! ```
+
+use std::fmt::{ self, Write };
+use serde::Serialize;
+use bson::{ self, Bson, Document };
+use export::to_json_value;
+use validate::validate;
+use BsonSchema;
+
+/// Asserts that two schema documents are equal, regardless of the order
+/// of their keys, using `schema_eq()`. If they aren't, the panic message
+/// contains both documents, pretty-printed as JSON.
+#[macro_export]
+macro_rules! assert_schema_eq {
+    ($lhs:expr, $rhs:expr) => ({
+        let lhs = $crate::testing::UnorderedDoc(&$lhs);
+        let rhs = $crate::testing::UnorderedDoc(&$rhs);
+
+        assert!(lhs == rhs,
+                "{}:{}: {} != {}! Values:\n{:#}\n-- VS. --\n{:#}",
+                file!(), line!(), stringify!($lhs), stringify!($rhs), lhs, rhs);
+    })
+}
+
+/// Asserts that two schema documents are different, even when disregarding
+/// the order of their keys. The counterpart of `assert_schema_eq!`.
+#[macro_export]
+macro_rules! assert_schema_ne {
+    ($lhs:expr, $rhs:expr) => ({
+        let lhs = $crate::testing::UnorderedDoc(&$lhs);
+        let rhs = $crate::testing::UnorderedDoc(&$rhs);
+
+        assert!(lhs != rhs,
+                "{}:{}: {} == {}! Values:\n{:#}\n-- VS. --\n{:#}",
+                file!(), line!(), stringify!($lhs), stringify!($rhs), lhs, rhs);
+    })
+}
+
+/// Checks whether two schemas are deeply equal, disregarding the order
+/// of keys in (sub)documents. The order of array items is significant.
+pub fn schema_eq(lhs: &Document, rhs: &Document) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().all(|(key, value)| {
+        rhs.get(key).is_some_and(|other| bson_eq(value, other))
+    })
+}
+
+/// Checks whether two BSON values are deeply equal, disregarding
+/// the order of keys in documents.
+fn bson_eq(lhs: &Bson, rhs: &Bson) -> bool {
+    match (lhs, rhs) {
+        (Bson::Document(doc_lhs), Bson::Document(doc_rhs)) => schema_eq(doc_lhs, doc_rhs),
+        (Bson::Array(arr_lhs), Bson::Array(arr_rhs)) => {
+            arr_lhs.len() == arr_rhs.len()
+            &&
+            arr_lhs.iter().zip(arr_rhs).all(|(x, y)| bson_eq(x, y))
+        },
+        _ => lhs == rhs,
+    }
+}
+
+/// A document that doesn't care about the order of its keys when compared
+/// for equality. Its alternate `Display` format (`{:#}`) is pretty-printed
+/// JSON, which is what the assertion macros use.
+#[derive(Debug, Clone, Copy)]
+pub struct UnorderedDoc<'a>(pub &'a Document);
+
+impl<'a> PartialEq for UnorderedDoc<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        schema_eq(self.0, other.0)
+    }
+}
+
+impl<'a> fmt::Display for UnorderedDoc<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#}", to_json_value(self.0))
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Serializes `value` using `bson::to_bson()`, then validates the
+/// result against `T::bson_schema()`.
+///
+/// # Panics
+///
+/// If the value can't be serialized, or if the serialized value doesn't
+/// match the schema. The panic message lists every violation along with
+/// its location, followed by the offending value and the schema.
+pub fn assert_valid<T>(value: &T) where T: ?Sized + Serialize + BsonSchema {
+    let type_name = ::std::any::type_name::<T>();
+    let bson = match bson::to_bson(value) {
+        Ok(bson) => bson,
+        Err(error) => panic!("can't serialize value of type `{}`: {}", type_name, error),
+    };
+    let schema = T::bson_schema();
+
+    if let Err(errors) = validate(&schema, &bson) {
+        let mut message = format!("value of type `{}` doesn't match its schema:\n", type_name);
+
+        for error in &errors.errors {
+            match lookup(&bson, &error.path) {
+                Some(actual) if !error.path.is_empty() => writeln!(
+                    message, "  at {}: {} (found {})", error.path, error.message, actual
+                ),
+                _ => writeln!(message, "  at /: {}", error.message),
+            }.expect("writing to a String can't fail");
+        }
+
+        writeln!(message, "value:  {}", bson).expect("writing to a String can't fail");
+        writeln!(message, "schema: {}", schema).expect("writing to a String can't fail");
+
+        panic!("{}", message);
+    }
+}
+
+/// Finds the value at a JSON pointer as produced by the validator.
+fn lookup<'a>(root: &'a Bson, path: &str) -> Option<&'a Bson> {
+    path.split('/').skip(1).try_fold(root, |value, token| {
+        let key = token.replace("~1", "/").replace("~0", "~");
+
+        match *value {
+            Bson::Document(ref doc) => doc.get(&key),
+            Bson::Array(ref array) => key.parse::<usize>().ok().and_then(|i| array.get(i)),
+            _ => None,
+        }
+    })
+}
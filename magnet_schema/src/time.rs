@@ -0,0 +1,198 @@
+//! ISO 8601 strings backed by `chrono` types, e.g. durations like `P30D`.
+//!
+//! `chrono::Duration` itself doesn't implement `BsonSchema`, because how
+//! it's serialized depends on the application. A field which is stored as
+//! an ISO 8601 duration string can either be of type `Iso8601Duration`,
+//! or keep its own type and serde representation, and get its schema by
+//! means of `#[magnet(with = "magnet_schema::time::duration_schema")]`.
+
+use std::fmt;
+use std::error::Error;
+use std::str::FromStr;
+use std::ops::Deref;
+use chrono::Duration;
+use serde::{ Serialize, Serializer, Deserialize, Deserializer };
+use serde::de;
+use serde::ser;
+use bson::Document;
+use support::patterns::ISO8601_DURATION;
+use BsonSchema;
+
+/// Returns the schema of an ISO 8601 duration string, with the pattern
+/// `support::patterns::ISO8601_DURATION`.
+pub fn duration_schema() -> Document {
+    doc! {
+        "type": "string",
+        "pattern": ISO8601_DURATION,
+    }
+}
+
+/// A `chrono::Duration` serialized as an ISO 8601 duration string. It's
+/// written in the form produced by `chrono`, e.g. `PT3600S` or `P0D`, and
+/// parsed from any string matching `support::patterns::ISO8601_DURATION`,
+/// except for those with years or months, which have no fixed length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Iso8601Duration(pub Duration);
+
+impl BsonSchema for Iso8601Duration {
+    fn bson_schema() -> Document {
+        duration_schema()
+    }
+}
+
+impl From<Duration> for Iso8601Duration {
+    fn from(duration: Duration) -> Self {
+        Iso8601Duration(duration)
+    }
+}
+
+impl From<Iso8601Duration> for Duration {
+    fn from(duration: Iso8601Duration) -> Self {
+        duration.0
+    }
+}
+
+impl Deref for Iso8601Duration {
+    type Target = Duration;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for Iso8601Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for Iso8601Duration {
+    type Err = ParseDurationError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut rest = string.strip_prefix('P').ok_or(ParseDurationError::Syntax)?;
+        let mut total = Duration::zero();
+        let mut in_time = false;
+        let mut last_unit = 0;
+
+        if rest.is_empty() {
+            return Err(ParseDurationError::Syntax);
+        }
+
+        while !rest.is_empty() {
+            if let Some(time) = rest.strip_prefix('T') {
+                if in_time || time.is_empty() {
+                    return Err(ParseDurationError::Syntax);
+                }
+                in_time = true;
+                rest = time;
+                continue;
+            }
+
+            let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.' && c != ',')
+                .ok_or(ParseDurationError::Syntax)?;
+            let (number, tail) = rest.split_at(end);
+            let designator = tail.chars().next().ok_or(ParseDurationError::Syntax)?;
+
+            // The units in the order they must appear, and their lengths.
+            let (unit, seconds) = match (in_time, designator) {
+                (false, 'Y') | (false, 'M') => return Err(ParseDurationError::Calendar),
+                (false, 'W') => (1, 7 * 24 * 3600),
+                (false, 'D') => (2, 24 * 3600),
+                (true, 'H') => (3, 3600),
+                (true, 'M') => (4, 60),
+                (true, 'S') => (5, 1),
+                _ => return Err(ParseDurationError::Syntax),
+            };
+
+            // Weeks can't be combined with other components.
+            if unit <= last_unit || last_unit == 1 {
+                return Err(ParseDurationError::Syntax);
+            }
+
+            let component = if unit == 5 {
+                parse_seconds(number)?
+            } else {
+                parse_integer(number)?.checked_mul(seconds).and_then(Duration::try_seconds)
+            };
+
+            total = component
+                .and_then(|part| total.checked_add(&part))
+                .ok_or(ParseDurationError::Overflow)?;
+            last_unit = unit;
+            rest = &tail[designator.len_utf8()..];
+        }
+
+        Ok(Iso8601Duration(total))
+    }
+}
+
+/// Parses the number of a component which can't have a fraction.
+fn parse_integer(number: &str) -> Result<i64, ParseDurationError> {
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseDurationError::Syntax);
+    }
+
+    number.parse().map_err(|_| ParseDurationError::Overflow)
+}
+
+/// Parses the number of seconds, with an optional fraction of at most
+/// nanosecond precision. Returns `Ok(None)` if it doesn't fit a `Duration`.
+fn parse_seconds(number: &str) -> Result<Option<Duration>, ParseDurationError> {
+    let (whole, fraction) = match number.find(['.', ',']) {
+        Some(index) => (&number[..index], Some(&number[index + 1..])),
+        None => (number, None),
+    };
+    let seconds = parse_integer(whole)?;
+    let nanos = match fraction {
+        Some(digits) if digits.len() > 9 => return Err(ParseDurationError::Syntax),
+        Some(digits) => parse_integer(&format!("{:0<9}", digits))?,
+        None => 0,
+    };
+
+    Ok(Duration::try_seconds(seconds).and_then(|duration| duration.checked_add(&Duration::nanoseconds(nanos))))
+}
+
+impl Serialize for Iso8601Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0 < Duration::zero() {
+            return Err(ser::Error::custom("ISO 8601 durations can't be negative"));
+        }
+
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Iso8601Duration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(de::Error::custom)
+    }
+}
+
+/// An error which can be returned when parsing an `Iso8601Duration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseDurationError {
+    /// The string isn't an ISO 8601 duration.
+    Syntax,
+    /// The duration has years or months, whose length varies.
+    Calendar,
+    /// The duration is too long for a `chrono::Duration`.
+    Overflow,
+}
+
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ParseDurationError::Syntax => "invalid ISO 8601 duration",
+            ParseDurationError::Calendar => "years and months have no fixed length",
+            ParseDurationError::Overflow => "duration out of range",
+        })
+    }
+}
+
+impl Error for ParseDurationError {
+    fn description(&self) -> &str {
+        "invalid ISO 8601 duration"
+    }
+}
@@ -0,0 +1,461 @@
+//! Validating BSON values against schemas in Rust, before they reach the
+//! database. Only the subset of keywords emitted by Magnet is supported:
+//!
+//! * `type` and `bsonType`
+//! * `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`
+//! * `minItems`, `maxItems`, `uniqueItems`, `items`, `additionalItems`
+//! * `properties`, `required`, `additionalProperties`, `minProperties`, `maxProperties`
+//! * `patternProperties`, but its patterns are only matched if the `regex`
+//!   feature is enabled; otherwise every key is assumed to match them
+//! * `enum`, `anyOf`, `allOf`, `oneOf`, `not`
+//! * `pattern`, but only if the `regex` feature is enabled
+//!
+//! Other keywords are ignored.
+
+use std::fmt;
+use std::error::Error;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use bson::{ Bson, Document };
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+/// Validates `value` against `schema`, collecting every violation.
+pub fn validate(schema: &Document, value: &Bson) -> Result<(), ValidationErrors> {
+    let mut errors = Vec::new();
+
+    validate_at(schema, value, "", &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+/// A single violation of a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// JSON pointer to the offending value; empty for the root value.
+    pub path: String,
+    /// Description of the violated constraint.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            self.message.fmt(f)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Error for ValidationError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// All the violations found while validating a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors {
+    /// The individual violations. Never empty.
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            error.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {
+    fn description(&self) -> &str {
+        "value does not match schema"
+    }
+}
+
+/// Validates the value found at `path` against a (sub)schema.
+fn validate_at(schema: &Document, value: &Bson, path: &str, errors: &mut Vec<ValidationError>) {
+    let mut error = |message: String| errors.push(ValidationError {
+        path: path.to_owned(),
+        message,
+    });
+
+    for key in &["type", "bsonType"] {
+        if let Some(spec) = schema.get(key) {
+            if !type_spec_matches(spec, value) {
+                error(format!("expected {} {}, found {}", key, spec, type_name(value)));
+            }
+        }
+    }
+
+    if let Some(values) = schema.get("enum").and_then(as_array) {
+        if !values.contains(value) {
+            error(format!("{} is not one of the allowed values", value));
+        }
+    }
+
+    check_bound(schema, value, "minimum", "exclusiveMinimum", Ordering::Less, &mut error);
+    check_bound(schema, value, "maximum", "exclusiveMaximum", Ordering::Greater, &mut error);
+
+    #[cfg(feature = "regex")]
+    check_pattern(schema, value, &mut error);
+
+    match *value {
+        Bson::Array(ref array) => validate_array(schema, array, path, errors),
+        Bson::Document(ref doc) => validate_document(schema, doc, path, errors),
+        _ => {},
+    }
+
+    validate_combinators(schema, value, path, errors);
+}
+
+/// Validates an array against the array-specific keywords of a schema.
+fn validate_array(schema: &Document, array: &[Bson], path: &str, errors: &mut Vec<ValidationError>) {
+    let len = i64::try_from(array.len()).unwrap_or(i64::MAX);
+
+    if let Some(min) = schema.get("minItems").and_then(as_i64) {
+        if len < min {
+            errors.push(error_at(path, format!("expected at least {} items, found {}", min, len)));
+        }
+    }
+
+    if let Some(max) = schema.get("maxItems").and_then(as_i64) {
+        if len > max {
+            errors.push(error_at(path, format!("expected at most {} items, found {}", max, len)));
+        }
+    }
+
+    if schema.get("uniqueItems") == Some(&Bson::Boolean(true)) {
+        let has_duplicates = array.iter().enumerate().any(
+            |(i, item)| array[i + 1..].contains(item)
+        );
+
+        if has_duplicates {
+            errors.push(error_at(path, String::from("items are not unique")));
+        }
+    }
+
+    match schema.get("items") {
+        Some(Bson::Document(item_schema)) => {
+            for (i, item) in array.iter().enumerate() {
+                validate_at(item_schema, item, &child_path(path, &i.to_string()), errors);
+            }
+        },
+        Some(Bson::Array(item_schemas)) => {
+            for (i, item) in array.iter().enumerate() {
+                let item_path = child_path(path, &i.to_string());
+
+                match item_schemas.get(i) {
+                    Some(Bson::Document(item_schema)) => {
+                        validate_at(item_schema, item, &item_path, errors)
+                    },
+                    Some(_) => {},
+                    None => match schema.get("additionalItems") {
+                        Some(&Bson::Boolean(false)) => errors.push(
+                            error_at(&item_path, String::from("additional items are not allowed"))
+                        ),
+                        Some(Bson::Document(item_schema)) => {
+                            validate_at(item_schema, item, &item_path, errors)
+                        },
+                        _ => {},
+                    },
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Validates a document against the object-specific keywords of a schema.
+fn validate_document(schema: &Document, doc: &Document, path: &str, errors: &mut Vec<ValidationError>) {
+    let len = i64::try_from(doc.len()).unwrap_or(i64::MAX);
+
+    if let Some(min) = schema.get("minProperties").and_then(as_i64) {
+        if len < min {
+            errors.push(error_at(path, format!("expected at least {} properties, found {}", min, len)));
+        }
+    }
+
+    if let Some(max) = schema.get("maxProperties").and_then(as_i64) {
+        if len > max {
+            errors.push(error_at(path, format!("expected at most {} properties, found {}", max, len)));
+        }
+    }
+
+    if let Some(required) = schema.get("required").and_then(as_array) {
+        for key in required.iter().filter_map(as_str) {
+            if !doc.contains_key(key) {
+                errors.push(error_at(path, format!("missing required property `{}`", key)));
+            }
+        }
+    }
+
+    let properties = schema.get_document("properties").ok();
+    let pattern_properties = schema.get_document("patternProperties").ok();
+
+    for (key, field) in doc {
+        let field_path = child_path(path, key);
+        let mut matched_pattern = false;
+
+        for (pattern, field_schema) in pattern_properties.into_iter().flatten() {
+            if let Bson::Document(ref pattern_schema) = *field_schema {
+                if key_matches(pattern, key) {
+                    matched_pattern = true;
+                    validate_at(pattern_schema, field, &field_path, errors);
+                }
+            }
+        }
+
+        match properties.and_then(|props| props.get_document(key).ok()) {
+            Some(field_schema) => validate_at(field_schema, field, &field_path, errors),
+            None if matched_pattern => {},
+            None => match schema.get("additionalProperties") {
+                Some(&Bson::Boolean(false)) => errors.push(
+                    error_at(&field_path, format!("additional property `{}` is not allowed", key))
+                ),
+                Some(Bson::Document(field_schema)) => {
+                    validate_at(field_schema, field, &field_path, errors)
+                },
+                _ => {},
+            },
+        }
+    }
+}
+
+/// Validates a value against the `anyOf`, `allOf`, `oneOf` and `not` keywords.
+fn validate_combinators(schema: &Document, value: &Bson, path: &str, errors: &mut Vec<ValidationError>) {
+    let matches = |subschema: &Bson| match *subschema {
+        Bson::Document(ref doc) => {
+            let mut sub_errors = Vec::new();
+            validate_at(doc, value, path, &mut sub_errors);
+            sub_errors.is_empty()
+        },
+        _ => true,
+    };
+
+    if let Some(branches) = schema.get("anyOf").and_then(as_array) {
+        if !branches.iter().any(&matches) {
+            errors.push(error_at(path, format!(
+                "value doesn't match any of the {} alternatives", branches.len()
+            )));
+        }
+    }
+
+    if let Some(branches) = schema.get("oneOf").and_then(as_array) {
+        let count = branches.iter().filter(|branch| matches(branch)).count();
+
+        if count != 1 {
+            errors.push(error_at(path, format!(
+                "value matches {} of the alternatives instead of exactly one", count
+            )));
+        }
+    }
+
+    if let Some(branches) = schema.get("allOf").and_then(as_array) {
+        for branch in branches {
+            if let Bson::Document(ref doc) = *branch {
+                validate_at(doc, value, path, errors);
+            }
+        }
+    }
+
+    if let Some(negated) = schema.get("not") {
+        if matches(negated) {
+            errors.push(error_at(path, String::from("value matches a forbidden schema")));
+        }
+    }
+}
+
+/// Checks a numeric value against a lower or an upper bound. The bound is
+/// violated if the value compares to it as `violation`, or if they are
+/// equal and the bound is exclusive. Both the MongoDB (boolean) and the
+/// draft-07 (numeric) forms of the exclusive keywords are understood.
+fn check_bound<F>(
+    schema: &Document,
+    value: &Bson,
+    bound_key: &str,
+    exclusive_key: &str,
+    violation: Ordering,
+    error: &mut F,
+) where F: FnMut(String) {
+    let bound = schema.get(bound_key);
+
+    match schema.get(exclusive_key) {
+        None | Some(&Bson::Boolean(false)) => if let Some(inclusive) = bound {
+            check_bound_value(value, inclusive, bound_key, false, violation, error);
+        },
+        Some(&Bson::Boolean(true)) => if let Some(exclusive) = bound {
+            check_bound_value(value, exclusive, bound_key, true, violation, error);
+        },
+        Some(exclusive) => {
+            // draft-07 form: the exclusive keyword holds a bound of its own
+            if let Some(inclusive) = bound {
+                check_bound_value(value, inclusive, bound_key, false, violation, error);
+            }
+            check_bound_value(value, exclusive, exclusive_key, true, violation, error);
+        },
+    }
+}
+
+/// Compares a single numeric value against a single bound.
+fn check_bound_value<F>(
+    value: &Bson,
+    bound: &Bson,
+    bound_key: &str,
+    exclusive: bool,
+    violation: Ordering,
+    error: &mut F,
+) where F: FnMut(String) {
+    match compare_numbers(value, bound) {
+        Some(ordering) if ordering == violation => {
+            error(format!("{} violates {} {}", value, bound_key, bound))
+        },
+        Some(Ordering::Equal) if exclusive => {
+            error(format!("{} violates exclusive {} {}", value, bound_key, bound))
+        },
+        _ => {},
+    }
+}
+
+/// Checks a string value against the `pattern` keyword.
+#[cfg(feature = "regex")]
+fn check_pattern<F>(schema: &Document, value: &Bson, error: &mut F) where F: FnMut(String) {
+    let (pattern, string) = match (schema.get_str("pattern"), value) {
+        (Ok(pattern), Bson::String(string)) => (pattern, string),
+        _ => return,
+    };
+
+    match Regex::new(pattern) {
+        Ok(regex) => if !regex.is_match(string) {
+            error(format!("{:?} doesn't match pattern {:?}", string, pattern))
+        },
+        Err(cause) => error(format!("invalid pattern {:?}: {}", pattern, cause)),
+    }
+}
+
+/// Checks whether a key matches a pattern of `patternProperties`.
+/// Invalid patterns don't match anything.
+#[cfg(feature = "regex")]
+fn key_matches(pattern: &str, key: &str) -> bool {
+    Regex::new(pattern).is_ok_and(|regex| regex.is_match(key))
+}
+
+/// Checks whether a key matches a pattern of `patternProperties`.
+/// Without the `regex` feature, every key is assumed to match.
+#[cfg(not(feature = "regex"))]
+fn key_matches(_pattern: &str, _key: &str) -> bool {
+    true
+}
+
+/// Checks whether the value of a `type` or `bsonType` keyword (a single
+/// type name or an array of type names) matches the type of `value`.
+fn type_spec_matches(spec: &Bson, value: &Bson) -> bool {
+    match *spec {
+        Bson::String(ref name) => type_matches(name, value),
+        Bson::Array(ref names) => names.iter().filter_map(as_str).any(|name| type_matches(name, value)),
+        _ => true,
+    }
+}
+
+/// Checks whether a single type name, as used by `type` or `bsonType`,
+/// matches the type of `value`.
+fn type_matches(name: &str, value: &Bson) -> bool {
+    match *value {
+        Bson::I32(_) => name == "int" || name == "integer" || name == "number",
+        Bson::I64(_) => name == "long" || name == "integer" || name == "number",
+        Bson::FloatingPoint(_) => name == "double" || name == "number",
+        Bson::Boolean(_) => name == "bool" || name == "boolean",
+        _ => name == type_name(value),
+    }
+}
+
+/// Returns the `bsonType` name of a value, for error messages.
+fn type_name(value: &Bson) -> &'static str {
+    match *value {
+        Bson::FloatingPoint(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Boolean(_) => "bool",
+        Bson::Null => "null",
+        Bson::RegExp(..) => "regex",
+        Bson::JavaScriptCode(_) => "javascript",
+        Bson::JavaScriptCodeWithScope(..) => "javascriptWithScope",
+        Bson::I32(_) => "int",
+        Bson::I64(_) => "long",
+        Bson::TimeStamp(_) => "timestamp",
+        Bson::Binary(..) => "binData",
+        Bson::ObjectId(_) => "objectId",
+        Bson::UtcDatetime(_) => "date",
+        Bson::Symbol(_) => "symbol",
+    }
+}
+
+/// Compares two numbers, exactly if both of them are integers.
+/// Returns `None` if either of them is not a number, or if one is NaN.
+#[allow(clippy::cast_precision_loss)]
+fn compare_numbers(lhs: &Bson, rhs: &Bson) -> Option<Ordering> {
+    match (as_i64(lhs), as_i64(rhs)) {
+        (Some(x), Some(y)) => Some(x.cmp(&y)),
+        _ => {
+            let to_f64 = |bson: &Bson| match *bson {
+                Bson::FloatingPoint(x) => Some(x),
+                Bson::I32(x) => Some(f64::from(x)),
+                Bson::I64(x) => Some(x as f64),
+                _ => None,
+            };
+
+            to_f64(lhs)?.partial_cmp(&to_f64(rhs)?)
+        },
+    }
+}
+
+/// Returns the value of an integer.
+fn as_i64(bson: &Bson) -> Option<i64> {
+    match *bson {
+        Bson::I32(x) => Some(i64::from(x)),
+        Bson::I64(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// Returns the elements of an array.
+fn as_array(bson: &Bson) -> Option<&Vec<Bson>> {
+    match *bson {
+        Bson::Array(ref array) => Some(array),
+        _ => None,
+    }
+}
+
+/// Returns the contents of a string.
+fn as_str(bson: &Bson) -> Option<&str> {
+    match *bson {
+        Bson::String(ref string) => Some(string),
+        _ => None,
+    }
+}
+
+/// Creates an error for the value found at `path`.
+fn error_at(path: &str, message: String) -> ValidationError {
+    ValidationError {
+        path: path.to_owned(),
+        message,
+    }
+}
+
+/// Appends a property name or array index to a JSON pointer,
+/// escaping `~` and `/` as required.
+fn child_path(path: &str, token: &str) -> String {
+    format!("{}/{}", path, token.replace('~', "~0").replace('/', "~1"))
+}
@@ -0,0 +1,59 @@
+//! Keeping several versions of a schema live, e.g. during a migration.
+//!
+//! The version of a schema is recorded as a `"$comment"` of the form
+//! `"schema version 2"`, which is what `#[magnet(version = 2)]` emits.
+//! A comment is used rather than a custom keyword, because `$jsonSchema`
+//! rejects keywords it doesn't know. `$comment` itself is removed by
+//! `compat::downlevel()` for MongoDB 4.2 and below, though.
+//!
+//! While old documents are being migrated, the validator of a collection
+//! can accept every live version by means of `any_version()`.
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate bson;
+//! # extern crate magnet_schema;
+//! # use magnet_schema::versioned::{ with_version, extract_version, any_version };
+//! # fn main() {
+//! let v1 = with_version(doc!{ "type": "object", "required": ["name"] }, 1);
+//! let v2 = with_version(doc!{ "type": "object", "required": ["first", "last"] }, 2);
+//!
+//! assert_eq!(extract_version(&v1), Some(1));
+//! assert_eq!(extract_version(&v2), Some(2));
+//!
+//! let transitional = any_version(&[v1.clone(), v2.clone()]);
+//!
+//! assert_eq!(transitional, doc!{ "anyOf": [v1, v2] });
+//! assert_eq!(extract_version(&transitional), None);
+//! # }
+//! ```
+
+use bson::Document;
+use combine::any_of;
+
+/// The text of the `"$comment"` which precedes the version number.
+const VERSION_PREFIX: &str = "schema version ";
+
+/// Records the version of a schema, replacing its `"$comment"`, if any.
+pub fn with_version(mut schema: Document, version: i64) -> Document {
+    schema.insert("$comment", format!("{}{}", VERSION_PREFIX, version));
+    schema
+}
+
+/// Returns the version recorded in the `"$comment"` of a schema by
+/// `with_version()`, if any. Subschemas, e.g. the branches of a schema
+/// returned by `any_version()`, are not searched.
+pub fn extract_version(schema: &Document) -> Option<i64> {
+    schema
+        .get_str("$comment")
+        .ok()
+        .and_then(|comment| comment.strip_prefix(VERSION_PREFIX))
+        .and_then(|version| version.parse().ok())
+}
+
+/// Returns a schema matching documents valid according to any of the
+/// versions in `schemas`, e.g. the old and the new one during a migration.
+/// Schemas which are themselves combinations of versions are inlined.
+pub fn any_version(schemas: &[Document]) -> Document {
+    any_of(schemas)
+}
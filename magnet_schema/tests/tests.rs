@@ -1,4 +1,3 @@
-#![recursion_limit = "128"]
 #![allow(clippy::cast_lossless)]
 
 #[macro_use]
@@ -8,130 +7,33 @@ extern crate serde;
 extern crate serde_derive;
 #[macro_use]
 extern crate magnet_derive;
+#[macro_use]
 extern crate magnet_schema;
 extern crate serde_json;
+#[cfg(feature = "url")]
+extern crate url;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "either")]
+extern crate either;
+#[cfg(feature = "semver")]
+extern crate semver;
+#[cfg(feature = "mime")]
+extern crate mime;
+#[cfg(feature = "ordered-float")]
+extern crate ordered_float;
+#[cfg(feature = "ipnet")]
+extern crate ipnet;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "mongodb")]
+extern crate mongodb;
 
-use std::io;
-use std::fmt;
 use std::str;
-use std::error;
 use std::cmp::PartialEq;
-use magnet_schema::BsonSchema;
+use magnet_schema::{ BsonSchema, SchemaError };
 use bson::{ Bson, Document };
 
-/// An unordered document: one that doesn't care about the order of its keys.
-#[derive(Debug, Clone, Copy)]
-struct UnorderedDoc<'a>(&'a Document);
-
-impl<'a> PartialEq for UnorderedDoc<'a> {
-    fn eq(&self, other: &Self) -> bool {
-        if self.0.len() != other.0.len() {
-            return false;
-        }
-
-        self.0.iter().all(|(key, value_lhs)| {
-            let value_rhs = match other.0.get(key) {
-                None => return false,
-                Some(bson) => bson,
-            };
-
-            match (value_lhs, value_rhs) {
-                (&Bson::Document(ref doc_lhs),
-                 &Bson::Document(ref doc_rhs)) => {
-                    let unord_lhs = UnorderedDoc(doc_lhs);
-                    let unord_rhs = UnorderedDoc(doc_rhs);
-                    unord_lhs == unord_rhs
-                },
-                (&Bson::Array(ref arr_lhs), &Bson::Array(ref arr_rhs)) => {
-                    if arr_lhs.len() != arr_rhs.len() {
-                        return false;
-                    }
-
-                    arr_lhs.iter().zip(arr_rhs).all(|args| match args {
-                        (&Bson::Document(ref doc_lhs),
-                         &Bson::Document(ref doc_rhs)) => {
-                            let unord_lhs = UnorderedDoc(doc_lhs);
-                            let unord_rhs = UnorderedDoc(doc_rhs);
-                            unord_lhs == unord_rhs
-                        },
-                        _ => args.0 == args.1,
-                    })
-                },
-                _ => value_lhs == value_rhs,
-            }
-        })
-    }
-}
-
-impl<'a> fmt::Display for UnorderedDoc<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if f.alternate() {
-            serde_json::to_writer_pretty(
-                FmtIoWriter(f),
-                &self.0
-            ).map_err(
-                |_| fmt::Error
-            )
-        } else {
-            self.0.fmt(f) // output is prettier than serde_json::to_writer()'s
-        }
-    }
-}
-
-/// Wraps an `fmt::Formatter` and implements `io::Write` for it.
-struct FmtIoWriter<'a, 'b: 'a>(&'a mut fmt::Formatter<'b>);
-
-impl<'a, 'b> io::Write for FmtIoWriter<'a, 'b> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        fn io_err<E>(error: E) -> io::Error
-            where E: Into<Box<error::Error + Send + Sync>> {
-
-            io::Error::new(io::ErrorKind::Other, error)
-        }
-
-        let s = str::from_utf8(buf).map_err(io_err)?;
-        self.0.write_str(s).map_err(io_err)?;
-
-        Ok(buf.len())
-    }
-
-    fn flush(&mut self) -> io::Result<()> {
-        Ok(())
-    }
-}
-
-macro_rules! assert_doc_eq {
-    ($lhs:expr, $rhs:expr) => ({
-        let lhs_str = stringify!($lhs);
-        let rhs_str = stringify!($rhs);
-
-        let lhs_val = &$lhs;
-        let rhs_val = &$rhs;
-        let lhs = UnorderedDoc(lhs_val);
-        let rhs = UnorderedDoc(rhs_val);
-
-        assert!(lhs == rhs,
-                "Line: {}, {} != {}! Values:\n{:#}\n-- VS. --\n{:#}",
-                line!(), lhs_str, rhs_str, lhs, rhs);
-    })
-}
-
-macro_rules! assert_doc_ne {
-    ($lhs:expr, $rhs:expr) => ({
-        let lhs_str = stringify!($lhs);
-        let rhs_str = stringify!($rhs);
-
-        let lhs_val = &$lhs;
-        let rhs_val = &$rhs;
-        let lhs = UnorderedDoc(lhs_val);
-        let rhs = UnorderedDoc(rhs_val);
-
-        assert!(lhs != rhs,
-                "Line {}: {} == {}! Values:\n{:#}\n-- VS. --\n{:#}",
-                line!(), lhs_str, rhs_str, lhs, rhs);
-    })
-}
-
 #[test]
 fn unordered_doc_equality() {
     let d1 = doc! {
@@ -191,14 +93,14 @@ fn unordered_doc_equality() {
         },
     };
 
-    assert_doc_eq!(d1, d2);
-    assert_doc_eq!(d2, d1);
+    assert_schema_eq!(d1, d2);
+    assert_schema_eq!(d2, d1);
 
-    assert_doc_ne!(d1, d3);
-    assert_doc_ne!(d3, d1);
+    assert_schema_ne!(d1, d3);
+    assert_schema_ne!(d3, d1);
 
-    assert_doc_ne!(d2, d3);
-    assert_doc_ne!(d3, d2);
+    assert_schema_ne!(d2, d3);
+    assert_schema_ne!(d3, d2);
 }
 
 #[test]
@@ -223,14 +125,206 @@ fn unit_struct() {
     let snd_schema = SndUnit::bson_schema();
     let phantom_schema = PhantomData::<PhantomInner>::bson_schema();
 
-    assert_doc_eq!(fst_schema, snd_schema);
-    assert_doc_eq!(snd_schema, fst_schema);
+    assert_schema_eq!(fst_schema, snd_schema);
+    assert_schema_eq!(snd_schema, fst_schema);
+
+    assert_schema_eq!(fst_schema, unit_schema);
+    assert_schema_eq!(snd_schema, unit_schema);
+
+    assert_schema_eq!(phantom_schema, unit_schema);
+    assert_schema_eq!(unit_schema, phantom_schema);
+}
+
+#[test]
+fn unit_struct_strict() {
+    use std::marker::PhantomData;
+    use magnet_schema::{ SchemaOptions, UnitRepresentation };
+
+    #[derive(BsonSchema)]
+    struct FstUnit;
+
+    #[derive(BsonSchema)]
+    struct SndUnit();
+
+    /// intentionally no impl or derive `BsonSchema` - it shouldn't be required!
+    struct PhantomInner;
+
+    let options = SchemaOptions::default().unit_representation(UnitRepresentation::Null);
+    let unit_schema = doc!{ "type": "null" };
+
+    assert_schema_eq!(<()>::bson_schema_with_options(&options), unit_schema);
+    assert_schema_eq!(FstUnit::bson_schema_with_options(&options), unit_schema);
+    assert_schema_eq!(SndUnit::bson_schema_with_options(&options), unit_schema);
+    assert_schema_eq!(PhantomData::<PhantomInner>::bson_schema_with_options(&options), unit_schema);
+    assert_schema_eq!(
+        Option::<FstUnit>::bson_schema_with_options(&options),
+        unit_schema
+    );
+
+    // the default is unchanged
+    assert_schema_eq!(FstUnit::bson_schema(), doc!{
+        "type": ["array", "null"],
+        "maxItems": 0_i64,
+    });
+}
+
+#[test]
+fn container_serde_default() {
+    #[allow(dead_code)]
+    #[derive(Default, Serialize, Deserialize, BsonSchema)]
+    #[serde(default)]
+    struct Settings {
+        verbose: bool,
+        retries: u8,
+    }
+
+    assert_schema_eq!(Settings::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "verbose": { "type": "boolean" },
+            "retries": {
+                "bsonType": ["int", "long"],
+                "minimum": std::u8::MIN as i64,
+                "maximum": std::u8::MAX as i64,
+            },
+        },
+    });
+
+    #[allow(dead_code)]
+    #[derive(Default, Serialize, Deserialize, BsonSchema)]
+    #[serde(default = "Tenant::new", rename_all = "camelCase")]
+    struct Tenant {
+        #[magnet(required)]
+        tenant_id: String,
+        display_name: String,
+    }
+
+    impl Tenant {
+        fn new() -> Self {
+            Tenant::default()
+        }
+    }
+
+    assert_schema_eq!(Tenant::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["tenantId"],
+        "properties": {
+            "tenantId": { "type": "string" },
+            "displayName": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn field_requirement_precedence() {
+    fn default_limit() -> u32 {
+        10
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    struct Query {
+        plain: String,
+        #[magnet(optional)]
+        optional: String,
+        #[serde(default)]
+        defaulted: String,
+        #[serde(default = "default_limit")]
+        limit: u32,
+        #[magnet(required)]
+        required: String,
+        #[serde(default)]
+        #[magnet(required)]
+        required_defaulted: String,
+        #[serde(skip)]
+        skipped: String,
+    }
+
+    let schema = Query::bson_schema();
+
+    assert_eq!(
+        schema.get_array("required").unwrap(),
+        &vec![Bson::from("plain"), Bson::from("required"), Bson::from("required_defaulted")]
+    );
+    assert_eq!(
+        schema.get_document("properties").unwrap().keys().collect::<Vec<_>>(),
+        vec!["plain", "optional", "defaulted", "limit", "required", "required_defaulted"]
+    );
+
+    #[allow(dead_code)]
+    #[derive(Default, Serialize, Deserialize, BsonSchema)]
+    #[serde(default)]
+    struct Defaults {
+        #[magnet(optional)]
+        optional: String,
+        #[serde(default)]
+        defaulted: String,
+        #[serde(default)]
+        #[magnet(required)]
+        required_defaulted: String,
+    }
+
+    assert_eq!(
+        Defaults::bson_schema().get_array("required").unwrap(),
+        &vec![Bson::from("required_defaulted")]
+    );
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "type")]
+    enum Event {
+        Login {
+            user: String,
+            #[magnet(optional)]
+            device: String,
+        },
+        Logout {
+            #[serde(default)]
+            reason: String,
+        },
+    }
+
+    let schema = Event::bson_schema();
+    let variants = schema.get_array("anyOf").unwrap();
+    let required: Vec<_> = variants
+        .iter()
+        .map(|variant| match *variant {
+            Bson::Document(ref doc) => doc.get_array("required").unwrap().clone(),
+            ref other => panic!("expected a document, got {:?}", other),
+        })
+        .collect();
+
+    assert_eq!(required, vec![
+        vec![Bson::from("type"), Bson::from("user")],
+        vec![Bson::from("type")],
+    ]);
+}
+
+#[test]
+fn empty_required_is_omitted() {
+    use magnet_schema::combine::merge;
+    use magnet_schema::support::{ exhaustive_map_schema, extend_schema_with_required };
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, BsonSchema)]
+    struct Patch {
+        #[magnet(optional)]
+        name: Option<String>,
+        #[serde(default)]
+        age: Option<u8>,
+    }
 
-    assert_doc_eq!(fst_schema, unit_schema);
-    assert_doc_eq!(snd_schema, unit_schema);
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Empty {}
 
-    assert_doc_eq!(phantom_schema, unit_schema);
-    assert_doc_eq!(unit_schema, phantom_schema);
+    assert!(!Patch::bson_schema().contains_key("required"));
+    assert!(!Empty::bson_schema().contains_key("required"));
+    assert!(!exhaustive_map_schema(&[], i32::bson_schema(), true).contains_key("required"));
+    assert!(!merge(Patch::bson_schema(), doc!{ "required": [] }).contains_key("required"));
+    assert!(!extend_schema_with_required(doc!{ "required": [] }, &[]).contains_key("required"));
 }
 
 #[test]
@@ -244,15 +338,23 @@ fn newtype_struct() {
         f32
     );
 
-    assert_doc_eq!(FloatingPoint::bson_schema(), f64::bson_schema());
+    assert_schema_eq!(FloatingPoint::bson_schema(), f64::bson_schema());
 
-    assert_doc_eq!(Angle::bson_schema(), doc! {
-        "type": "number",
-        "minimum": -180.0,
+    assert_schema_eq!(Angle::bson_schema(), doc! {
+        "bsonType": ["double", "int", "long"],
+        "minimum": -180_i64,
         "exclusiveMinimum": false,
-        "maximum": 180.0,
+        "maximum": 180_i64,
         "exclusiveMaximum": true,
     });
+
+    #[derive(BsonSchema)]
+    struct Ratio(#[magnet(min_incl = "0", max_incl = "0.5")] f64);
+
+    let ratio = Ratio::bson_schema();
+
+    assert_eq!(ratio.get("minimum"), Some(&Bson::I64(0)));
+    assert_eq!(ratio.get("maximum"), Some(&Bson::FloatingPoint(0.5)));
 }
 
 #[test]
@@ -263,16 +365,16 @@ fn tuple_struct() {
     #[derive(BsonSchema)]
     struct IntRange(Option<u32>, Option<u32>);
 
-    assert_doc_eq!(Complex::bson_schema(), doc! {
+    assert_schema_eq!(Complex::bson_schema(), doc! {
         "type": "array",
         "additionalItems": false,
         "items": [
-            { "type": "number" },
-            { "type": "number" },
+            { "bsonType": ["double", "int", "long"] },
+            { "bsonType": ["double", "int", "long"] },
         ],
     });
 
-    assert_doc_eq!(IntRange::bson_schema(), doc! {
+    assert_schema_eq!(IntRange::bson_schema(), doc! {
         "type": "array",
         "additionalItems": false,
         "items": [
@@ -312,7 +414,7 @@ fn struct_with_named_fields() {
         provider_name: String,
     }
 
-    assert_doc_eq!(Contact::bson_schema(), doc! {
+    assert_schema_eq!(Contact::bson_schema(), doc! {
         "type": "object",
         "additionalProperties": false,
         "required": [
@@ -362,113 +464,521 @@ fn struct_with_named_fields() {
 }
 
 #[test]
-fn untagged_enum() {
+fn all_fields_skipped() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    struct Skipped {
+        #[serde(skip)]
+        cache: Vec<u8>,
+        #[magnet(skip)]
+        #[serde(skip_serializing, default)]
+        hidden: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    struct PartlySkipped {
+        #[serde(skip)]
+        cache: Vec<u8>,
+        name: String,
+    }
+
     #[derive(Serialize, Deserialize, BsonSchema)]
+    struct Empty {}
+
+    let empty_object = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {},
+    };
+
+    assert_schema_eq!(Skipped::bson_schema(), empty_object.clone());
+    assert_schema_eq!(Empty::bson_schema(), empty_object);
+    assert_schema_eq!(PartlySkipped::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name"],
+        "properties": {
+            "name": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn doc_comment_description() {
+    /// A user of the service.
+    ///
+    /// Users are identified by their e-mail address.
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    struct User {
+        /// The display name.
+        /// It doesn't need to be unique.
+        ///   Neither does it need to be stable.
+        name: String,
+        /// The settings, for example:
+        ///
+        /// ```json
+        /// {
+        ///     "theme": "dark"
+        /// }
+        /// ```
+        settings: Vec<String>,
+        email: String,
+    }
+
+    /// Roles determine permissions.
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    let schema = User::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+    let description = |name: &str| {
+        properties.get_document(name).unwrap().get_str("description").ok().map(String::from)
+    };
+
+    assert_eq!(
+        schema.get_str("description").unwrap(),
+        "A user of the service.\n\nUsers are identified by their e-mail address."
+    );
+    assert_eq!(
+        description("name").unwrap(),
+        "The display name.\nIt doesn't need to be unique.\n  Neither does it need to be stable."
+    );
+    assert_eq!(
+        description("settings").unwrap(),
+        "The settings, for example:\n\n```json\n{\n    \"theme\": \"dark\"\n}\n```"
+    );
+    assert_eq!(description("email"), None);
+    assert_eq!(Role::bson_schema().get_str("description").unwrap(), "Roles determine permissions.");
+}
+
+#[test]
+fn variant_doc_comment_description() {
+    #[derive(Serialize, BsonSchema)]
+    #[allow(dead_code)]
+    enum External {
+        /// Nothing to see here.
+        Unit,
+        /// A point in the plane.
+        Struct { x: f64, y: f64 },
+        Undocumented(String),
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "type", content = "value")]
+    #[allow(dead_code)]
+    enum Adjacent {
+        /// Nothing to see here.
+        Unit,
+        /// A point in the plane.
+        Struct { x: f64, y: f64 },
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "type")]
+    #[allow(dead_code)]
+    enum Internal {
+        /// Nothing to see here.
+        Unit,
+        /// A point in the plane.
+        Struct { x: f64, y: f64 },
+    }
+
+    #[derive(Serialize, BsonSchema)]
     #[serde(untagged)]
+    #[allow(dead_code)]
     enum Untagged {
+        /// Nothing to see here.
         Unit,
-        NewType(Option<String>),
-        TwoTuple(u8, i16),
-        Struct {
-            field: i32,
-        },
+        /// A point in the plane.
+        Struct { x: f64, y: f64 },
     }
 
-    assert_doc_eq!(Untagged::bson_schema(), doc! {
+    let point = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["x", "y"],
+        "properties": {
+            "x": { "bsonType": ["double", "int", "long"] },
+            "y": { "bsonType": ["double", "int", "long"] },
+        },
+    };
+
+    let mut described_point = point.clone();
+    described_point.insert("description", "A point in the plane.");
+
+    // the payload of a non-unit variant is described, not its wrapper
+    assert_schema_eq!(External::bson_schema(), doc!{
         "anyOf": [
             {
-                "type": ["array", "null"],
-                "maxItems": 0_i64,
-            },
-            {
-                "type": ["string", "null"],
+                "enum": ["Unit"],
+                "description": "Nothing to see here.",
             },
             {
-                "type": "array",
-                "additionalItems": false,
-                "items": [
-                    {
-                        "bsonType": ["int", "long"],
-                        "minimum": std::u8::MIN as i64,
-                        "maximum": std::u8::MAX as i64,
-                    },
-                    {
-                        "bsonType": ["int", "long"],
-                        "minimum": std::i16::MIN as i64,
-                        "maximum": std::i16::MAX as i64,
-                    },
-                ],
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Struct"],
+                "properties": {
+                    "Struct": described_point.clone(),
+                },
             },
             {
                 "type": "object",
                 "additionalProperties": false,
-                "required": [ "field" ],
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Undocumented"],
                 "properties": {
-                    "field": {
-                        "bsonType": ["int", "long"],
-                        "minimum": std::i32::MIN as i64,
-                        "maximum": std::i32::MAX as i64,
-                    },
+                    "Undocumented": { "type": "string" },
                 },
             },
         ]
     });
-}
-
-#[test]
-fn externally_tagged_enum() {
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    #[serde(rename_all = "snake_case")]
-    enum ExternallyTagged {
-        Unit,
-        NewType(Option<String>),
-        TwoTuple(u8, i16),
-        Struct {
-            field: i32,
-        },
-    }
 
-    assert_doc_eq!(ExternallyTagged::bson_schema(), doc! {
+    assert_schema_eq!(Adjacent::bson_schema(), doc!{
         "anyOf": [
-            {
-                "enum": ["unit"],
-            },
             {
                 "type": "object",
                 "additionalProperties": false,
-                "required": [ "new_type" ],
+                "required": ["type"],
                 "properties": {
-                    "new_type": {
-                        "type": ["string", "null"],
-                    },
+                    "type": { "enum": ["Unit"] },
                 },
+                "description": "Nothing to see here.",
             },
             {
                 "type": "object",
                 "additionalProperties": false,
-                "required": ["two_tuple"],
+                "required": ["type", "value"],
                 "properties": {
-                    "two_tuple": {
-                        "type": "array",
-                        "additionalItems": false,
-                        "items": [
-                            {
-                                "bsonType": ["int", "long"],
-                                "minimum": std::u8::MIN as i64,
-                                "maximum": std::u8::MAX as i64,
-                            },
-                            {
-                                "bsonType": ["int", "long"],
-                                "minimum": std::i16::MIN as i64,
-                                "maximum": std::i16::MAX as i64,
-                            },
-                        ],
+                    "type": { "enum": ["Struct"] },
+                    "value": described_point,
+                },
+            },
+        ]
+    });
+
+    // the payload of internally tagged and untagged variants is the branch
+    let branch_descriptions = |schema: Document| -> Vec<String> {
+        schema
+            .get_array("anyOf")
+            .unwrap()
+            .iter()
+            .map(|branch| match *branch {
+                Bson::Document(ref doc) => doc.get_str("description").unwrap().to_owned(),
+                ref other => panic!("branch is not a document: {}", other),
+            })
+            .collect()
+    };
+    let expected = vec![
+        String::from("Nothing to see here."),
+        String::from("A point in the plane."),
+    ];
+
+    assert_eq!(branch_descriptions(Internal::bson_schema()), expected);
+    assert_eq!(branch_descriptions(Untagged::bson_schema()), expected);
+}
+
+#[test]
+fn description_modes() {
+    use magnet_schema::SchemaOptions;
+
+    /// Internal widget.
+    ///
+    /// TODO(someone): see issue #123.
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    struct FullDoc {
+        /// The name.
+        /// FIXME: validate.
+        name: String,
+    }
+
+    /// Internal widget.
+    ///
+    /// TODO(someone): see issue #123.
+    #[derive(BsonSchema)]
+    #[magnet(doc = "first_line")]
+    #[allow(dead_code)]
+    struct FirstLine {
+        /// The name.
+        /// FIXME: validate.
+        name: String,
+    }
+
+    /// Internal widget.
+    #[derive(BsonSchema)]
+    #[magnet(doc = "skip")]
+    #[allow(dead_code)]
+    enum Skip {
+        /// A variant.
+        Unit,
+    }
+
+    let descriptions = |schema: &Document| -> (Option<String>, Option<String>) {
+        let field_description = schema
+            .get_document("properties").unwrap()
+            .get_document("name").unwrap()
+            .get_str("description").ok().map(String::from);
+
+        (schema.get_str("description").ok().map(String::from), field_description)
+    };
+
+    assert_eq!(descriptions(&FullDoc::bson_schema()), (
+        Some(String::from("Internal widget.\n\nTODO(someone): see issue #123.")),
+        Some(String::from("The name.\nFIXME: validate.")),
+    ));
+    assert_eq!(descriptions(&FirstLine::bson_schema()), (
+        Some(String::from("Internal widget.")),
+        Some(String::from("The name.")),
+    ));
+    assert_schema_eq!(Skip::bson_schema(), doc!{
+        "anyOf": [
+            { "enum": ["Unit"] },
+        ]
+    });
+
+    let options = SchemaOptions::new().include_descriptions(false);
+    assert_eq!(descriptions(&FullDoc::bson_schema_with_options(&options)), (None, None));
+}
+
+#[test]
+fn tuple_struct_doc_comment_description() {
+    /// An angle in radians.
+    #[derive(BsonSchema)]
+    struct Angle(f64);
+
+    /// A point in the plane.
+    #[derive(BsonSchema)]
+    struct Point(
+        /// The abscissa.
+        f64,
+        /// The ordinate.
+        f64,
+    );
+
+    assert_schema_eq!(Angle::bson_schema(), doc!{
+        "bsonType": ["double", "int", "long"],
+        "description": "An angle in radians.",
+    });
+    assert_schema_eq!(Point::bson_schema(), doc!{
+        "type": "array",
+        "additionalItems": false,
+        "items": [
+            { "bsonType": ["double", "int", "long"], "description": "The abscissa." },
+            { "bsonType": ["double", "int", "long"], "description": "The ordinate." },
+        ],
+        "description": "A point in the plane.",
+    });
+}
+
+#[test]
+fn empty_enum() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    enum Never {}
+
+    assert_schema_eq!(Never::bson_schema(), doc!{ "not": {} });
+}
+
+#[test]
+fn undocumented_enum() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    enum Plain {
+        Unit,
+    }
+
+    let schema = Plain::bson_schema();
+
+    assert!(!schema.contains_key("description"));
+    assert_schema_eq!(schema, doc!{
+        "enum": ["Unit"],
+    });
+}
+
+#[test]
+fn unit_only_enum() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum External {
+        Red,
+        Green,
+        #[serde(rename = "azure")]
+        Blue,
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "color")]
+    enum Internal {
+        Red,
+        Green,
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "color", content = "shade")]
+    enum Adjacent {
+        Red,
+        Green,
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Untagged {
+        Red,
+        Green,
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    enum Documented {
+        Red,
+        /// The color of grass.
+        Green,
+    }
+
+    assert_schema_eq!(External::bson_schema(), doc!{
+        "enum": ["red", "green", "azure"],
+    });
+
+    let tagged = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["color"],
+        "properties": {
+            "color": { "enum": ["Red", "Green"] },
+        },
+    };
+
+    assert_schema_eq!(Internal::bson_schema(), tagged);
+    assert_schema_eq!(Adjacent::bson_schema(), tagged);
+    assert_schema_eq!(Untagged::bson_schema(), <()>::bson_schema());
+
+    // a doc comment can only describe a variant in a branch of its own
+    assert_schema_eq!(Documented::bson_schema(), doc!{
+        "anyOf": [
+            { "enum": ["Red"] },
+            { "enum": ["Green"], "description": "The color of grass." },
+        ]
+    });
+}
+
+#[test]
+fn untagged_enum() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Untagged {
+        Unit,
+        NewType(Option<String>),
+        TwoTuple(u8, i16),
+        Struct {
+            field: i32,
+        },
+    }
+
+    assert_schema_eq!(Untagged::bson_schema(), doc! {
+        "anyOf": [
+            {
+                "type": ["array", "null"],
+                "maxItems": 0_i64,
+            },
+            {
+                "type": ["string", "null"],
+            },
+            {
+                "type": "array",
+                "additionalItems": false,
+                "items": [
+                    {
+                        "bsonType": ["int", "long"],
+                        "minimum": std::u8::MIN as i64,
+                        "maximum": std::u8::MAX as i64,
+                    },
+                    {
+                        "bsonType": ["int", "long"],
+                        "minimum": std::i16::MIN as i64,
+                        "maximum": std::i16::MAX as i64,
+                    },
+                ],
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": [ "field" ],
+                "properties": {
+                    "field": {
+                        "bsonType": ["int", "long"],
+                        "minimum": std::i32::MIN as i64,
+                        "maximum": std::i32::MAX as i64,
+                    },
+                },
+            },
+        ]
+    });
+}
+
+#[test]
+fn externally_tagged_enum() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(rename_all = "snake_case")]
+    enum ExternallyTagged {
+        Unit,
+        NewType(Option<String>),
+        TwoTuple(u8, i16),
+        Struct {
+            field: i32,
+        },
+    }
+
+    assert_schema_eq!(ExternallyTagged::bson_schema(), doc! {
+        "anyOf": [
+            {
+                "enum": ["unit"],
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": [ "new_type" ],
+                "properties": {
+                    "new_type": {
+                        "type": ["string", "null"],
+                    },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["two_tuple"],
+                "properties": {
+                    "two_tuple": {
+                        "type": "array",
+                        "additionalItems": false,
+                        "items": [
+                            {
+                                "bsonType": ["int", "long"],
+                                "minimum": std::u8::MIN as i64,
+                                "maximum": std::u8::MAX as i64,
+                            },
+                            {
+                                "bsonType": ["int", "long"],
+                                "minimum": std::i16::MIN as i64,
+                                "maximum": std::i16::MAX as i64,
+                            },
+                        ],
                     },
                 },
             },
             {
                 "type": "object",
                 "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
                 "required": ["struct"],
                 "properties": {
                     "struct": {
@@ -502,7 +1012,7 @@ fn adjacently_tagged_enum() {
         },
     }
 
-    assert_doc_eq!(AdjacentlyTagged::bson_schema(), doc! {
+    assert_schema_eq!(AdjacentlyTagged::bson_schema(), doc! {
         "anyOf": [
             {
                 "type": "object",
@@ -569,6 +1079,94 @@ fn adjacently_tagged_enum() {
     });
 }
 
+#[test]
+fn merged_unit_variants() {
+    use magnet_schema::validate::validate;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    enum Event {
+        Started,
+        Moved { x: i32, y: i32 },
+        #[serde(rename = "halt")]
+        Stopped,
+        #[serde(skip)]
+        Internal,
+        Paused,
+        Resized { width: u32 },
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind", content = "data")]
+    enum Command {
+        Start,
+        Move(i32),
+        Stop,
+    }
+
+    let units = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["kind"],
+        "properties": {
+            "kind": { "enum": ["started", "halt", "paused"] },
+        },
+    };
+    let schema = Event::bson_schema();
+    let branches = schema.get_array("anyOf").unwrap();
+
+    assert_eq!(branches.len(), 3);
+    assert_schema_eq!(branches[0].as_document().unwrap().clone(), units);
+
+    // the schema with one branch per unit variant accepts the same values
+    let separate: Vec<Bson> = ["started", "halt", "paused"]
+        .iter()
+        .map(|name| Bson::from(doc!{
+            "type": "object",
+            "additionalProperties": false,
+            "required": ["kind"],
+            "properties": {
+                "kind": { "enum": [*name] },
+            },
+        }))
+        .chain(branches[1..].iter().cloned())
+        .collect();
+    let separate = doc!{ "anyOf": separate };
+
+    let values = vec![
+        Bson::from(doc!{ "kind": "started" }),
+        Bson::from(doc!{ "kind": "halt" }),
+        Bson::from(doc!{ "kind": "paused" }),
+        Bson::from(doc!{ "kind": "moved", "x": 1, "y": 2 }),
+        Bson::from(doc!{ "kind": "resized", "width": 3 }),
+        Bson::from(doc!{ "kind": "stopped" }),
+        Bson::from(doc!{ "kind": "internal" }),
+        Bson::from(doc!{ "kind": "started", "x": 1 }),
+        Bson::from(doc!{ "kind": "moved" }),
+        Bson::from(doc!{}),
+        Bson::from("started"),
+    ];
+
+    for value in &values {
+        assert_eq!(validate(&schema, value).is_ok(), validate(&separate, value).is_ok(), "{}", value);
+    }
+
+    let schema = Command::bson_schema();
+    let branches = schema.get_array("anyOf").unwrap();
+
+    assert_eq!(branches.len(), 2);
+    assert_schema_eq!(branches[0].as_document().unwrap().clone(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["kind"],
+        "properties": {
+            "kind": { "enum": ["Start", "Stop"] },
+        },
+    });
+}
+
 #[test]
 fn internally_tagged_enum() {
     use std::collections::HashMap;
@@ -589,7 +1187,7 @@ fn internally_tagged_enum() {
         name: String,
     }
 
-    assert_doc_eq!(InternallyTagged::bson_schema(), doc! {
+    assert_schema_eq!(InternallyTagged::bson_schema(), doc! {
         "anyOf": [
             {
                 "type": "object",
@@ -636,52 +1234,448 @@ fn internally_tagged_enum() {
 }
 
 #[test]
-#[should_panic]
-fn malformed_internally_tagged_enum_1() {
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    #[serde(tag = "variant")]
-    enum Foo {
-        Bar(Lol),
+fn internally_tagged_enum_unique_required() {
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "kind")]
+    enum Shape {
+        Circle {
+            radius: f64,
+        },
+        Square(Side),
+        Point(Empty),
     }
 
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    struct Lol;
+    #[derive(Serialize, BsonSchema)]
+    struct Side {
+        kind: String,
+        length: f64,
+    }
 
-    Foo::bson_schema();
-}
+    #[derive(Serialize, BsonSchema)]
+    struct Empty {}
 
-#[test]
-#[should_panic]
-fn malformed_internally_tagged_enum_2() {
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    #[serde(tag = "variant")]
-    enum Foo {
-        Bar(u32),
-    }
+    let schema = Shape::bson_schema();
+    let variants = schema.get_array("anyOf").unwrap();
+    let required: Vec<_> = variants
+        .iter()
+        .map(|variant| match *variant {
+            Bson::Document(ref doc) => doc.get_array("required").unwrap().clone(),
+            ref other => panic!("variant schema is not a document: {}", other),
+        })
+        .collect();
 
-    Foo::bson_schema();
+    assert_eq!(required, vec![
+        vec![Bson::from("kind"), Bson::from("radius")],
+        vec![Bson::from("kind"), Bson::from("length")],
+        vec![Bson::from("kind")],
+    ]);
 }
 
 #[test]
-#[should_panic]
-fn malformed_internally_tagged_enum_3() {
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    #[serde(tag = "variant")]
-    enum Foo {
-        Bar(Option<S>),
+fn relaxed_keys() {
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "$type")]
+    #[magnet(relaxed_keys)]
+    enum Shape {
+        Circle {
+            radius: String,
+        },
     }
 
-    #[derive(Serialize, Deserialize, BsonSchema)]
-    struct S {
-        f: bool,
+    #[derive(Serialize, BsonSchema)]
+    struct Point {
+        #[serde(rename = "a.b")]
+        #[magnet(relaxed_keys)]
+        x: String,
     }
 
-    Foo::bson_schema();
-}
+    assert_schema_eq!(Shape::bson_schema(), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["$type", "radius"],
+                "properties": {
+                    "$type": { "enum": ["Circle"] },
+                    "radius": { "type": "string" },
+                },
+            },
+        ],
+    });
+
+    assert_schema_eq!(Point::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["a.b"],
+        "properties": {
+            "a.b": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn illegal_keys_at_runtime() {
+    use magnet_schema::{ KeyPolicy, SchemaOptions, try_root_schema_with_options };
+
+    struct Location;
+
+    impl BsonSchema for Location {
+        fn bson_schema() -> Document {
+            doc!{
+                "type": "object",
+                "properties": { "$lat": { "type": "number" } },
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Place {
+        location: Location,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    struct Renamed {
+        #[serde(rename = "a.b")]
+        #[magnet(relaxed_keys)]
+        name: String,
+    }
+
+    let strict = SchemaOptions::new().key_policy(KeyPolicy::Error);
+    let allow = SchemaOptions::new().key_policy(KeyPolicy::Allow);
+    let warn = SchemaOptions::new().key_policy(KeyPolicy::Warn);
+
+    assert_eq!(
+        try_root_schema_with_options::<Place>(&strict),
+        Err(SchemaError::IllegalKey {
+            key: String::from("$lat"),
+            path: String::from("/properties/location"),
+        })
+    );
+    assert_eq!(
+        try_root_schema_with_options::<Renamed>(&strict),
+        Err(SchemaError::IllegalKey {
+            key: String::from("a.b"),
+            path: String::new(),
+        })
+    );
+
+    assert_eq!(try_root_schema_with_options::<Place>(&allow), Ok(Place::bson_schema()));
+    assert_eq!(try_root_schema_with_options::<Renamed>(&allow), Ok(Renamed::bson_schema()));
+    assert_eq!(try_root_schema_with_options::<Place>(&warn), Ok(Place::bson_schema()));
+}
+
+#[test]
+fn string_enum_adapter() {
+    // stands in for `http::Method`, which can't derive `BsonSchema`
+    #[allow(dead_code)]
+    enum RemoteMethod {
+        Get,
+        Post,
+        Delete,
+    }
+
+    impl_string_enum_schema! {
+        RemoteMethod => ["GET", "POST", "DELETE"];
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(rename_all = "UPPERCASE")]
+    enum LocalMethod {
+        Get,
+        Post,
+        Delete,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Request {
+        method: RemoteMethod,
+    }
+
+    assert_schema_eq!(RemoteMethod::bson_schema(), doc!{
+        "enum": ["GET", "POST", "DELETE"],
+    });
+    assert_schema_eq!(RemoteMethod::bson_schema(), LocalMethod::bson_schema());
+    assert_schema_eq!(Request::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["method"],
+        "properties": {
+            "method": { "enum": ["GET", "POST", "DELETE"] },
+        },
+    });
+}
+
+#[test]
+fn repeated_identical_attributes() {
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    struct Reading {
+        #[magnet(min_incl = "0", max_excl = "100")]
+        #[magnet(min_incl = "0")]
+        percent: i32,
+        #[serde(rename(serialize = "ts"))]
+        #[serde(rename(deserialize = "timestamp"))]
+        time: String,
+    }
+
+    assert_schema_eq!(Reading::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["percent", "ts"],
+        "properties": {
+            "percent": {
+                "bsonType": ["int", "long"],
+                "minimum": 0_i64,
+                "exclusiveMinimum": false,
+                "maximum": 100_i64,
+                "exclusiveMaximum": true,
+            },
+            "ts": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn split_rename_all_follows_serialize() {
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(rename_all(serialize = "camelCase", deserialize = "snake_case"))]
+    struct Account {
+        user_name: String,
+        is_active: bool,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(rename_all(deserialize = "lowercase", serialize = "SCREAMING_SNAKE_CASE"))]
+    enum Event {
+        #[serde(rename_all(serialize = "kebab-case", deserialize = "camelCase"))]
+        SignedUp { user_name: String },
+        LoggedOut,
+    }
+
+    assert_schema_eq!(Account::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["userName", "isActive"],
+        "properties": {
+            "userName": { "type": "string" },
+            "isActive": { "type": "boolean" },
+        },
+    });
+
+    assert_schema_eq!(Event::bson_schema(), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["SIGNED_UP"],
+                "properties": {
+                    "SIGNED_UP": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["user-name"],
+                        "properties": {
+                            "user-name": { "type": "string" },
+                        },
+                    },
+                },
+            },
+            { "enum": ["LOGGED_OUT"] },
+        ],
+    });
+}
+
+#[test]
+fn rename_all_rules_match_serde() {
+    use std::collections::BTreeSet;
+
+    /// Returns the names of the properties of a struct schema.
+    fn property_names(schema: &Document) -> BTreeSet<String> {
+        schema.get_document("properties").unwrap().keys().cloned().collect()
+    }
+
+    /// Returns the values of an `enum` schema.
+    fn enum_values(schema: &Document) -> BTreeSet<String> {
+        schema.get_array("enum").unwrap().iter().map(|value| value.as_str().unwrap().to_owned()).collect()
+    }
+
+    macro_rules! check_rule {
+        ($rule:tt, [$($variant:expr),*], [$($field:expr),*]) => ({
+            #[derive(Serialize, BsonSchema)]
+            #[serde(rename_all = $rule)]
+            enum Variants {
+                Outcome,
+                VeryTasty,
+                A,
+                Z42,
+            }
+
+            #[derive(Serialize, BsonSchema)]
+            #[serde(rename_all = $rule)]
+            struct Fields {
+                outcome: i32,
+                very_tasty: i32,
+                a: i32,
+                z42: i32,
+            }
+
+            let expected_variants: BTreeSet<String> = vec![$(String::from($variant)),*].into_iter().collect();
+            let expected_fields: BTreeSet<String> = vec![$(String::from($field)),*].into_iter().collect();
+
+            // what Serde itself produces
+            let serialized_variants: BTreeSet<String> = [Variants::Outcome, Variants::VeryTasty, Variants::A, Variants::Z42]
+                .iter()
+                .map(|variant| serde_json::to_value(variant).unwrap().as_str().unwrap().to_owned())
+                .collect();
+            let fields = Fields { outcome: 0, very_tasty: 0, a: 0, z42: 0 };
+            let serialized_fields: BTreeSet<String> = serde_json::to_value(&fields)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+
+            assert_eq!(serialized_variants, expected_variants, "{}", $rule);
+            assert_eq!(serialized_fields, expected_fields, "{}", $rule);
+            assert_eq!(enum_values(&Variants::bson_schema()), expected_variants, "{}", $rule);
+            assert_eq!(property_names(&Fields::bson_schema()), expected_fields, "{}", $rule);
+        })
+    }
+
+    // the expected names are those in the tests of `serde_derive`
+    check_rule!("lowercase", ["outcome", "verytasty", "a", "z42"], ["outcome", "very_tasty", "a", "z42"]);
+    check_rule!("UPPERCASE", ["OUTCOME", "VERYTASTY", "A", "Z42"], ["OUTCOME", "VERY_TASTY", "A", "Z42"]);
+    check_rule!("PascalCase", ["Outcome", "VeryTasty", "A", "Z42"], ["Outcome", "VeryTasty", "A", "Z42"]);
+    check_rule!("camelCase", ["outcome", "veryTasty", "a", "z42"], ["outcome", "veryTasty", "a", "z42"]);
+    check_rule!("snake_case", ["outcome", "very_tasty", "a", "z42"], ["outcome", "very_tasty", "a", "z42"]);
+    check_rule!("SCREAMING_SNAKE_CASE", ["OUTCOME", "VERY_TASTY", "A", "Z42"], ["OUTCOME", "VERY_TASTY", "A", "Z42"]);
+    check_rule!("kebab-case", ["outcome", "very-tasty", "a", "z42"], ["outcome", "very-tasty", "a", "z42"]);
+    check_rule!("SCREAMING-KEBAB-CASE", ["OUTCOME", "VERY-TASTY", "A", "Z42"], ["OUTCOME", "VERY-TASTY", "A", "Z42"]);
+}
+
+#[test]
+fn untagged_enum_variant_attributes() {
+    /// A search result.
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Untagged {
+        /// A matching user.
+        #[serde(rename_all = "camelCase")]
+        User {
+            /// The login name.
+            user_name: String,
+            #[serde(rename = "ts")]
+            created_at: String,
+            #[magnet(min_incl = "1", max_incl = "10")]
+            search_rank: i32,
+            #[serde(default)]
+            display_name: Option<String>,
+            #[serde(skip)]
+            cache: Vec<u8>,
+        },
+        /// A matching tag.
+        Tag(String),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    enum External {
+        /// A matching user.
+        #[serde(rename_all = "camelCase")]
+        User {
+            /// The login name.
+            user_name: String,
+            #[serde(rename = "ts")]
+            created_at: String,
+            #[magnet(min_incl = "1", max_incl = "10")]
+            search_rank: i32,
+            #[serde(default)]
+            display_name: Option<String>,
+            #[serde(skip)]
+            cache: Vec<u8>,
+        },
+        /// A matching tag.
+        Tag(String),
+    }
+
+    let user = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["userName", "ts", "searchRank"],
+        "properties": {
+            "userName": {
+                "type": "string",
+                "description": "The login name.",
+            },
+            "ts": { "type": "string" },
+            "searchRank": {
+                "bsonType": ["int", "long"],
+                "minimum": 1_i64,
+                "exclusiveMinimum": false,
+                "maximum": 10_i64,
+                "exclusiveMaximum": false,
+            },
+            "displayName": { "type": ["string", "null"] },
+        },
+    };
+
+    let untagged = Untagged::bson_schema();
+    let mut described_user = user.clone();
+    described_user.insert("description", "A matching user.");
+
+    assert_schema_eq!(untagged, doc!{
+        "description": "A search result.",
+        "anyOf": [
+            described_user.clone(),
+            {
+                "type": "string",
+                "description": "A matching tag.",
+            },
+        ],
+    });
+
+    // the same as the contents of the externally tagged variants,
+    // which are also described by the doc comments of the variants
+    let external = External::bson_schema();
+    let branches = external.get_array("anyOf").unwrap();
+    let inner = |index: usize, name: &str| match branches[index] {
+        Bson::Document(ref doc) => doc.get_document("properties").unwrap().get_document(name).unwrap().clone(),
+        ref other => panic!("variant schema is not a document: {}", other),
+    };
+
+    assert_schema_eq!(inner(0, "User"), described_user);
+    assert_schema_eq!(inner(1, "Tag"), doc!{ "type": "string", "description": "A matching tag." });
+}
+
+#[test]
+fn malformed_internally_tagged_enum_1() {
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "variant")]
+    enum Foo {
+        Bar(Lol),
+    }
+
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    struct Lol;
+
+    assert_eq!(Foo::try_bson_schema(), Err(SchemaError::UntaggableVariant {
+        tag: String::from("variant"),
+        variant: String::from("Bar"),
+    }));
+}
 
 #[test]
-#[should_panic]
-fn malformed_internally_tagged_enum_4() {
+fn malformed_internally_tagged_enum_2() {
     #[derive(Serialize, Deserialize, BsonSchema)]
     #[serde(tag = "variant")]
     enum Foo {
@@ -690,11 +1684,161 @@ fn malformed_internally_tagged_enum_4() {
 
     #[derive(Serialize, Deserialize, BsonSchema)]
     enum E {
-        Qux,
-        Moo,
+        Qux { a: bool },
+        Moo { b: bool },
+    }
+
+    assert_eq!(Foo::try_bson_schema(), Err(SchemaError::Unsupported(
+        String::from("internally-tagged newtype variants around enums are")
+    )));
+}
+
+#[test]
+fn struct_with_many_fields() {
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Wide {
+        f00: u8,
+        f01: u8,
+        f02: u8,
+        f03: u8,
+        f04: u8,
+        f05: u8,
+        f06: u8,
+        f07: u8,
+        f08: u8,
+        f09: u8,
+        f10: u8,
+        f11: u8,
+        f12: u8,
+        f13: u8,
+        f14: u8,
+        f15: u8,
+        f16: u8,
+        f17: u8,
+        f18: u8,
+        f19: u8,
+        f20: u8,
+        f21: u8,
+        f22: u8,
+        f23: u8,
+        f24: u8,
+        f25: u8,
+        f26: u8,
+        f27: u8,
+        f28: u8,
+        f29: u8,
+        f30: u8,
+        f31: u8,
+        f32: u8,
+        f33: u8,
+        f34: u8,
+        f35: u8,
+        f36: u8,
+        f37: u8,
+        f38: u8,
+        f39: u8,
+        f40: u8,
+        f41: u8,
+        f42: u8,
+        f43: u8,
+        f44: u8,
+        f45: u8,
+        f46: u8,
+        f47: u8,
+        f48: u8,
+        f49: u8,
+        f50: u8,
+        f51: u8,
+        f52: u8,
+        f53: u8,
+        f54: u8,
+        f55: u8,
+        f56: u8,
+        f57: u8,
+        f58: u8,
+        f59: u8,
+        f60: u8,
+        f61: u8,
+        f62: u8,
+        f63: u8,
+        f64: u8,
+        f65: u8,
+        f66: u8,
+        f67: u8,
+        f68: u8,
+        f69: u8,
+        f70: u8,
+        f71: u8,
+        f72: u8,
+        f73: u8,
+        f74: u8,
+        f75: u8,
+        f76: u8,
+        f77: u8,
+        f78: u8,
+        f79: u8,
+        f80: u8,
+        f81: u8,
+        f82: u8,
+        f83: u8,
+        f84: u8,
+        f85: u8,
+        f86: u8,
+        f87: u8,
+        f88: u8,
+        f89: u8,
+        f90: u8,
+        f91: u8,
+        f92: u8,
+        f93: u8,
+        f94: u8,
+        f95: u8,
+        f96: u8,
+        f97: u8,
+        f98: u8,
+        f99: u8,
+    }
+
+    let schema = Wide::bson_schema();
+    let required = schema.get_array("required").unwrap();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_eq!(required.len(), 100);
+    assert_eq!(properties.len(), 100);
+    assert_eq!(required[42], Bson::from("f42"));
+    assert_eq!(*properties.get_document("f99").unwrap(), u8::bson_schema());
+}
+
+#[test]
+fn unannotated_field_schemas() {
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Plain {
+        count: u32,
+        ///
+        name: Option<String>,
+        #[magnet(min_incl = "0")]
+        ratio: f64,
     }
 
-    Foo::bson_schema();
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Newtype(Vec<i64>);
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Pair(bool, String);
+
+    let schema = Plain::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_eq!(*properties.get_document("count").unwrap(), u32::bson_schema());
+    assert_eq!(*properties.get_document("name").unwrap(), Option::<String>::bson_schema());
+    assert_ne!(*properties.get_document("ratio").unwrap(), f64::bson_schema());
+
+    assert_eq!(Newtype::bson_schema(), Vec::<i64>::bson_schema());
+    assert_eq!(Pair::bson_schema(), <(bool, String)>::bson_schema());
 }
 
 #[test]
@@ -707,7 +1851,7 @@ fn generic_struct() {
         other: U,
     }
 
-    assert_doc_eq!(Generic::<Option<f32>, Box<u16>>::bson_schema(), doc! {
+    assert_schema_eq!(Generic::<Option<f32>, Box<u16>>::bson_schema(), doc! {
         "type": "object",
         "additionalProperties": false,
         "required": [
@@ -719,7 +1863,7 @@ fn generic_struct() {
             "ts": {
                 "type": "array",
                 "items": {
-                    "type": ["number", "null"],
+                    "bsonType": ["double", "int", "long", "null"],
                 },
             },
             "title": { "type": "string" },
@@ -731,7 +1875,7 @@ fn generic_struct() {
         },
     });
 
-    assert_doc_eq!(Generic::<f64>::bson_schema(), doc! {
+    assert_schema_eq!(Generic::<f64>::bson_schema(), doc! {
         "type": "object",
         "additionalProperties": false,
         "required": [
@@ -743,7 +1887,7 @@ fn generic_struct() {
             "ts": {
                 "type": "array",
                 "items": {
-                    "type": "number",
+                    "bsonType": ["double", "int", "long"],
                 },
             },
             "title": { "type": "string" },
@@ -757,209 +1901,5112 @@ fn generic_struct() {
 }
 
 #[test]
-fn generic_enum() {
-    use std::collections::{ HashMap, BTreeMap };
+#[deny(warnings)]
+fn defaulted_type_parameters() {
+    use std::marker::PhantomData;
+
+    struct NotASchema;
 
+    // the default isn't used, so it needn't implement `BsonSchema`
     #[allow(dead_code)]
-    #[derive(BsonSchema, Serialize)]
-    #[serde(tag = "kind")]
-    enum EitherRefMut<
-        'a,
-        'b: 'a,
-        L,
-        R = BTreeMap<&'a str, bool>
-    > where L: 'a, R: 'b {
-        Left(&'a mut L),
-        Right(&'b mut R),
+    #[derive(BsonSchema)]
+    struct Tagged<T, U = NotASchema> {
+        value: T,
+        marker: PhantomData<U>,
     }
 
-    type E<'life1, 'life2> = EitherRefMut<
-        'life1,
-        'life2,
-        HashMap<String, ()>,
-    >;
-
-    assert_doc_eq!(E::bson_schema(), doc! {
-        "anyOf": [
-            {
-                "type": "object",
-                "additionalProperties": {
-                    "type": ["array", "null"],
-                    "maxItems": 0_i64,
-                },
-                "required": ["kind"],
-                "properties": {
-                    "kind": {
-                        "enum": ["Left"],
-                    }
-                },
-            },
-            {
-                "type": "object",
-                "additionalProperties": {
-                    "type": "boolean",
-                },
-                "required": ["kind"],
-                "properties": {
-                    "kind": {
-                        "enum": ["Right"],
-                    }
-                },
-            },
-        ]
-    });
-}
-
-#[test]
-fn serde_rename_struct_field() {
-    #[derive(Serialize, BsonSchema)]
-    struct Foo {
-        #[serde(rename = "newname")]
-        field: i32,
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Counter<'a, T: 'a + ?Sized = str, N = u32> {
+        label: &'a T,
+        count: N,
     }
 
-    assert_doc_eq!(Foo::bson_schema(), doc!{
+    assert_schema_eq!(Tagged::<i32>::bson_schema(), doc!{
         "type": "object",
         "additionalProperties": false,
-        "required": ["newname"],
+        "required": ["value", "marker"],
         "properties": {
-            "newname": {
-                "bsonType": ["int", "long"],
-                "minimum": std::i32::MIN as i64,
-                "maximum": std::i32::MAX as i64,
+            "value": i32::bson_schema(),
+            "marker": PhantomData::<NotASchema>::bson_schema(),
+        },
+    });
+
+    let counter = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["label", "count"],
+        "properties": {
+            "label": { "type": "string" },
+            "count": u32::bson_schema(),
+        },
+    };
+
+    assert_schema_eq!(<Counter>::bson_schema(), counter.clone());
+    assert_schema_eq!(Counter::<str, u32>::bson_schema(), counter);
+    assert_eq!(<Counter>::schema_name(), Counter::<str, u32>::schema_name());
+    assert_schema_eq!(Counter::<String, u8>::bson_schema().get_document("properties").unwrap().clone(), doc!{
+        "label": { "type": "string" },
+        "count": u8::bson_schema(),
+    });
+}
+
+#[test]
+fn phantom_type_parameter() {
+    use std::marker::PhantomData;
+    use bson::oid::ObjectId;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Id<T> {
+        value: ObjectId,
+        _marker: PhantomData<T>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Tagged<T, U> {
+        value: T,
+        _marker: ::std::marker::PhantomData<fn() -> (T, U)>,
+    }
+
+    /// intentionally no impl or derive `BsonSchema` - it shouldn't be required!
+    struct NonSchemaType;
+
+    assert_schema_eq!(Id::<NonSchemaType>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["value", "_marker"],
+        "properties": {
+            "value": { "bsonType": "objectId" },
+            "_marker": {
+                "type": ["array", "null"],
+                "maxItems": 0_i64,
             },
         },
     });
+    assert!(Id::<NonSchemaType>::schema_name().starts_with("Id<"));
+
+    let tagged_schema = Tagged::<String, NonSchemaType>::bson_schema();
+    let properties = tagged_schema.get_document("properties").unwrap();
+
+    assert_schema_eq!(properties.get_document("value").unwrap().clone(), doc!{ "type": "string" });
 }
 
 #[test]
-fn serde_rename_enum_variant() {
+fn unused_type_parameter() {
     #[allow(dead_code)]
-    #[derive(Serialize, BsonSchema)]
-    #[serde(tag = "variant", content = "value")]
-    enum Quux {
-        #[serde(rename = "LongName")]
-        Variant(String),
+    #[derive(BsonSchema)]
+    enum Response<T, E> {
+        Ok(T),
+        Skipped {
+            #[magnet(skip)]
+            reason: Option<E>,
+        },
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Cached<T, C> {
+        value: T,
+        #[magnet(skip)]
+        cache: Option<C>,
     }
 
-    assert_doc_eq!(Quux::bson_schema(), doc!{
+    /// intentionally no impl or derive `BsonSchema` - it shouldn't be required!
+    struct NonSchemaType;
+
+    assert_schema_eq!(Response::<bool, NonSchemaType>::bson_schema(), doc!{
         "anyOf": [
             {
                 "type": "object",
                 "additionalProperties": false,
-                "required": ["variant", "value"],
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Ok"],
                 "properties": {
-                    "variant": {
-                        "enum": ["LongName"],
-                    },
-                    "value": {
-                        "type": "string",
+                    "Ok": { "type": "boolean" },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Skipped"],
+                "properties": {
+                    "Skipped": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {},
                     },
                 },
             },
-        ],
+        ]
+    });
+    assert_schema_eq!(Cached::<bool, NonSchemaType>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["value"],
+        "properties": {
+            "value": { "type": "boolean" },
+        },
     });
 }
 
 #[test]
-fn optional_enum() {
+fn associated_type_parameter() {
+    trait Config {
+        type Output: BsonSchema;
+    }
+
+    #[derive(BsonSchema)]
+    struct Small;
+
+    impl Config for Small {
+        type Output = u8;
+    }
+
     #[allow(dead_code)]
-    #[derive(Serialize, BsonSchema)]
-    enum Value {
-        Val(String)
+    #[derive(BsonSchema)]
+    struct Projected<T: Config> {
+        output: T::Output,
+    }
+
+    assert_schema_eq!(Projected::<Small>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["output"],
+        "properties": {
+            "output": {
+                "bsonType": ["int", "long"],
+                "minimum": 0_i64,
+                "maximum": 255_i64,
+            },
+        },
+    });
+}
+
+#[test]
+fn associated_type_projections() {
+    use std::collections::HashMap;
+
+    trait Config {
+        type Payload;
+    }
+
+    // neither implements `BsonSchema`, only their associated types do
+    struct Small;
+    struct Large;
+
+    impl Config for Small {
+        type Payload = u8;
+    }
+
+    impl Config for Large {
+        type Payload = Vec<i64>;
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Wrapper<T: Config> where T::Payload: Clone {
+        payload: T::Payload,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    enum Message<T: Config> {
+        Single(<T as Config>::Payload),
+        Batch(Vec<T::Payload>),
+        Keyed(HashMap<String, Option<<T as Config>::Payload>>),
     }
 
-    assert_doc_eq!(Option::<Value>::bson_schema(), doc!{
+    assert_schema_eq!(Wrapper::<Small>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["payload"],
+        "properties": {
+            "payload": u8::bson_schema(),
+        },
+    });
+    assert_schema_eq!(
+        Wrapper::<Large>::bson_schema().get_document("properties").unwrap().clone(),
+        doc!{ "payload": Vec::<i64>::bson_schema() }
+    );
+
+    assert_schema_eq!(Message::<Small>::bson_schema(), doc!{
         "anyOf": [
             {
                 "type": "object",
                 "additionalProperties": false,
-                "required": ["Val"],
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Single"],
                 "properties": {
-                    "Val": {
-                        "type": "string"
-                    },
+                    "Single": u8::bson_schema(),
                 },
             },
             {
-                "type": "null"
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Batch"],
+                "properties": {
+                    "Batch": Vec::<u8>::bson_schema(),
+                },
             },
-        ]
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Keyed"],
+                "properties": {
+                    "Keyed": HashMap::<String, Option<u8>>::bson_schema(),
+                },
+            },
+        ],
     });
 }
 
 #[test]
-fn std_ranges() {
-    use std::i32;
-    use std::ops::{ Range, RangeInclusive };
+fn excluded_type_parameter_bounds() {
+    use std::marker::PhantomData;
+    use bson::oid::ObjectId;
+
+    /// The ID of a document in the collection of `T`s.
+    struct Id<T>(ObjectId, PhantomData<T>);
+
+    // the schema of an ID doesn't depend on `T`
+    impl<T> BsonSchema for Id<T> {
+        fn bson_schema() -> Document {
+            ObjectId::bson_schema()
+        }
+    }
+
+    struct Customer;
 
     #[allow(dead_code)]
     #[derive(BsonSchema)]
-    struct Ranges {
-        half_open: Range<i32>,
-        closed: RangeInclusive<f64>,
+    #[magnet(no_bound(T))]
+    struct Reference<T, U> {
+        id: Id<T>,
+        note: U,
     }
 
-    assert_doc_eq!(Ranges::bson_schema(), doc!{
+    assert_schema_eq!(Reference::<Customer, String>::bson_schema(), doc!{
         "type": "object",
         "additionalProperties": false,
-        "required": ["half_open", "closed"],
+        "required": ["id", "note"],
         "properties": {
-            "half_open": {
+            "id": { "bsonType": "objectId" },
+            "note": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn generic_enum() {
+    use std::collections::{ HashMap, BTreeMap };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema, Serialize)]
+    #[serde(tag = "kind")]
+    enum EitherRefMut<
+        'a,
+        'b: 'a,
+        L,
+        R = BTreeMap<&'a str, bool>
+    > where L: 'a, R: 'b {
+        Left(&'a mut L),
+        Right(&'b mut R),
+    }
+
+    type E<'life1, 'life2> = EitherRefMut<
+        'life1,
+        'life2,
+        HashMap<String, ()>,
+    >;
+
+    assert_schema_eq!(E::bson_schema(), doc! {
+        "anyOf": [
+            {
                 "type": "object",
-                "additionalProperties": false,
-                "required": ["start", "end"],
+                "additionalProperties": {
+                    "type": ["array", "null"],
+                    "maxItems": 0_i64,
+                },
+                "required": ["kind"],
                 "properties": {
-                    "start": {
-                        "bsonType": ["int", "long"],
-                        "minimum": i32::MIN as i64,
-                        "maximum": i32::MAX as i64,
-                    },
-                    "end": {
-                        "bsonType": ["int", "long"],
-                        "minimum": i32::MIN as i64,
-                        "maximum": i32::MAX as i64,
-                    },
-                }
+                    "kind": {
+                        "enum": ["Left"],
+                    }
+                },
             },
-            "closed": {
+            {
                 "type": "object",
-                "additionalProperties": false,
-                "required": ["start", "end"],
+                "additionalProperties": {
+                    "type": "boolean",
+                },
+                "required": ["kind"],
                 "properties": {
-                    "start": {
-                        "type": "number",
-                    },
-                    "end": {
-                        "type": "number",
-                    },
-                }
+                    "kind": {
+                        "enum": ["Right"],
+                    }
+                },
             },
-        }
+        ]
     });
 }
 
 #[test]
-fn std_sequence_collections() {
-    use std::collections::{ VecDeque, BinaryHeap, LinkedList };
+fn serde_rename_struct_field() {
+    #[derive(Serialize, BsonSchema)]
+    struct Foo {
+        #[serde(rename = "newname")]
+        field: i32,
+    }
 
-    #[allow(dead_code)]
-    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, BsonSchema)]
-    enum ElaborateType {
-        Foo(Option<u64>),
-        Bar {
-            field: String,
+    assert_schema_eq!(Foo::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["newname"],
+        "properties": {
+            "newname": {
+                "bsonType": ["int", "long"],
+                "minimum": std::i32::MIN as i64,
+                "maximum": std::i32::MAX as i64,
+            },
         },
-        Qux(Box<[bool; 4]>),
+    });
+}
+
+#[test]
+fn serde_rename_enum_variant() {
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "variant", content = "value")]
+    enum Quux {
+        #[serde(rename = "LongName")]
+        Variant(String),
     }
 
-    let array_schema = doc!{
-        "type": "array",
-        "items": ElaborateType::bson_schema(),
-    };
+    assert_schema_eq!(Quux::bson_schema(), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["variant", "value"],
+                "properties": {
+                    "variant": {
+                        "enum": ["LongName"],
+                    },
+                    "value": {
+                        "type": "string",
+                    },
+                },
+            },
+        ],
+    });
+}
+
+#[test]
+fn serde_rename_precedence() {
+    // `rename` of a variant wins over `rename_all` of the enum,
+    // and aliases never show up, since they're never serialized.
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(rename_all = "kebab-case")]
+    enum External {
+        FirstOne(u8),
+        #[serde(rename = "SECOND", alias = "second-one")]
+        SecondOne(u8),
+        #[serde(rename(serialize = "third", deserialize = "3rd"))]
+        ThirdOne(u8),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind", rename_all = "kebab-case")]
+    enum Internal {
+        FirstOne { x: u8 },
+        #[serde(rename = "SECOND", alias = "second-one")]
+        SecondOne { x: u8 },
+        #[serde(rename(serialize = "third", deserialize = "3rd"))]
+        ThirdOne { x: u8 },
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind", content = "value", rename_all = "kebab-case")]
+    enum Adjacent {
+        FirstOne(u8),
+        #[serde(rename = "SECOND", alias = "second-one")]
+        SecondOne(u8),
+        #[serde(rename(serialize = "third", deserialize = "3rd"))]
+        ThirdOne(u8),
+    }
+
+    // names of untagged variants aren't stored, and aliases can't collide
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(untagged, rename_all = "kebab-case")]
+    enum Untagged {
+        #[serde(alias = "SECOND")]
+        FirstOne(u8),
+        #[serde(rename = "SECOND")]
+        SecondOne(String),
+    }
+
+    let expected = ["first-one", "SECOND", "third"];
+
+    let external = External::bson_schema();
+    let external_names: Vec<&str> = external
+        .get_array("anyOf").unwrap()
+        .iter()
+        .map(|branch| {
+            let properties = branch.as_document().unwrap().get_document("properties").unwrap();
+            properties.keys().next().unwrap().as_str()
+        })
+        .collect();
+
+    assert_eq!(external_names, expected);
+
+    for schema in &[Internal::bson_schema(), Adjacent::bson_schema()] {
+        let tag_names: Vec<&str> = schema
+            .get_array("anyOf").unwrap()
+            .iter()
+            .map(|branch| {
+                let properties = branch.as_document().unwrap().get_document("properties").unwrap();
+                let tag = properties.get_document("kind").unwrap();
+                assert_eq!(tag.get_array("enum").unwrap().len(), 1);
+                tag.get_array("enum").unwrap()[0].as_str().unwrap()
+            })
+            .collect();
+
+        assert_eq!(tag_names, expected);
+    }
+
+    assert_schema_eq!(Untagged::bson_schema(), doc!{
+        "anyOf": [u8::bson_schema(), String::bson_schema()],
+    });
+}
+
+#[test]
+fn tag_summary() {
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    #[magnet(tag_summary)]
+    enum Event {
+        LoggedIn { user: String },
+        #[serde(rename = "bye")]
+        LoggedOut { user: String },
+        Restarted,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "t", content = "c")]
+    #[magnet(tag_summary)]
+    enum Value {
+        #[serde(rename = "int")]
+        Integer(i32),
+        Text(String),
+    }
+
+    assert_schema_eq!(Event::bson_schema(), doc!{
+        "required": ["kind"],
+        "properties": {
+            "kind": { "enum": ["logged_in", "bye", "restarted"] },
+        },
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind", "user"],
+                "properties": {
+                    "kind": { "enum": ["logged_in"] },
+                    "user": { "type": "string" },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind", "user"],
+                "properties": {
+                    "kind": { "enum": ["bye"] },
+                    "user": { "type": "string" },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind"],
+                "properties": {
+                    "kind": { "enum": ["restarted"] },
+                },
+            },
+        ],
+    });
+
+    let value = Value::bson_schema();
+    let keys: Vec<&str> = value.keys().map(String::as_str).collect();
+
+    assert_eq!(keys, ["required", "properties", "anyOf"]);
+    assert_eq!(value.get_array("required").unwrap(), &vec![Bson::from("t")]);
+    assert_eq!(
+        value.get_document("properties").unwrap(),
+        &doc!{ "t": { "enum": ["int", "Text"] } }
+    );
+    assert_eq!(value.get_array("anyOf").unwrap().len(), 2);
+}
+
+#[test]
+fn optional_enum() {
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    enum Value {
+        Val(String)
+    }
+
+    assert_schema_eq!(Option::<Value>::bson_schema(), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Val"],
+                "properties": {
+                    "Val": {
+                        "type": "string"
+                    },
+                },
+            },
+            {
+                "type": "null"
+            },
+        ]
+    });
+}
+
+#[test]
+fn std_ranges() {
+    use std::i32;
+    use std::ops::{ Range, RangeInclusive };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Ranges {
+        half_open: Range<i32>,
+        closed: RangeInclusive<f64>,
+    }
+
+    assert_schema_eq!(Ranges::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["half_open", "closed"],
+        "properties": {
+            "half_open": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["start", "end"],
+                "properties": {
+                    "start": {
+                        "bsonType": ["int", "long"],
+                        "minimum": i32::MIN as i64,
+                        "maximum": i32::MAX as i64,
+                    },
+                    "end": {
+                        "bsonType": ["int", "long"],
+                        "minimum": i32::MIN as i64,
+                        "maximum": i32::MAX as i64,
+                    },
+                }
+            },
+            "closed": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["start", "end"],
+                "properties": {
+                    "start": {
+                        "bsonType": ["double", "int", "long"],
+                    },
+                    "end": {
+                        "bsonType": ["double", "int", "long"],
+                    },
+                }
+            },
+        }
+    });
+}
+
+#[test]
+fn std_sequence_collections() {
+    use std::collections::{ VecDeque, BinaryHeap, LinkedList };
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, BsonSchema)]
+    enum ElaborateType {
+        Foo(Option<u64>),
+        Bar {
+            field: String,
+        },
+        Qux(Box<[bool; 4]>),
+    }
+
+    let array_schema = doc!{
+        "type": "array",
+        "items": ElaborateType::bson_schema(),
+    };
+
+    assert_schema_eq!(Vec::<ElaborateType>::bson_schema(),        array_schema);
+    assert_schema_eq!(VecDeque::<ElaborateType>::bson_schema(),   array_schema);
+    assert_schema_eq!(BinaryHeap::<ElaborateType>::bson_schema(), array_schema);
+    assert_schema_eq!(LinkedList::<ElaborateType>::bson_schema(), array_schema);
+}
+
+#[test]
+fn collections_of_unordered_unhashable_types() {
+    use std::borrow::Cow;
+    use std::collections::{ BinaryHeap, HashSet, HashMap, BTreeSet, BTreeMap };
+
+    // neither `Eq`, nor `Hash`, nor `Ord`
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Measurement {
+        value: f64,
+    }
+
+    // not a `BuildHasher`
+    struct NotAHasher;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Wrapper<T> {
+        heap: BinaryHeap<T>,
+        set: HashSet<T>,
+    }
+
+    let array_schema = doc!{
+        "type": "array",
+        "items": Measurement::bson_schema(),
+    };
+    let set_schema = doc!{
+        "type": "array",
+        "uniqueItems": true,
+        "items": Measurement::bson_schema(),
+    };
+    let map_schema = doc!{
+        "type": "object",
+        "additionalProperties": Measurement::bson_schema(),
+    };
+
+    assert_schema_eq!(BinaryHeap::<Measurement>::bson_schema(), array_schema.clone());
+    assert_schema_eq!(HashSet::<Measurement>::bson_schema(), set_schema.clone());
+    assert_schema_eq!(HashSet::<Measurement, NotAHasher>::bson_schema(), set_schema.clone());
+    assert_schema_eq!(BTreeSet::<Measurement>::bson_schema(), set_schema.clone());
+    assert_schema_eq!(HashMap::<String, Measurement, NotAHasher>::bson_schema(), map_schema.clone());
+    assert_schema_eq!(BTreeMap::<Cow<str>, Measurement>::bson_schema(), map_schema);
+
+    assert_schema_eq!(Wrapper::<Measurement>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["heap", "set"],
+        "properties": {
+            "heap": array_schema,
+            "set": set_schema,
+        },
+    });
+
+    // `Cow` only requires `ToOwned`, not `Clone`, so unsized types work too
+    assert_schema_eq!(Cow::<str>::bson_schema(), String::bson_schema());
+    assert_schema_eq!(Cow::<[bool]>::bson_schema(), Vec::<bool>::bson_schema());
+}
+
+#[test]
+fn cow_fields() {
+    use std::borrow::Cow;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Borrowed<'a> {
+        name: Cow<'a, str>,
+        bytes: Cow<'a, [u8]>,
+        aliases: Cow<'a, [String]>,
+        scores: Option<Cow<'a, [f64]>>,
+    }
+
+    assert_schema_eq!(Borrowed::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name", "bytes", "aliases", "scores"],
+        "properties": {
+            "name": String::bson_schema(),
+            "bytes": Vec::<u8>::bson_schema(),
+            "aliases": Vec::<String>::bson_schema(),
+            "scores": Option::<Vec<f64>>::bson_schema(),
+        },
+    });
+}
+
+#[test]
+fn schema_override_for_trait_objects() {
+    use std::sync::Arc;
+
+    trait Payload {
+        fn kind(&self) -> &str;
+    }
+
+    fn payload_schema() -> Document {
+        doc!{
+            "type": "object",
+            "required": ["kind"],
+            "properties": { "kind": { "type": "string" } },
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Message {
+        id: String,
+        #[magnet(with = "payload_schema")]
+        payload: Box<dyn Payload>,
+        #[magnet(with = "payload_schema")]
+        attachments: Vec<Arc<dyn Payload>>,
+    }
+
+    assert_schema_eq!(Message::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["id", "payload", "attachments"],
+        "properties": {
+            "id": { "type": "string" },
+            "payload": payload_schema(),
+            "attachments": payload_schema(),
+        },
+    });
+}
+
+#[test]
+fn std_ip_addresses() {
+    use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr };
+    use magnet_schema::support::{
+        IP_ADDR_PATTERN, IPV4_ADDR_PATTERN, IPV6_ADDR_PATTERN,
+    };
+
+    assert_schema_eq!(IpAddr::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IP_ADDR_PATTERN,
+    });
+    assert_schema_eq!(Ipv4Addr::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IPV4_ADDR_PATTERN,
+    });
+    assert_schema_eq!(Ipv6Addr::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IPV6_ADDR_PATTERN,
+    });
+}
+
+#[test]
+fn bson_value_types() {
+    use bson::{ UtcDateTime, TimeStamp };
+    use bson::oid::ObjectId;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Raw {
+        id: ObjectId,
+        anything: Bson,
+        maybe_anything: Option<Bson>,
+        created_at: UtcDateTime,
+        updated_at: Option<UtcDateTime>,
+        op_time: TimeStamp,
+        extra: Document,
+    }
+
+    assert_schema_eq!(Bson::bson_schema(), doc!{});
+    assert_schema_eq!(Option::<Bson>::bson_schema(), doc!{ "anyOf": [{}, { "type": "null" }] });
+    assert_schema_eq!(UtcDateTime::bson_schema(), doc!{ "bsonType": "date" });
+    assert_schema_eq!(TimeStamp::bson_schema(), doc!{ "bsonType": "timestamp" });
+
+    assert_schema_eq!(Raw::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": [
+            "id",
+            "anything",
+            "maybe_anything",
+            "created_at",
+            "updated_at",
+            "op_time",
+            "extra",
+        ],
+        "properties": {
+            "id": { "bsonType": "objectId" },
+            "anything": {},
+            "maybe_anything": { "anyOf": [{}, { "type": "null" }] },
+            "created_at": { "bsonType": "date" },
+            "updated_at": { "bsonType": ["date", "null"] },
+            "op_time": { "bsonType": "timestamp" },
+            "extra": { "type": "object" },
+        },
+    });
+}
+
+#[cfg(feature = "url")]
+#[test]
+fn url_pattern() {
+    use magnet_schema::support::{ URL_PATTERN, HTTP_URL_PATTERN };
+
+    let schema = url::Url::bson_schema();
+    let pattern = schema.get_str("pattern").expect("no URL pattern");
+
+    assert_eq!(pattern, URL_PATTERN);
+    assert!(pattern.starts_with('^'));
+    assert!(pattern.ends_with('$'));
+    assert!(HTTP_URL_PATTERN.starts_with('^'));
+    assert!(HTTP_URL_PATTERN.ends_with('$'));
+
+    assert_schema_eq!(schema, doc!{
+        "type": "string",
+        "pattern": URL_PATTERN,
+    });
+}
+
+#[test]
+fn named_string_formats() {
+    use magnet_schema::support::patterns::{ self, FORMATS };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Contact {
+        #[magnet(format = "email")]
+        email: String,
+        #[magnet(format = "hostname")]
+        hostname: String,
+        #[magnet(format = "ipv4")]
+        ipv4: String,
+        #[magnet(format = "ipv6")]
+        ipv6: String,
+        #[magnet(format = "uri")]
+        uri: String,
+        #[magnet(format = "uuid")]
+        uuid: String,
+        #[magnet(format = "date")]
+        date: String,
+        #[magnet(format = "date-time")]
+        date_time: String,
+        #[magnet(format = "objectid-hex")]
+        objectid_hex: String,
+        #[magnet(format = "email")]
+        backup_email: Option<String>,
+    }
+
+    let schema = Contact::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    for &(format, pattern) in FORMATS {
+        assert!(pattern.starts_with('^') && pattern.ends_with('$'), "{}", format);
+        assert_eq!(patterns::for_format(format), Some(pattern));
+
+        let property = format.replace('-', "_");
+        assert_schema_eq!(properties.get_document(&property).unwrap(), doc!{
+            "type": "string",
+            "pattern": pattern,
+            "format": format,
+        });
+    }
+
+    assert_schema_eq!(properties.get_document("backup_email").unwrap(), doc!{
+        "type": ["string", "null"],
+        "pattern": patterns::EMAIL_PATTERN,
+        "format": "email",
+    });
+    assert_eq!(patterns::for_format("phone"), None);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn named_string_format_patterns() {
+    use regex::Regex;
+    use magnet_schema::support::patterns;
+
+    let cases: &[(&str, &[&str], &[&str])] = &[
+        ("email", &["user@example.com", "first.last+tag@sub.example.org"], &["user", "@example.com", "a b@example.com"]),
+        ("hostname", &["localhost", "db-1.example.com"], &["-db.example.com", "db..example.com", "db_1"]),
+        ("ipv4", &["127.0.0.1", "255.255.255.255"], &["256.0.0.1", "1.2.3"]),
+        ("ipv6", &["::1", "fe80::1:2"], &["1:2:3", "::g"]),
+        ("uri", &["https://example.com/a?b#c", "mongodb://host:27017"], &["example.com", "://example.com"]),
+        ("uuid", &["67e55044-10b1-426f-9247-bb680e5fe0c8"], &["67e5504410b1426f9247bb680e5fe0c8"]),
+        ("date", &["2019-02-28", "2020-12-31"], &["2019-13-01", "2019-2-28", "2019-02-28T00:00:00Z"]),
+        ("date-time", &["2019-02-28T13:45:00Z", "2019-02-28t13:45:00.123+01:00"], &["2019-02-28 13:45:00Z", "2019-02-28T13:45:00"]),
+        ("objectid-hex", &["5c7fc8fa4d3c2e0a5c2b6a1f"], &["5c7fc8fa4d3c2e0a5c2b6a1", "zc7fc8fa4d3c2e0a5c2b6a1f"]),
+    ];
+
+    for &(format, valid, invalid) in cases {
+        let regex = Regex::new(patterns::for_format(format).unwrap()).unwrap();
+
+        for value in valid {
+            assert!(regex.is_match(value), "{} should be a valid {}", value, format);
+        }
+
+        for value in invalid {
+            assert!(!regex.is_match(value), "{} should be an invalid {}", value, format);
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn bytes_as_binary() {
+    use bytes::{ Bytes, BytesMut };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Blob {
+        payload: Bytes,
+        buffer: BytesMut,
+        optional: Option<Bytes>,
+    }
+
+    assert_schema_eq!(Bytes::bson_schema(), doc!{ "bsonType": "binData" });
+    assert_schema_eq!(BytesMut::bson_schema(), doc!{ "bsonType": "binData" });
+
+    assert_schema_eq!(Blob::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["payload", "buffer", "optional"],
+        "properties": {
+            "payload": { "bsonType": "binData" },
+            "buffer": { "bsonType": "binData" },
+            "optional": { "bsonType": ["binData", "null"] },
+        },
+    });
+}
+
+#[cfg(feature = "either")]
+#[test]
+fn either_as_externally_tagged_enum() {
+    use either::Either;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    enum E<L, R> {
+        Left(L),
+        Right(R),
+    }
+
+    assert_schema_eq!(
+        Either::<String, Option<u16>>::bson_schema(),
+        E::<String, Option<u16>>::bson_schema()
+    );
+
+    assert_schema_eq!(Either::<bool, f64>::bson_schema(), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Left"],
+                "properties": {
+                    "Left": { "type": "boolean" },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Right"],
+                "properties": {
+                    "Right": { "bsonType": ["double", "int", "long"] },
+                },
+            },
+        ]
+    });
+}
+
+#[cfg(feature = "semver")]
+#[test]
+fn semver_version_pattern() {
+    use magnet_schema::support::SEMVER_PATTERN;
+
+    assert!(SEMVER_PATTERN.starts_with('^'));
+    assert!(SEMVER_PATTERN.ends_with('$'));
+
+    assert_schema_eq!(semver::Version::bson_schema(), doc!{
+        "type": "string",
+        "pattern": SEMVER_PATTERN,
+    });
+    assert_schema_eq!(Option::<semver::Version>::bson_schema(), doc!{
+        "type": ["string", "null"],
+        "pattern": SEMVER_PATTERN,
+    });
+}
+
+#[cfg(feature = "mime")]
+#[test]
+fn mime_pattern() {
+    use magnet_schema::support::MIME_PATTERN;
+
+    assert!(MIME_PATTERN.starts_with('^'));
+    assert!(MIME_PATTERN.ends_with('$'));
+
+    assert_schema_eq!(mime::Mime::bson_schema(), doc!{
+        "type": "string",
+        "pattern": MIME_PATTERN,
+    });
+}
+
+#[cfg(feature = "ordered-float")]
+#[test]
+fn ordered_float_wrappers() {
+    use ordered_float::{ OrderedFloat, NotNan };
+
+    assert_schema_eq!(OrderedFloat::<f64>::bson_schema(), f64::bson_schema());
+    assert_schema_eq!(OrderedFloat::<f32>::bson_schema(), f32::bson_schema());
+    assert_schema_eq!(NotNan::<f64>::bson_schema(), f64::bson_schema());
+    assert_schema_eq!(NotNan::<f32>::bson_schema(), f32::bson_schema());
+
+    assert_schema_eq!(Vec::<OrderedFloat<f64>>::bson_schema(), doc!{
+        "type": "array",
+        "items": f64::bson_schema(),
+    });
+}
+
+#[cfg(feature = "ipnet")]
+#[test]
+fn ipnet_networks() {
+    use ipnet::{ IpNet, Ipv4Net, Ipv6Net };
+    use magnet_schema::support::{
+        IP_NET_PATTERN, IPV4_NET_PATTERN, IPV6_NET_PATTERN,
+    };
+
+    for &pattern in &[IP_NET_PATTERN, IPV4_NET_PATTERN, IPV6_NET_PATTERN] {
+        assert!(pattern.starts_with('^'));
+        assert!(pattern.ends_with('$'));
+    }
+
+    assert!(IPV4_NET_PATTERN.contains("/(?:3[0-2]|[12]?\\d)"));
+    assert!(IPV6_NET_PATTERN.contains("/(?:12[0-8]|1[01]\\d|[1-9]?\\d)"));
+
+    assert_schema_eq!(IpNet::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IP_NET_PATTERN,
+    });
+    assert_schema_eq!(Ipv4Net::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IPV4_NET_PATTERN,
+    });
+    assert_schema_eq!(Ipv6Net::bson_schema(), doc!{
+        "type": "string",
+        "pattern": IPV6_NET_PATTERN,
+    });
+}
+
+#[test]
+fn json_schema_struct() {
+    use bson::oid::ObjectId;
+    use magnet_schema::convert::JSON_SCHEMA_DRAFT_07;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Account {
+        id: ObjectId,
+        name: String,
+        age: Option<u8>,
+        #[magnet(min_excl = 0, max_incl = 100)]
+        score: f64,
+        tags: Vec<String>,
+    }
+
+    assert_schema_eq!(Account::json_schema(), doc!{
+        "$schema": JSON_SCHEMA_DRAFT_07,
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["id", "name", "age", "score", "tags"],
+        "properties": {
+            "id": {
+                "type": "string",
+                "pattern": "^[0-9a-fA-F]{24}$",
+            },
+            "name": { "type": "string" },
+            "age": {
+                "type": ["integer", "null"],
+                "minimum": std::u8::MIN as i64,
+                "maximum": std::u8::MAX as i64,
+            },
+            "score": {
+                "type": "number",
+                "exclusiveMinimum": 0_i64,
+                "maximum": 100_i64,
+            },
+            "tags": {
+                "type": "array",
+                "items": { "type": "string" },
+            },
+        },
+    });
+}
+
+#[test]
+fn json_schema_enum() {
+    use magnet_schema::convert::to_json_subschema;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind", content = "value")]
+    enum Shape {
+        Point,
+        Circle(#[magnet(min_incl = 0)] f32),
+        Rect(i32, i32),
+    }
+
+    assert_schema_eq!(to_json_subschema(Option::<Shape>::bson_schema()), doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind"],
+                "properties": {
+                    "kind": { "enum": ["Point"] },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind", "value"],
+                "properties": {
+                    "kind": { "enum": ["Circle"] },
+                    "value": {
+                        "type": "number",
+                        "minimum": 0_i64,
+                    },
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["kind", "value"],
+                "properties": {
+                    "kind": { "enum": ["Rect"] },
+                    "value": {
+                        "type": "array",
+                        "additionalItems": false,
+                        "items": [
+                            {
+                                "type": "integer",
+                                "minimum": std::i32::MIN as i64,
+                                "maximum": std::i32::MAX as i64,
+                            },
+                            {
+                                "type": "integer",
+                                "minimum": std::i32::MIN as i64,
+                                "maximum": std::i32::MAX as i64,
+                            },
+                        ],
+                    },
+                },
+            },
+            {
+                "type": "null",
+            },
+        ],
+    });
+}
+
+#[test]
+fn json_schema_bson_types() {
+    use bson::UtcDateTime;
+    use magnet_schema::convert::to_json_subschema;
+
+    assert_schema_eq!(to_json_subschema(doc!{ "bsonType": "int" }), doc!{
+        "type": "integer",
+        "format": "int32",
+    });
+    assert_schema_eq!(to_json_subschema(doc!{ "bsonType": ["long", "null"] }), doc!{
+        "type": ["integer", "null"],
+        "format": "int64",
+    });
+    assert_schema_eq!(to_json_subschema(Option::<UtcDateTime>::bson_schema()), doc!{
+        "type": ["string", "null"],
+        "format": "date-time",
+    });
+    assert_schema_eq!(to_json_subschema(doc!{
+        "minimum": 1,
+        "exclusiveMinimum": false,
+        "maximum": 9,
+        "exclusiveMaximum": true,
+    }), doc!{
+        "minimum": 1,
+        "exclusiveMaximum": 9,
+    });
+}
+
+#[test]
+fn recursive_struct() {
+    use magnet_schema::flatten_refs;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Tree {
+        value: bool,
+        children: Vec<Tree>,
+    }
+
+    let tree_schema = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["value", "children"],
+        "properties": {
+            "value": { "type": "boolean" },
+            "children": {
+                "type": "array",
+                "items": { "$ref": "#/definitions/Tree" },
+            },
+        },
+    };
+
+    let mut expected = tree_schema.clone();
+    expected.insert("definitions", doc!{ "Tree": tree_schema.clone() });
+
+    assert_schema_eq!(Tree::bson_schema(), expected);
+
+    let mut flat = tree_schema.clone();
+    flat.insert("properties", doc!{
+        "value": { "type": "boolean" },
+        "children": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["value", "children"],
+                "properties": {
+                    "value": { "type": "boolean" },
+                    "children": {
+                        "type": "array",
+                        "items": { "not": {} },
+                    },
+                },
+            },
+        },
+    });
+
+    assert_schema_eq!(flatten_refs(Tree::bson_schema(), 1), flat);
+}
+
+#[test]
+fn recursive_enum() {
+    use magnet_schema::flatten_refs;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    enum Expr {
+        Lit(i64),
+        Add(Box<Expr>, Box<Expr>),
+    }
+
+    let expr_ref = doc!{ "$ref": "#/definitions/Expr" };
+    let schema = Expr::bson_schema();
+    let definitions = schema.get_document("definitions").expect("no definitions");
+    let expr_schema = definitions.get_document("Expr").expect("no Expr definition");
+
+    assert_eq!(definitions.len(), 1);
+    assert_schema_eq!(expr_schema, doc!{
+        "anyOf": [
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Lit"],
+                "properties": {
+                    "Lit": i64::bson_schema(),
+                },
+            },
+            {
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "required": ["Add"],
+                "properties": {
+                    "Add": {
+                        "type": "array",
+                        "additionalItems": false,
+                        "items": [expr_ref.clone(), expr_ref],
+                    },
+                },
+            },
+        ]
+    });
+
+    let flat = flatten_refs(schema, 3);
+
+    assert!(!flat.contains_key("definitions"));
+    assert!(!format!("{}", flat).contains("$ref"));
+}
+
+#[test]
+fn mutually_recursive_structs() {
+    use magnet_schema::flatten_refs;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Directory {
+        name: String,
+        entries: Vec<Entry>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Entry {
+        size: u32,
+        subdirectory: Box<Directory>,
+    }
+
+    let dir_schema = Directory::bson_schema();
+    let entry_schema = Entry::bson_schema();
+
+    let dir_defs = dir_schema.get_document("definitions").expect("no definitions");
+    let entry_defs = entry_schema.get_document("definitions").expect("no definitions");
+
+    assert_eq!(dir_defs.keys().collect::<Vec<_>>(), vec!["Directory"]);
+    assert_eq!(entry_defs.keys().collect::<Vec<_>>(), vec!["Entry"]);
+
+    assert_schema_eq!(dir_defs.get_document("Directory").expect("no Directory"), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name", "entries"],
+        "properties": {
+            "name": { "type": "string" },
+            "entries": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["size", "subdirectory"],
+                    "properties": {
+                        "size": u32::bson_schema(),
+                        "subdirectory": { "$ref": "#/definitions/Directory" },
+                    },
+                },
+            },
+        },
+    });
+
+    let flat = flatten_refs(entry_schema, 2);
+
+    assert!(!flat.contains_key("definitions"));
+    assert!(!format!("{}", flat).contains("$ref"));
+
+    // non-recursive types don't get `definitions`
+    assert!(!u32::bson_schema().contains_key("definitions"));
+    assert!(!Vec::<Option<String>>::bson_schema().contains_key("definitions"));
+}
+
+#[test]
+fn schema_names() {
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Plain {
+        field: u8,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Generic<'a, T: 'a, U> {
+        first: &'a T,
+        second: U,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(schema_name = "CustomName")]
+    enum Renamed {
+        Variant,
+    }
+
+    assert_eq!(Plain::schema_name(), "Plain");
+    assert_eq!(Renamed::schema_name(), "CustomName");
+    assert_eq!(Generic::<Plain, Renamed>::schema_name(), "Generic<Plain, CustomName>");
+    assert_eq!(Generic::<Option<i32>, String>::schema_name(), "Generic<Option<i32>, String>");
+    assert_eq!(i64::schema_name(), "i64");
+    assert_eq!(str::schema_name(), "str");
+    assert_eq!(Vec::<Option<bool>>::schema_name(), "Vec<Option<bool>>");
+    assert_eq!(HashMap::<String, u8>::schema_name(), "HashMap<String, u8>");
+}
+
+#[test]
+fn recursive_generic_struct() {
+    use magnet_schema::flatten_refs;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct List<T> {
+        head: T,
+        tail: Vec<List<T>>,
+    }
+
+    let schema = List::<bool>::bson_schema();
+    let definitions = schema.get_document("definitions").expect("no definitions");
+
+    assert_eq!(definitions.keys().collect::<Vec<_>>(), vec!["List<bool>"]);
+    assert_schema_eq!(schema.get_document("properties").expect("no properties"), doc!{
+        "head": { "type": "boolean" },
+        "tail": {
+            "type": "array",
+            "items": { "$ref": "#/definitions/List%3Cbool%3E" },
+        },
+    });
+    assert!(!format!("{}", flatten_refs(schema, 2)).contains("$ref"));
+}
+
+#[test]
+fn shared_definitions() {
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use magnet_schema::{ SchemaContext, SchemaOptions, flatten_refs };
+
+    static GENERATED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts how many times its schema is generated.
+    struct Leaf;
+
+    impl BsonSchema for Leaf {
+        fn bson_schema() -> Document {
+            magnet_schema::root_schema::<Self>()
+        }
+
+        fn bson_schema_with(ctx: &mut SchemaContext) -> Document {
+            ctx.define("Leaf", |_| {
+                GENERATED.fetch_add(1, Ordering::SeqCst);
+                doc!{ "type": "string", "maxLength": 64_i64 }
+            })
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Big {
+        a: Leaf, b: Leaf, c: Leaf, d: Leaf,
+        e: f64, f: i32, g: String, h: Vec<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Level1 {
+        left: Vec<(Option<Big>, Option<Big>)>,
+        right: Option<Big>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Level2 {
+        left: Level1,
+        right: Vec<Level1>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Level3 {
+        left: Level2,
+        right: Option<Level2>,
+    }
+
+    let size = |schema: &Document| {
+        let mut buf = Vec::new();
+        bson::encode_document(&mut buf, schema).expect("can't encode schema");
+        buf.len()
+    };
+
+    // `Big` occurs 12 times, each with 4 `Leaf`s
+    GENERATED.store(0, Ordering::SeqCst);
+    let inline = Level3::bson_schema_with_options(&SchemaOptions::new());
+    assert_eq!(GENERATED.load(Ordering::SeqCst), 48);
+    assert!(!inline.contains_key("definitions"));
+
+    GENERATED.store(0, Ordering::SeqCst);
+    let options = SchemaOptions::new().share_definitions(true);
+    let shared = Level3::bson_schema_with_options(&options);
+    assert_eq!(GENERATED.load(Ordering::SeqCst), 1);
+
+    assert_eq!(
+        shared.get_document("definitions").unwrap().keys().collect::<Vec<_>>(),
+        vec!["Leaf", "Big", "Level1", "Level2"]
+    );
+    assert_schema_eq!(
+        shared.get_document("properties").unwrap().get_document("right").unwrap().clone(),
+        doc!{ "anyOf": [{ "$ref": "#/definitions/Level2" }, { "type": "null" }] }
+    );
+
+    assert!(size(&inline) > 5 * size(&shared), "{} vs. {}", size(&inline), size(&shared));
+
+    let flattened = format!("{}", flatten_refs(shared, 4));
+    assert!(!flattened.contains("$ref") && !flattened.contains("not"));
+}
+
+#[test]
+fn schema_registry() {
+    use magnet_schema::{ SchemaRegistry, RegistryError, NameCollisionError };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct User {
+        name: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Order {
+        buyer: User,
+        items: Vec<Item>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Item {
+        sub_items: Vec<Item>,
+    }
+
+    mod other {
+        #[allow(dead_code)]
+        #[derive(BsonSchema)]
+        pub struct User {
+            id: i32,
+        }
+    }
+
+    let mut registry = SchemaRegistry::new();
+
+    registry
+        .register::<User>().expect("can't register User")
+        .register::<Order>().expect("can't register Order")
+        .register::<User>().expect("can't register User twice");
+
+    assert_eq!(
+        registry.register::<other::User>().err(),
+        Some(RegistryError::NameCollision(NameCollisionError { name: String::from("User") }))
+    );
+
+    let doc = registry.into_document();
+    let definitions = doc.get_document("definitions").expect("no definitions");
+
+    assert_eq!(definitions.keys().collect::<Vec<_>>(), vec!["User", "Item", "Order"]);
+    assert_schema_eq!(definitions.get_document("User").expect("no User"), User::bson_schema());
+    assert_schema_eq!(definitions.get_document("Item").expect("no Item"), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["sub_items"],
+        "properties": {
+            "sub_items": {
+                "type": "array",
+                "items": { "$ref": "#/definitions/Item" },
+            },
+        },
+    });
+}
+
+#[test]
+fn validator_commands() {
+    use magnet_schema::{ ValidatorOptions, ValidationLevel, ValidationAction };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    assert_schema_eq!(
+        ValidatorOptions::new().create_collection_command::<Person>("people"),
+        doc!{
+            "create": "people",
+            "validator": { "$jsonSchema": Person::bson_schema() },
+            "validationLevel": "strict",
+            "validationAction": "error",
+        }
+    );
+
+    let options = ValidatorOptions::new()
+        .level(ValidationLevel::Moderate)
+        .action(ValidationAction::Warn);
+
+    assert_schema_eq!(
+        options.coll_mod_command::<Person>("people"),
+        doc!{
+            "collMod": "people",
+            "validator": { "$jsonSchema": Person::bson_schema() },
+            "validationLevel": "moderate",
+            "validationAction": "warn",
+        }
+    );
+}
+
+#[test]
+fn validator_command_recursive_type() {
+    use magnet_schema::ValidatorOptions;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Comment {
+        text: String,
+        replies: Vec<Comment>,
+    }
+
+    let command = ValidatorOptions::new()
+        .recursion_limit(2)
+        .create_collection_command::<Comment>("comments");
+
+    assert!(!format!("{}", command).contains("$ref"));
+    assert!(!format!("{}", command).contains("definitions"));
+}
+
+#[test]
+fn validate_values() {
+    use magnet_schema::validate::validate;
+
+    #[derive(Serialize, BsonSchema)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    struct Shape {
+        name: String,
+        sides: u8,
+        label: Option<String>,
+        points: Vec<Point>,
+    }
+
+    let schema = Shape::bson_schema();
+    let shape = Shape {
+        name: String::from("triangle"),
+        sides: 3,
+        label: None,
+        points: vec![Point { x: 0, y: 0 }, Point { x: 1, y: 2 }],
+    };
+
+    assert!(validate(&schema, &bson::to_bson(&shape).unwrap()).is_ok());
+
+    let invalid = bson!({
+        "name": 42,
+        "sides": 256,
+        "label": null,
+        "points": [{ "x": 0, "y": 0 }, { "x": "one" }],
+        "color": "red",
+    });
+    let errors = validate(&schema, &invalid).unwrap_err();
+    let paths: Vec<&str> = errors.errors.iter().map(|e| e.path.as_str()).collect();
+
+    assert_eq!(paths, ["/name", "/sides", "/points/1", "/points/1/x", "/color"]);
+
+    let not_an_object = validate(&schema, &Bson::from("shape")).unwrap_err();
+    assert_eq!(not_an_object.errors.len(), 1);
+    assert_eq!(not_an_object.errors[0].path, "");
+}
+
+#[test]
+fn externally_tagged_variant_single_key() {
+    use magnet_schema::validate::validate;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    enum Shape {
+        Circle { radius: f64 },
+        Square { side: f64 },
+    }
+
+    let schema = Shape::bson_schema();
+    let mut branch = schema.get_array("anyOf").unwrap()[0].as_document().unwrap().clone();
+
+    assert_eq!(branch.get_i32("minProperties"), Ok(1));
+    assert_eq!(branch.get_i32("maxProperties"), Ok(1));
+
+    // the single key is enforced even if additional properties are allowed
+    branch.insert("additionalProperties", true);
+
+    let one = Bson::from(doc!{ "Circle": { "radius": 1.0 } });
+    let two = Bson::from(doc!{ "Circle": { "radius": 1.0 }, "Square": { "side": 2.0 } });
+
+    assert!(validate(&branch, &one).is_ok());
+    assert!(validate(&branch, &two).is_err());
+    assert!(validate(&schema, &two).is_err());
+}
+
+#[test]
+fn map_key_patterns() {
+    use std::collections::{ HashMap, BTreeMap };
+    use magnet_schema::BsonSchemaKey;
+    use magnet_schema::validate::validate;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[serde(rename_all = "lowercase")]
+    enum Channel {
+        Email,
+        Sms,
+        #[serde(rename = "push.v2")]
+        Push,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "type")]
+    enum Tagged {
+        A,
+        B,
+    }
+
+    assert_eq!(HashMap::<u32, bool>::bson_schema(), doc!{
+        "type": "object",
+        "patternProperties": {
+            "^[0-9]+$": { "type": "boolean" },
+        },
+        "additionalProperties": false,
+    });
+    assert_eq!(BTreeMap::<i64, bool>::bson_schema(), doc!{
+        "type": "object",
+        "patternProperties": {
+            "^-?[0-9]+$": { "type": "boolean" },
+        },
+        "additionalProperties": false,
+    });
+    assert_eq!(HashMap::<String, bool>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": { "type": "boolean" },
+    });
+
+    assert_eq!(Channel::key_pattern(), Some(String::from(r"^(?:email|sms|push\.v2)$")));
+    assert_eq!(<&str>::key_pattern(), None);
+
+    let schema = HashMap::<Channel, u8>::bson_schema();
+
+    assert!(validate(&schema, &Bson::from(doc!{ "email": 1, "push.v2": 2 })).is_ok());
+    assert!(validate(&schema, &Bson::from(doc!{ "email": -1 })).is_err());
+
+    // internally-tagged enums aren't serialized as strings
+    let _: fn() -> Document = HashMap::<String, Tagged>::bson_schema;
+}
+
+#[test]
+fn map_known_keys() {
+    use std::collections::{ HashMap, BTreeMap };
+    use magnet_schema::BsonSchemaKey;
+    use magnet_schema::support::exhaustive_map_schema;
+    use magnet_schema::validate::validate;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    enum Axis {
+        X,
+        Y,
+        #[serde(rename = "z")]
+        Z,
+    }
+
+    assert_eq!(Axis::known_keys(), Some(vec![String::from("X"), String::from("Y"), String::from("z")]));
+
+    let expected = doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "X": { "bsonType": ["double", "int", "long"] },
+            "Y": { "bsonType": ["double", "int", "long"] },
+            "z": { "bsonType": ["double", "int", "long"] },
+        },
+    };
+
+    assert_eq!(HashMap::<Axis, f64>::bson_schema(), expected);
+    assert_eq!(BTreeMap::<Axis, f64>::bson_schema(), expected);
+
+    let schema = Option::<HashMap<Axis, Option<f64>>>::bson_schema();
+
+    assert_eq!(schema.get_array("type").unwrap(), &vec![Bson::from("object"), Bson::from("null")]);
+    assert!(validate(&schema, &Bson::Null).is_ok());
+    assert!(validate(&schema, &Bson::from(doc!{ "X": 1.0, "z": Bson::Null })).is_ok());
+    assert!(validate(&schema, &Bson::from(doc!{ "Z": 1.0 })).is_err());
+
+    let axes = ["X", "Y", "z"].iter().map(|&axis| String::from(axis)).collect::<Vec<_>>();
+    let full = exhaustive_map_schema(&axes, f64::bson_schema(), true);
+
+    assert_eq!(full.get_array("required").unwrap().len(), 3);
+    assert!(validate(&full, &Bson::from(doc!{ "X": 1.0, "Y": 2.0 })).is_err());
+    assert!(validate(&full, &Bson::from(doc!{ "X": 1.0, "Y": 2.0, "z": 3.0 })).is_ok());
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn uuid_map_key_pattern() {
+    use std::collections::HashMap;
+    use magnet_schema::support::UUID_PATTERN;
+
+    assert_eq!(HashMap::<uuid::Uuid, f64>::bson_schema(), doc!{
+        "type": "object",
+        "patternProperties": {
+            UUID_PATTERN: { "bsonType": ["double", "int", "long"] },
+        },
+        "additionalProperties": false,
+    });
+}
+
+#[test]
+fn validate_keywords() {
+    use magnet_schema::validate::validate;
+
+    let bounds = doc!{
+        "bsonType": "number",
+        "minimum": 0,
+        "maximum": 10,
+        "exclusiveMaximum": true,
+    };
+    assert!(validate(&bounds, &Bson::I32(0)).is_ok());
+    assert!(validate(&bounds, &Bson::FloatingPoint(9.5)).is_ok());
+    assert!(validate(&bounds, &Bson::I64(10)).is_err());
+    assert!(validate(&bounds, &Bson::I32(-1)).is_err());
+
+    let draft_07_bounds = doc!{ "type": "integer", "exclusiveMinimum": 0 };
+    assert!(validate(&draft_07_bounds, &Bson::I32(1)).is_ok());
+    assert!(validate(&draft_07_bounds, &Bson::I32(0)).is_err());
+
+    let set = doc!{ "bsonType": "array", "uniqueItems": true, "maxItems": 3 };
+    assert!(validate(&set, &bson!([1, 2, 3])).is_ok());
+    assert!(validate(&set, &bson!([1, 2, 1])).is_err());
+    assert!(validate(&set, &bson!([1, 2, 3, 4])).is_err());
+
+    let tuple = doc!{
+        "bsonType": "array",
+        "items": [{ "bsonType": "string" }, { "bsonType": "bool" }],
+        "additionalItems": false,
+    };
+    assert!(validate(&tuple, &bson!(["foo", true])).is_ok());
+    assert!(validate(&tuple, &bson!([true, "foo"])).is_err());
+    assert!(validate(&tuple, &bson!(["foo", true, null])).is_err());
+
+    let choice = doc!{
+        "anyOf": [{ "enum": ["Foo", "Bar"] }, { "bsonType": "null" }],
+    };
+    assert!(validate(&choice, &Bson::from("Bar")).is_ok());
+    assert!(validate(&choice, &Bson::Null).is_ok());
+    assert!(validate(&choice, &Bson::from("Qux")).is_err());
+
+    assert!(validate(&doc!{ "not": {} }, &Bson::Null).is_err());
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn validate_pattern() {
+    use magnet_schema::validate::validate;
+
+    let schema = doc!{ "bsonType": "string", "pattern": "^[a-z]+$" };
+
+    assert!(validate(&schema, &Bson::from("magnet")).is_ok());
+    assert!(validate(&schema, &Bson::from("Magnet")).is_err());
+}
+
+#[test]
+fn round_trip_self_validation() {
+    use std::collections::{ BTreeMap, HashSet };
+    use magnet_schema::testing::assert_valid;
+
+    #[derive(Serialize, BsonSchema)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    struct Inventory {
+        item_name: String,
+        #[serde(rename = "qty")]
+        quantity: u32,
+        serial: u64,
+        offset: i64,
+        tags: HashSet<String>,
+        location: Option<Point>,
+        dimensions: [u16; 3],
+        pair: (bool, i8),
+        attributes: BTreeMap<String, usize>,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    enum External {
+        Unit,
+        Newtype(i32),
+        Tuple(String, u8),
+        Struct { value: f32 },
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "kind")]
+    enum Internal {
+        Unit,
+        Newtype(Point),
+        Struct { value: f32 },
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Unit,
+        Newtype(i32),
+        Tuple(String, u8),
+        Struct { value: f32 },
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Untagged {
+        Unit,
+        Newtype(i32),
+        Tuple(String, u8),
+        Struct { value: f32 },
+    }
+
+    assert_valid(&true);
+    assert_valid(&u8::max_value());
+    assert_valid(&i64::min_value());
+    assert_valid(&(i64::max_value() as u64));
+    assert_valid(&-0.5_f32);
+    assert_valid("string slice");
+    assert_valid(&Some(vec![1_u16, 2, 3]));
+    assert_valid(&None::<String>);
+
+    assert_valid(&Inventory {
+        item_name: String::from("widget"),
+        quantity: u32::max_value(),
+        serial: 1 << 40,
+        offset: -1,
+        tags: vec![String::from("a"), String::from("b")].into_iter().collect(),
+        location: Some(Point { x: 1.0, y: -1.0 }),
+        dimensions: [1, 2, 3],
+        pair: (false, -128),
+        attributes: vec![(String::from("weight"), 42)].into_iter().collect(),
+    });
+
+    for value in &[External::Unit, External::Newtype(1), External::Tuple(String::new(), 2), External::Struct { value: 3.0 }] {
+        assert_valid(value);
+    }
+
+    for value in &[Internal::Unit, Internal::Newtype(Point { x: 0.0, y: 0.0 }), Internal::Struct { value: 3.0 }] {
+        assert_valid(value);
+    }
+
+    for value in &[Adjacent::Unit, Adjacent::Newtype(1), Adjacent::Tuple(String::new(), 2), Adjacent::Struct { value: 3.0 }] {
+        assert_valid(value);
+    }
+
+    for value in &[Untagged::Unit, Untagged::Newtype(1), Untagged::Tuple(String::new(), 2), Untagged::Struct { value: 3.0 }] {
+        assert_valid(value);
+    }
+}
+
+#[test]
+#[should_panic(expected = "doesn't match its schema")]
+fn round_trip_self_validation_failure() {
+    use magnet_schema::testing::assert_valid;
+
+    #[derive(Serialize)]
+    struct Celsius(f64);
+
+    impl BsonSchema for Celsius {
+        fn bson_schema() -> Document {
+            doc!{ "bsonType": "int" }
+        }
+    }
+
+    assert_valid(&Celsius(36.6));
+}
+
+#[test]
+fn dynamic_schema_lookup() {
+    use magnet_schema::BsonSchemaDyn;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Account {
+        balance: i64,
+    }
+
+    let values: Vec<Box<dyn BsonSchemaDyn>> = vec![
+        Box::new(Account { balance: 0 }),
+        Box::new(String::from("not a document")),
+        Box::new(Some(true)),
+    ];
+    let schemas: Vec<_> = values.iter().map(|value| value.bson_schema_dyn()).collect();
+    let names: Vec<_> = values.iter().map(|value| value.schema_name_dyn()).collect();
+
+    assert_eq!(schemas, [Account::bson_schema(), String::bson_schema(), Option::<bool>::bson_schema()]);
+    assert_eq!(names, [Account::schema_name(), String::schema_name(), Option::<bool>::schema_name()]);
+
+    let borrowed: &dyn BsonSchemaDyn = &"string slice";
+    assert_eq!(borrowed.bson_schema_dyn(), str::bson_schema());
+}
+
+#[test]
+fn cached_schema() {
+    use std::thread;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Payload {
+        id: u64,
+        items: Vec<String>,
+    }
+
+    let first = Payload::bson_schema_cached();
+    let second = Payload::bson_schema_cached();
+
+    assert_eq!(*first, Payload::bson_schema());
+    assert!(std::ptr::eq(first, second));
+    assert!(!std::ptr::eq(first, String::bson_schema_cached()));
+    assert_eq!(*str::bson_schema_cached(), str::bson_schema());
+
+    let addresses: Vec<usize> = (0..8)
+        .map(|_| thread::spawn(|| Payload::bson_schema_cached() as *const _ as usize))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    assert!(addresses.iter().all(|&address| address == first as *const _ as usize));
+}
+
+#[test]
+fn static_schema_of_non_generic_type() {
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use magnet_schema::SchemaContext;
+
+    static GENERATED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl BsonSchema for Counted {
+        fn bson_schema() -> Document {
+            magnet_schema::root_schema::<Self>()
+        }
+
+        fn bson_schema_with(_: &mut SchemaContext) -> Document {
+            GENERATED.fetch_add(1, Ordering::SeqCst);
+            doc!{ "bsonType": "int" }
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Plain {
+        counted: Counted,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Generic<T> {
+        counted: Counted,
+        other: T,
+    }
+
+    let first = Plain::bson_schema();
+    let second = Plain::bson_schema();
+
+    assert_eq!(first, second);
+    assert_eq!(GENERATED.load(Ordering::SeqCst), 1);
+    assert!(std::ptr::eq(Plain::bson_schema_cached(), Plain::bson_schema_cached()));
+    assert_eq!(GENERATED.load(Ordering::SeqCst), 1);
+
+    Generic::<bool>::bson_schema();
+    Generic::<bool>::bson_schema();
+
+    assert_eq!(GENERATED.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn openapi_schema() {
+    use magnet_schema::openapi::to_openapi_schema;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "type")]
+    enum Shape {
+        Circle { radius: f64 },
+        Polygon { sides: u8 },
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Drawing {
+        title: Option<String>,
+        layer: i32,
+        size: u64,
+        position: (f32, f32),
+        shape: Option<Shape>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u32),
+        Name(String),
+    }
+
+    assert_schema_eq!(to_openapi_schema(&Drawing::bson_schema()), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["title", "layer", "size", "position", "shape"],
+        "properties": {
+            "title": { "type": "string", "nullable": true },
+            "layer": {
+                "type": "integer",
+                "format": "int32",
+                "minimum": std::i32::MIN as i64,
+                "maximum": std::i32::MAX as i64,
+            },
+            "size": {
+                "type": "integer",
+                "format": "int64",
+                "minimum": 0_i64,
+                "maximum": std::i64::MAX,
+            },
+            "position": {
+                "type": "array",
+                "minItems": 2_i64,
+                "maxItems": 2_i64,
+                "items": { "type": "number" },
+            },
+            "shape": {
+                "nullable": true,
+                "oneOf": [
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["type", "radius"],
+                        "properties": {
+                            "type": { "enum": ["Circle"] },
+                            "radius": { "type": "number" },
+                        },
+                    },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["type", "sides"],
+                        "properties": {
+                            "type": { "enum": ["Polygon"] },
+                            "sides": {
+                                "type": "integer",
+                                "format": "int32",
+                                "minimum": 0_i64,
+                                "maximum": 255_i64,
+                            },
+                        },
+                    },
+                ],
+            },
+        },
+    });
+
+    assert_schema_eq!(to_openapi_schema(&Id::bson_schema()), doc!{
+        "anyOf": [
+            {
+                "type": "integer",
+                "format": "int64",
+                "minimum": 0_i64,
+                "maximum": std::u32::MAX as i64,
+            },
+            { "type": "string" },
+        ],
+    });
+
+    assert_schema_eq!(
+        to_openapi_schema(&doc!{ "type": "number", "minimum": 0, "exclusiveMinimum": true }),
+        doc!{ "type": "number", "minimum": 0, "exclusiveMinimum": true }
+    );
+}
+
+#[test]
+fn explain_schema() {
+    use magnet_schema::display::{ explain, Explain };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Contact {
+        email: String,
+        phones: Vec<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Person {
+        name: String,
+        age: u64,
+        contact: Option<Contact>,
+        scores: [f32; 3],
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "kind", content = "data")]
+    enum Event {
+        Created,
+        Renamed(String),
+        Moved { x: i32, y: i32 },
+    }
+
+    assert_eq!(explain(&Person::bson_schema()), "\
+object \u{2192} {
+  name: string (required)
+  age: int|long [0, 9223372036854775807] (required)
+  contact: object? (required) \u{2192} {
+    email: string (required)
+    phones: array of string (required)
+  }
+  scores: array of double|int|long length 3 (required)
+}");
+
+    assert_eq!(Explain(&Option::<Event>::bson_schema()).to_string(), "\
+one of?
+  - Created: object \u{2192} {
+    kind: \"Created\" (required)
+  }
+  - Renamed: object \u{2192} {
+    kind: \"Renamed\" (required)
+    data: string (required)
+  }
+  - Moved: object \u{2192} {
+    kind: \"Moved\" (required)
+    data: object (required) \u{2192} {
+      x: int|long [-2147483648, 2147483647] (required)
+      y: int|long [-2147483648, 2147483647] (required)
+    }
+  }");
+}
+
+#[test]
+fn schema_diff() {
+    use magnet_schema::diff::{ diff, ChangeKind };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct UserV1 {
+        name: String,
+        nickname: Option<String>,
+        age: u64,
+        legacy: bool,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct UserV2 {
+        name: String,
+        nickname: Option<String>,
+        age: u8,
+        email: String,
+        phone: Option<String>,
+    }
+
+    let old = UserV1::bson_schema();
+    let new = UserV2::bson_schema();
+    let changes = diff(&old, &new);
+    let mut summary: Vec<_> = changes.changes.iter().map(|change| (
+        change.path.as_str(), change.kind.clone(), change.breaking
+    )).collect();
+    summary.sort_by_key(|&(path, ref kind, _)| (path, format!("{:?}", kind)));
+
+    assert!(changes.is_breaking());
+    assert_eq!(summary, vec![
+        ("", ChangeKind::PropertyAdded { name: "email".into(), required: true }, true),
+        ("", ChangeKind::PropertyAdded { name: "phone".into(), required: true }, true),
+        ("", ChangeKind::PropertyRemoved { name: "legacy".into() }, true),
+        ("/properties/age", ChangeKind::BoundChanged {
+            keyword: "maximum".into(),
+            old: Some(Bson::I64(std::i64::MAX)),
+            new: Some(Bson::I64(255)),
+        }, true),
+    ]);
+
+    // key order doesn't matter
+    let mut entries: Vec<_> = old.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    entries.reverse();
+    let reordered: Document = entries.into_iter().collect();
+    assert!(diff(&old, &reordered).is_empty());
+    assert_eq!(diff(&old, &old).to_string(), "no changes");
+
+    // tightening and relaxing requirements
+    let optional = doc!{ "type": "object", "properties": { "x": { "type": "number" } } };
+    let required = doc!{ "type": "object", "required": ["x"], "properties": { "x": { "type": "number" } } };
+
+    assert_eq!(diff(&optional, &required).to_string(), "breaking at /: property `x` became required");
+    assert_eq!(diff(&required, &optional).to_string(), "compatible at /: property `x` became optional");
+
+    // widening bounds, types and enums
+    let narrow = doc!{ "bsonType": "int", "minimum": 0, "enum": [1, 2] };
+    let wide = doc!{ "bsonType": ["int", "long"], "minimum": -1, "enum": [2, 1, 3] };
+
+    assert!(!diff(&narrow, &wide).is_empty());
+    assert!(!diff(&narrow, &wide).is_breaking());
+    assert_eq!(diff(&wide, &narrow).breaking_changes().count(), 3);
+}
+
+#[test]
+fn schema_combinators() {
+    use magnet_schema::combine::{ all_of, any_of, one_of, not };
+
+    let a = doc!{ "type": "string" };
+    let b = doc!{ "type": "number" };
+    let c = doc!{ "type": "null" };
+
+    assert_schema_eq!(any_of(&[any_of(&[a.clone(), b.clone()]), c.clone()]), doc!{
+        "anyOf": [{ "type": "string" }, { "type": "number" }, { "type": "null" }],
+    });
+    assert_schema_eq!(all_of(&[a.clone(), all_of(&[b.clone(), c.clone()])]), doc!{
+        "allOf": [{ "type": "string" }, { "type": "number" }, { "type": "null" }],
+    });
+    assert_schema_eq!(all_of(&[any_of(&[a.clone(), b.clone()]), c.clone()]), doc!{
+        "allOf": [{ "anyOf": [{ "type": "string" }, { "type": "number" }] }, { "type": "null" }],
+    });
+    assert_schema_eq!(one_of(&[one_of(&[a.clone(), b.clone()]), c.clone()]), doc!{
+        "oneOf": [{ "oneOf": [{ "type": "string" }, { "type": "number" }] }, { "type": "null" }],
+    });
+    assert_schema_eq!(not(&a), doc!{ "not": { "type": "string" } });
+    assert_schema_eq!(not(&not(&a)), a);
+}
+
+#[test]
+fn schema_merge() {
+    use magnet_schema::combine::merge;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct User {
+        name: String,
+        age: u8,
+    }
+
+    let overlay = doc!{
+        "required": ["age", "email"],
+        "properties": {
+            "age": { "minimum": 18 },
+            "email": { "type": "string" },
+        },
+        "additionalProperties": true,
+    };
+
+    assert_schema_eq!(merge(User::bson_schema(), overlay), doc!{
+        "type": "object",
+        "additionalProperties": true,
+        "required": ["name", "age", "email"],
+        "properties": {
+            "name": { "type": "string" },
+            "age": {
+                "bsonType": ["int", "long"],
+                "minimum": 18,
+                "maximum": std::u8::MAX as i64,
+            },
+            "email": { "type": "string" },
+        },
+    });
+}
+
+#[test]
+fn support_bounds() {
+    use magnet_schema::{ Bound, Bounds };
+    use magnet_schema::support::extend_schema_with_bounds;
+
+    assert_eq!(Bounds::default(), Bounds::new(Bound::Unbounded, Bound::Unbounded));
+    assert_eq!(Bounds::at_least(1.0), Bounds { lower: Bound::inclusive(1.0), upper: Bound::Unbounded });
+    assert_eq!(Bounds::at_most(2.0), Bounds { lower: Bound::Unbounded, upper: Bound::inclusive(2.0) });
+
+    let base = doc!{ "type": "number" };
+
+    assert_schema_eq!(extend_schema_with_bounds(base.clone(), Bounds::default()), base);
+    assert_schema_eq!(
+        extend_schema_with_bounds(base.clone(), Bounds::new(Bound::exclusive(-1.5), Bound::inclusive(3.0))),
+        doc!{
+            "type": "number",
+            "minimum": -1.5,
+            "exclusiveMinimum": true,
+            "maximum": 3.0,
+            "exclusiveMaximum": false,
+        }
+    );
+}
+
+#[test]
+fn support_null() {
+    use magnet_schema::support::extend_schema_with_null;
+
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "type": "string" }),
+        doc!{ "type": ["string", "null"] }
+    );
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "bsonType": ["int", "long"], "minimum": 0 }),
+        doc!{ "bsonType": ["int", "long", "null"], "minimum": 0 }
+    );
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "type": ["string", "null"] }),
+        doc!{ "type": ["string", "null"] }
+    );
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "anyOf": [{ "enum": ["A"] }] }),
+        doc!{ "anyOf": [{ "enum": ["A"] }, { "type": "null" }] }
+    );
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "enum": ["A", "B"] }),
+        doc!{ "enum": ["A", "B", null] }
+    );
+    assert_schema_eq!(extend_schema_with_null(doc!{}), doc!{ "anyOf": [{}, { "type": "null" }] });
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "$ref": "#/definitions/Node" }),
+        doc!{ "anyOf": [{ "$ref": "#/definitions/Node" }, { "type": "null" }] }
+    );
+}
+
+#[test]
+fn support_tag() {
+    use std::collections::HashMap;
+    use magnet_schema::support::extend_schema_with_tag;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Inner {
+        value: String,
+    }
+
+    assert_schema_eq!(extend_schema_with_tag(Inner::bson_schema(), "kind", "Wrapped"), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["value", "kind"],
+        "properties": {
+            "value": { "type": "string" },
+            "kind": { "enum": ["Wrapped"] },
+        },
+    });
+
+    assert_schema_eq!(extend_schema_with_tag(HashMap::<String, bool>::bson_schema(), "kind", "Flags"), doc!{
+        "type": "object",
+        "additionalProperties": { "type": "boolean" },
+        "required": ["kind"],
+        "properties": {
+            "kind": { "enum": ["Flags"] },
+        },
+    });
+}
+
+#[test]
+#[should_panic(expected = "doesn't describe a struct, a map, or an enum")]
+fn support_tag_non_object() {
+    use magnet_schema::support::extend_schema_with_tag;
+
+    extend_schema_with_tag(String::bson_schema(), "kind", "Text");
+}
+
+#[test]
+fn support_try_tag_non_object() {
+    use magnet_schema::support::try_extend_schema_with_tag;
+
+    assert_eq!(
+        try_extend_schema_with_tag(String::bson_schema(), "kind", "Text"),
+        Err(SchemaError::UntaggableVariant {
+            tag: String::from("kind"),
+            variant: String::from("Text"),
+        })
+    );
+}
+
+#[test]
+fn bounds_dialects() {
+    use magnet_schema::{ Bound, Bounds };
+    use magnet_schema::support::{ BoundsDialect, extend_schema_with_bounds_in };
+    use magnet_schema::convert::to_bounds_dialect;
+
+    let cases = [
+        (
+            Bounds::new(Bound::exclusive(0.0), Bound::Unbounded),
+            doc!{ "type": "number", "minimum": 0.0, "exclusiveMinimum": true },
+            doc!{ "type": "number", "exclusiveMinimum": 0.0 },
+        ),
+        (
+            Bounds::new(Bound::Unbounded, Bound::exclusive(10.0)),
+            doc!{ "type": "number", "maximum": 10.0, "exclusiveMaximum": true },
+            doc!{ "type": "number", "exclusiveMaximum": 10.0 },
+        ),
+        (
+            Bounds::new(Bound::inclusive(-1.0), Bound::exclusive(1.0)),
+            doc!{
+                "type": "number",
+                "minimum": -1.0,
+                "exclusiveMinimum": false,
+                "maximum": 1.0,
+                "exclusiveMaximum": true,
+            },
+            doc!{ "type": "number", "minimum": -1.0, "exclusiveMaximum": 1.0 },
+        ),
+        (
+            Bounds::new(Bound::exclusive(-1.0), Bound::inclusive(1.0)),
+            doc!{
+                "type": "number",
+                "minimum": -1.0,
+                "exclusiveMinimum": true,
+                "maximum": 1.0,
+                "exclusiveMaximum": false,
+            },
+            doc!{ "type": "number", "exclusiveMinimum": -1.0, "maximum": 1.0 },
+        ),
+    ];
+
+    for &(bounds, ref boolean, ref numeric) in &cases {
+        let base = doc!{ "type": "number" };
+        let boolean_schema = extend_schema_with_bounds_in(base.clone(), bounds, BoundsDialect::Boolean);
+        let numeric_schema = extend_schema_with_bounds_in(base, bounds, BoundsDialect::Numeric);
+
+        assert_schema_eq!(&boolean_schema, boolean);
+        assert_schema_eq!(&numeric_schema, numeric);
+
+        // converting between the dialects works recursively too
+        let wrap = |schema: &Document| doc!{ "type": "array", "items": schema.clone() };
+
+        assert_schema_eq!(to_bounds_dialect(wrap(&boolean_schema), BoundsDialect::Numeric), wrap(numeric));
+        // `"exclusiveMinimum": false` is redundant, so it's not generated
+        let without_false_flags: Document = boolean
+            .iter()
+            .filter(|&(_, value)| value != &Bson::Boolean(false))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        assert_schema_eq!(
+            to_bounds_dialect(wrap(&numeric_schema), BoundsDialect::Boolean),
+            wrap(&without_false_flags)
+        );
+    }
+}
+
+#[test]
+fn bounds_intersect_intrinsic_range() {
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Limits {
+        #[magnet(max_incl = "300")]
+        small: u8,
+        #[magnet(min_incl = "0")]
+        non_negative: i32,
+        #[magnet(min_excl = "-1000", max_excl = "100")]
+        narrowed: i16,
+        #[magnet(min_excl = "-100000")]
+        widened: i16,
+    }
+
+    assert_schema_eq!(Limits::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["small", "non_negative", "narrowed", "widened"],
+        "properties": {
+            "small": {
+                "bsonType": ["int", "long"],
+                "minimum": std::u8::MIN as i64,
+                "maximum": std::u8::MAX as i64,
+            },
+            "non_negative": {
+                "bsonType": ["int", "long"],
+                "minimum": 0_i64,
+                "exclusiveMinimum": false,
+                "maximum": std::i32::MAX as i64,
+            },
+            "narrowed": {
+                "bsonType": ["int", "long"],
+                "minimum": -1000_i64,
+                "exclusiveMinimum": true,
+                "maximum": 100_i64,
+                "exclusiveMaximum": true,
+            },
+            "widened": {
+                "bsonType": ["int", "long"],
+                "minimum": std::i16::MIN as i64,
+                "maximum": std::i16::MAX as i64,
+            },
+        },
+    });
+}
+
+#[test]
+#[should_panic(expected = "leave no valid values")]
+fn bounds_empty_intersection() {
+    use magnet_schema::{ Bound, Bounds };
+    use magnet_schema::support::extend_schema_with_bounds;
+
+    extend_schema_with_bounds(u8::bson_schema(), Bounds::new(Bound::exclusive(255.0), Bound::Unbounded));
+}
+
+#[test]
+fn bounds_from_ranges() {
+    use magnet_schema::{ Bound, Bounds };
+    use magnet_schema::support::extend_schema_with_bounds;
+
+    let extend = |bounds: Bounds| extend_schema_with_bounds(doc!{ "bsonType": "double" }, bounds);
+
+    assert_schema_eq!(extend(Bounds::from(0.0..1.0)), doc!{
+        "bsonType": "double",
+        "minimum": 0.0,
+        "exclusiveMinimum": false,
+        "maximum": 1.0,
+        "exclusiveMaximum": true,
+    });
+    assert_schema_eq!(extend(Bounds::from(0..=100)), doc!{
+        "bsonType": "double",
+        "minimum": 0_i64,
+        "exclusiveMinimum": false,
+        "maximum": 100_i64,
+        "exclusiveMaximum": false,
+    });
+    assert_schema_eq!(extend(Bounds::from(-1.5..)), doc!{
+        "bsonType": "double",
+        "minimum": -1.5,
+        "exclusiveMinimum": false,
+    });
+    assert_schema_eq!(extend(Bounds::from(..10_u32)), doc!{
+        "bsonType": "double",
+        "maximum": 10_i64,
+        "exclusiveMaximum": true,
+    });
+    assert_schema_eq!(extend(Bounds::from(..=10)), doc!{
+        "bsonType": "double",
+        "maximum": 10_i64,
+        "exclusiveMaximum": false,
+    });
+    assert_schema_eq!(extend(Bounds::from(..)), doc!{ "bsonType": "double" });
+
+    assert_eq!(Bounds::between(-1.0, 1.0), Bounds::from(-1.0..=1.0));
+    assert_eq!(Bounds::at_least(2.0), Bounds::from(2.0..));
+    assert_eq!(Bounds::at_most(2.0), Bounds::from(..=2.0));
+
+    // integers stay integers, even beyond 2^53
+    let large = std::i64::MAX - 1;
+
+    assert_schema_eq!(extend(Bounds::from(large..)), doc!{
+        "bsonType": "double",
+        "minimum": large,
+        "exclusiveMinimum": false,
+    });
+    assert_eq!(
+        extend(Bounds::from(-5_i64..5)).get("minimum"),
+        Some(&Bson::I64(-5))
+    );
+}
+
+#[test]
+fn bounds_on_map_and_set_values() {
+    use std::collections::{ HashMap, BTreeMap, HashSet };
+    use magnet_schema::SchemaError;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Ratings {
+        #[magnet(values_min_incl = "0", values_max_incl = "1")]
+        scores: HashMap<String, f64>,
+        #[magnet(values_min_excl = "0")]
+        counts: BTreeMap<String, u32>,
+        #[magnet(values_max_incl = "10")]
+        levels: Option<HashSet<u8>>,
+    }
+
+    assert_schema_eq!(Ratings::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["scores", "counts", "levels"],
+        "properties": {
+            "scores": {
+                "type": "object",
+                "additionalProperties": {
+                    "bsonType": ["double", "int", "long"],
+                    "minimum": 0_i64,
+                    "exclusiveMinimum": false,
+                    "maximum": 1_i64,
+                    "exclusiveMaximum": false,
+                },
+            },
+            "counts": {
+                "type": "object",
+                "additionalProperties": {
+                    "bsonType": ["int", "long"],
+                    "minimum": 0_i64,
+                    "exclusiveMinimum": true,
+                    "maximum": std::u32::MAX as i64,
+                },
+            },
+            "levels": {
+                "type": ["array", "null"],
+                "uniqueItems": true,
+                "items": {
+                    "bsonType": ["int", "long"],
+                    "minimum": std::u8::MIN as i64,
+                    "maximum": 10_i64,
+                    "exclusiveMaximum": false,
+                },
+            },
+        },
+    });
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Misused {
+        #[magnet(values_min_incl = "0")]
+        score: f64,
+    }
+
+    match Misused::try_bson_schema() {
+        Err(SchemaError::MalformedSchema(_)) => {},
+        other => panic!("expected a malformed schema error, got {:?}", other),
+    }
+}
+
+#[test]
+fn try_bounds_empty_intersection() {
+    use magnet_schema::{ Bound, Bounds };
+    use magnet_schema::support::{ BoundsDialect, try_extend_schema_with_bounds_in };
+
+    let bounds = Bounds::new(Bound::exclusive(255.0), Bound::Unbounded);
+
+    assert_eq!(
+        try_extend_schema_with_bounds_in(u8::bson_schema(), bounds, BoundsDialect::Boolean),
+        Err(SchemaError::EmptyRange(bounds))
+    );
+}
+
+#[test]
+fn try_bson_schema_derived() {
+    #[derive(BsonSchema)]
+    struct Percent {
+        #[magnet(min_incl = "0", max_incl = "100")]
+        value: u8,
+    }
+
+    assert_eq!(Percent::try_bson_schema(), Ok(Percent::bson_schema()));
+    assert_eq!(u8::try_bson_schema(), Ok(u8::bson_schema()));
+}
+
+#[test]
+fn schema_options() {
+    use magnet_schema::{ SchemaOptions, Nullability };
+    use magnet_schema::support::BoundsDialect;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Reading {
+        #[magnet(min_excl = 0)]
+        value: f64,
+        unit: Option<String>,
+    }
+
+    assert_eq!(Reading::bson_schema_with_options(&SchemaOptions::default()), Reading::bson_schema());
+
+    let options = SchemaOptions::new()
+        .additional_properties(true)
+        .nullability(Nullability::AnyOf)
+        .bounds_dialect(BoundsDialect::Numeric);
+
+    assert_schema_eq!(Reading::bson_schema_with_options(&options), doc!{
+        "type": "object",
+        "additionalProperties": true,
+        "required": ["value", "unit"],
+        "properties": {
+            "value": { "bsonType": ["double", "int", "long"], "exclusiveMinimum": 0_i64 },
+            "unit": { "anyOf": [{ "type": "string" }, { "type": "null" }] },
+        },
+    });
+
+    // options are passed down to nested types
+    assert_schema_eq!(Vec::<Option<i8>>::bson_schema_with_options(&options), doc!{
+        "type": "array",
+        "items": {
+            "anyOf": [
+                {
+                    "bsonType": ["int", "long"],
+                    "minimum": std::i8::MIN as i64,
+                    "maximum": std::i8::MAX as i64,
+                },
+                { "type": "null" },
+            ],
+        },
+    });
+}
+
+#[test]
+fn compat_levels() {
+    use std::collections::HashSet;
+    use magnet_schema::{ SchemaOptions, CompatLevel };
+    use magnet_schema::compat::downlevel;
+
+    let schema = doc!{
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "required": [],
+        "properties": {
+            "count": { "type": ["integer", "null"], "exclusiveMinimum": 0_i64, "default": 1_i64 },
+            "email": { "type": "string", "format": "email", "examples": ["a@b.c"] },
+            "kind": { "const": "user" },
+            "tags": {
+                "type": "array",
+                "uniqueItems": true,
+                "items": { "anyOf": [{ "type": "integer" }, { "type": "string" }] },
+                "contains": { "const": "admin" },
+            },
+        },
+        "propertyNames": { "pattern": "^[a-z]+$" },
+    };
+
+    assert_schema_eq!(downlevel(schema.clone(), CompatLevel::Latest), schema.clone());
+
+    let mongo42 = doc!{
+        "type": "object",
+        "required": [],
+        "properties": {
+            "count": { "bsonType": ["int", "long", "null"], "minimum": 0_i64, "exclusiveMinimum": true },
+            "email": { "type": "string" },
+            "kind": { "enum": ["user"] },
+            "tags": {
+                "type": "array",
+                "uniqueItems": true,
+                "items": { "anyOf": [{ "bsonType": ["int", "long"] }, { "type": "string" }] },
+            },
+        },
+    };
+
+    assert_schema_eq!(downlevel(schema.clone(), CompatLevel::Mongo42), mongo42);
+
+    assert_schema_eq!(downlevel(schema, CompatLevel::Mongo36), doc!{
+        "type": "object",
+        "properties": {
+            "count": { "bsonType": ["int", "long", "null"], "minimum": 0_i64, "exclusiveMinimum": true },
+            "email": { "type": "string" },
+            "kind": { "enum": ["user"] },
+            "tags": {
+                "type": "array",
+                "items": { "anyOf": [{ "bsonType": ["int", "long"] }, { "type": "string" }] },
+            },
+        },
+    });
+
+    // generated schemas are rewritten according to the options
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Labels {
+        names: HashSet<String>,
+    }
+
+    let options = SchemaOptions::new().compat_level(CompatLevel::Mongo36);
+
+    assert_eq!(Labels::bson_schema_with_options(&SchemaOptions::new()), Labels::bson_schema());
+    assert_schema_eq!(Labels::bson_schema_with_options(&options), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["names"],
+        "properties": {
+            "names": {
+                "type": "array",
+                "items": { "type": "string" },
+            },
+        },
+    });
+}
+
+#[test]
+#[cfg(feature = "uuid")]
+fn schema_options_uuid() {
+    use magnet_schema::{ SchemaOptions, UuidRepresentation };
+
+    let options = SchemaOptions::new().uuid_representation(UuidRepresentation::Binary);
+
+    assert_schema_eq!(uuid::Uuid::bson_schema_with_options(&options), doc!{ "bsonType": "binData" });
+    assert_schema_eq!(uuid::Uuid::bson_schema_with_options(&SchemaOptions::new()), uuid::Uuid::bson_schema());
+}
+
+#[test]
+fn schema_options_u64_policy() {
+    use magnet_schema::{ SchemaOptions, U64Policy };
+    use magnet_schema::validate::validate;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Counter {
+        hits: u64,
+        len: usize,
+    }
+
+    let clamped = doc!{
+        "bsonType": ["int", "long"],
+        "minimum": 0_i64,
+        "maximum": std::i64::MAX,
+    };
+
+    assert_schema_eq!(u64::bson_schema(), clamped.clone());
+    assert_schema_eq!(
+        u64::bson_schema_with_options(&SchemaOptions::new().u64_policy(U64Policy::ClampToI64Max)),
+        clamped.clone()
+    );
+    assert!(validate(&u64::bson_schema(), &Bson::I64(-1)).is_err());
+
+    let wrapping = SchemaOptions::new().u64_policy(U64Policy::AllowNegativeWraparound);
+    let wrapping_schema = u64::bson_schema_with_options(&wrapping);
+
+    assert_schema_eq!(wrapping_schema.clone(), doc!{ "bsonType": ["int", "long"] });
+    assert!(validate(&wrapping_schema, &Bson::I64(std::u64::MAX as i64)).is_ok());
+
+    let decimal = SchemaOptions::new().u64_policy(U64Policy::AsDecimal);
+
+    assert_schema_eq!(u64::bson_schema_with_options(&decimal), doc!{ "bsonType": "decimal" });
+
+    // the policy applies to nested fields, and to `usize` on 64-bit targets
+    let counter = Counter::bson_schema_with_options(&decimal);
+    let properties = counter.get_document("properties").unwrap();
+
+    assert_schema_eq!(properties.get_document("hits").unwrap().clone(), doc!{ "bsonType": "decimal" });
+
+    if cfg!(target_pointer_width = "64") {
+        assert_schema_eq!(properties.get_document("len").unwrap().clone(), doc!{ "bsonType": "decimal" });
+        assert_schema_eq!(usize::bson_schema(), clamped);
+    }
+}
+
+#[test]
+fn schema_options_float_policy() {
+    use magnet_schema::{ SchemaOptions, FloatPolicy };
+    use magnet_schema::convert::to_json_subschema;
+    use magnet_schema::validate::validate;
+
+    let lenient = f64::bson_schema();
+
+    assert_schema_eq!(lenient.clone(), doc!{ "bsonType": ["double", "int", "long"] });
+    assert_schema_eq!(f32::bson_schema(), lenient.clone());
+    assert!(validate(&lenient, &Bson::I32(42)).is_ok());
+    assert!(validate(&lenient, &Bson::FloatingPoint(4.2)).is_ok());
+
+    // standard JSON `number` covers integers already
+    assert_eq!(to_json_subschema(lenient), doc!{ "type": "number" });
+
+    let strict = f64::bson_schema_with_options(
+        &SchemaOptions::new().float_policy(FloatPolicy::StrictDouble)
+    );
+
+    assert_schema_eq!(strict.clone(), doc!{ "bsonType": "double" });
+    assert!(validate(&strict, &Bson::I32(42)).is_err());
+    assert!(validate(&strict, &Bson::FloatingPoint(4.2)).is_ok());
+    assert_eq!(to_json_subschema(strict), doc!{ "type": "number", "format": "double" });
+}
+
+#[test]
+fn optional_enum_list() {
+    use magnet_schema::testing::assert_valid;
+
+    #[derive(Serialize, BsonSchema)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    #[derive(Serialize)]
+    struct Level(&'static str);
+
+    impl BsonSchema for Level {
+        fn bson_schema() -> Document {
+            doc!{ "enum": ["low", "high"] }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Code(&'static str);
+
+    impl BsonSchema for Code {
+        fn bson_schema() -> Document {
+            doc!{ "type": "string", "enum": ["A", "B"] }
+        }
+    }
+
+    assert_schema_eq!(Option::<Level>::bson_schema(), doc!{ "enum": ["low", "high", null] });
+    assert_schema_eq!(Option::<Option<Level>>::bson_schema(), doc!{ "enum": ["low", "high", null] });
+    assert_schema_eq!(Option::<Code>::bson_schema(), doc!{
+        "type": ["string", "null"],
+        "enum": ["A", "B", null],
+    });
+
+    assert_valid(&Some(Color::Red));
+    assert_valid(&None::<Color>);
+    assert_valid(&Some(Level("low")));
+    assert_valid(&None::<Level>);
+    assert_valid(&Some(Code("B")));
+    assert_valid(&None::<Code>);
+}
+
+#[test]
+fn nullable_schema_in_place() {
+    use magnet_schema::support::extend_schema_with_null;
+
+    let schema = extend_schema_with_null(doc!{
+        "bsonType": "int",
+        "minimum": 0,
+        "maximum": 255,
+    });
+    let keys: Vec<_> = schema.keys().map(String::as_str).collect();
+
+    assert_eq!(keys, ["bsonType", "minimum", "maximum"]);
+    assert_eq!(schema, doc!{
+        "bsonType": ["int", "null"],
+        "minimum": 0,
+        "maximum": 255,
+    });
+    assert_eq!(extend_schema_with_null(schema.clone()), schema);
+}
+
+#[test]
+fn nullable_unconstrained_schemas() {
+    use magnet_schema::{ SchemaOptions, Nullability };
+    use magnet_schema::validate::validate;
+
+    struct Opaque;
+
+    impl BsonSchema for Opaque {
+        fn bson_schema() -> Document {
+            Document::new()
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Node {
+        next: Option<Box<Node>>,
+    }
+
+    let wrapped = doc!{ "anyOf": [{}, { "type": "null" }] };
+
+    assert_schema_eq!(Option::<Document>::bson_schema(), doc!{ "type": ["object", "null"] });
+    assert_schema_eq!(Option::<serde_json::Value>::bson_schema(), wrapped.clone());
+    assert_schema_eq!(Option::<Opaque>::bson_schema(), wrapped.clone());
+    assert!(validate(&Option::<Opaque>::bson_schema(), &Bson::Null).is_ok());
+
+    // idempotent, and the same in both nullability styles
+    assert_schema_eq!(Option::<Option<Opaque>>::bson_schema(), wrapped.clone());
+    assert_schema_eq!(
+        Option::<Opaque>::bson_schema_with_options(&SchemaOptions::new().nullability(Nullability::AnyOf)),
+        wrapped
+    );
+
+    // a `$ref` to a recursive type stays nullable
+    let node = Node::bson_schema();
+    let next = node
+        .get_document("definitions").unwrap()
+        .get_document("Node").unwrap()
+        .get_document("properties").unwrap()
+        .get_document("next").unwrap();
+
+    assert_schema_eq!(next.clone(), doc!{
+        "anyOf": [{ "$ref": "#/definitions/Node" }, { "type": "null" }],
+    });
+}
+
+#[test]
+fn nested_option_nullability() {
+    use magnet_schema::{ SchemaOptions, Nullability };
+    use magnet_schema::support::make_nullable;
+
+    #[derive(BsonSchema)]
+    enum Shape {
+        Circle(f64),
+        Square { side: f64 },
+    }
+
+    assert_schema_eq!(Option::<Option<String>>::bson_schema(), Option::<String>::bson_schema());
+    assert_schema_eq!(Option::<Option<Shape>>::bson_schema(), Option::<Shape>::bson_schema());
+    assert_schema_eq!(Option::<Option<u32>>::bson_schema(), Option::<u32>::bson_schema());
+
+    let shape_branches = Option::<Option<Shape>>::bson_schema();
+    let nulls = shape_branches
+        .get_array("anyOf")
+        .unwrap()
+        .iter()
+        .filter(|branch| **branch == bson!({ "type": "null" }))
+        .count();
+    assert_eq!(nulls, 1);
+
+    let options = SchemaOptions::default().nullability(Nullability::AnyOf);
+
+    assert_schema_eq!(
+        Option::<Option<String>>::bson_schema_with_options(&options),
+        Option::<String>::bson_schema_with_options(&options)
+    );
+    assert_schema_eq!(
+        Option::<Option<Shape>>::bson_schema_with_options(&options),
+        Option::<Shape>::bson_schema_with_options(&options)
+    );
+    assert_schema_eq!(
+        Option::<Option<u32>>::bson_schema_with_options(&options),
+        Option::<u32>::bson_schema_with_options(&options)
+    );
+
+    assert_schema_eq!(
+        make_nullable(doc!{ "type": "null" }, Nullability::TypeArray),
+        doc!{ "type": "null" }
+    );
+    assert_schema_eq!(
+        make_nullable(doc!{ "type": "string" }, Nullability::AnyOf),
+        doc!{ "anyOf": [{ "type": "string" }, { "type": "null" }] }
+    );
+}
+
+#[test]
+fn sample_round_trip() {
+    use std::collections::{ BTreeMap, HashSet };
+    use std::net::Ipv6Addr;
+    use magnet_schema::sample::{ sample, sample_from };
+    use magnet_schema::validate::validate;
+
+    #[derive(BsonSchema)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    #[derive(BsonSchema)]
+    struct Inventory {
+        item_name: String,
+        #[magnet(min_incl = "10", max_excl = "20")]
+        quantity: u32,
+        #[magnet(min_excl = "0.5")]
+        ratio: f64,
+        serial: u64,
+        tags: HashSet<String>,
+        location: Option<Point>,
+        dimensions: [u16; 3],
+        pair: (bool, i8),
+        attributes: BTreeMap<String, usize>,
+        address: Ipv6Addr,
+    }
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum Shape {
+        Circle { radius: f64 },
+        Polygon(Vec<Point>),
+    }
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum List {
+        Cons(i32, Box<List>),
+        Nil,
+    }
+
+    #[derive(BsonSchema)]
+    struct Tree {
+        value: i8,
+        children: Vec<Tree>,
+    }
+
+    fn assert_sample_valid<T: BsonSchema>() -> Bson {
+        let schema = T::bson_schema();
+        let value = sample::<T>();
+
+        if let Err(errors) = validate(&schema, &value) {
+            panic!("sample {} doesn't match schema {}: {}", value, schema, errors);
+        }
+
+        assert_eq!(value, sample::<T>(), "sampling must be deterministic");
+        value
+    }
+
+    let inventory = assert_sample_valid::<Inventory>();
+    assert_eq!(inventory, bson!({
+        "item_name": "",
+        "quantity": 14,
+        "ratio": 1.5,
+        "serial": 4611686018427387903_i64,
+        "tags": [],
+        "location": { "x": 0.0, "y": 0.0 },
+        "dimensions": [32767, 32767, 32767],
+        "pair": [false, 0],
+        "attributes": {},
+        "address": "::",
+    }));
+
+    assert_eq!(assert_sample_valid::<Shape>(), bson!({ "Circle": { "radius": 0.0 } }));
+    assert_eq!(assert_sample_valid::<List>(), bson!("Nil"));
+    assert_eq!(assert_sample_valid::<Tree>(), bson!({ "value": 0, "children": [] }));
+    assert_sample_valid::<Option<Vec<String>>>();
+    assert_sample_valid::<(u8, i64, String)>();
+
+    let schema = doc!{
+        "bsonType": "object",
+        "required": ["id", "tags", "code", "when"],
+        "properties": {
+            "id": { "bsonType": "objectId" },
+            "tags": { "type": "array", "minItems": 2, "items": { "enum": ["x", "y"] } },
+            "code": { "type": "string", "minLength": 4 },
+            "when": { "bsonType": "date" },
+        },
+    };
+    let value = sample_from(&schema);
+
+    assert!(validate(&schema, &value).is_ok());
+    assert_eq!(value.as_document().unwrap().get_array("tags").unwrap(), &vec![Bson::from("x"), Bson::from("x")]);
+    assert_eq!(value.as_document().unwrap().get_str("code").unwrap(), "aaaa");
+}
+
+#[test]
+fn export_json() {
+    use std::fs;
+    use std::collections::HashMap;
+    use serde_json::Value;
+    use magnet_schema::SchemaRegistry;
+    use magnet_schema::export::{ to_json_string, write_json, write_registry_json, write_json_files };
+
+    #[derive(BsonSchema)]
+    struct Account {
+        #[magnet(min_incl = "1")]
+        id: u64,
+        owner: Option<String>,
+        balance: f64,
+        limits: HashMap<String, i32>,
+    }
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum Event {
+        Opened,
+        Deposit { amount: f64 },
+        Transfer(String, u32),
+    }
+
+    #[derive(BsonSchema)]
+    struct Tree {
+        value: i32,
+        children: Vec<Tree>,
+    }
+
+    fn expected<T: BsonSchema>() -> Value {
+        serde_json::to_value(T::bson_schema()).unwrap()
+    }
+
+    fn parse(json: &[u8]) -> Value {
+        serde_json::from_slice(json).unwrap()
+    }
+
+    assert_eq!(parse(to_json_string::<Account>(false).as_bytes()), expected::<Account>());
+    assert_eq!(parse(to_json_string::<Event>(true).as_bytes()), expected::<Event>());
+    assert_eq!(parse(to_json_string::<Tree>(true).as_bytes()), expected::<Tree>());
+    assert!(!to_json_string::<Account>(false).contains('\n'));
+    assert!(to_json_string::<Account>(true).contains('\n'));
+
+    let mut buf = Vec::new();
+    write_json::<Account, _>(&mut buf).unwrap();
+    assert_eq!(parse(&buf), expected::<Account>());
+
+    let mut registry = SchemaRegistry::new();
+    registry.register::<Account>().unwrap().register::<Event>().unwrap();
+
+    let mut buf = Vec::new();
+    write_registry_json(&registry, &mut buf).unwrap();
+    assert_eq!(parse(&buf), serde_json::to_value(registry.clone().into_document()).unwrap());
+
+    let dir = std::env::temp_dir().join(format!("magnet_schema_export_{}", std::process::id()));
+    registry.register::<Option<Event>>().unwrap();
+    write_json_files(&registry, &dir).unwrap();
+
+    assert_eq!(parse(&fs::read(dir.join("Account.json")).unwrap()), expected::<Account>());
+    assert_eq!(parse(&fs::read(dir.join("Event.json")).unwrap()), expected::<Event>());
+    assert_eq!(
+        parse(&fs::read(dir.join("Option_Event_.json")).unwrap()),
+        expected::<Option<Event>>()
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mongo_validator_commands() {
+    use magnet_schema::{ ValidatorOptions, ValidationLevel };
+    use magnet_schema::mongo::{ ensure_validator, fetch_validator };
+
+    #[derive(BsonSchema)]
+    struct User {
+        name: String,
+        age: u8,
+    }
+
+    /// Records the commands it runs, and replies as if `existing`
+    /// were the only collection, with the validator `validator`.
+    struct MockDatabase {
+        existing: &'static str,
+        validator: Document,
+        commands: Vec<Document>,
+    }
+
+    impl MockDatabase {
+        fn run(&mut self, command: Document) -> Result<Document, String> {
+            let reply = if let Ok(filter) = command.get_document("filter") {
+                let batch = if filter.get_str("name") == Ok(self.existing) {
+                    vec![Bson::from(doc!{
+                        "name": self.existing,
+                        "type": "collection",
+                        "options": { "validator": self.validator.clone() },
+                    })]
+                } else {
+                    Vec::new()
+                };
+
+                doc!{ "cursor": { "id": 0_i64, "firstBatch": batch }, "ok": 1.0 }
+            } else if command.get_str("create") == Ok(self.existing) {
+                return Err("collection already exists".into());
+            } else {
+                doc!{ "ok": 1.0 }
+            };
+
+            self.commands.push(command);
+            Ok(reply)
+        }
+    }
+
+    let mut db = MockDatabase {
+        existing: "users",
+        validator: doc!{ "$jsonSchema": User::bson_schema() },
+        commands: Vec::new(),
+    };
+    let options = ValidatorOptions::new().level(ValidationLevel::Moderate);
+
+    ensure_validator::<User, _>(&mut |cmd| db.run(cmd), "users", &options).unwrap();
+    ensure_validator::<User, _>(&mut |cmd| db.run(cmd), "admins", &options).unwrap();
+
+    assert_eq!(db.commands.len(), 4);
+    assert_schema_eq!(db.commands[0], doc!{ "listCollections": 1, "filter": { "name": "users" } });
+    assert_schema_eq!(db.commands[1], options.coll_mod_command::<User>("users"));
+    assert_schema_eq!(db.commands[2], doc!{ "listCollections": 1, "filter": { "name": "admins" } });
+    assert_schema_eq!(db.commands[3], options.create_collection_command::<User>("admins"));
+
+    let current = fetch_validator(&mut |cmd| db.run(cmd), "users").unwrap();
+    assert_schema_eq!(current.unwrap(), User::bson_schema());
+    assert!(fetch_validator(&mut |cmd| db.run(cmd), "admins").unwrap().is_none());
+
+    db.validator = doc!{ "age": { "$gte": 18 } };
+    assert!(fetch_validator(&mut |cmd| db.run(cmd), "users").unwrap().is_none());
+}
+
+#[test]
+fn schema_fingerprint() {
+    use magnet_schema::{ canonicalize, fingerprint };
+
+    #[derive(BsonSchema)]
+    struct Order {
+        id: String,
+        #[magnet(min_incl = "1")]
+        quantity: u32,
+        note: Option<String>,
+    }
+
+    let schema = Order::bson_schema();
+    let reordered = doc!{
+        "properties": {
+            "note": { "type": ["null", "string"] },
+            "quantity": { "maximum": 4294967295_i64, "bsonType": ["long", "int"], "minimum": 1_i64, "exclusiveMinimum": false },
+            "id": { "type": "string" },
+        },
+        "required": ["note", "id", "quantity"],
+        "type": "object",
+        "additionalProperties": false,
+    };
+    let relaxed = doc!{
+        "properties": {
+            "note": { "type": ["null", "string"] },
+            "quantity": { "maximum": 4294967295_i64, "bsonType": ["long", "int"], "minimum": 0_i64, "exclusiveMinimum": false },
+            "id": { "type": "string" },
+        },
+        "required": ["note", "id", "quantity"],
+        "type": "object",
+        "additionalProperties": false,
+    };
+
+    assert_schema_eq!(canonicalize(&schema), canonicalize(&reordered));
+    assert_eq!(
+        canonicalize(&reordered).keys().collect::<Vec<_>>(),
+        vec!["additionalProperties", "properties", "required", "type"]
+    );
+    assert_eq!(
+        canonicalize(&reordered).get_array("required").unwrap(),
+        &vec![Bson::from("id"), Bson::from("note"), Bson::from("quantity")]
+    );
+
+    assert_eq!(fingerprint(&schema), fingerprint(&reordered));
+    assert_eq!(fingerprint(&schema), fingerprint(&Order::bson_schema()));
+    assert_ne!(fingerprint(&schema), fingerprint(&relaxed));
+
+    // the order of keys within `enum` values is significant
+    let fst = doc!{ "enum": [{ "a": 1, "b": 2 }] };
+    let snd = doc!{ "enum": [{ "b": 2, "a": 1 }] };
+    assert_ne!(fingerprint(&fst), fingerprint(&snd));
+    assert_eq!(
+        fingerprint(&doc!{ "enum": ["x", "y"] }),
+        fingerprint(&doc!{ "enum": ["y", "x"] })
+    );
+}
+
+#[test]
+fn schema_stats() {
+    use magnet_schema::stats::{ SchemaStats, measure, assert_within_limits };
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    enum Shape {
+        Circle { radius: String },
+        Square { side: String },
+        Point,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    struct Pair<T> {
+        a: T,
+        b: T,
+    }
+
+    // { "anyOf": [
+    //     { ..., "required": [...], "properties": { "Circle": { ..., "properties": { "radius": {} } } } },
+    //     { ..., "required": [...], "properties": { "Square": { ..., "properties": { "side": {} } } } },
+    //     { "enum": [...] },
+    // ] }
+    let shape = Shape::bson_schema();
+    let stats = assert_within_limits(&shape);
+    let mut encoded = Vec::new();
+    bson::encode_document(&mut encoded, &shape).unwrap();
+
+    assert_eq!(stats, SchemaStats {
+        byte_size: encoded.len(),
+        max_depth: 7,
+        documents: 12,
+        any_of_count: 1,
+        any_of_branches: 3,
+        max_any_of_branches: 3,
+    });
+
+    // every level adds 2 documents (the struct and its `properties`) and
+    // 2 levels of nesting, and doubles the number of documents below it
+    type Nested = Pair<Pair<Pair<Pair<Pair<Pair<Pair<Pair<Pair<Pair<String>>>>>>>>>>;
+
+    let stats = assert_within_limits(&Nested::bson_schema());
+
+    assert_eq!(stats.documents, 3 * 1024 - 2);
+    assert_eq!(stats.max_depth, 2 * 10 + 1);
+    assert_eq!(stats.any_of_count, 0);
+    assert!(stats.byte_size > 1024 * 20);
+}
+
+#[test]
+#[should_panic(expected = "schema exceeds the limits of MongoDB")]
+fn schema_stats_depth_limit() {
+    use magnet_schema::stats::{ MAX_NESTING_DEPTH, assert_within_limits };
+
+    let mut schema = doc!{ "type": "string" };
+
+    for _ in 0..MAX_NESTING_DEPTH {
+        schema = doc!{ "type": "array", "items": schema };
+    }
+
+    assert_within_limits(&schema);
+}
+
+#[test]
+fn minimize_schema() {
+    use magnet_schema::optimize::minimize;
+
+    // defaults are removed, also in subschemas
+    assert_eq!(
+        minimize(&doc!{
+            "bsonType": "object",
+            "additionalProperties": true,
+            "minProperties": 0,
+            "required": [],
+            "properties": {
+                "tags": {
+                    "bsonType": "array",
+                    "uniqueItems": false,
+                    "minItems": 0_i64,
+                    "additionalItems": {},
+                    "items": { "bsonType": "string", "minLength": 0.0 },
+                },
+            },
+        }),
+        doc!{
+            "bsonType": "object",
+            "properties": {
+                "tags": {
+                    "bsonType": "array",
+                    "items": { "bsonType": "string" },
+                },
+            },
+        }
+    );
+
+    // non-default values are kept
+    let schema = doc!{
+        "bsonType": "array",
+        "uniqueItems": true,
+        "minItems": 1,
+        "additionalItems": false,
+        "items": [{ "bsonType": "string" }],
+    };
+    assert_eq!(minimize(&schema), schema);
+
+    // `additionalItems` is ignored without a list of `items`
+    assert_eq!(
+        minimize(&doc!{
+            "bsonType": "array",
+            "additionalItems": false,
+            "items": { "bsonType": "string" },
+        }),
+        doc!{ "bsonType": "array", "items": { "bsonType": "string" } }
+    );
+
+    // the bounds of `long` are redundant for integers only
+    assert_eq!(minimize(&i64::bson_schema()), doc!{ "bsonType": ["int", "long"] });
+    assert_eq!(
+        minimize(&doc!{
+            "bsonType": ["int", "long"],
+            "minimum": i64::min_value(),
+            "maximum": 10_i64,
+        }),
+        doc!{ "bsonType": ["int", "long"], "maximum": 10_i64 }
+    );
+
+    let schema = doc!{
+        "bsonType": ["double", "int", "long"],
+        "minimum": i64::min_value(),
+        "maximum": i64::max_value(),
+    };
+    assert_eq!(minimize(&schema), schema);
+
+    let schema = doc!{
+        "bsonType": "long",
+        "minimum": i64::min_value(),
+        "exclusiveMinimum": true,
+    };
+    assert_eq!(minimize(&schema), schema);
+
+    // identical branches of `anyOf` and `allOf`, regardless of key order
+    assert_eq!(
+        minimize(&doc!{
+            "anyOf": [
+                { "bsonType": "string", "minLength": 1 },
+                { "bsonType": "null" },
+                { "minLength": 1, "bsonType": "string", "maxLength": 0 },
+                { "minLength": 1, "bsonType": "string" },
+            ],
+            "allOf": [
+                { "bsonType": "null", "uniqueItems": false },
+                { "bsonType": "null" },
+            ],
+        }),
+        doc!{
+            "anyOf": [
+                { "bsonType": "string", "minLength": 1 },
+                { "bsonType": "null" },
+                { "minLength": 1, "bsonType": "string", "maxLength": 0 },
+            ],
+            "allOf": [
+                { "bsonType": "null" },
+            ],
+        }
+    );
+
+    // repeating a branch of `oneOf` changes its meaning
+    let schema = doc!{
+        "oneOf": [{ "bsonType": "null" }, { "bsonType": "null" }],
+    };
+    assert_eq!(minimize(&schema), schema);
+}
+
+#[test]
+fn minimize_schema_accepts_same_documents() {
+    use std::collections::BTreeMap;
+    use magnet_schema::optimize::minimize;
+    use magnet_schema::sample::sample;
+    use magnet_schema::validate::validate;
+
+    #[derive(BsonSchema)]
+    struct Point {
+        x: f64,
+        y: i64,
+    }
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    struct Inventory {
+        item_name: String,
+        #[magnet(min_incl = "10", max_excl = "20")]
+        quantity: u32,
+        serial: u64,
+        offset: i64,
+        tags: Vec<String>,
+        location: Option<Point>,
+        dimensions: [u16; 3],
+        pair: (bool, i8),
+        attributes: BTreeMap<String, i64>,
+    }
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum Shape {
+        Circle { radius: i64 },
+        Polygon(Vec<Point>),
+        Empty,
+    }
+
+    /// Values that mutated documents are made of.
+    fn palette() -> Vec<Bson> {
+        vec![
+            Bson::Null,
+            Bson::from(""),
+            Bson::I32(0),
+            Bson::I64(i64::min_value()),
+            Bson::I64(i64::max_value()),
+            Bson::FloatingPoint(1.5),
+            Bson::Boolean(true),
+            Bson::Array(Vec::new()),
+            Bson::Document(Document::new()),
+        ]
+    }
+
+    /// The value itself, and variations of it which differ in one place.
+    fn mutations(value: &Bson) -> Vec<Bson> {
+        let mut values = vec![value.clone()];
+
+        values.extend(palette());
+
+        match *value {
+            Bson::Document(ref doc) => {
+                for (key, item) in doc {
+                    let mut removed = doc.clone();
+                    removed.remove(key);
+                    values.push(removed.into());
+
+                    for mutated in mutations(item) {
+                        let mut replaced = doc.clone();
+                        replaced.insert(key.clone(), mutated);
+                        values.push(replaced.into());
+                    }
+                }
+
+                let mut extended = doc.clone();
+                extended.insert("extra_key", 0_i32);
+                values.push(extended.into());
+            },
+            Bson::Array(ref array) => {
+                for (index, item) in array.iter().enumerate() {
+                    let mut removed = array.clone();
+                    removed.remove(index);
+                    values.push(removed.into());
+
+                    for mutated in mutations(item) {
+                        let mut replaced = array.clone();
+                        replaced[index] = mutated;
+                        values.push(replaced.into());
+                    }
+                }
+
+                let mut extended = array.clone();
+                extended.push(Bson::Null);
+                values.push(extended.into());
+            },
+            _ => {},
+        }
+
+        values
+    }
+
+    fn assert_equivalent<T: BsonSchema>() {
+        let schema = T::bson_schema();
+        let minimized = minimize(&schema);
+
+        assert_ne!(schema, minimized, "nothing to minimize");
+
+        for value in mutations(&sample::<T>()) {
+            assert_eq!(
+                validate(&schema, &value).is_ok(),
+                validate(&minimized, &value).is_ok(),
+                "{} is validated differently by {} and {}",
+                value, schema, minimized
+            );
+        }
+    }
+
+    assert_equivalent::<Inventory>();
+    assert_equivalent::<Shape>();
+    assert_equivalent::<Option<Vec<i64>>>();
+    assert_equivalent::<(u8, i64, String)>();
+}
+
+#[test]
+fn schema_for_matches_field_attributes() {
+    use std::collections::HashMap;
+
+    #[derive(BsonSchema)]
+    struct Wrapper {
+        /// Number of items in stock.
+        #[magnet(min_incl = "10", max_excl = "20")]
+        quantity: u32,
+        #[magnet(min_excl = "0.5")]
+        ratio: f64,
+        #[magnet(values_min_incl = "0", values_max_incl = "1.0")]
+        weights: HashMap<String, f64>,
+        /// Contact address
+        #[magnet(format = "email")]
+        email: Option<String>,
+    }
+
+    let schema = Wrapper::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_eq!(
+        properties.get_document("quantity").unwrap(),
+        &schema_for!(u32, min_incl = 10, max_excl = 20, description = "Number of items in stock.")
+    );
+    assert_eq!(
+        properties.get_document("ratio").unwrap(),
+        &schema_for!(f64, min_excl = 0.5)
+    );
+    assert_eq!(
+        properties.get_document("weights").unwrap(),
+        &schema_for!(HashMap<String, f64>, values_min_incl = 0, values_max_incl = 1.0)
+    );
+    assert_eq!(
+        properties.get_document("email").unwrap(),
+        &schema_for!(Option<String>, format = "email", description = "Contact address",)
+    );
+
+    assert_eq!(schema_for!(String), String::bson_schema());
+    assert_eq!(
+        schema_for!(Vec<String>, min_items = 1, max_items = 3, description = "tags"),
+        doc!{
+            "type": "array",
+            "items": { "type": "string" },
+            "minItems": 1_i64,
+            "maxItems": 3_i64,
+            "description": "tags",
+        }
+    );
+}
+
+#[test]
+#[should_panic(expected = "string format `phone`")]
+fn schema_for_unknown_format() {
+    schema_for!(String, format = "phone");
+}
+
+#[test]
+fn map_entries_and_entry_pairs() {
+    use std::collections::{ BTreeMap, HashMap };
+    use magnet_schema::support::{ try_map_from_entries, try_entries_from_map };
+
+    #[derive(BsonSchema)]
+    struct Settings {
+        /// Stored as a document.
+        #[magnet(map_entries)]
+        limits: Vec<(String, u16)>,
+        #[magnet(entry_pairs)]
+        labels: BTreeMap<String, String>,
+        #[magnet(entry_pairs, values_min_incl = "0")]
+        scores: HashMap<String, i32>,
+        #[magnet(map_entries, values_max_incl = "10")]
+        weights: Vec<(String, i32)>,
+    }
+
+    let schema = Settings::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    let mut limits = HashMap::<String, u16>::bson_schema();
+    limits.insert("description", "Stored as a document.");
+    assert_eq!(properties.get_document("limits").unwrap(), &limits);
+
+    assert_eq!(
+        properties.get_document("labels").unwrap(),
+        &Vec::<(String, String)>::bson_schema()
+    );
+
+    // bounds on values apply to the values of the map, in both directions
+    let scores = properties.get_document("scores").unwrap();
+    let score_pair = scores.get_document("items").unwrap().get_array("items").unwrap();
+    assert_eq!(score_pair[1].as_document().unwrap().get_i64("minimum").unwrap(), 0);
+
+    let weights = properties.get_document("weights").unwrap();
+    let weight = weights.get_document("additionalProperties").unwrap();
+    assert_eq!(weight.get_i64("maximum").unwrap(), 10);
+
+    // round trip, also with restricted and known keys
+    let pairs = Vec::<(String, bool)>::bson_schema();
+    assert_eq!(try_entries_from_map(try_map_from_entries(pairs.clone()).unwrap()).unwrap(), pairs);
+
+    let patterned = doc!{
+        "type": "array",
+        "items": {
+            "type": "array",
+            "additionalItems": false,
+            "items": [{ "type": "string", "pattern": "^[a-z]+$" }, { "type": "null" }],
+        },
+    };
+    let map = try_map_from_entries(patterned.clone()).unwrap();
+    assert_eq!(map, doc!{
+        "type": "object",
+        "patternProperties": { "^[a-z]+$": { "type": "null" } },
+        "additionalProperties": false,
+    });
+    assert_eq!(try_entries_from_map(map).unwrap(), patterned);
+
+    let known = magnet_schema::support::exhaustive_map_schema(
+        &[String::from("x"), String::from("y")],
+        doc!{ "bsonType": "double" },
+        true,
+    );
+    assert_eq!(try_entries_from_map(known).unwrap(), doc!{
+        "type": "array",
+        "items": {
+            "type": "array",
+            "additionalItems": false,
+            "items": [{ "enum": ["x", "y"] }, { "bsonType": "double" }],
+        },
+    });
+
+    // other shapes are rejected
+    for schema in vec![String::bson_schema(), Vec::<String>::bson_schema(), Vec::<(String, bool, i32)>::bson_schema()] {
+        match try_map_from_entries(schema) {
+            Err(SchemaError::MalformedSchema(message)) => assert!(message.contains("map_entries")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    match try_entries_from_map(Vec::<(String, bool)>::bson_schema()) {
+        Err(SchemaError::MalformedSchema(message)) => assert!(message.contains("entry_pairs")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn newtype_collection_attributes() {
+    use magnet_schema::validate::validate;
+
+    /// Labels of an article.
+    #[derive(BsonSchema)]
+    #[magnet(unique_items, min_items = "1", max_items = "10", items_regex = "^[a-z]+$")]
+    struct Tags(Vec<String>);
+
+    #[derive(BsonSchema)]
+    #[magnet(values_min_incl = "0", values_max_incl = "100")]
+    struct Scores(#[magnet(min_items = "3")] Vec<f64>);
+
+    #[derive(BsonSchema)]
+    struct Article {
+        tags: Tags,
+        #[magnet(unique_items, max_items = "2")]
+        authors: Vec<String>,
+        #[magnet(min_items = "1", max_items = "5")]
+        point: [i32; 3],
+    }
+
+    assert_eq!(Tags::bson_schema(), doc!{
+        "type": "array",
+        "items": { "type": "string", "pattern": "^[a-z]+$" },
+        "minItems": 1_i64,
+        "maxItems": 10_i64,
+        "uniqueItems": true,
+        "description": "Labels of an article.",
+    });
+
+    let scores = Scores::bson_schema();
+    let score = scores.get_document("items").unwrap();
+    assert_eq!(scores.get_i64("minItems").unwrap(), 3);
+    assert_eq!(score.get_i64("minimum").unwrap(), 0);
+    assert_eq!(score.get_i64("maximum").unwrap(), 100);
+
+    let article = Article::bson_schema();
+    let properties = article.get_document("properties").unwrap();
+    let authors = properties.get_document("authors").unwrap();
+    assert_eq!(authors.get_i64("maxItems").unwrap(), 2);
+    assert_eq!(authors.get_bool("uniqueItems").unwrap(), true);
+
+    // the fixed length of an array is stricter than both bounds
+    let point = properties.get_document("point").unwrap();
+    assert_eq!(point.get_i64("minItems").unwrap(), 3);
+    assert_eq!(point.get_i64("maxItems").unwrap(), 3);
+
+    let tags = Tags::bson_schema();
+    assert!(validate(&tags, &bson!(["news", "rust"])).is_ok());
+    assert!(validate(&tags, &bson!(["news", "news"])).is_err());
+    assert!(validate(&tags, &bson!([])).is_err());
+
+    if cfg!(feature = "regex") {
+        assert!(validate(&tags, &bson!(["News"])).is_err());
+    }
+}
+
+#[test]
+fn items_constraints_require_arrays() {
+    use magnet_schema::support::{ try_extend_schema_with_items, try_extend_items_with_regex };
+
+    #[derive(BsonSchema)]
+    #[magnet(min_items = "1")]
+    struct Name(String);
+
+    match Name::try_bson_schema() {
+        Err(SchemaError::MalformedSchema(message)) => assert!(message.contains("array")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // nullable arrays are arrays, too
+    let schema = try_extend_schema_with_items(Option::<Vec<i32>>::bson_schema(), None, Some(2), false).unwrap();
+    assert_eq!(schema.get_i64("maxItems").unwrap(), 2);
+
+    match try_extend_schema_with_items(<[u8; 4]>::bson_schema(), Some(5), None, false) {
+        Err(SchemaError::MalformedSchema(message)) => assert!(message.contains("exceeds")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(try_extend_items_with_regex(<(String, String)>::bson_schema(), "^x$").is_err());
+    assert!(try_extend_items_with_regex(String::bson_schema(), "^x$").is_err());
+}
+
+#[test]
+fn std_unit_enums() {
+    use std::cmp::Ordering;
+    use std::net::Shutdown;
+    use std::num::FpCategory;
+
+    #[derive(BsonSchema)]
+    #[allow(dead_code)]
+    enum LocalOrdering {
+        Less,
+        Equal,
+        Greater,
+    }
+
+    assert_eq!(Ordering::bson_schema(), LocalOrdering::bson_schema());
+    assert_eq!(Ordering::bson_schema(), doc!{ "enum": ["Less", "Equal", "Greater"] });
+    assert_eq!(Shutdown::bson_schema(), doc!{ "enum": ["Read", "Write", "Both"] });
+    assert_eq!(
+        FpCategory::bson_schema(),
+        doc!{ "enum": ["Nan", "Infinite", "Zero", "Subnormal", "Normal"] }
+    );
+
+    // the names of the variants match their `Debug` representation
+    for value in &[Ordering::Less, Ordering::Equal, Ordering::Greater] {
+        let name = Bson::from(format!("{:?}", value));
+        assert!(Ordering::bson_schema().get_array("enum").unwrap().contains(&name));
+    }
+}
+
+#[test]
+fn export_key_order() {
+    use magnet_schema::support::reorder_canonical;
+    use magnet_schema::export::{ to_json_string, write_json };
+
+    /// A point in the plane.
+    #[derive(BsonSchema)]
+    struct Point {
+        /// Horizontal.
+        #[magnet(min_incl = "0")]
+        x: i32,
+        y: Option<i32>,
+        label: Option<String>,
+    }
+
+    // byte-identical across calls
+    let json = to_json_string::<Point>(true);
+    assert_eq!(json, to_json_string::<Point>(true));
+
+    let mut buf = Vec::new();
+    write_json::<Point, _>(&mut buf).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), json + "\n");
+
+    // structure first, annotations last, properties in declaration order
+    let point = to_json_string::<Point>(false);
+    assert_eq!(point, concat!(
+        r#"{"type":"object","required":["x","y","label"],"properties":{"#,
+        r#""x":{"bsonType":["int","long"],"minimum":0,"exclusiveMinimum":false,"#,
+        r#""maximum":2147483647,"description":"Horizontal."},"#,
+        r#""y":{"bsonType":["int","long","null"],"minimum":-2147483648,"maximum":2147483647},"#,
+        r#""label":{"type":["string","null"]}},"#,
+        r#""additionalProperties":false,"description":"A point in the plane."}"#,
+    ));
+
+    // making a schema nullable doesn't move `type` to the end
+    assert_eq!(
+        to_json_string::<Option<Point>>(false),
+        point.replacen(r#""type":"object""#, r#""type":["object","null"]"#, 1)
+    );
+    assert_eq!(
+        to_json_string::<Option<i32>>(false),
+        to_json_string::<i32>(false).replacen(r#""long"]"#, r#""long","null"]"#, 1)
+    );
+
+    // unknown keywords come before annotations; the rest is left alone
+    let schema = doc!{
+        "description": "d",
+        "x-b": 1,
+        "anyOf": [{ "description": "e", "type": "string" }],
+        "x-a": 2,
+        "enum": [{ "z": 1, "a": 2 }],
+    };
+    assert_eq!(reorder_canonical(&schema), doc!{
+        "enum": [{ "z": 1, "a": 2 }],
+        "anyOf": [{ "type": "string", "description": "e" }],
+        "x-a": 2,
+        "x-b": 1,
+        "description": "d",
+    });
+    assert_eq!(reorder_canonical(&reorder_canonical(&schema)), reorder_canonical(&schema));
+}
+
+#[test]
+fn nullable_schema_with_exotic_type() {
+    use magnet_schema::{ SchemaOptions, Nullability };
+    use magnet_schema::support::extend_schema_with_null;
+
+    /// A hand-written impl with a `type` that's neither a string nor an array.
+    struct Weird;
+
+    impl BsonSchema for Weird {
+        fn bson_schema() -> Document {
+            doc!{ "type": { "$ref": "#/definitions/Weird" } }
+        }
+    }
+
+    let wrapped = doc!{
+        "anyOf": [
+            { "type": { "$ref": "#/definitions/Weird" } },
+            { "type": "null" },
+        ],
+    };
+
+    assert_schema_eq!(Option::<Weird>::bson_schema(), wrapped.clone());
+    assert_schema_eq!(Option::<Option<Weird>>::bson_schema(), wrapped.clone());
+    assert_schema_eq!(
+        Option::<Weird>::bson_schema_with_options(&SchemaOptions::new().nullability(Nullability::AnyOf)),
+        wrapped.clone()
+    );
+    assert_eq!(Option::<Weird>::try_bson_schema(), Ok(wrapped));
+
+    assert_schema_eq!(
+        extend_schema_with_null(doc!{ "bsonType": 42_i32, "enum": [1_i32] }),
+        doc!{ "anyOf": [{ "bsonType": 42_i32, "enum": [1_i32, null] }, { "type": "null" }] }
+    );
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn iso8601_duration() {
+    use magnet_schema::Iso8601Duration;
+    use magnet_schema::time::ParseDurationError;
+    use magnet_schema::support::patterns::ISO8601_DURATION;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Retention {
+        window: Iso8601Duration,
+        #[magnet(with = "magnet_schema::time::duration_schema")]
+        grace: String,
+    }
+
+    let duration_schema = doc!{
+        "type": "string",
+        "pattern": ISO8601_DURATION,
+    };
+    let schema = Retention::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_schema_eq!(Iso8601Duration::bson_schema(), duration_schema.clone());
+    assert_schema_eq!(properties.get_document("window").unwrap(), duration_schema.clone());
+    assert_schema_eq!(properties.get_document("grace").unwrap(), duration_schema);
+
+    let parse = |string: &str| string.parse::<Iso8601Duration>().map(|d| d.num_milliseconds());
+
+    assert_eq!(parse("P30D"), Ok(30 * 24 * 3600 * 1000));
+    assert_eq!(parse("P2W"), Ok(14 * 24 * 3600 * 1000));
+    assert_eq!(parse("PT1H30M"), Ok(90 * 60 * 1000));
+    assert_eq!(parse("P1DT0.5S"), Ok(24 * 3600 * 1000 + 500));
+    assert_eq!(parse("PT1,25S"), Ok(1250));
+    assert_eq!(parse("P1Y"), Err(ParseDurationError::Calendar));
+    assert_eq!(parse("P1M"), Err(ParseDurationError::Calendar));
+
+    for &invalid in &["", "P", "PT", "P1DT", "30D", "P1.5D", "PT1S1M", "P1W1D", "P-1D", "P1H"] {
+        assert_eq!(parse(invalid), Err(ParseDurationError::Syntax), "{}", invalid);
+    }
+
+    let retention: Iso8601Duration = "P30D".parse().unwrap();
+    let value = bson::to_bson(&retention).unwrap();
+
+    assert_eq!(value, Bson::String(String::from("PT2592000S")));
+    assert_eq!(bson::from_bson::<Iso8601Duration>(value).unwrap(), retention);
+    assert!(bson::to_bson(&Iso8601Duration(-*retention)).is_err());
+}
+
+#[cfg(all(feature = "chrono", feature = "regex"))]
+#[test]
+fn iso8601_duration_pattern() {
+    use magnet_schema::Iso8601Duration;
+    use magnet_schema::support::patterns::ISO8601_DURATION;
+
+    let regex = regex::Regex::new(ISO8601_DURATION).unwrap();
+
+    for &valid in &["P30D", "PT1H30M", "P2W", "P1Y2M3DT4H5M6.5S", "P1M", "PT36H", "PT0,5S", "P0D"] {
+        assert!(regex.is_match(valid), "{}", valid);
+    }
+
+    for &invalid in &["", "P", "PT", "P1DT", "30D", "P1.5D", "PT1S1M", "P1W1D", "-P1D", "P1H", "p1d"] {
+        assert!(!regex.is_match(invalid), "{}", invalid);
+    }
+
+    // Everything written by `Iso8601Duration` matches its own pattern.
+    for &string in &["P30D", "PT1.5S", "PT0S", "P2W"] {
+        let duration: Iso8601Duration = string.parse().unwrap();
+        assert!(regex.is_match(&duration.to_string()), "{}", duration);
+    }
+}
+
+#[test]
+fn builders_match_derived_schemas() {
+    use magnet_schema::builder::{ ObjectSchema, ArraySchema, StringSchema };
+
+    /// A registered user.
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    struct User {
+        #[magnet(format = "email")]
+        email: String,
+        #[magnet(unique_items, min_items = 1, max_items = 8)]
+        roles: Vec<String>,
+        #[magnet(optional)]
+        nickname: Option<String>,
+        #[serde(default)]
+        logins: u32,
+    }
+
+    let built = ObjectSchema::new()
+        .property("email", StringSchema::new().format("email").build())
+        .property("roles", ArraySchema::new(String::bson_schema()).min_items(1).max_items(8).unique_items().build())
+        .optional_property("nickname", Option::<String>::bson_schema())
+        .optional_property("logins", u32::bson_schema())
+        .description("A registered user.")
+        .build();
+
+    assert_eq!(built, User::bson_schema());
+
+    let built = ObjectSchema::new()
+        .optional_property("id", String::bson_schema())
+        .required("id")
+        .required("id")
+        .allow_extra(true)
+        .build();
+
+    assert_eq!(built, doc!{
+        "type": "object",
+        "additionalProperties": true,
+        "required": ["id"],
+        "properties": {
+            "id": { "type": "string" },
+        },
+    });
+    assert_eq!(ObjectSchema::new().build(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {},
+    });
+
+    let built = StringSchema::new()
+        .pattern("^[a-z]+$")
+        .min_length(2)
+        .max_length(16)
+        .description("A slug.")
+        .build();
+
+    assert_eq!(built, doc!{
+        "type": "string",
+        "pattern": "^[a-z]+$",
+        "minLength": 2_i64,
+        "maxLength": 16_i64,
+        "description": "A slug.",
+    });
+}
+
+#[test]
+#[should_panic(expected = "`minItems` (3) exceeds `maxItems` (2)")]
+fn array_builder_empty_length_range() {
+    use magnet_schema::builder::ArraySchema;
+
+    ArraySchema::new(i32::bson_schema()).min_items(3).max_items(2).build();
+}
+
+#[test]
+fn versioned_schemas() {
+    use magnet_schema::versioned::{ with_version, extract_version, any_version };
+
+    /// A customer, before the name was split.
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(version = 1, schema_name = "Customer")]
+    struct CustomerV1 {
+        name: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(version = "2", schema_name = "Customer")]
+    struct CustomerV2 {
+        first_name: String,
+        last_name: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(version = 3)]
+    enum Status {
+        Active,
+        Closed,
+    }
+
+    let v1 = CustomerV1::bson_schema();
+    let v2 = CustomerV2::bson_schema();
+
+    assert_eq!(v1, doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name"],
+        "properties": {
+            "name": { "type": "string" },
+        },
+        "description": "A customer, before the name was split.",
+        "$comment": "schema version 1",
+    });
+    assert_eq!(extract_version(&v1), Some(1));
+    assert_eq!(extract_version(&v2), Some(2));
+    assert_eq!(extract_version(&Status::bson_schema()), Some(3));
+    assert_eq!(extract_version(&String::bson_schema()), None);
+    assert_eq!(extract_version(&doc!{ "$comment": "schema version two" }), None);
+    assert_eq!(
+        extract_version(&with_version(doc!{ "$comment": "old" }, -4)),
+        Some(-4)
+    );
+
+    let transitional = any_version(&[v1.clone(), v2.clone()]);
+
+    assert_eq!(transitional, doc!{ "anyOf": [v1.clone(), v2.clone()] });
+    assert_eq!(extract_version(&transitional), None);
+
+    let v3 = with_version(v2.clone(), 3);
+
+    assert_eq!(
+        any_version(&[transitional, v3.clone()]),
+        doc!{ "anyOf": [v1, v2, v3] }
+    );
+}
+
+#[test]
+fn borrowed_fields() {
+    use std::borrow::Cow;
+    use magnet_schema::validate::validate;
+
+    fn binary_schema() -> Document {
+        doc!{ "bsonType": "binData" }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    struct Raw<'a> {
+        #[serde(borrow)]
+        name: &'a str,
+        #[serde(borrow)]
+        label: Cow<'a, str>,
+        bytes: &'a [u8],
+        spans: Vec<&'a [u8]>,
+        #[magnet(with = "binary_schema")]
+        blob: &'a [u8],
+        #[magnet(min_incl = 1, max_excl = 10)]
+        count: &'a u32,
+        #[magnet(values_min_incl = 0, values_max_incl = 255, max_items = 4)]
+        levels: &'a [i32],
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, BsonSchema)]
+    #[serde(tag = "kind")]
+    enum Token<'a> {
+        Word {
+            #[serde(borrow)]
+            text: &'a str,
+        },
+        Bytes {
+            #[serde(borrow = "'a")]
+            data: &'a [u8],
+        },
+    }
+
+    let schema = Raw::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_schema_eq!(properties.get_document("name").unwrap(), doc!{ "type": "string" });
+    assert_schema_eq!(properties.get_document("label").unwrap(), doc!{ "type": "string" });
+    assert_schema_eq!(properties.get_document("bytes").unwrap(), Vec::<u8>::bson_schema());
+    assert_schema_eq!(properties.get_document("spans").unwrap(), Vec::<Vec<u8>>::bson_schema());
+    assert_schema_eq!(properties.get_document("blob").unwrap(), binary_schema());
+    assert_eq!(properties.get_document("count").unwrap().get_i64("minimum"), Ok(1));
+    assert_eq!(properties.get_document("count").unwrap().get_i64("maximum"), Ok(10));
+
+    let levels = properties.get_document("levels").unwrap();
+    let level_items = levels.get_document("items").unwrap();
+
+    assert_eq!(levels.get_i64("maxItems"), Ok(4));
+    assert_eq!(level_items.get_i64("minimum"), Ok(0));
+    assert_eq!(level_items.get_i64("maximum"), Ok(255));
+
+    // Byte slices are serialized as sequences of integers, unless a
+    // `#[serde(with = "...")]` module writes them as binary.
+    let raw = Raw {
+        name: "name",
+        label: Cow::Borrowed("label"),
+        bytes: b"\x00\xff",
+        spans: vec![b"ab", b""],
+        blob: b"blob",
+        count: &3,
+        levels: &[0, 128, 255],
+    };
+    let mut value = bson::to_bson(&raw).unwrap();
+
+    if let Bson::Document(ref mut document) = value {
+        document.insert("blob", Bson::Binary(bson::spec::BinarySubtype::Generic, b"blob".to_vec()));
+    }
+
+    assert_eq!(validate(&schema, &value), Ok(()));
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Window<'a, T: 'a> {
+        items: &'a [T],
+        first: Option<&'a T>,
+    }
+
+    assert_schema_eq!(Window::<'static, u8>::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["items", "first"],
+        "properties": {
+            "items": Vec::<u8>::bson_schema(),
+            "first": Option::<u8>::bson_schema(),
+        },
+    });
+
+    let token_schema = Token::bson_schema();
+    let word = bson::to_bson(&Token::Word { text: "hello" }).unwrap();
+    let bytes = bson::to_bson(&Token::Bytes { data: b"\x01\x02" }).unwrap();
+
+    assert_eq!(validate(&token_schema, &word), Ok(()));
+    assert_eq!(validate(&token_schema, &bytes), Ok(()));
+}
+
+#[test]
+fn examples_from_paths() {
+    use magnet_schema::support::extend_schema_with_example;
+
+    const UNKNOWN: Bson = Bson::Null;
+
+    fn admin() -> Document {
+        doc!{ "name": "root", "roles": ["admin"] }
+    }
+
+    fn guest_name() -> &'static str {
+        "guest"
+    }
+
+    mod examples {
+        pub fn viewer() -> ::bson::Document {
+            doc!{ "name": "alice", "roles": ["viewer"] }
+        }
+    }
+
+    /// An account.
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(examples_from = "admin")]
+    #[magnet(examples_from = "examples::viewer", version = 1)]
+    struct Account {
+        name: String,
+        roles: Vec<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(examples_from = "guest_name", examples_from = "UNKNOWN")]
+    struct Name(Option<String>);
+
+    let schema = Account::bson_schema();
+
+    assert_eq!(schema.get_array("examples").unwrap(), &vec![
+        Bson::Document(admin()),
+        Bson::Document(examples::viewer()),
+    ]);
+    assert_eq!(schema.keys().map(String::as_str).collect::<Vec<_>>(), [
+        "type", "additionalProperties", "required", "properties",
+        "description", "examples", "$comment",
+    ]);
+    assert_eq!(
+        Name::bson_schema().get_array("examples").unwrap(),
+        &vec![Bson::from("guest"), Bson::Null]
+    );
+
+    let schema = extend_schema_with_example(doc!{ "examples": "invalid" }, Bson::I32(1));
+    assert_eq!(schema, doc!{ "examples": [1] });
+}
+
+#[test]
+fn field_name_constants() {
+    use std::marker::PhantomData;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    #[magnet(field_names)]
+    struct MyDoc {
+        #[serde(rename = "_id")]
+        id: String,
+        created_at: i64,
+        #[serde(skip)]
+        cache: Vec<u8>,
+        r#type: String,
+    }
+
+    struct NoSchema;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    #[magnet(field_names, no_bound(T))]
+    struct Tagged<T> {
+        tag_name: String,
+        marker: PhantomData<T>,
+    }
+
+    let schema = MyDoc::bson_schema();
+    let properties = schema.get_document("properties").unwrap();
+
+    assert_eq!(MyDoc::FIELD_ID, "_id");
+    assert_eq!(MyDoc::FIELD_CREATED_AT, "createdAt");
+    assert!(properties.contains_key(MyDoc::FIELD_ID));
+    assert!(properties.contains_key(MyDoc::FIELD_CREATED_AT));
+    assert_eq!(MyDoc::FIELD_TYPE, "type");
+    assert!(properties.contains_key(MyDoc::FIELD_TYPE));
+    assert_eq!(properties.len(), 3);
+
+    assert_eq!(Tagged::<NoSchema>::FIELD_TAG_NAME, "tag_name");
+    assert_eq!(Tagged::<NoSchema>::FIELD_MARKER, "marker");
+}
+
+#[test]
+fn normalized_type_keywords() {
+    use magnet_schema::{ SchemaOptions, KeyStyle };
+    use magnet_schema::convert::to_key_style;
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Address {
+        street: String,
+        number: i32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Person {
+        name: String,
+        nickname: Option<String>,
+        age: u8,
+        score: f64,
+        verified: bool,
+        address: Address,
+        tags: Vec<String>,
+        extra: Option<Document>,
+    }
+
+    let mixed = Person::bson_schema_with_options(&SchemaOptions::default());
+    let typed = Person::bson_schema_with_options(&SchemaOptions::new().key_style(KeyStyle::TypeKey));
+    let bson_typed = Person::bson_schema_with_options(&SchemaOptions::new().key_style(KeyStyle::BsonTypeKey));
+
+    assert_eq!(mixed, Person::bson_schema());
+    assert_eq!(to_key_style(mixed.clone(), KeyStyle::Mixed), mixed);
+
+    let age = doc!{ "bsonType": ["int", "long"], "minimum": 0_i64, "maximum": 255_i64 };
+    let score = doc!{ "bsonType": ["double", "int", "long"] };
+
+    assert_eq!(typed, doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name", "nickname", "age", "score", "verified", "address", "tags", "extra"],
+        "properties": {
+            "name": { "type": "string" },
+            "nickname": { "type": ["string", "null"] },
+            "age": age.clone(),
+            "score": score.clone(),
+            "verified": { "type": "boolean" },
+            "address": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["street", "number"],
+                "properties": {
+                    "street": { "type": "string" },
+                    "number": i32::bson_schema(),
+                },
+            },
+            "tags": { "type": "array", "items": { "type": "string" } },
+            "extra": { "type": ["object", "null"] },
+        },
+    });
+    assert_eq!(bson_typed, doc!{
+        "bsonType": "object",
+        "additionalProperties": false,
+        "required": ["name", "nickname", "age", "score", "verified", "address", "tags", "extra"],
+        "properties": {
+            "name": { "bsonType": "string" },
+            "nickname": { "bsonType": ["string", "null"] },
+            "age": age,
+            "score": score,
+            "verified": { "bsonType": "bool" },
+            "address": {
+                "bsonType": "object",
+                "additionalProperties": false,
+                "required": ["street", "number"],
+                "properties": {
+                    "street": { "bsonType": "string" },
+                    "number": i32::bson_schema(),
+                },
+            },
+            "tags": { "bsonType": "array", "items": { "bsonType": "string" } },
+            "extra": { "bsonType": ["object", "null"] },
+        },
+    });
+    assert_eq!(to_key_style(bson_typed.clone(), KeyStyle::TypeKey), typed);
+
+    assert_eq!(
+        to_key_style(doc!{ "bsonType": ["decimal", "long", "int", "double", "null"] }, KeyStyle::TypeKey),
+        doc!{ "type": ["number", "null"] }
+    );
+    assert_eq!(
+        to_key_style(doc!{ "type": ["integer", "number"] }, KeyStyle::BsonTypeKey),
+        doc!{ "bsonType": ["int", "long", "double", "decimal"] }
+    );
+
+    let both = doc!{ "type": "string", "bsonType": "string" };
+    assert_eq!(to_key_style(both.clone(), KeyStyle::TypeKey), both);
+    assert_eq!(to_key_style(both.clone(), KeyStyle::BsonTypeKey), both);
+
+    let named = doc!{ "type": "object", "properties": { "bsonType": { "bsonType": "bool" } } };
+    assert_eq!(
+        to_key_style(named, KeyStyle::TypeKey),
+        doc!{ "type": "object", "properties": { "bsonType": { "type": "boolean" } } }
+    );
+}
+
+#[test]
+fn tuple_variant_field_attributes() {
+    use std::collections::BTreeMap;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    enum External {
+        Clamp(#[magnet(min_incl = "0")] f64, #[magnet(max_excl = "1")] f64),
+        Tags(#[magnet(unique_items)] Vec<String>, #[magnet(format = "email")] String),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "op", content = "args")]
+    enum Adjacent {
+        Clamp(#[magnet(min_incl = "0")] f64, #[magnet(max_excl = "1")] f64),
+        Tags(#[magnet(unique_items)] Vec<String>, #[magnet(format = "email")] String),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(untagged)]
+    enum Untagged {
+        Clamp(#[magnet(min_incl = "0")] f64, #[magnet(max_excl = "1")] f64),
+        Tags(#[magnet(unique_items)] Vec<String>, #[magnet(format = "email")] String),
+    }
+
+    // Internally tagged enums can't have tuple variants, only newtype ones.
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    #[serde(tag = "op")]
+    enum Internal {
+        Weights(#[magnet(values_min_incl = "0", values_max_incl = "1")] BTreeMap<String, f64>),
+    }
+
+    let clamp_items = vec![
+        Bson::Document(magnet_schema::support::extend_schema_with_bounds(
+            f64::bson_schema(), magnet_schema::Bounds::from(0_i64..)
+        )),
+        Bson::Document(magnet_schema::support::extend_schema_with_bounds(
+            f64::bson_schema(), magnet_schema::Bounds::from(..1_i64)
+        )),
+    ];
+    let tags_items = vec![
+        Bson::Document(magnet_schema::support::try_extend_schema_with_items(
+            Vec::<String>::bson_schema(), None, None, true
+        ).unwrap()),
+        Bson::Document(magnet_schema::support::try_extend_schema_with_format(
+            String::bson_schema(), "email"
+        ).unwrap()),
+    ];
+    let payload = |items: &Vec<Bson>| doc!{
+        "type": "array",
+        "additionalItems": false,
+        "items": items.clone(),
+    };
+
+    let external = External::bson_schema();
+    let external_variants = external.get_array("anyOf").unwrap();
+    let external_payload = |index: usize, name: &str| {
+        external_variants[index]
+            .as_document().unwrap()
+            .get_document("properties").unwrap()
+            .get_document(name).unwrap()
+            .clone()
+    };
+
+    assert_eq!(external_payload(0, "Clamp"), payload(&clamp_items));
+    assert_eq!(external_payload(1, "Tags"), payload(&tags_items));
+
+    let adjacent = Adjacent::bson_schema();
+    let adjacent_variants = adjacent.get_array("anyOf").unwrap();
+    let adjacent_payload = |index: usize| {
+        adjacent_variants[index]
+            .as_document().unwrap()
+            .get_document("properties").unwrap()
+            .get_document("args").unwrap()
+            .clone()
+    };
+
+    assert_eq!(adjacent_payload(0), payload(&clamp_items));
+    assert_eq!(adjacent_payload(1), payload(&tags_items));
+
+    assert_eq!(Untagged::bson_schema(), doc!{
+        "anyOf": [payload(&clamp_items), payload(&tags_items)],
+    });
+
+    let internal = Internal::bson_schema();
+    let weights_variant = internal.get_array("anyOf").unwrap()[0].as_document().unwrap();
+    let weights = weights_variant.get_document("additionalProperties").unwrap();
+
+    assert_eq!(weights.get_i64("minimum"), Ok(0));
+    assert_eq!(weights.get_i64("maximum"), Ok(1));
+    assert_eq!(weights_variant.get_document("properties").unwrap(), &doc!{ "op": { "enum": ["Weights"] } });
+}
+
+
+#[test]
+fn same_named_types_in_different_modules() {
+    use magnet_schema::{ SchemaRegistry, RegistryError, NameCollisionError };
+
+    mod modules {
+        pub mod a {
+            #[allow(dead_code)]
+            #[derive(BsonSchema)]
+            pub struct Node {
+                name: String,
+                inner: super::b::Node,
+            }
+        }
+
+        pub mod b {
+            #[allow(dead_code)]
+            #[derive(BsonSchema)]
+            pub struct Node {
+                value: i32,
+            }
+        }
+
+        pub mod c {
+            #[allow(dead_code)]
+            #[derive(BsonSchema)]
+            pub struct Node {
+                children: Vec<Node>,
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Tree {
+        root: modules::c::Node,
+    }
+
+    assert_eq!(modules::a::Node::bson_schema(), doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name", "inner"],
+        "properties": {
+            "name": String::bson_schema(),
+            "inner": modules::b::Node::bson_schema(),
+        },
+    });
+
+    let mut registry = SchemaRegistry::new();
+
+    registry.register::<modules::b::Node>().expect("can't register b::Node");
+
+    assert_eq!(
+        registry.register::<modules::a::Node>().err(),
+        Some(RegistryError::NameCollision(NameCollisionError { name: String::from("Node") }))
+    );
+
+    // `c::Node` is only registered as a dependency of `Tree`
+    assert_eq!(
+        registry.register::<Tree>().err(),
+        Some(RegistryError::NameCollision(NameCollisionError { name: String::from("Node") }))
+    );
+
+    let doc = registry.into_document();
+    let definitions = doc.get_document("definitions").expect("no definitions");
+
+    assert_eq!(definitions.keys().collect::<Vec<_>>(), vec!["Node"]);
+    assert_eq!(definitions.get_document("Node").unwrap(), &modules::b::Node::bson_schema());
+}
+
+#[test]
+fn shared_definitions_of_same_named_types() {
+    use magnet_schema::{ SchemaOptions, root_schema_with_options };
+
+    mod modules {
+        pub mod a {
+            #[allow(dead_code)]
+            #[derive(BsonSchema)]
+            pub struct Item {
+                name: String,
+            }
+        }
+
+        pub mod b {
+            #[allow(dead_code)]
+            #[derive(BsonSchema)]
+            pub struct Item {
+                count: u32,
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Outer {
+        x: modules::a::Item,
+        y: modules::b::Item,
+        z: modules::a::Item,
+    }
+
+    let options = SchemaOptions::new().share_definitions(true);
+    let schema = root_schema_with_options::<Outer>(&options);
+    let b_key = std::any::type_name::<modules::b::Item>();
+
+    assert_eq!(schema, doc!{
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["x", "y", "z"],
+        "properties": {
+            "x": { "$ref": "#/definitions/Item" },
+            "y": { "$ref": format!("#/definitions/{}", b_key) },
+            "z": { "$ref": "#/definitions/Item" },
+        },
+        "definitions": {
+            "Item": modules::a::Item::bson_schema(),
+            b_key: modules::b::Item::bson_schema(),
+        },
+    });
+}
+
+#[test]
+fn user_defined_map_keys() {
+    use std::fmt;
+    use std::collections::{ HashMap, BTreeMap };
+    use bson::oid::ObjectId;
+    use magnet_schema::BsonSchemaKey;
+    use magnet_schema::support::patterns::OBJECT_ID_HEX_PATTERN;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema, PartialEq, Eq, Hash)]
+    struct UserId(String);
+
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema, PartialEq, Eq, PartialOrd, Ord)]
+    struct Port(u16);
+
+    /// Not derived, so not a key unless it says so.
+    #[derive(PartialEq, Eq, Hash)]
+    struct Slug(String);
+
+    impl fmt::Display for Slug {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(&self.0)
+        }
+    }
+
+    impl BsonSchemaKey for Slug {}
+
+    // a newtype over a non-key type is still a valid `BsonSchema`
+    #[allow(dead_code)]
+    #[derive(Serialize, BsonSchema)]
+    struct Tags(Vec<String>);
+
+    let any_key = doc!{
+        "type": "object",
+        "additionalProperties": i32::bson_schema(),
+    };
+
+    assert_eq!(HashMap::<UserId, i32>::bson_schema(), any_key);
+    assert_eq!(HashMap::<Slug, i32>::bson_schema(), any_key);
+    assert_eq!(BTreeMap::<Port, bool>::bson_schema(), BTreeMap::<u16, bool>::bson_schema());
+    assert_eq!(HashMap::<ObjectId, i32>::bson_schema(), doc!{
+        "type": "object",
+        "patternProperties": {
+            OBJECT_ID_HEX_PATTERN: i32::bson_schema(),
+        },
+        "additionalProperties": false,
+    });
+    assert_eq!(Tags::bson_schema(), Vec::<String>::bson_schema());
+}
+
+#[test]
+fn openapi_map_key_patterns() {
+    use std::collections::HashMap;
+    use magnet_schema::openapi::to_openapi_schema;
+
+    assert_schema_eq!(to_openapi_schema(&HashMap::<u32, String>::bson_schema()), doc!{
+        "type": "object",
+        "additionalProperties": { "type": "string" },
+    });
+    assert_schema_eq!(to_openapi_schema(&HashMap::<String, Option<u8>>::bson_schema()), doc!{
+        "type": "object",
+        "additionalProperties": {
+            "type": "integer",
+            "nullable": true,
+            "format": "int32",
+            "minimum": 0_i64,
+            "maximum": 255_i64,
+        },
+    });
+    assert_schema_eq!(
+        to_openapi_schema(&doc!{
+            "type": "object",
+            "patternProperties": {
+                "^a": { "bsonType": "bool" },
+                "^b": { "type": "string" },
+            },
+            "additionalProperties": false,
+        }),
+        doc!{ "type": "object" }
+    );
+}
+
+#[test]
+fn schema_registry_errors() {
+    use magnet_schema::{ SchemaRegistry, RegistryError, Bounds };
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Bad {
+        #[magnet(min_incl = 300)]
+        x: u8,
+        y: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(BsonSchema)]
+    struct Good {
+        x: u8,
+    }
+
+    let mut registry = SchemaRegistry::new();
+
+    match registry.register::<Bad>() {
+        Err(RegistryError::Schema(SchemaError::EmptyRange(bounds))) => {
+            assert_eq!(bounds, Bounds::from(300_i64..));
+        },
+        other => panic!("unexpected result: {:?}", other.map(|r| r.definitions().clone())),
+    }
 
-    assert_doc_eq!(Vec::<ElaborateType>::bson_schema(),        array_schema);
-    assert_doc_eq!(VecDeque::<ElaborateType>::bson_schema(),   array_schema);
-    assert_doc_eq!(BinaryHeap::<ElaborateType>::bson_schema(), array_schema);
-    assert_doc_eq!(LinkedList::<ElaborateType>::bson_schema(), array_schema);
+    registry.register::<Good>().expect("can't register Good");
+
+    let doc = registry.into_document();
+    let definitions = doc.get_document("definitions").expect("no definitions");
+
+    assert_eq!(definitions.keys().collect::<Vec<_>>(), vec!["Good"]);
+}
+
+#[test]
+fn mongo_failed_commands() {
+    use magnet_schema::ValidatorOptions;
+    use magnet_schema::mongo::{ ensure_validator, fetch_validator, CommandError };
+
+    let unauthorized = || doc!{
+        "ok": 0.0,
+        "errmsg": "not authorized on test to execute command",
+        "code": 13,
+        "codeName": "Unauthorized",
+    };
+    let options = ValidatorOptions::new();
+
+    // a failed `listCollections` doesn't mean that the collection is missing
+    let mut commands = Vec::new();
+    let result = ensure_validator::<String, _>(
+        &mut |command: Document| -> Result<Document, String> {
+            commands.push(command);
+            Ok(unauthorized())
+        },
+        "names",
+        &options,
+    );
+
+    assert_eq!(result, Err(CommandError::Failed {
+        code: Some(13),
+        message: String::from("not authorized on test to execute command"),
+    }));
+    assert_eq!(commands.len(), 1);
+
+    assert_eq!(
+        fetch_validator(&mut |_| -> Result<Document, String> { Ok(unauthorized()) }, "names"),
+        Err(CommandError::Failed {
+            code: Some(13),
+            message: String::from("not authorized on test to execute command"),
+        })
+    );
+
+    // neither does a failed `create` or `collMod` succeed
+    for &existing in &[false, true] {
+        let result = ensure_validator::<String, _>(
+            &mut |command: Document| -> Result<Document, String> {
+                if command.contains_key("listCollections") {
+                    let batch = if existing { vec![Bson::from(doc!{ "name": "names" })] } else { Vec::new() };
+                    Ok(doc!{ "cursor": { "id": 0_i64, "firstBatch": batch }, "ok": 1 })
+                } else {
+                    Ok(doc!{ "ok": 0, "errmsg": "validator is invalid" })
+                }
+            },
+            "names",
+            &options,
+        );
+
+        assert_eq!(result, Err(CommandError::Failed {
+            code: None,
+            message: String::from("validator is invalid"),
+        }));
+    }
+
+    // errors of the runner itself are passed through
+    assert_eq!(
+        fetch_validator(&mut |_| Err("connection refused"), "names"),
+        Err(CommandError::Runner("connection refused"))
+    );
+}
+
+#[cfg(all(feature = "url", feature = "regex"))]
+#[test]
+fn url_patterns_without_authority() {
+    use regex::Regex;
+    use magnet_schema::support::{ URL_PATTERN, AUTHORITY_URL_PATTERN };
+
+    let pattern = Regex::new(URL_PATTERN).unwrap();
+    let authority_pattern = Regex::new(AUTHORITY_URL_PATTERN).unwrap();
+
+    for &value in &[
+        "file:///etc/passwd",
+        "mailto:user@example.com",
+        "data:text/plain,hi",
+        "urn:isbn:0451450523",
+    ] {
+        let url = url::Url::parse(value).unwrap();
+
+        assert!(pattern.is_match(url.as_str()), "{} should be a valid URL", url);
+        assert!(!authority_pattern.is_match(url.as_str()), "{} has no authority", url);
+    }
+
+    for &value in &["https://example.com/a?b#c", "mongodb://user@host:27017/db"] {
+        assert!(pattern.is_match(value), "{} should be a valid URL", value);
+        assert!(authority_pattern.is_match(value), "{} has an authority", value);
+    }
+
+    for &value in &["example.com", "/etc/passwd", "://example.com", "http://exa mple.com"] {
+        assert!(!pattern.is_match(value), "{} should be an invalid URL", value);
+    }
+}
+
+#[test]
+fn flatten_shared_non_recursive_definitions() {
+    use magnet_schema::{ SchemaOptions, root_schema_with_options, flatten_refs };
+    use magnet_schema::validate::validate;
+
+    #[derive(Serialize, BsonSchema)]
+    struct Inner {
+        value: i32,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    struct Middle {
+        inner: Inner,
+    }
+
+    #[derive(Serialize, BsonSchema)]
+    struct Outer {
+        middle: Middle,
+        other: Inner,
+    }
+
+    let options = SchemaOptions::new().share_definitions(true);
+    let shared = root_schema_with_options::<Outer>(&options);
+    let flat = flatten_refs(shared, 1);
+
+    assert_schema_eq!(flat, Outer::bson_schema());
+
+    let value = Outer {
+        middle: Middle { inner: Inner { value: 1 } },
+        other: Inner { value: 2 },
+    };
+
+    assert!(validate(&flat, &bson::to_bson(&value).unwrap()).is_ok());
+    assert_schema_eq!(flatten_refs(root_schema_with_options::<Outer>(&options), 0), flat);
+}
+
+#[cfg(feature = "mongodb")]
+#[test]
+fn mongodb_driver_commands() {
+    use bson::oid::ObjectId;
+    use mongodb::sync::Client;
+    use magnet_schema::ValidatorOptions;
+    use magnet_schema::mongo::{
+        ensure_validator, to_driver_document, from_driver_document, CommandError, DriverError,
+    };
+
+    #[derive(BsonSchema)]
+    struct User {
+        name: String,
+        age: u8,
+    }
+
+    let command = ValidatorOptions::new().create_collection_command::<User>("users");
+    let driver_command = to_driver_document(&command).unwrap();
+
+    assert_eq!(driver_command.get_str("create"), Ok("users"));
+    assert_eq!(from_driver_document(&driver_command).unwrap(), command);
+
+    let reply = doc!{ "ok": 1.0, "id": ObjectId::new().unwrap(), "n": 2_i64, "flag": true };
+    assert_eq!(from_driver_document(&to_driver_document(&reply).unwrap()).unwrap(), reply);
+
+    // nothing listens on port 1, so the driver itself fails
+    let uri = "mongodb://127.0.0.1:1/?directConnection=true&serverSelectionTimeoutMS=100";
+    let mut db = Client::with_uri_str(uri).unwrap().database("test");
+
+    match ensure_validator::<User, _>(&mut db, "users", &ValidatorOptions::new()) {
+        Err(CommandError::Runner(DriverError::Driver(_))) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
 }